@@ -12,10 +12,17 @@ def median(lst):
     s = sorted(lst)
     return (sum(s[n//2-1:n//2+1])/2.0, s[n//2])[n % 2] if n else None
 
+# Stats of the functions that were lifted and analyzed (functions with errors have none)
+def func_stats(report):
+    return [func["stats"] for func in report["functions"] if func["stats"] is not None]
+
+def total_time(stats):
+    return stats["times"]["total"]
+
 def graph_blocks_vs_time(dataset):
     for data in dataset.values():
-        times = [x[1] for x in data]
-        block_counts = [x[2] + x[3] + x[4] + x[5] for x in data]
+        times = [total_time(x) for x in data]
+        block_counts = [x["blocks"] for x in data]
         plt.plot(block_counts, times, 'o')
     plt.show()
     #raise NotImplementedError
@@ -25,8 +32,7 @@ def graph_funcs_vs_time(dataset):
     total_times = []
     for data in dataset.values():
         func_count.append(len(data))
-        total_time = sum([x[1] for x in data])
-        total_times.append(total_time)
+        total_times.append(sum([total_time(x) for x in data]))
         plt.plot(func_count, total_times, 'o')
     plt.show()
 
@@ -34,8 +40,10 @@ def get_data(filenames):
     dataset = {}
     for filename in filenames:
         with open(filename) as f:
-            data = json.load(f)
-        dataset[filename] = data
+            report = json.load(f)
+        if report.get("version") != 1:
+            raise ValueError(f"{filename}: unsupported report version {report.get('version')}")
+        dataset[filename] = func_stats(report)
     return dataset
 
 def get_aggregate_data(dataset):
@@ -43,7 +51,7 @@ def get_aggregate_data(dataset):
     #print(len(dataset.keys()))
     for name,data in dataset.items():
         name = name.split('/')[-1].split(".")[0]
-        times = [x[2] + x[3] + x[4] + x[5] + x[6] for x in data]
+        times = [total_time(x) for x in data]
         average_t = sum(times) / len(times)
         median_t = median(times)
         max_t = max(times)
@@ -52,16 +60,16 @@ def get_aggregate_data(dataset):
         num_funcs = len(times)
         N = len(times) // 100
         print("top 1% = ", N, " functions out of", len(times))
-        top_n = sorted(data, key=lambda x: (x[2] + x[3] + x[4] + x[5] + x[6]), reverse = True)[:N] 
-        top_percent = sum([x[2] + x[3] + x[4] + x[5] + x[6] for x in top_n]) / total_t
-        top_percent_medians = median([x[1] for x in top_n])
-        cfg_percent = sum([x[2] for x in data]) / total_t
-        stack_percent = sum([x[3] for x in data]) / total_t
-        heap_percent = sum([x[4] for x in data]) / total_t
-        call_percent = sum([x[5] for x in data]) / total_t
-        locals_percent = sum([x[6] for x in data]) / total_t
+        top_n = sorted(data, key=total_time, reverse = True)[:N] 
+        top_percent = sum([total_time(x) for x in top_n]) / total_t
+        top_percent_medians = median([x["blocks"] for x in top_n])
+        cfg_percent = sum([x["times"]["cfg"] for x in data]) / total_t
+        stack_percent = sum([x["times"]["stack"] for x in data]) / total_t
+        heap_percent = sum([x["times"]["heap"] for x in data]) / total_t
+        call_percent = sum([x["times"]["call"] for x in data]) / total_t
+        locals_percent = sum([x["times"]["locals"] for x in data]) / total_t
         print(top_n, top_percent)
-        median_blocks = median([x[1] for x in data])
+        median_blocks = median([x["blocks"] for x in data])
         aggregate_data.append( (name,average_t,median_t,max_t,min_t,num_funcs,total_t,top_percent, cfg_percent, stack_percent, heap_percent, call_percent, locals_percent, median_blocks, top_percent_medians))
     return aggregate_data
     
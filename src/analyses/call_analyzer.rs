@@ -1,6 +1,8 @@
 use crate::{analyses, ir, lattices, loaders};
 use analyses::reaching_defs::ReachingDefnAnalyzer;
-use analyses::{AbstractAnalyzer, AnalysisResult};
+use analyses::{
+    adjust_stack_offset, preserved_at, AbstractAnalyzer, AnalysisError, AnalysisResult, Summaries,
+};
 use ir::types::*;
 // use ir::utils::{extract_stack_offset, is_stack_access};
 use crate::ir::types::RegT;
@@ -11,6 +13,7 @@ use lattices::{VarSlot, VarState};
 use loaders::types::VwMetadata;
 use std::convert::TryFrom;
 use std::default::Default;
+use std::sync::Arc;
 use yaxpeax_core::analyses::control_flow::VW_CFG;
 use yaxpeax_x86::long_mode::Opcode;
 
@@ -25,6 +28,8 @@ pub struct CallAnalyzer {
     pub funcs: Vec<u64>,
     pub irmap: IRMap,
     pub cfg: VW_CFG,
+    /// The summaries of the functions of the module, applied at direct calls.
+    pub summaries: Arc<Summaries>,
 }
 
 impl CallAnalyzer {
@@ -47,7 +52,7 @@ impl CallAnalyzer {
                 if &loc_idx.addr == addr && (loc_idx.idx as usize) == idx {
                     return a_state;
                 }
-                self.aexec(
+                self.replay(
                     &mut a_state,
                     ir_insn,
                     &LocIdx {
@@ -78,7 +83,11 @@ impl CallAnalyzer {
 }
 
 impl AbstractAnalyzer<CallCheckLattice> for CallAnalyzer {
-    fn analyze_block(&self, state: &CallCheckLattice, irblock: &IRBlock) -> CallCheckLattice {
+    fn analyze_block(
+        &self,
+        state: &CallCheckLattice,
+        irblock: &IRBlock,
+    ) -> Result<CallCheckLattice, AnalysisError> {
         let mut new_state = state.clone();
         for (addr, instruction) in irblock.iter() {
             for (idx, ir_insn) in instruction.iter().enumerate() {
@@ -95,10 +104,31 @@ impl AbstractAnalyzer<CallCheckLattice> for CallAnalyzer {
                         addr: *addr,
                         idx: idx as u32,
                     },
-                );
+                )?;
+            }
+        }
+        Ok(new_state)
+    }
+
+    fn aexec(
+        &self,
+        in_state: &mut CallCheckLattice,
+        ir_instr: &Stmt,
+        loc_idx: &LocIdx,
+    ) -> Result<(), AnalysisError> {
+        match ir_instr {
+            Stmt::Clear(dst, _srcs) => in_state.set_to_bot(dst),
+            Stmt::Unop(opcode, dst, src) => self.aexec_unop(in_state, opcode, dst, src, loc_idx),
+            Stmt::Binop(opcode, dst, src1, src2) => {
+                self.aexec_binop(in_state, opcode, dst, src1, src2, loc_idx);
+                adjust_stack_offset(in_state, opcode, dst, src1, src2, loc_idx)?
+            }
+            Stmt::Call(target) => {
+                in_state.on_call_preserving(&preserved_at(&self.summaries, target, loc_idx))
             }
+            _ => (),
         }
-        new_state
+        Ok(())
     }
 
     fn aexec_unop(
@@ -218,7 +248,10 @@ impl AbstractAnalyzer<CallCheckLattice> for CallAnalyzer {
                     //1. propagate checked values
                     let defs_state = self.reaching_defs.get(addr).unwrap();
                     let ir_block = irmap.get(addr).unwrap();
-                    let defs_state = self.reaching_analyzer.analyze_block(defs_state, ir_block);
+                    let defs_state = self
+                        .reaching_analyzer
+                        .analyze_block(defs_state, ir_block)
+                        .unwrap_or_default();
                     let checked_defs = defs_state.regs.get_reg(regnum, Size64);
                     for idx in X86Regs::iter() {
                         let reg_def = defs_state.regs.get_reg(idx, Size64);
@@ -0,0 +1,398 @@
+use super::{edge_state, AbstractAnalyzer, AnalysisResult};
+use crate::ir::types::*;
+use crate::lattices::reachingdefslattice::LocIdx;
+use crate::lattices::{Lattice, VarState};
+use petgraph::Direction;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use yaxpeax_core::analyses::control_flow::VW_CFG;
+
+/// Where a tracked value was lost.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValueDrop {
+    /// The value did not hold in the initial state of the function.
+    Entry,
+    /// Executing the statement lost the value.
+    Stmt(LocIdx),
+    /// Every edge into the block held the value, but their meet does not.
+    Meet(u64),
+    /// Refining the state on the branch from the first block to the second lost the value.
+    Branch(u64, u64),
+}
+
+/// A block where edges that still held the value met edges that had lost it, so the value
+/// dropped to bottom.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MeetPoint {
+    pub block: u64,
+    /// Predecessors whose edges held the value.
+    pub held: Vec<u64>,
+    /// Predecessors whose edges had lost it.
+    pub lost: Vec<u64>,
+}
+
+/// A path from the function entry to a failing statement along which a value degrades.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CounterexamplePath {
+    /// Blocks from the function entry to the block of the failing statement.
+    pub blocks: Vec<u64>,
+    /// Where the value was last lost before the failing statement.
+    pub origin: ValueDrop,
+    /// Meets along the path where the value dropped to bottom, in path order.
+    pub meets: Vec<MeetPoint>,
+}
+
+impl fmt::Display for CounterexamplePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let blocks: Vec<String> = self
+            .blocks
+            .iter()
+            .map(|block| format!("0x{:x}", block))
+            .collect();
+        writeln!(f, "path: {}", blocks.join(" -> "))?;
+        match &self.origin {
+            ValueDrop::Entry => write!(f, "not established at function entry")?,
+            ValueDrop::Stmt(loc_idx) => write!(f, "lost at 0x{:x}:{}", loc_idx.addr, loc_idx.idx)?,
+            ValueDrop::Meet(block) => write!(f, "lost at the meet into 0x{:x}", block)?,
+            ValueDrop::Branch(from, to) => {
+                write!(f, "lost on the branch from 0x{:x} to 0x{:x}", from, to)?
+            }
+        }
+        for meet in &self.meets {
+            let addrs = |blocks: &Vec<u64>| -> String {
+                let addrs: Vec<String> = blocks.iter().map(|b| format!("0x{:x}", b)).collect();
+                addrs.join(", ")
+            };
+            write!(
+                f,
+                "\nmeet at 0x{:x}: held from [{}], lost from [{}]",
+                meet.block,
+                addrs(&meet.held),
+                addrs(&meet.lost)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The block containing the statements of `addr`.
+fn block_of<Ar: RegT>(irmap: &IRMap<Ar>, addr: u64) -> Option<u64> {
+    irmap
+        .iter()
+        .find(|(_, irblock)| irblock.iter().any(|(stmt_addr, _)| *stmt_addr == addr))
+        .map(|(block_addr, _)| *block_addr)
+}
+
+// Replays `block` from `state` up to, but not including, `end` (or the whole block). Returns
+// the state there and the last statement that turned the value from held to lost.
+fn replay<T, State, Ar>(
+    analyzer: &T,
+    state: &State,
+    irblock: &IRBlock<Ar>,
+    end: Option<&LocIdx>,
+    holds: &impl Fn(&State) -> bool,
+) -> (State, Option<LocIdx>)
+where
+    T: AbstractAnalyzer<State, Ar>,
+    State: VarState<Ar> + Lattice + Clone,
+    Ar: RegT,
+{
+    let mut state = state.clone();
+    let mut drop = None;
+    for (addr, stmts) in irblock {
+        for (idx, stmt) in stmts.iter().enumerate() {
+            let loc_idx = LocIdx {
+                addr: *addr,
+                idx: idx as u32,
+            };
+            if Some(&loc_idx) == end {
+                return (state, drop);
+            }
+            let held = holds(&state);
+            analyzer.replay(&mut state, stmt, &loc_idx);
+            if held && !holds(&state) {
+                drop = Some(loc_idx);
+            }
+        }
+    }
+    (state, drop)
+}
+
+/// The state of `result` right before the statement at `loc_idx`, and the block it is in.
+pub fn state_before<T, State, Ar>(
+    irmap: &IRMap<Ar>,
+    analyzer: &T,
+    result: &AnalysisResult<State>,
+    loc_idx: &LocIdx,
+) -> Option<(u64, State)>
+where
+    T: AbstractAnalyzer<State, Ar>,
+    State: VarState<Ar> + Lattice + Clone,
+    Ar: RegT,
+{
+    let block = block_of(irmap, loc_idx.addr)?;
+    let (state, _) = replay(
+        analyzer,
+        result.get(&block)?,
+        &irmap[&block],
+        Some(loc_idx),
+        &|_: &State| true,
+    );
+    Some((block, state))
+}
+
+// A shortest path of analyzed blocks from the entrypoint to `target`.
+fn path_from_entry<State>(cfg: &VW_CFG, result: &AnalysisResult<State>, target: u64) -> Vec<u64> {
+    let mut parents: HashMap<u64, u64> = HashMap::new();
+    let mut worklist: VecDeque<u64> = VecDeque::new();
+    worklist.push_back(cfg.entrypoint);
+    parents.insert(cfg.entrypoint, cfg.entrypoint);
+    while let Some(block) = worklist.pop_front() {
+        if block == target {
+            break;
+        }
+        for succ in cfg.graph.neighbors(block) {
+            if result.contains_key(&succ) && !parents.contains_key(&succ) {
+                parents.insert(succ, block);
+                worklist.push_back(succ);
+            }
+        }
+    }
+    let mut path = vec![target];
+    let mut block = target;
+    while block != cfg.entrypoint {
+        block = parents[&block];
+        path.push(block);
+    }
+    path.reverse();
+    path
+}
+
+/// Finds a path from the function entry to the statement at `loc_idx` along which `holds`
+/// stops being true, by walking predecessor blocks back from the statement through the edges
+/// on which the value was already lost. Returns `None` if the value holds before the statement.
+pub fn counterexample_path<T, State, Ar>(
+    cfg: &VW_CFG,
+    irmap: &IRMap<Ar>,
+    analyzer: &T,
+    result: &AnalysisResult<State>,
+    loc_idx: &LocIdx,
+    holds: impl Fn(&State) -> bool,
+) -> Option<CounterexamplePath>
+where
+    T: AbstractAnalyzer<State, Ar>,
+    State: VarState<Ar> + Lattice + Clone,
+    Ar: RegT,
+{
+    let (fail_block, state) = state_before(irmap, analyzer, result, loc_idx)?;
+    if holds(&state) {
+        return None;
+    }
+
+    // Nodes are (block, whole block?). The failing block is first searched only up to the
+    // failing statement, and again in full if a loop leads back to it.
+    let start = (fail_block, false);
+    let mut parents: HashMap<(u64, bool), (u64, bool)> = HashMap::new();
+    let mut meets: HashMap<u64, MeetPoint> = HashMap::new();
+    let mut visited: HashSet<(u64, bool)> = HashSet::new();
+    let mut worklist: VecDeque<(u64, bool)> = VecDeque::new();
+    visited.insert(start);
+    worklist.push_back(start);
+
+    let mut found = None;
+    while let Some((block, whole)) = worklist.pop_front() {
+        let end = if whole { None } else { Some(loc_idx) };
+        let (_, drop) = replay(analyzer, &result[&block], &irmap[&block], end, &holds);
+        if let Some(drop) = drop {
+            found = Some(((block, whole), ValueDrop::Stmt(drop), block));
+            break;
+        }
+        // The value was already lost on entry to `block`
+        if block == cfg.entrypoint && !holds(&analyzer.init_state()) {
+            found = Some(((block, whole), ValueDrop::Entry, block));
+            break;
+        }
+        let mut preds: Vec<u64> = cfg
+            .graph
+            .neighbors_directed(block, Direction::Incoming)
+            .filter(|pred| result.contains_key(pred))
+            .collect();
+        preds.sort();
+        let mut held = Vec::new();
+        let mut lost = Vec::new();
+        let mut branch_drop = None;
+        for pred in preds {
+            let (out_state, branch_state) =
+                edge_state(cfg, irmap, analyzer, &result[&pred], pred, block);
+            let edge_holds = branch_state.map_or(true, |state| holds(&state));
+            if edge_holds {
+                held.push(pred);
+                continue;
+            }
+            lost.push(pred);
+            if holds(&out_state) {
+                branch_drop.get_or_insert(pred);
+            } else if visited.insert((pred, true)) {
+                parents.insert((pred, true), (block, whole));
+                worklist.push_back((pred, true));
+            }
+        }
+        if lost.is_empty() {
+            let prev = held.first().copied();
+            meets.insert(block, MeetPoint { block, held, lost });
+            found = Some((
+                (block, whole),
+                ValueDrop::Meet(block),
+                prev.unwrap_or(block),
+            ));
+            break;
+        }
+        if let Some(pred) = branch_drop {
+            found = Some(((block, whole), ValueDrop::Branch(pred, block), pred));
+            break;
+        }
+        if !held.is_empty() {
+            meets.insert(block, MeetPoint { block, held, lost });
+        }
+    }
+
+    // `prefix_to` is the last block before `node` on the path; it is `node` itself unless the
+    // value was lost on the way into `node`.
+    let (node, origin, prefix_to) = found?;
+    let mut blocks = path_from_entry(cfg, result, prefix_to);
+    if prefix_to != node.0 {
+        blocks.push(node.0);
+    }
+    let mut node = node;
+    while let Some(next) = parents.get(&node) {
+        blocks.push(next.0);
+        node = *next;
+    }
+    let meets = blocks
+        .iter()
+        .filter_map(|block| meets.remove(block))
+        .collect();
+    Some(CounterexamplePath {
+        blocks,
+        origin,
+        meets,
+    })
+}
+
+#[cfg(test)]
+pub fn diamond_cfg(join_stmts: Vec<Stmt>, left: Vec<Stmt>, right: Vec<Stmt>) -> (VW_CFG, IRMap) {
+    use petgraph::graphmap::GraphMap;
+    use std::collections::BTreeMap;
+    use yaxpeax_core::analyses::control_flow::VW_Block;
+
+    // 0x0 branches to 0x10 and 0x20, which both fall into 0x30
+    let mut cfg = VW_CFG {
+        entrypoint: 0x0,
+        blocks: BTreeMap::new(),
+        graph: GraphMap::new(),
+    };
+    let mut irmap = IRMap::new();
+    for (block, stmts) in vec![
+        (0x0, vec![]),
+        (0x10, left),
+        (0x20, right),
+        (0x30, join_stmts),
+    ] {
+        cfg.blocks.insert(
+            block,
+            VW_Block {
+                start: block,
+                end: block + 0xf,
+            },
+        );
+        cfg.graph.add_node(block);
+        irmap.insert(block, vec![(block, stmts)]);
+    }
+    for (from, to) in vec![(0x0, 0x10), (0x0, 0x20), (0x10, 0x30), (0x20, 0x30)] {
+        cfg.graph.add_edge(from, to, ());
+    }
+    (cfg, irmap)
+}
+
+#[test]
+fn counterexample_path_test() {
+    use crate::analyses::{run_worklist, HeapAnalyzer};
+    use crate::lattices::heaplattice::{HeapLattice, HeapValue};
+    use crate::{HeapLayout, HeapRegs, HeapStrategy, VwMetadata};
+    use ValSize::*;
+    use X86Regs::*;
+
+    let analyzer = HeapAnalyzer {
+        metadata: VwMetadata {
+            guest_table_0: 0,
+            lucet_tables: 0,
+            lucet_probestack: 0,
+            heap_layout: HeapLayout::default(),
+            heap_regs: HeapRegs::default(),
+        },
+        strategy: HeapStrategy::HeapPtrFirstArgWithGuards,
+        summaries: Default::default(),
+    };
+    let is_heap_base =
+        |state: &HeapLattice| state.regs.get_reg(Rax, Size64).v == Some(HeapValue::HeapBase(0));
+    let access = LocIdx { addr: 0x30, idx: 0 };
+    let load = Stmt::Unop(
+        Unopcode::Mov,
+        Value::Reg(Rbx, Size64),
+        Value::Mem(Size64, MemArgs::Mem1Arg(MemArg::Reg(Rax, Size64))),
+    );
+    let copy_heap_base = Stmt::Unop(
+        Unopcode::Mov,
+        Value::Reg(Rax, Size64),
+        Value::Reg(Rdi, Size64),
+    );
+
+    // rax is clobbered on the left path only
+    let (cfg, irmap) = diamond_cfg(
+        vec![load.clone()],
+        vec![
+            copy_heap_base.clone(),
+            Stmt::Clear(Value::Reg(Rax, Size64), vec![]),
+        ],
+        vec![copy_heap_base.clone()],
+    );
+    let result = run_worklist(&cfg, &irmap, &analyzer).unwrap();
+    let path =
+        counterexample_path(&cfg, &irmap, &analyzer, &result, &access, is_heap_base).unwrap();
+    assert_eq!(path.blocks, vec![0x0, 0x10, 0x30]);
+    assert_eq!(path.origin, ValueDrop::Stmt(LocIdx { addr: 0x10, idx: 1 }));
+    assert_eq!(
+        path.meets,
+        vec![MeetPoint {
+            block: 0x30,
+            held: vec![0x20],
+            lost: vec![0x10],
+        }]
+    );
+
+    // rax is the heap base on one path and a bounded index on the other, so both edges into
+    // the join know something about it but their meet does not
+    let (cfg, irmap) = diamond_cfg(
+        vec![load],
+        vec![copy_heap_base],
+        vec![Stmt::Clear(Value::Reg(Rax, Size32), vec![])],
+    );
+    let result = run_worklist(&cfg, &irmap, &analyzer).unwrap();
+    let is_known = |state: &HeapLattice| state.regs.get_reg(Rax, Size64).v.is_some();
+    let path = counterexample_path(&cfg, &irmap, &analyzer, &result, &access, is_known).unwrap();
+    assert_eq!(path.origin, ValueDrop::Meet(0x30));
+    assert_eq!(path.blocks, vec![0x0, 0x10, 0x30]);
+    assert_eq!(path.meets[0].held, vec![0x10, 0x20]);
+
+    // Along the right path rax never held the heap base
+    let path =
+        counterexample_path(&cfg, &irmap, &analyzer, &result, &access, is_heap_base).unwrap();
+    assert_eq!(path.origin, ValueDrop::Entry);
+    assert_eq!(path.blocks, vec![0x0, 0x20, 0x30]);
+
+    // Nothing to explain when the value holds
+    let access = LocIdx { addr: 0x10, idx: 0 };
+    let holds =
+        |state: &HeapLattice| state.regs.get_reg(Rdi, Size64).v == Some(HeapValue::HeapBase(0));
+    assert!(counterexample_path(&cfg, &irmap, &analyzer, &result, &access, holds).is_none());
+}
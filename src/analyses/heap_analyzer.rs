@@ -1,6 +1,9 @@
 use crate::ir::types::Stmt;
 use crate::{analyses, ir, lattices, loaders};
-use analyses::{AbstractAnalyzer, AnalysisResult};
+use crate::{HeapLayout, HeapStrategy};
+use analyses::{
+    adjust_stack_offset, callee_summary, AbstractAnalyzer, AnalysisError, AnalysisResult, Summaries,
+};
 use ir::types::*;
 // use ir::utils::{extract_stack_offset, is_stack_access};
 use lattices::heaplattice::{HeapLattice, HeapValue, HeapValueLattice};
@@ -8,25 +11,74 @@ use lattices::reachingdefslattice::LocIdx;
 use lattices::{ConstLattice, VarState};
 use loaders::types::VwMetadata;
 use std::default::Default;
+use std::sync::Arc;
+use yaxpeax_x86::long_mode::Opcode as JumpOpcode;
 
 use HeapValue::*;
 use ValSize::*;
-use X86Regs::*;
 
 pub struct HeapAnalyzer {
     pub metadata: VwMetadata,
+    pub strategy: HeapStrategy,
+    /// The summaries of the functions of the module, applied at direct calls.
+    pub summaries: Arc<Summaries>,
 }
 
-impl AbstractAnalyzer<HeapLattice> for HeapAnalyzer {
-    fn init_state(&self) -> HeapLattice {
-        let mut result: HeapLattice = Default::default();
+impl<Ar: RegT> AbstractAnalyzer<HeapLattice<Ar>, Ar> for HeapAnalyzer {
+    fn init_state(&self) -> HeapLattice<Ar> {
+        let mut result: HeapLattice<Ar> = Default::default();
+        let (reg, first_arg) = self.entry_arg();
         result
             .regs
-            .set_reg(Rdi, Size64, HeapValueLattice::new(HeapBase));
+            .set_reg(reg, Size64, HeapValueLattice::new(first_arg));
+        self.set_pinned_reg(&mut result);
         result
     }
 
-    fn aexec(&self, in_state: &mut HeapLattice, ir_instr: &Stmt, loc_idx: &LocIdx) -> () {
+    // On the edges of a bounds check `cmp idx, bound; jcc`, the edge on which the index is
+    // below the heap length gets the index as a CheckedIndex. Likewise, on the edges of the
+    // check `cmp idx, n; jcc` that guards a jump table, the edge on which the index is below
+    // the entry count gets the index as a TableIndex.
+    fn process_branch(
+        &self,
+        irmap: &IRMap<Ar>,
+        in_state: &HeapLattice<Ar>,
+        succ_addrs: &Vec<u64>,
+        addr: &u64,
+    ) -> Vec<(u64, HeapLattice<Ar>)> {
+        let checks = if succ_addrs.len() == 2 {
+            self.bounds_check(irmap, in_state, addr)
+                .or_else(|| table_check(irmap, in_state, addr))
+        } else {
+            None
+        };
+        match checks {
+            Some((regnum, not_branch_check, branch_check)) => succ_addrs
+                .iter()
+                .zip([not_branch_check, branch_check].iter())
+                .map(|(addr, check)| {
+                    let mut state = in_state.clone();
+                    if let Some(v) = check {
+                        state
+                            .regs
+                            .set_reg(regnum, Size64, HeapValueLattice::new(v.clone()));
+                    }
+                    (*addr, state)
+                })
+                .collect(),
+            None => succ_addrs
+                .iter()
+                .map(|addr| (*addr, in_state.clone()))
+                .collect(),
+        }
+    }
+
+    fn aexec(
+        &self,
+        in_state: &mut HeapLattice<Ar>,
+        ir_instr: &Stmt<Ar>,
+        loc_idx: &LocIdx,
+    ) -> Result<(), AnalysisError> {
         match ir_instr {
             Stmt::Clear(dst, _srcs) => {
                 if let &Value::Reg(rd, Size32) | &Value::Reg(rd, Size16) | &Value::Reg(rd, Size8) =
@@ -42,25 +94,40 @@ impl AbstractAnalyzer<HeapLattice> for HeapAnalyzer {
             Stmt::Unop(opcode, dst, src) => self.aexec_unop(in_state, opcode, &dst, &src, loc_idx),
             Stmt::Binop(opcode, dst, src1, src2) => {
                 self.aexec_binop(in_state, opcode, dst, src1, src2, loc_idx);
-                in_state.adjust_stack_offset(opcode, dst, src1, src2)
+                adjust_stack_offset(in_state, opcode, dst, src1, src2, loc_idx)?
             }
-            Stmt::Call(_) => {
-                // TODO: this should only be for probestack
-                // RDI is conserved on calls
-                // let v = in_state.regs.get_reg(Rdi, Size64);
-                in_state.on_call();
-                // in_state.regs.set_reg(Rdi, Size64, v);
+            Stmt::Call(target) => {
+                // A heap base in a callee-saved register, in the caller's frame or in a
+                // register the callee preserves survives the call, unless the callee may
+                // grow and so move its memory
+                let summary = callee_summary(&self.summaries, target, loc_idx);
+                // The callee's result is only known if it is passed the caller's heap
+                let (entry_reg, entry_value) = self.entry_arg::<Ar>();
+                let ret = summary
+                    .and_then(|summary| summary.ret)
+                    .filter(|_| in_state.regs.get_reg(entry_reg, Size64).v == Some(entry_value));
+                in_state.on_call_preserving(
+                    &summary.map_or_else(Vec::new, |summary| summary.preserved_regs()),
+                );
+                if let Some((regnum, value)) = ret {
+                    in_state
+                        .regs
+                        .set_reg_index(regnum, Size64, HeapValueLattice::new(value));
+                }
+                self.forget_movable_heaps(in_state);
+                self.set_pinned_reg(in_state);
             }
             _ => (),
         }
+        Ok(())
     }
 
     fn aexec_unop(
         &self,
-        in_state: &mut HeapLattice,
+        in_state: &mut HeapLattice<Ar>,
         opcode: &Unopcode,
-        dst: &Value,
-        src: &Value,
+        dst: &Value<Ar>,
+        src: &Value<Ar>,
         _loc_idx: &LocIdx,
     ) -> () {
         // Any write to a 32-bit register will clear the upper 32 bits of the containing 64-bit
@@ -89,32 +156,50 @@ impl AbstractAnalyzer<HeapLattice> for HeapAnalyzer {
 
     fn aexec_binop(
         &self,
-        in_state: &mut HeapLattice,
+        in_state: &mut HeapLattice<Ar>,
         opcode: &Binopcode,
-        dst: &Value,
-        src1: &Value,
-        src2: &Value,
+        dst: &Value<Ar>,
+        src1: &Value<Ar>,
+        src2: &Value<Ar>,
         _loc_idx: &LocIdx,
     ) {
         match opcode {
             Binopcode::Add => {
-                if let (
-                    &Value::Reg(rd, Size64),
-                    &Value::Reg(rs1, Size64),
-                    &Value::Reg(rs2, Size64),
-                ) = (dst, src1, src2)
-                {
-                    let rs1_val = in_state.regs.get_reg(rs1, Size64).v;
-                    let rs2_val = in_state.regs.get_reg(rs2, Size64).v;
+                if let &Value::Reg(rd, Size64) = dst {
+                    let rs1_val = add_operand_value(in_state, src1);
+                    let rs2_val = add_operand_value(in_state, src2);
                     match (rs1_val, rs2_val) {
-                        (Some(HeapBase), Some(Bounded4GB)) | (Some(Bounded4GB), Some(HeapBase)) => {
-                            in_state
-                                .regs
-                                .set_reg(rd, Size64, ConstLattice { v: Some(HeapAddr) });
+                        (Some(HeapBase(memory)), Some(Bounded4GB))
+                        | (Some(Bounded4GB), Some(HeapBase(memory))) => {
+                            in_state.regs.set_reg(
+                                rd,
+                                Size64,
+                                ConstLattice {
+                                    v: Some(HeapAddr(memory)),
+                                },
+                            );
                             return;
                         }
                         _ => {}
                     }
+                    // `adrp; add` forms the address of a constant from its page and the
+                    // offset within the page
+                    if let (Some(RIPConst), Value::Imm(_, _, _)) = (rs1_val, src2) {
+                        in_state
+                            .regs
+                            .set_reg(rd, Size64, HeapValueLattice::new(RIPConst));
+                        return;
+                    }
+                }
+            }
+            Binopcode::And => {
+                if let &Value::Reg(rd, Size64) = dst {
+                    if let Some(mask) = and_mask(src1, src2) {
+                        in_state
+                            .regs
+                            .set_reg(rd, Size64, HeapValueLattice::new(MaskedIndex(mask)));
+                        return;
+                    }
                 }
             }
             _ => {}
@@ -137,12 +222,51 @@ impl AbstractAnalyzer<HeapLattice> for HeapAnalyzer {
     }
 }
 
-pub fn is_globalbase_access(in_state: &HeapLattice, memargs: &MemArgs) -> bool {
+// A 32-bit register operand of a 64-bit add is zero-extended (AArch64's
+// `add x0, x1, w2, uxtw`), so it is bounded whatever the register holds.
+fn add_operand_value<Ar: RegT>(in_state: &HeapLattice<Ar>, value: &Value<Ar>) -> Option<HeapValue> {
+    match value {
+        Value::Reg(regnum, Size64) => in_state.regs.get_reg(*regnum, Size64).v,
+        Value::Reg(_, Size32) => Some(Bounded4GB),
+        _ => None,
+    }
+}
+
+// The non-negative constant an operand of an and is masked with.
+fn and_mask<Ar: RegT>(src1: &Value<Ar>, src2: &Value<Ar>) -> Option<u64> {
+    match (src1, src2) {
+        (Value::Imm(_, _, mask), _) | (_, Value::Imm(_, _, mask)) if *mask >= 0 => {
+            Some(*mask as u64)
+        }
+        _ => None,
+    }
+}
+
+// Is `memargs` the field at `field_offset` within vmctx?
+fn is_vmctx_load<Ar: RegT>(
+    in_state: &HeapLattice<Ar>,
+    memsize: ValSize,
+    memargs: &MemArgs<Ar>,
+    field_offset: usize,
+) -> bool {
+    if memsize != Size64 {
+        return false;
+    }
+    let (regnum, offset) = match memargs {
+        MemArgs::Mem1Arg(MemArg::Reg(regnum, Size64)) => (regnum, 0),
+        MemArgs::Mem2Args(MemArg::Reg(regnum, Size64), MemArg::Imm(_, _, offset)) => {
+            (regnum, *offset)
+        }
+        _ => return false,
+    };
+    offset == field_offset as i64 && in_state.regs.get_reg(*regnum, Size64).v == Some(VMCtxPtr)
+}
+
+pub fn is_globalbase_access<Ar: RegT>(in_state: &HeapLattice<Ar>, memargs: &MemArgs<Ar>) -> bool {
     if let MemArgs::Mem2Args(arg1, _arg2) = memargs {
-        if let MemArg::Reg(regnum, size) = arg1 {
-            assert_eq!(size.into_bits(), 64);
-            let base = in_state.regs.get_reg(*regnum, *size);
-            if let Some(HeapBase) = base.v {
+        if let MemArg::Reg(regnum, Size64) = arg1 {
+            let base = in_state.regs.get_reg(*regnum, Size64);
+            if let Some(HeapBase(_)) = base.v {
                 return true;
             }
         }
@@ -151,10 +275,137 @@ pub fn is_globalbase_access(in_state: &HeapLattice, memargs: &MemArgs) -> bool {
 }
 
 impl HeapAnalyzer {
-    pub fn aeval_unop(&self, in_state: &HeapLattice, value: &Value) -> HeapValueLattice {
+    // The register the hidden first argument is in, and its value on entry.
+    fn entry_arg<Ar: RegT>(&self) -> (Ar, HeapValue) {
+        let heap_regs = self.metadata.heap_regs;
+        match self.strategy {
+            HeapStrategy::HeapPtrFirstArgWithGuards => (heap_regs.heap_base_reg(), HeapBase(0)),
+            _ => (heap_regs.vmctx_reg(), VMCtxPtr),
+        }
+    }
+
+    // The pinned register holds the base of memory 0 on entry and across calls, which the
+    // checker requires it to hold at each call and return.
+    fn set_pinned_reg<Ar: RegT>(&self, state: &mut HeapLattice<Ar>) {
+        if let Some(pinned) = self.metadata.heap_regs.pinned_heap_base_reg() {
+            state
+                .regs
+                .set_reg(pinned, Size64, HeapValueLattice::new(HeapBase(0)));
+        }
+    }
+
+    // Forgets the bases of, and addresses into, the memories that may have moved, so that
+    // they are reloaded from vmctx after a call.
+    fn forget_movable_heaps<Ar: RegT>(&self, state: &mut HeapLattice<Ar>) {
+        let movable = |value: &HeapValueLattice| match value.v {
+            Some(HeapBase(memory)) | Some(HeapAddr(memory)) => {
+                self.strategy.is_bounds_checked(memory)
+            }
+            _ => false,
+        };
+        state.regs.map.retain(|_, slot| !movable(&slot.value));
+        state.stack.map.retain(|_, slot| !movable(&slot.value));
+    }
+
+    /// The layout of memory `memory`.
+    pub fn heap_layout(&self, memory: u32) -> HeapLayout {
+        match &self.strategy {
+            HeapStrategy::VMCtxFirstArgMultiMemory { memories } => memories
+                .get(memory as usize)
+                .map_or(self.metadata.heap_layout, |m| m.layout),
+            _ => self.metadata.heap_layout,
+        }
+    }
+
+    // When the first argument is the vmctx, the base of each memory is loaded from
+    // [vmctx + base pointer offset]. Returns the index of the memory.
+    fn is_heapbase_load<Ar: RegT>(
+        &self,
+        in_state: &HeapLattice<Ar>,
+        memsize: ValSize,
+        memargs: &MemArgs<Ar>,
+    ) -> Option<u32> {
+        self.strategy
+            .vmctx_offsets()
+            .iter()
+            .position(|(base_offset, _)| is_vmctx_load(in_state, memsize, memargs, *base_offset))
+            .map(|memory| memory as u32)
+    }
+
+    // With explicit bounds checks, the length of each memory is loaded from
+    // [vmctx + bound offset]. Returns the index of the memory.
+    fn is_heapbound_load<Ar: RegT>(
+        &self,
+        in_state: &HeapLattice<Ar>,
+        memsize: ValSize,
+        memargs: &MemArgs<Ar>,
+    ) -> Option<u32> {
+        self.strategy
+            .vmctx_offsets()
+            .iter()
+            .position(|(_, bound_offset)| match bound_offset {
+                Some(bound_offset) => is_vmctx_load(in_state, memsize, memargs, *bound_offset),
+                None => false,
+            })
+            .map(|memory| memory as u32)
+    }
+
+    // Is `value` the length of a memory, in a register or in vmctx? Returns the index of the
+    // memory.
+    fn is_heapbound<Ar: RegT>(&self, in_state: &HeapLattice<Ar>, value: &Value<Ar>) -> Option<u32> {
+        match value {
+            Value::Reg(regnum, Size64) => match in_state.regs.get_reg(*regnum, Size64).v {
+                Some(HeapBound(memory)) => Some(memory),
+                _ => None,
+            },
+            Value::Mem(memsize, memargs) => self.is_heapbound_load(in_state, *memsize, memargs),
+            _ => None,
+        }
+    }
+
+    // If the block at `addr` ends with a comparison of a 64-bit register against the length of
+    // a memory, followed by a conditional branch, the register and what it is known to be on
+    // the fall-through edge and on the branch edge.
+    fn bounds_check<Ar: RegT>(
+        &self,
+        irmap: &IRMap<Ar>,
+        in_state: &HeapLattice<Ar>,
+        addr: &u64,
+    ) -> Option<(Ar, Option<HeapValue>, Option<HeapValue>)> {
+        let (src1, src2, br_opcode) = compare_and_branch(irmap, addr)?;
+        // `cmp idx, bound` or `cmp bound, idx`
+        let bounds = (
+            self.is_heapbound(in_state, src1),
+            self.is_heapbound(in_state, src2),
+        );
+        let (regnum, memory, idx_first) = match (src1, src2, bounds) {
+            (Value::Reg(regnum, Size64), _, (_, Some(memory))) => (*regnum, memory, true),
+            (_, Value::Reg(regnum, Size64), (Some(memory), _)) => (*regnum, memory, false),
+            _ => return None,
+        };
+        // How many bytes past the index are within the memory on each edge: 1 if idx < bound,
+        // 0 if idx <= bound
+        let (not_branch_check, branch_check) = below_bound_edges(br_opcode, idx_first)?;
+        let checked = |bytes: Option<u32>| bytes.map(|bytes| CheckedIndex(memory, bytes));
+        Some((regnum, checked(not_branch_check), checked(branch_check)))
+    }
+
+    pub fn aeval_unop<Ar: RegT>(
+        &self,
+        in_state: &HeapLattice<Ar>,
+        value: &Value<Ar>,
+    ) -> HeapValueLattice {
         match value {
             Value::Mem(memsize, memargs) => {
-                if is_globalbase_access(in_state, memargs) {
+                if let Some(memory) = self.is_heapbase_load(in_state, *memsize, memargs) {
+                    return HeapValueLattice::new(HeapBase(memory));
+                }
+                if let Some(memory) = self.is_heapbound_load(in_state, *memsize, memargs) {
+                    return HeapValueLattice::new(HeapBound(memory));
+                }
+                if self.strategy == HeapStrategy::HeapPtrFirstArgWithGuards
+                    && is_globalbase_access(in_state, memargs)
+                {
                     return HeapValueLattice::new(GlobalsBase);
                 }
                 if value.is_stack_access() {
@@ -172,11 +423,13 @@ impl HeapAnalyzer {
                 }
             }
 
-            Value::Imm(_, _, immval) => {
+            Value::Imm(imm_type, _, immval) => {
                 if (*immval as u64) == self.metadata.guest_table_0 {
                     return HeapValueLattice::new(GuestTable0);
                 } else if (*immval as u64) == self.metadata.lucet_tables {
                     return HeapValueLattice::new(LucetTables);
+                } else if let ImmType::RIPRelative = imm_type {
+                    return HeapValueLattice::new(RIPConst);
                 } else if (*immval >= 0) && (*immval < (1 << 32)) {
                     return HeapValueLattice::new(Bounded4GB);
                 }
@@ -189,3 +442,68 @@ impl HeapAnalyzer {
         Default::default()
     }
 }
+
+// If the block at `addr` ends with a comparison followed by a conditional branch, the operands
+// of the comparison and the branch opcode.
+fn compare_and_branch<'a, Ar: RegT>(
+    irmap: &'a IRMap<Ar>,
+    addr: &u64,
+) -> Option<(&'a Value<Ar>, &'a Value<Ar>, &'a JumpOpcode)> {
+    let block = irmap.get(addr)?;
+    let br_opcode = match block.last()?.1.last()? {
+        Stmt::Branch(op, _) => op,
+        _ => return None,
+    };
+    // The comparison must be the instruction just before the branch, so that the state at the
+    // end of the block still holds its operands.
+    let (_, cmp_stmts) = block.iter().rev().nth(1)?;
+    let (src1, src2) = cmp_stmts.iter().find_map(|stmt| match stmt {
+        Stmt::Binop(Binopcode::Cmp, dst, src1, src2) if dst.is_zf() => Some((src1, src2)),
+        _ => None,
+    })?;
+    Some((src1, src2, br_opcode))
+}
+
+// For an unsigned comparison `cmp idx, bound` (or `cmp bound, idx` if not `idx_first`) followed
+// by the branch `br_opcode`, whether the index is below the bound on the fall-through edge and
+// on the branch edge, as Some(1) if idx < bound and Some(0) if idx <= bound.
+fn below_bound_edges(
+    br_opcode: &JumpOpcode,
+    idx_first: bool,
+) -> Option<(Option<u32>, Option<u32>)> {
+    match (br_opcode, idx_first) {
+        (JumpOpcode::JNB, true) | (JumpOpcode::JNA, false) => Some((Some(1), None)),
+        (JumpOpcode::JA, true) | (JumpOpcode::JB, false) => Some((Some(0), None)),
+        (JumpOpcode::JB, true) | (JumpOpcode::JA, false) => Some((None, Some(1))),
+        (JumpOpcode::JNA, true) | (JumpOpcode::JNB, false) => Some((None, Some(0))),
+        _ => None,
+    }
+}
+
+// If the block at `addr` ends with a comparison of a register against an entry count `n`,
+// followed by a conditional branch, as before a `br_table`, the register and what it is known
+// to be on the fall-through edge and on the branch edge. A 32-bit register is only bounded if
+// its upper half is known to be zero.
+fn table_check<Ar: RegT>(
+    irmap: &IRMap<Ar>,
+    in_state: &HeapLattice<Ar>,
+    addr: &u64,
+) -> Option<(Ar, Option<HeapValue>, Option<HeapValue>)> {
+    let (src1, src2, br_opcode) = compare_and_branch(irmap, addr)?;
+    let (regnum, n) = match (src1, src2) {
+        (Value::Reg(regnum, Size64), Value::Imm(_, _, n)) => (*regnum, *n),
+        (Value::Reg(regnum, Size32), Value::Imm(_, _, n))
+            if in_state.regs.get_reg(*regnum, Size64).v == Some(Bounded4GB) =>
+        {
+            (*regnum, *n)
+        }
+        _ => return None,
+    };
+    if n < 0 {
+        return None;
+    }
+    let (not_branch_check, branch_check) = below_bound_edges(br_opcode, true)?;
+    // idx < n + 1 - below
+    let entries = |below: Option<u32>| below.map(|below| TableIndex(n as u64 + 1 - below as u64));
+    Some((regnum, entries(not_branch_check), entries(branch_check)))
+}
@@ -89,7 +89,10 @@ impl AbstractAnalyzer<SwitchLattice> for SwitchAnalyzer {
                 );
                 let defs_state = self.reaching_defs.get(addr).unwrap();
                 let ir_block = irmap.get(addr).unwrap();
-                let defs_state = self.reaching_analyzer.analyze_block(defs_state, ir_block);
+                let defs_state = self
+                    .reaching_analyzer
+                    .analyze_block(defs_state, ir_block)
+                    .unwrap_or_default();
                 //propagate bound across registers with the same reaching def
                 for idx in X86Regs::iter() {
                     if idx != *regnum {
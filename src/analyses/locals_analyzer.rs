@@ -2,7 +2,9 @@ use crate::{analyses, ir, lattices, loaders};
 use std::collections::HashMap;
 use std::collections::HashSet;
 
-use analyses::{AbstractAnalyzer, AnalysisResult, CallAnalyzer};
+use analyses::{
+    adjust_stack_offset, AbstractAnalyzer, AnalysisError, AnalysisResult, CallAnalyzer,
+};
 use ir::types::*;
 use lattices::calllattice::CallCheckLattice;
 use lattices::localslattice::*;
@@ -83,10 +85,25 @@ impl<'a> AbstractAnalyzer<LocalsLattice> for LocalsAnalyzer<'a> {
         lattice.regs.set_reg(R13, Size64, UninitCalleeReg(R13));
         lattice.regs.set_reg(R14, Size64, UninitCalleeReg(R14));
         lattice.regs.set_reg(R15, Size64, UninitCalleeReg(R15));
+        // The heap base or vmctx is a hidden argument, and a register pinned to the heap base
+        // is live throughout
+        let heap_regs = self.call_analyzer.metadata.heap_regs;
+        lattice
+            .regs
+            .set_reg(heap_regs.heap_base_reg(), Size64, Init);
+        lattice.regs.set_reg(heap_regs.vmctx_reg(), Size64, Init);
+        if let Some(pinned) = heap_regs.pinned_heap_base_reg() {
+            lattice.regs.set_reg(pinned, Size64, Init);
+        }
         lattice
     }
 
-    fn aexec(&self, in_state: &mut LocalsLattice, ir_instr: &Stmt, loc_idx: &LocIdx) -> () {
+    fn aexec(
+        &self,
+        in_state: &mut LocalsLattice,
+        ir_instr: &Stmt,
+        loc_idx: &LocIdx,
+    ) -> Result<(), AnalysisError> {
         let debug_addrs: HashSet<u64> = vec![].into_iter().collect();
         if debug_addrs.contains(&loc_idx.addr) {
             println!("========================================");
@@ -102,7 +119,7 @@ impl<'a> AbstractAnalyzer<LocalsLattice> for LocalsAnalyzer<'a> {
                     .meet(&self.aeval_val(in_state, src2, loc_idx), loc_idx);
                 in_state.set(dst, dst_val);
                 let prev_offset = in_state.stack.offset;
-                in_state.adjust_stack_offset(opcode, dst, src1, src2);
+                adjust_stack_offset(in_state, opcode, dst, src1, src2, loc_idx)?;
                 // if prev_offset != in_state.stack.offset {
                 //     println!("adjusting offset 0x{:x?}: {:?}\n\t{:?} -> {:?}",
                 //              loc_idx.addr,
@@ -145,12 +162,14 @@ impl<'a> AbstractAnalyzer<LocalsLattice> for LocalsAnalyzer<'a> {
                 // println!("unhandled branch 0x{:x?}: {:?} {:?}", loc_idx.addr, br_type, val);
             }
             Stmt::ProbeStack(x) => {
-                in_state.adjust_stack_offset(
+                adjust_stack_offset(
+                    in_state,
                     &Binopcode::Sub,
                     &Value::Reg(Rsp, Size64),
                     &Value::Reg(Rsp, Size64),
                     &mk_value_i64(*x as i64),
-                );
+                    loc_idx,
+                )?;
             }
             stmt => {
                 // println!("unhandled instruction 0x{:x?}: {:?}", loc_idx.addr, stmt);
@@ -160,6 +179,7 @@ impl<'a> AbstractAnalyzer<LocalsLattice> for LocalsAnalyzer<'a> {
             println!("{}", in_state);
             println!("========================================");
         }
+        Ok(())
     }
 
     fn process_branch(
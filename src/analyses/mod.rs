@@ -1,30 +1,90 @@
 mod call_analyzer;
+mod counterexample;
 mod heap_analyzer;
 mod jump_analyzer;
 pub mod locals_analyzer;
+mod provenance;
 pub mod reaching_defs;
 mod stack_analyzer;
+mod summaries;
+use crate::checkers::Operand;
 use crate::ir::types::*;
 use crate::lattices::reachingdefslattice::LocIdx;
 use crate::lattices::{Lattice, VarState};
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use yaxpeax_core::analyses::control_flow::VW_CFG;
 
 /*     Public API     */
 pub use self::call_analyzer::CallAnalyzer;
+pub use self::counterexample::{
+    counterexample_path, state_before, CounterexamplePath, MeetPoint, ValueDrop,
+};
 pub use self::heap_analyzer::HeapAnalyzer;
 pub use self::jump_analyzer::SwitchAnalyzer;
+pub use self::provenance::{provenance, BotCause, BotProvenance, BotRecord, Location, Provenance};
 pub use self::stack_analyzer::StackAnalyzer;
+pub use self::summaries::{
+    call_graph, callee_summary, compute_summaries, preserved_at, summary_dependents, CallGraph,
+    FunctionSummary, Summaries,
+};
 
 pub type AnalysisResult<T> = HashMap<u64, T>;
 
-pub trait AbstractAnalyzer<State: Lattice + VarState + Clone> {
+/// Why an analysis could not run to a fixpoint.
+#[derive(Clone, Debug)]
+pub enum AnalysisError {
+    /// The statement at `loc_idx` moves the stack pointer by an unknown or misaligned
+    /// amount, or the blocks that join at `loc_idx` disagree on the stack pointer.
+    StackUpdate {
+        loc_idx: LocIdx,
+        value: Option<Operand>,
+        reason: String,
+    },
+}
+
+impl AnalysisError {
+    pub fn loc_idx(&self) -> LocIdx {
+        match self {
+            AnalysisError::StackUpdate { loc_idx, .. } => *loc_idx,
+        }
+    }
+
+    pub fn reason(&self) -> &str {
+        match self {
+            AnalysisError::StackUpdate { reason, .. } => reason,
+        }
+    }
+
+    // The error for a stack pointer update through `value` that the state rejected.
+    fn stack_update<Ar: RegT>(loc_idx: &LocIdx, value: &Value<Ar>, reason: String) -> Self {
+        AnalysisError::StackUpdate {
+            loc_idx: *loc_idx,
+            value: Some(Operand::from(value)),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnalysisError::StackUpdate {
+                loc_idx, reason, ..
+            } => {
+                write!(f, "0x{:x}: {}", loc_idx.addr, reason)
+            }
+        }
+    }
+}
+
+pub trait AbstractAnalyzer<State: Lattice + VarState<Ar> + Clone, Ar: RegT = X86Regs> {
     fn init_state(&self) -> State {
         Default::default()
     }
     fn process_branch(
         &self,
-        _irmap: &IRMap,
+        _irmap: &IRMap<Ar>,
         in_state: &State,
         succ_addrs: &Vec<u64>,
         _addr: &u64,
@@ -38,8 +98,8 @@ pub trait AbstractAnalyzer<State: Lattice + VarState + Clone> {
         &self,
         in_state: &mut State,
         _opcode: &Unopcode,
-        dst: &Value,
-        _src: &Value,
+        dst: &Value<Ar>,
+        _src: &Value<Ar>,
         _loc_idx: &LocIdx,
     ) -> () {
         in_state.set_to_bot(dst)
@@ -48,9 +108,9 @@ pub trait AbstractAnalyzer<State: Lattice + VarState + Clone> {
         &self,
         in_state: &mut State,
         opcode: &Binopcode,
-        dst: &Value,
-        _src1: &Value,
-        _src2: &Value,
+        dst: &Value<Ar>,
+        _src1: &Value<Ar>,
+        _src2: &Value<Ar>,
         _loc_idx: &LocIdx,
     ) -> () {
         match opcode {
@@ -60,20 +120,35 @@ pub trait AbstractAnalyzer<State: Lattice + VarState + Clone> {
         }
     }
 
-    fn aexec(&self, in_state: &mut State, ir_instr: &Stmt, loc_idx: &LocIdx) -> () {
+    fn aexec(
+        &self,
+        in_state: &mut State,
+        ir_instr: &Stmt<Ar>,
+        loc_idx: &LocIdx,
+    ) -> Result<(), AnalysisError> {
         match ir_instr {
             Stmt::Clear(dst, _srcs) => in_state.set_to_bot(dst),
             Stmt::Unop(opcode, dst, src) => self.aexec_unop(in_state, opcode, &dst, &src, loc_idx),
             Stmt::Binop(opcode, dst, src1, src2) => {
                 self.aexec_binop(in_state, opcode, dst, src1, src2, loc_idx);
-                in_state.adjust_stack_offset(opcode, dst, src1, src2)
+                adjust_stack_offset(in_state, opcode, dst, src1, src2, loc_idx)?
             }
             Stmt::Call(_) => in_state.on_call(),
             _ => (),
         }
+        Ok(())
     }
 
-    fn analyze_block(&self, state: &State, irblock: &IRBlock) -> State {
+    /// Executes `ir_instr` on a state the fixpoint has already reached, for the checkers and
+    /// the reports. The worklist has executed every statement on such a state without
+    /// error, so if this fails anyway the state after `ir_instr` is unknown.
+    fn replay(&self, in_state: &mut State, ir_instr: &Stmt<Ar>, loc_idx: &LocIdx) {
+        if self.aexec(in_state, ir_instr, loc_idx).is_err() {
+            *in_state = Default::default();
+        }
+    }
+
+    fn analyze_block(&self, state: &State, irblock: &IRBlock<Ar>) -> Result<State, AnalysisError> {
         let mut new_state = state.clone();
         for (addr, instruction) in irblock.iter() {
             for (idx, ir_insn) in instruction.iter().enumerate() {
@@ -90,13 +165,31 @@ pub trait AbstractAnalyzer<State: Lattice + VarState + Clone> {
                         addr: *addr,
                         idx: idx as u32,
                     },
-                );
+                )?;
             }
         }
-        new_state
+        Ok(new_state)
     }
 }
 
+/// Moves the stack of `in_state` along with an update of the stack pointer by `ir_instr` at
+/// `loc_idx`, if it is one.
+pub fn adjust_stack_offset<State: VarState<Ar>, Ar: RegT>(
+    in_state: &mut State,
+    opcode: &Binopcode,
+    dst: &Value<Ar>,
+    src1: &Value<Ar>,
+    src2: &Value<Ar>,
+    loc_idx: &LocIdx,
+) -> Result<(), AnalysisError> {
+    in_state
+        .adjust_stack_offset(opcode, dst, src1, src2)
+        .map_err(|reason| {
+            let value = if src1.is_rsp() { src2 } else { src1 };
+            AnalysisError::stack_update(loc_idx, value, reason)
+        })
+}
+
 fn align_succ_addrs(addr: u64, succ_addrs: Vec<u64>) -> Vec<u64> {
     if succ_addrs.len() != 2 {
         return succ_addrs;
@@ -118,11 +211,42 @@ fn align_succ_addrs(addr: u64, succ_addrs: Vec<u64>) -> Vec<u64> {
     panic!("Unreachable");
 }
 
-pub fn run_worklist<T: AbstractAnalyzer<State>, State: VarState + Lattice + Clone>(
+// The state at the end of `pred` and the state it passes to `succ`, given the state `in_state`
+// at the start of `pred`.
+fn edge_state<T: AbstractAnalyzer<State, Ar>, State: VarState<Ar> + Lattice + Clone, Ar: RegT>(
     cfg: &VW_CFG,
-    irmap: &IRMap,
+    irmap: &IRMap<Ar>,
     analyzer: &T,
-) -> AnalysisResult<State> {
+    in_state: &State,
+    pred: u64,
+    succ: u64,
+) -> (State, Option<State>) {
+    // `in_state` is a fixpoint state, so the block has been analyzed from it before
+    let out_state = analyzer
+        .analyze_block(in_state, &irmap[&pred])
+        .unwrap_or_default();
+    let succ_addrs = align_succ_addrs(pred, cfg.graph.neighbors(pred).collect());
+    let branch_state = analyzer
+        .process_branch(irmap, &out_state, &succ_addrs, &pred)
+        .into_iter()
+        .filter(|(addr, _)| *addr == succ)
+        .map(|(_, state)| state)
+        .fold(None, |merged: Option<State>, state| match merged {
+            Some(merged) => Some(merged.meet(&state, &LocIdx { addr: pred, idx: 0 })),
+            None => Some(state),
+        });
+    (out_state, branch_state)
+}
+
+pub fn run_worklist<
+    T: AbstractAnalyzer<State, Ar>,
+    State: VarState<Ar> + Lattice + Clone,
+    Ar: RegT,
+>(
+    cfg: &VW_CFG,
+    irmap: &IRMap<Ar>,
+    analyzer: &T,
+) -> Result<AnalysisResult<State>, AnalysisError> {
     let mut statemap: HashMap<u64, State> = HashMap::new();
     let mut worklist: VecDeque<u64> = VecDeque::new();
     worklist.push_back(cfg.entrypoint);
@@ -132,7 +256,7 @@ pub fn run_worklist<T: AbstractAnalyzer<State>, State: VarState + Lattice + Clon
         let addr = worklist.pop_front().unwrap();
         let irblock = irmap.get(&addr).unwrap();
         let state = statemap.get(&addr).unwrap();
-        let new_state = analyzer.analyze_block(state, irblock);
+        let new_state = analyzer.analyze_block(state, irblock)?;
         let succ_addrs_unaligned: Vec<u64> = cfg.graph.neighbors(addr).collect();
         let succ_addrs: Vec<u64> = align_succ_addrs(addr, succ_addrs_unaligned);
         log::debug!("Processing Block: 0x{:x} -> {:?}", addr, succ_addrs);
@@ -141,6 +265,19 @@ pub fn run_worklist<T: AbstractAnalyzer<State>, State: VarState + Lattice + Clon
         {
             let has_change = if statemap.contains_key(&succ_addr) {
                 let old_state = statemap.get(&succ_addr).unwrap();
+                if old_state.stack_offset() != branch_state.stack_offset() {
+                    return Err(AnalysisError::StackUpdate {
+                        loc_idx: LocIdx {
+                            addr: succ_addr,
+                            idx: 0,
+                        },
+                        value: None,
+                        reason: format!(
+                            "the stack pointer differs between the predecessors of 0x{:x}",
+                            succ_addr
+                        ),
+                    });
+                }
                 let merged_state = old_state.meet(&branch_state, &LocIdx { addr: addr, idx: 0 });
 
                 if merged_state > *old_state {
@@ -166,5 +303,5 @@ pub fn run_worklist<T: AbstractAnalyzer<State>, State: VarState + Lattice + Clon
             }
         }
     }
-    statemap
+    Ok(statemap)
 }
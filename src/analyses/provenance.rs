@@ -0,0 +1,451 @@
+use super::{edge_state, AbstractAnalyzer, AnalysisResult};
+use crate::ir::types::*;
+use crate::lattices::reachingdefslattice::LocIdx;
+use crate::lattices::{mem_to_stack_offset, Explain, Lattice, VariableState};
+use petgraph::Direction;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use yaxpeax_core::analyses::control_flow::VW_CFG;
+
+/// A register or a stack slot. Stack slots are offsets from the stack pointer at function
+/// entry, like the keys of `StackLattice`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Location<Ar: RegT = X86Regs> {
+    Reg(Ar),
+    Stack(i64),
+}
+
+impl<Ar: RegT> fmt::Display for Location<Ar> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Reg(reg) => write!(f, "{}", format!("{:?}", reg).to_lowercase()),
+            Location::Stack(offset) => write!(f, "stack[{:+}]", offset),
+        }
+    }
+}
+
+/// Why a location is bottom.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BotCause {
+    /// The statement wrote a value the analysis knows nothing about.
+    Clobber(LocIdx),
+    /// The statement was lifted from an instruction without precise semantics, which clears
+    /// everything it writes.
+    GenericClear(LocIdx),
+    /// A call cleared a caller-saved register. `target` is the callee of a direct call.
+    Call {
+        loc_idx: LocIdx,
+        target: Option<u64>,
+    },
+    /// The edges into `block` disagreed. `incoming` is the value along each predecessor; the
+    /// initial state of the function is listed as coming from the entry block itself.
+    Meet {
+        block: u64,
+        incoming: Vec<(u64, String)>,
+    },
+}
+
+/// The last value a location had before it became bottom, and why it became bottom.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BotRecord {
+    pub lost: String,
+    pub cause: BotCause,
+}
+
+impl BotRecord {
+    /// A sentence like "rsi lost Bounded4GB at 0x1234 due to call to foo". `names` maps
+    /// function addresses to names.
+    pub fn describe<Ar: RegT>(
+        &self,
+        location: &Location<Ar>,
+        names: &HashMap<u64, String>,
+    ) -> String {
+        let lost = format!("{} lost {}", location, self.lost);
+        match &self.cause {
+            BotCause::Clobber(loc_idx) => format!("{} at 0x{:x}", lost, loc_idx.addr),
+            BotCause::GenericClear(loc_idx) => format!(
+                "{} at 0x{:x} due to an instruction lifted without precise semantics",
+                lost, loc_idx.addr
+            ),
+            BotCause::Call { loc_idx, target } => {
+                let callee = match target {
+                    Some(target) => names
+                        .get(target)
+                        .cloned()
+                        .unwrap_or_else(|| format!("0x{:x}", target)),
+                    None => "an indirect target".to_string(),
+                };
+                format!("{} at 0x{:x} due to call to {}", lost, loc_idx.addr, callee)
+            }
+            BotCause::Meet { block, incoming } => {
+                let incoming: Vec<String> = incoming
+                    .iter()
+                    .map(|(pred, value)| format!("0x{:x}: {}", pred, value))
+                    .collect();
+                format!(
+                    "{} at the join at 0x{:x} ({})",
+                    lost,
+                    block,
+                    incoming.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// The causes of the bottom locations at one point. Locations that are bottom without a
+/// record were never known since the function entry.
+pub type BotProvenance<Ar = X86Regs> = HashMap<Location<Ar>, BotRecord>;
+
+/// The provenance of bottom values before every statement of a function.
+pub struct Provenance<Ar: RegT = X86Regs> {
+    // The stack offset and provenance before each statement
+    before: BTreeMap<LocIdx, (i64, BotProvenance<Ar>)>,
+}
+
+impl<Ar: RegT> Provenance<Ar> {
+    pub fn before(&self, loc_idx: &LocIdx) -> Option<&BotProvenance<Ar>> {
+        self.before.get(loc_idx).map(|(_, records)| records)
+    }
+
+    /// The records of the locations that `stmt`, at `loc_idx`, reads or writes through:
+    /// registers, memory operand registers, and stack slots.
+    pub fn of_stmt(&self, loc_idx: &LocIdx, stmt: &Stmt<Ar>) -> Vec<(Location<Ar>, &BotRecord)> {
+        let (stack_offset, records) = match self.before.get(loc_idx) {
+            Some(before) => before,
+            None => return vec![],
+        };
+        let values: Vec<&Value<Ar>> = match stmt {
+            Stmt::Unop(_, dst, src) => vec![dst, src],
+            Stmt::Binop(_, dst, src1, src2) => vec![dst, src1, src2],
+            Stmt::Clear(dst, srcs) => std::iter::once(dst).chain(srcs.iter()).collect(),
+            Stmt::Branch(_, v) | Stmt::Call(v) => vec![v],
+            _ => vec![],
+        };
+        let mut locations = Vec::new();
+        for value in values {
+            match value {
+                Value::Reg(reg, _) => locations.push(Location::Reg(*reg)),
+                Value::Mem(_, memargs) => match mem_to_stack_offset(memargs) {
+                    Some(offset) => locations.push(Location::Stack(stack_offset + offset)),
+                    None => locations.extend(memarg_regs(memargs).into_iter().map(Location::Reg)),
+                },
+                _ => (),
+            }
+        }
+        let mut seen = HashSet::new();
+        locations
+            .into_iter()
+            .filter(|location| seen.insert(*location))
+            .filter_map(|location| records.get(&location).map(|record| (location, record)))
+            .collect()
+    }
+}
+
+fn memarg_regs<Ar: RegT>(memargs: &MemArgs<Ar>) -> Vec<Ar> {
+    let memargs = match memargs {
+        MemArgs::Mem1Arg(x) => vec![x],
+        MemArgs::Mem2Args(x, y) => vec![x, y],
+        MemArgs::Mem3Args(x, y, z) | MemArgs::MemScale(x, y, z) => vec![x, y, z],
+        MemArgs::MemScaleDisp(x, y, z, w) => vec![x, y, z, w],
+    };
+    memargs
+        .into_iter()
+        .filter_map(|memarg| match memarg {
+            MemArg::Reg(reg, _) => Some(*reg),
+            MemArg::Imm(..) => None,
+        })
+        .collect()
+}
+
+// Every location that is not bottom in `state`.
+fn known<T: Lattice + Clone, Ar: RegT>(state: &VariableState<T, Ar>) -> HashMap<Location<Ar>, T> {
+    let regs = state
+        .regs
+        .map
+        .iter()
+        .map(|(reg, slot)| (Location::Reg(*reg), slot));
+    let slots = state
+        .stack
+        .map
+        .iter()
+        .map(|(offset, slot)| (Location::Stack(*offset), slot));
+    regs.chain(slots)
+        .filter(|(_, slot)| slot.value != Default::default())
+        .map(|(location, slot)| (location, slot.value.clone()))
+        .collect()
+}
+
+fn cause<Ar: RegT>(stmt: &Stmt<Ar>, loc_idx: &LocIdx, generic_cleared: &HashSet<u64>) -> BotCause {
+    match stmt {
+        // x64 direct calls are relative to the end of the 5 byte instruction
+        Stmt::Call(Value::Imm(_, _, offset)) => BotCause::Call {
+            loc_idx: *loc_idx,
+            target: Some((*offset + loc_idx.addr as i64 + 5) as u64),
+        },
+        Stmt::Call(_) => BotCause::Call {
+            loc_idx: *loc_idx,
+            target: None,
+        },
+        Stmt::Clear(..) if generic_cleared.contains(&loc_idx.addr) => {
+            BotCause::GenericClear(*loc_idx)
+        }
+        _ => BotCause::Clobber(*loc_idx),
+    }
+}
+
+// Replays `irblock` from `state`, updating `records` as locations become bottom or known again.
+// `visit` sees the stack offset and records before each statement.
+fn replay<A, T, Ar>(
+    analyzer: &A,
+    state: &VariableState<T, Ar>,
+    irblock: &IRBlock<Ar>,
+    mut records: BotProvenance<Ar>,
+    generic_cleared: &HashSet<u64>,
+    mut visit: impl FnMut(LocIdx, i64, &BotProvenance<Ar>),
+) -> BotProvenance<Ar>
+where
+    A: AbstractAnalyzer<VariableState<T, Ar>, Ar>,
+    T: Lattice + Clone + Explain,
+    Ar: RegT,
+{
+    let mut state = state.clone();
+    for (addr, stmts) in irblock {
+        for (idx, stmt) in stmts.iter().enumerate() {
+            let loc_idx = LocIdx {
+                addr: *addr,
+                idx: idx as u32,
+            };
+            visit(loc_idx, state.stack.offset, &records);
+            let before = known(&state);
+            analyzer.replay(&mut state, stmt, &loc_idx);
+            let after = known(&state);
+            // A location that was already bottom keeps its original cause
+            for (location, value) in before {
+                if !after.contains_key(&location) {
+                    records.insert(
+                        location,
+                        BotRecord {
+                            lost: value.explain(),
+                            cause: cause(stmt, &loc_idx, generic_cleared),
+                        },
+                    );
+                }
+            }
+            for location in after.keys() {
+                records.remove(location);
+            }
+        }
+    }
+    records
+}
+
+/// Records why every bottom location of `result` is bottom: the statement that clobbered it,
+/// the call that cleared it, the lifting of an instruction in `generic_cleared` (addresses of
+/// instructions lifted with `generic_clear`), or the join where the values along the incoming
+/// edges disagreed. This replays the analysis, so it is only run on demand.
+pub fn provenance<A, T, Ar>(
+    cfg: &VW_CFG,
+    irmap: &IRMap<Ar>,
+    analyzer: &A,
+    result: &AnalysisResult<VariableState<T, Ar>>,
+    generic_cleared: &HashSet<u64>,
+) -> Provenance<Ar>
+where
+    A: AbstractAnalyzer<VariableState<T, Ar>, Ar>,
+    T: Lattice + Clone + Explain,
+    Ar: RegT,
+{
+    let exit_records = |block: u64, records: &BotProvenance<Ar>| {
+        replay(
+            analyzer,
+            &result[&block],
+            &irmap[&block],
+            records.clone(),
+            generic_cleared,
+            |_, _, _| (),
+        )
+    };
+
+    // Records at the start of each block. A location keeps the first record it is given, so
+    // records are only ever added and this terminates.
+    let mut entry_records: HashMap<u64, BotProvenance<Ar>> = HashMap::new();
+    let mut blocks: Vec<u64> = result.keys().copied().collect();
+    blocks.sort();
+    let mut worklist: VecDeque<u64> = blocks.into_iter().collect();
+    while let Some(block) = worklist.pop_front() {
+        let known_here = known(&result[&block]);
+        let mut preds: Vec<u64> = cfg
+            .graph
+            .neighbors_directed(block, Direction::Incoming)
+            .filter(|pred| result.contains_key(pred))
+            .collect();
+        preds.sort();
+        // (predecessor, what it knows on its edge into `block`, its records at the end)
+        let mut incoming: Vec<(u64, HashMap<Location<Ar>, T>, BotProvenance<Ar>)> = Vec::new();
+        if block == cfg.entrypoint {
+            incoming.push((block, known(&analyzer.init_state()), HashMap::new()));
+        }
+        for pred in preds {
+            let (out_state, branch_state) =
+                edge_state(cfg, irmap, analyzer, &result[&pred], pred, block);
+            let pred_records = entry_records.get(&pred).cloned().unwrap_or_default();
+            incoming.push((
+                pred,
+                known(&branch_state.unwrap_or(out_state)),
+                exit_records(pred, &pred_records),
+            ));
+        }
+
+        let mut candidates: Vec<Location<Ar>> = incoming
+            .iter()
+            .flat_map(|(_, edge_known, records)| edge_known.keys().chain(records.keys()))
+            .filter(|location| !known_here.contains_key(location))
+            .copied()
+            .collect();
+        candidates.sort_by_key(|location| format!("{:?}", location));
+        candidates.dedup();
+
+        let records = entry_records.entry(block).or_default();
+        let mut changed = false;
+        for location in candidates {
+            if records.contains_key(&location) {
+                continue;
+            }
+            let values: Vec<(u64, Option<&T>)> = incoming
+                .iter()
+                .map(|(pred, edge_known, _)| (*pred, edge_known.get(&location)))
+                .collect();
+            let record = match values.iter().find_map(|(_, value)| *value) {
+                Some(lost) => Some(BotRecord {
+                    lost: lost.explain(),
+                    cause: BotCause::Meet {
+                        block,
+                        incoming: values
+                            .iter()
+                            .map(|(pred, value)| {
+                                (
+                                    *pred,
+                                    value.map_or("⊥".to_string(), |value| value.explain()),
+                                )
+                            })
+                            .collect(),
+                    },
+                }),
+                None => incoming
+                    .iter()
+                    .find_map(|(_, _, records)| records.get(&location).cloned()),
+            };
+            if let Some(record) = record {
+                records.insert(location, record);
+                changed = true;
+            }
+        }
+        if changed {
+            for succ in cfg.graph.neighbors(block) {
+                if result.contains_key(&succ) && !worklist.contains(&succ) {
+                    worklist.push_back(succ);
+                }
+            }
+        }
+    }
+
+    let mut before = BTreeMap::new();
+    for (block, records) in entry_records {
+        replay(
+            analyzer,
+            &result[&block],
+            &irmap[&block],
+            records,
+            generic_cleared,
+            |loc_idx, stack_offset, records| {
+                before.insert(loc_idx, (stack_offset, records.clone()));
+            },
+        );
+    }
+    Provenance { before }
+}
+
+#[test]
+fn provenance_test() {
+    use super::counterexample::diamond_cfg;
+    use crate::analyses::{run_worklist, HeapAnalyzer};
+    use crate::{HeapLayout, HeapRegs, HeapStrategy, VwMetadata};
+    use ValSize::*;
+    use X86Regs::*;
+
+    let analyzer = HeapAnalyzer {
+        metadata: VwMetadata {
+            guest_table_0: 0,
+            lucet_tables: 0,
+            lucet_probestack: 0,
+            heap_layout: HeapLayout::default(),
+            heap_regs: HeapRegs::default(),
+        },
+        strategy: HeapStrategy::HeapPtrFirstArgWithGuards,
+        summaries: Default::default(),
+    };
+    let copy_heap_base = Stmt::Unop(
+        Unopcode::Mov,
+        Value::Reg(Rax, Size64),
+        Value::Reg(Rdi, Size64),
+    );
+    let load = Stmt::Unop(
+        Unopcode::Mov,
+        Value::Reg(Rbx, Size64),
+        Value::Mem(Size64, MemArgs::Mem1Arg(MemArg::Reg(Rax, Size64))),
+    );
+    // The left path calls 0x1000, which clobbers rax; the right path sets rax to a bounded
+    // index, and rsi is cleared by an instruction the lifter does not model.
+    let (cfg, irmap) = diamond_cfg(
+        vec![load.clone()],
+        vec![
+            copy_heap_base.clone(),
+            Stmt::Call(Value::Imm(ImmType::Signed, Size32, 0x1000 - 0x10 - 5)),
+            Stmt::Unop(
+                Unopcode::Mov,
+                Value::Reg(Rbx, Size64),
+                Value::Reg(Rcx, Size64),
+            ),
+        ],
+        vec![
+            Stmt::Clear(Value::Reg(Rax, Size32), vec![]),
+            Stmt::Clear(Value::Reg(Rsi, Size32), vec![]),
+            Stmt::Clear(Value::Reg(Rsi, Size64), vec![]),
+        ],
+    );
+    let result = run_worklist(&cfg, &irmap, &analyzer).unwrap();
+    let generic_cleared: HashSet<u64> = vec![0x20].into_iter().collect();
+    let provenance = provenance(&cfg, &irmap, &analyzer, &result, &generic_cleared);
+
+    let names: HashMap<u64, String> = vec![(0x1000, "foo".to_string())].into_iter().collect();
+    let join = LocIdx { addr: 0x30, idx: 0 };
+    let records = provenance.before(&join).unwrap();
+    assert_eq!(
+        records[&Location::Reg(Rax)].cause,
+        BotCause::Meet {
+            block: 0x30,
+            incoming: vec![(0x10, "⊥".to_string()), (0x20, "Bounded4GB".to_string())],
+        }
+    );
+    // rdi is caller-saved, so the call clears it, and the right path keeps the heap base
+    assert_eq!(
+        records[&Location::Reg(Rdi)].describe(&Location::Reg(Rdi), &names),
+        "rdi lost HeapBase(0) at the join at 0x30 (0x10: ⊥, 0x20: HeapBase(0))"
+    );
+    assert_eq!(
+        provenance.before(&LocIdx { addr: 0x10, idx: 2 }).unwrap()[&Location::Reg(Rax)]
+            .describe(&Location::Reg(Rax), &names),
+        "rax lost HeapBase(0) at 0x10 due to call to foo"
+    );
+
+    // Nothing is known about rsi on either path, so its record comes from the right path
+    assert_eq!(
+        records[&Location::Reg(Rsi)].describe(&Location::Reg(Rsi), &names),
+        "rsi lost Bounded4GB at 0x20 due to an instruction lifted without precise semantics"
+    );
+    assert_eq!(
+        provenance.of_stmt(&join, &load),
+        vec![(Location::Reg(Rax), &records[&Location::Reg(Rax)])]
+    );
+}
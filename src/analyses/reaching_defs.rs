@@ -1,5 +1,7 @@
 use crate::{analyses, ir, lattices, loaders};
-use analyses::{run_worklist, AbstractAnalyzer, AnalysisResult};
+use analyses::{
+    adjust_stack_offset, run_worklist, AbstractAnalyzer, AnalysisError, AnalysisResult,
+};
 use ir::types::*;
 use lattices::reachingdefslattice::{loc, singleton, LocIdx, ReachLattice};
 use lattices::VarState;
@@ -14,7 +16,7 @@ pub fn analyze_reaching_defs(
     cfg: &VW_CFG,
     irmap: &IRMap,
     _metadata: VwMetadata,
-) -> AnalysisResult<ReachLattice> {
+) -> Result<AnalysisResult<ReachLattice>, AnalysisError> {
     run_worklist(
         cfg,
         irmap,
@@ -50,7 +52,7 @@ impl ReachingDefnAnalyzer {
                 if &loc_idx.addr == addr && (loc_idx.idx as usize) == idx {
                     return def_state;
                 }
-                self.aexec(
+                self.replay(
                     &mut def_state,
                     ir_insn,
                     &LocIdx {
@@ -93,7 +95,12 @@ impl AbstractAnalyzer<ReachLattice> for ReachingDefnAnalyzer {
         s
     }
 
-    fn aexec(&self, in_state: &mut ReachLattice, ir_instr: &Stmt, loc_idx: &LocIdx) -> () {
+    fn aexec(
+        &self,
+        in_state: &mut ReachLattice,
+        ir_instr: &Stmt,
+        loc_idx: &LocIdx,
+    ) -> Result<(), AnalysisError> {
         match ir_instr {
             Stmt::Clear(dst, _) => in_state.set(dst, singleton(loc_idx.clone())),
             Stmt::Unop(Unopcode::Mov, dst, src) | Stmt::Unop(Unopcode::Movsx, dst, src) => {
@@ -115,7 +122,7 @@ impl AbstractAnalyzer<ReachLattice> for ReachingDefnAnalyzer {
                 //Ignore test
             }
             Stmt::Binop(opcode, dst, src1, src2) => {
-                in_state.adjust_stack_offset(opcode, dst, src1, src2);
+                adjust_stack_offset(in_state, opcode, dst, src1, src2, loc_idx)?;
                 in_state.set(dst, singleton(loc_idx.clone()))
             }
             Stmt::Call(_) => {
@@ -137,5 +144,6 @@ impl AbstractAnalyzer<ReachLattice> for ReachingDefnAnalyzer {
             }
             _ => (),
         }
+        Ok(())
     }
 }
@@ -1,17 +1,23 @@
-use crate::{analyses, ir, lattices};
-use analyses::AbstractAnalyzer;
+use crate::{analyses, checkers, ir, lattices};
+use analyses::{AbstractAnalyzer, AnalysisError};
+use checkers::Operand;
 use ir::types::*;
 use lattices::reachingdefslattice::LocIdx;
 use lattices::stackgrowthlattice::StackGrowthLattice;
 
 pub struct StackAnalyzer {}
 
-impl AbstractAnalyzer<StackGrowthLattice> for StackAnalyzer {
+impl<Ar: RegT> AbstractAnalyzer<StackGrowthLattice, Ar> for StackAnalyzer {
     fn init_state(&self) -> StackGrowthLattice {
         StackGrowthLattice::new((0, 4096, 0))
     }
 
-    fn aexec(&self, in_state: &mut StackGrowthLattice, ir_instr: &Stmt, loc_idx: &LocIdx) -> () {
+    fn aexec(
+        &self,
+        in_state: &mut StackGrowthLattice,
+        ir_instr: &Stmt<Ar>,
+        loc_idx: &LocIdx,
+    ) -> Result<(), AnalysisError> {
         match ir_instr {
             Stmt::Clear(dst, _) => {
                 if dst.is_rsp() {
@@ -47,7 +53,16 @@ impl AbstractAnalyzer<StackGrowthLattice> for StackAnalyzer {
                             loc_idx.addr,
                             ir_instr
                         );
-                        let offset = src2.as_imm_val();
+                        let offset = match src2 {
+                            Value::Imm(_, _, imm) => *imm,
+                            _ => {
+                                return Err(stack_update_error(
+                                    loc_idx,
+                                    src2,
+                                    "the stack pointer is updated by an unknown amount",
+                                ))
+                            }
+                        };
                         if let Some((x, probestack, rbp)) = in_state.v {
                             match opcode {
                                 Binopcode::Add => {
@@ -57,20 +72,30 @@ impl AbstractAnalyzer<StackGrowthLattice> for StackAnalyzer {
                                 }
                                 Binopcode::Sub => {
                                     if (offset - x) > probestack + 4096 {
-                                        panic!("Probestack violation")
+                                        return Err(stack_update_error(
+                                            loc_idx,
+                                            src2,
+                                            "the stack grows past the probed stack and the guard page",
+                                        ));
                                     } else if (offset - x) > probestack {
                                         //if we touch next page after the space
                                         //we've probed, it cannot skip guard page
                                         *in_state = StackGrowthLattice {
                                             v: Some((x - offset, probestack + 4096, rbp)),
                                         };
-                                        return;
+                                        return Ok(());
                                     }
                                     *in_state = StackGrowthLattice {
                                         v: Some((x - offset, probestack, rbp)),
                                     }
                                 }
-                                _ => panic!("Illegal RSP write"),
+                                _ => {
+                                    return Err(stack_update_error(
+                                        loc_idx,
+                                        src2,
+                                        "the stack pointer is updated by an unknown amount",
+                                    ))
+                                }
                             }
                         } else {
                             *in_state = Default::default()
@@ -92,5 +117,18 @@ impl AbstractAnalyzer<StackGrowthLattice> for StackAnalyzer {
             }
             _ => (),
         }
+        Ok(())
+    }
+}
+
+fn stack_update_error<Ar: RegT>(
+    loc_idx: &LocIdx,
+    value: &Value<Ar>,
+    reason: &str,
+) -> AnalysisError {
+    AnalysisError::StackUpdate {
+        loc_idx: *loc_idx,
+        value: Some(Operand::from(value)),
+        reason: reason.to_string(),
     }
 }
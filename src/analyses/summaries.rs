@@ -0,0 +1,460 @@
+use crate::HeapStrategy;
+use crate::{analyses, ir, lattices, loaders};
+use analyses::{
+    adjust_stack_offset, run_worklist, AbstractAnalyzer, AnalysisError, AnalysisResult,
+    HeapAnalyzer,
+};
+use ir::types::*;
+use lattices::heaplattice::{HeapLattice, HeapValue};
+use lattices::reachingdefslattice::LocIdx;
+use lattices::{ConstLattice, Lattice, VarState, VariableState};
+use loaders::types::VwMetadata;
+use petgraph::algo::kosaraju_scc;
+use petgraph::graphmap::DiGraphMap;
+use petgraph::Direction::Incoming;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use yaxpeax_core::analyses::control_flow::VW_CFG;
+
+use HeapValue::*;
+use ValSize::*;
+use X86Regs::*;
+
+// Summaries of a recursive component that still change after this many rounds are used as
+// they are; every round's summaries are sound.
+const MAX_SCC_ROUNDS: usize = 8;
+
+/// What a call to a function of the module leaves in the registers, for the analyses of its
+/// callers. Registers are numbered as in the architecture's register enum, as in `HeapRegs`.
+/// Without a summary, a call leaves every caller-saved register unknown.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FunctionSummary {
+    /// The caller-saved registers the function returns with their values on entry.
+    pub preserved: Vec<u8>,
+    /// The register the function returns its result in and the result's value, if it is the
+    /// same heap base, heap address or `Bounded4GB` at every return. The value assumes the
+    /// caller passes the function its own heap base or vmctx.
+    pub ret: Option<(u8, HeapValue)>,
+}
+
+impl FunctionSummary {
+    pub fn preserved_regs<Ar: RegT>(&self) -> Vec<Ar> {
+        self.preserved
+            .iter()
+            .filter_map(|regnum| Ar::try_from(*regnum).ok())
+            .collect()
+    }
+}
+
+/// Function summaries by function address.
+pub type Summaries = HashMap<u64, FunctionSummary>;
+
+/// The summary of the function that the direct call `call rel32` at `loc_idx` targets.
+pub fn callee_summary<'a, Ar: RegT>(
+    summaries: &'a Summaries,
+    target: &Value<Ar>,
+    loc_idx: &LocIdx,
+) -> Option<&'a FunctionSummary> {
+    match target {
+        Value::Imm(_, _, offset) => summaries.get(&((*offset + (loc_idx.addr as i64) + 5) as u64)),
+        _ => None,
+    }
+}
+
+/// The registers that keep their values across the call to `target` at `loc_idx`, besides the
+/// callee-saved ones.
+pub fn preserved_at<Ar: RegT>(
+    summaries: &Summaries,
+    target: &Value<Ar>,
+    loc_idx: &LocIdx,
+) -> Vec<Ar> {
+    callee_summary(summaries, target, loc_idx)
+        .map_or_else(Vec::new, |summary| summary.preserved_regs())
+}
+
+// System V: the general-purpose registers a callee may clobber
+const CALLER_SAVED: [X86Regs; 9] = [Rax, Rcx, Rdx, Rsi, Rdi, R8, R9, R10, R11];
+
+type PreservedLattice = VariableState<ConstLattice<X86Regs>>;
+
+// Tracks which registers and stack slots hold the value a caller-saved register had on entry.
+struct PreservedAnalyzer {
+    summaries: Arc<Summaries>,
+}
+
+impl AbstractAnalyzer<PreservedLattice> for PreservedAnalyzer {
+    fn init_state(&self) -> PreservedLattice {
+        let mut state = PreservedLattice::default();
+        for reg in CALLER_SAVED.iter() {
+            state.regs.set_reg(*reg, Size64, ConstLattice::new(*reg));
+        }
+        state
+    }
+
+    fn aexec_unop(
+        &self,
+        in_state: &mut PreservedLattice,
+        opcode: &Unopcode,
+        dst: &Value,
+        src: &Value,
+        _loc_idx: &LocIdx,
+    ) -> () {
+        // Only a 64-bit move keeps the whole value
+        let value = match (opcode, dst) {
+            (Unopcode::Mov, Value::Reg(_, Size64)) | (Unopcode::Mov, Value::Mem(Size64, _)) => {
+                in_state.get(src).unwrap_or_default()
+            }
+            _ => Default::default(),
+        };
+        in_state.set(dst, value)
+    }
+
+    fn aexec(
+        &self,
+        in_state: &mut PreservedLattice,
+        ir_instr: &Stmt,
+        loc_idx: &LocIdx,
+    ) -> Result<(), AnalysisError> {
+        match ir_instr {
+            Stmt::Clear(dst, _srcs) => in_state.set_to_bot(dst),
+            Stmt::Unop(opcode, dst, src) => self.aexec_unop(in_state, opcode, dst, src, loc_idx),
+            Stmt::Binop(opcode, dst, src1, src2) => {
+                self.aexec_binop(in_state, opcode, dst, src1, src2, loc_idx);
+                adjust_stack_offset(in_state, opcode, dst, src1, src2, loc_idx)?
+            }
+            Stmt::Call(target) => {
+                in_state.on_call_preserving(&preserved_at(&self.summaries, target, loc_idx))
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+// The states of `result` before each return of the function.
+fn return_states<State, A>(
+    irmap: &IRMap,
+    analyzer: &A,
+    result: &AnalysisResult<State>,
+) -> Vec<State>
+where
+    State: Lattice + VarState<X86Regs> + Clone,
+    A: AbstractAnalyzer<State>,
+{
+    let mut states = Vec::new();
+    for (block_addr, state) in result {
+        let mut state = state.clone();
+        for (addr, stmts) in &irmap[block_addr] {
+            for (idx, stmt) in stmts.iter().enumerate() {
+                if let Stmt::Ret = stmt {
+                    states.push(state.clone());
+                }
+                analyzer.replay(
+                    &mut state,
+                    stmt,
+                    &LocIdx {
+                        addr: *addr,
+                        idx: idx as u32,
+                    },
+                );
+            }
+        }
+    }
+    states
+}
+
+// The summary of a function, given the summaries of the functions it calls. A function whose
+// analyses fail has no summary.
+fn summarize(
+    cfg: &VW_CFG,
+    irmap: &IRMap,
+    metadata: &VwMetadata,
+    strategy: &HeapStrategy,
+    summaries: &Arc<Summaries>,
+) -> Result<FunctionSummary, AnalysisError> {
+    let preserved_analyzer = PreservedAnalyzer {
+        summaries: summaries.clone(),
+    };
+    let preserved_result = run_worklist(cfg, irmap, &preserved_analyzer)?;
+    let preserved_states = return_states(irmap, &preserved_analyzer, &preserved_result);
+    let preserved = CALLER_SAVED
+        .iter()
+        .filter(|reg| {
+            !preserved_states.is_empty()
+                && preserved_states
+                    .iter()
+                    .all(|state| state.regs.get_reg(**reg, Size64).v == Some(**reg))
+        })
+        .map(|reg| u8::from(*reg))
+        .collect();
+
+    let heap_analyzer = HeapAnalyzer {
+        metadata: metadata.clone(),
+        strategy: strategy.clone(),
+        summaries: summaries.clone(),
+    };
+    let heap_result = run_worklist(cfg, irmap, &heap_analyzer)?;
+    let ret = return_states(irmap, &heap_analyzer, &heap_result)
+        .iter()
+        .map(|state: &HeapLattice| state.regs.get_reg(Rax, Size64))
+        .fold(
+            None,
+            |ret: Option<ConstLattice<HeapValue>>, value| match ret {
+                Some(ret) => Some(ret.meet(&value, &LocIdx { addr: 0, idx: 0 })),
+                None => Some(value),
+            },
+        )
+        .and_then(|value| value.v)
+        .filter(|value| matches!(value, HeapBase(_) | HeapAddr(_) | Bounded4GB))
+        .map(|value| (u8::from(Rax), value));
+
+    Ok(FunctionSummary { preserved, ret })
+}
+
+// The addresses of the functions the direct calls of `irmap` target.
+fn direct_callees(irmap: &IRMap) -> Vec<u64> {
+    let mut callees = Vec::new();
+    for (addr, stmts) in irmap.values().flatten() {
+        for stmt in stmts {
+            if let Stmt::Call(Value::Imm(_, _, offset)) = stmt {
+                callees.push((*offset + (*addr as i64) + 5) as u64);
+            }
+        }
+    }
+    callees
+}
+
+/// The direct calls between the functions of a module, from caller to callee.
+pub type CallGraph = DiGraphMap<u64, ()>;
+
+/// The call graph of the lifted x64 functions `funcs`. Calls to functions outside `funcs` are
+/// left out.
+pub fn call_graph(funcs: &HashMap<u64, (&VW_CFG, &IRMap)>) -> CallGraph {
+    let mut call_graph: CallGraph = DiGraphMap::new();
+    for (addr, (_, irmap)) in funcs {
+        call_graph.add_node(*addr);
+        for callee in direct_callees(irmap) {
+            if funcs.contains_key(&callee) {
+                call_graph.add_edge(*addr, callee, ());
+            }
+        }
+    }
+    call_graph
+}
+
+// Summarizes the functions of a strongly connected component of the call graph, given the
+// summaries of the functions it calls. The functions of a recursive component are summarized
+// together until their summaries stop changing.
+fn summarize_component(
+    component: &Vec<u64>,
+    recursive: bool,
+    funcs: &HashMap<u64, (&VW_CFG, &IRMap)>,
+    metadata: &VwMetadata,
+    strategy: &HeapStrategy,
+    summaries: &Arc<Summaries>,
+) -> Vec<(u64, Option<FunctionSummary>)> {
+    let summarize_func = |addr: &u64, summaries: &Arc<Summaries>| {
+        let (cfg, irmap) = funcs[addr];
+        panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            summarize(cfg, irmap, metadata, strategy, summaries)
+        }))
+        .ok()
+        .and_then(Result::ok)
+    };
+    if !recursive {
+        return component
+            .iter()
+            .map(|addr| (*addr, summarize_func(addr, summaries)))
+            .collect();
+    }
+
+    let mut summaries = summaries.clone();
+    for _ in 0..MAX_SCC_ROUNDS {
+        let mut changed = false;
+        for addr in component {
+            let summary = summarize_func(addr, &summaries);
+            let summaries = Arc::make_mut(&mut summaries);
+            changed |= summaries.get(addr) != summary.as_ref();
+            match summary {
+                Some(summary) => summaries.insert(*addr, summary),
+                None => summaries.remove(addr),
+            };
+        }
+        if !changed {
+            break;
+        }
+    }
+    component
+        .iter()
+        .map(|addr| (*addr, summaries.get(addr).cloned()))
+        .collect()
+}
+
+// The strongly connected components of `call_graph`, grouped so that every component only
+// calls components of earlier groups.
+fn component_levels(call_graph: &CallGraph) -> Vec<Vec<Vec<u64>>> {
+    let mut levels: Vec<Vec<Vec<u64>>> = Vec::new();
+    let mut level_of: HashMap<u64, usize> = HashMap::new();
+    // Components come callees first
+    for component in kosaraju_scc(call_graph) {
+        let level = component
+            .iter()
+            .flat_map(|addr| call_graph.neighbors(*addr))
+            .filter_map(|callee| level_of.get(&callee))
+            .map(|level| level + 1)
+            .max()
+            .unwrap_or(0);
+        for addr in &component {
+            level_of.insert(*addr, level);
+        }
+        if levels.len() <= level {
+            levels.resize(level + 1, Vec::new());
+        }
+        levels[level].push(component);
+    }
+    levels
+}
+
+/// Summarizes the lifted x64 functions `funcs`, callees first. The components of `call_graph`
+/// whose callees are all summarized are summarized on up to `num_jobs` threads; see
+/// `summarize_component`. Functions that fail to analyze have no summary.
+pub fn compute_summaries(
+    metadata: &VwMetadata,
+    funcs: &HashMap<u64, (&VW_CFG, &IRMap)>,
+    call_graph: &CallGraph,
+    strategy: &HeapStrategy,
+    num_jobs: usize,
+) -> Summaries {
+    let mut summaries: Arc<Summaries> = Arc::new(HashMap::new());
+    for level in component_levels(call_graph) {
+        let summarize_level = |component: &Vec<u64>| {
+            let recursive =
+                component.len() > 1 || call_graph.contains_edge(component[0], component[0]);
+            summarize_component(component, recursive, funcs, metadata, strategy, &summaries)
+        };
+        let num_workers = std::cmp::min(num_jobs, level.len());
+        let level_summaries: Vec<(u64, Option<FunctionSummary>)> = if num_workers <= 1 {
+            level.iter().flat_map(summarize_level).collect()
+        } else {
+            let next = AtomicUsize::new(0);
+            thread::scope(|scope| {
+                let workers: Vec<_> = (0..num_workers)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            let mut worker_summaries = Vec::new();
+                            while let Some(component) =
+                                level.get(next.fetch_add(1, Ordering::Relaxed))
+                            {
+                                worker_summaries.extend(summarize_level(component));
+                            }
+                            worker_summaries
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .flat_map(|worker| worker.join().unwrap())
+                    .collect()
+            })
+        };
+        let summaries = Arc::make_mut(&mut summaries);
+        for (addr, summary) in level_summaries {
+            if let Some(summary) = summary {
+                summaries.insert(addr, summary);
+            }
+        }
+    }
+    Arc::try_unwrap(summaries).unwrap_or_else(|summaries| (*summaries).clone())
+}
+
+/// The functions of `call_graph` whose summary-based analyses rely on the summary of one of
+/// `failed`, directly or through the summaries of other callees, each with the callee whose
+/// summary it relies on. A function that fails verification may not behave as its summary
+/// says, and neither may its summarized callers.
+pub fn summary_dependents(
+    call_graph: &CallGraph,
+    summaries: &Summaries,
+    failed: &HashSet<u64>,
+) -> HashMap<u64, u64> {
+    let mut dependents = HashMap::new();
+    let mut worklist: Vec<u64> = failed
+        .iter()
+        .copied()
+        .filter(|addr| summaries.contains_key(addr) && call_graph.contains_node(*addr))
+        .collect();
+    while let Some(callee) = worklist.pop() {
+        for caller in call_graph.neighbors_directed(callee, Incoming) {
+            if failed.contains(&caller) || dependents.contains_key(&caller) {
+                continue;
+            }
+            dependents.insert(caller, callee);
+            if summaries.contains_key(&caller) {
+                worklist.push(caller);
+            }
+        }
+    }
+    dependents
+}
+
+#[test]
+fn summary_test() {
+    use super::counterexample::diamond_cfg;
+    use crate::{HeapLayout, HeapRegs};
+
+    let metadata = VwMetadata {
+        guest_table_0: 0,
+        lucet_tables: 0,
+        lucet_probestack: 0,
+        heap_layout: HeapLayout::default(),
+        heap_regs: HeapRegs::default(),
+    };
+    let strategy = HeapStrategy::HeapPtrFirstArgWithGuards;
+    let heap_addr = Stmt::Unop(
+        Unopcode::Mov,
+        Value::Reg(Rax, Size64),
+        Value::Reg(Rdi, Size64),
+    );
+
+    // Both paths return the heap base in rax, and rsi is only clobbered on one
+    let (cfg, irmap) = diamond_cfg(
+        vec![Stmt::Ret],
+        vec![heap_addr.clone()],
+        vec![heap_addr, Stmt::Clear(Value::Reg(Rsi, Size64), vec![])],
+    );
+    let summary = summarize(
+        &cfg,
+        &irmap,
+        &metadata,
+        &strategy,
+        &Arc::new(Summaries::new()),
+    )
+    .unwrap();
+    let preserved: Vec<X86Regs> = summary.preserved_regs();
+    assert!(preserved.contains(&Rdi));
+    assert!(!preserved.contains(&Rax));
+    assert!(!preserved.contains(&Rsi));
+    assert_eq!(summary.ret, Some((u8::from(Rax), HeapBase(0))));
+
+    // At a call to the function, the caller keeps the heap base in rdi and gets it in rax
+    let analyzer = HeapAnalyzer {
+        metadata,
+        strategy,
+        summaries: Arc::new(vec![(0x100, summary)].into_iter().collect()),
+    };
+    let call = |target: i64| Stmt::Call(Value::Imm(ImmType::Signed, Size32, target - 5));
+    let call_loc = LocIdx { addr: 0, idx: 0 };
+    let mut state: HeapLattice = analyzer.init_state();
+    analyzer.aexec(&mut state, &call(0x100), &call_loc).unwrap();
+    assert_eq!(state.regs.get_reg(Rax, Size64).v, Some(HeapBase(0)));
+    assert_eq!(state.regs.get_reg(Rdi, Size64).v, Some(HeapBase(0)));
+
+    // A function without a summary clobbers both
+    let mut state: HeapLattice = analyzer.init_state();
+    analyzer.aexec(&mut state, &call(0x200), &call_loc).unwrap();
+    assert_eq!(state.regs.get_reg(Rax, Size64).v, None);
+    assert_eq!(state.regs.get_reg(Rdi, Size64).v, None);
+}
@@ -1,14 +1,31 @@
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 use loaders::types::{ExecutableType, VwArch};
+use std::fs;
 use std::str::FromStr;
 use veriwasm::loaders;
 use veriwasm::runner::*;
+use veriwasm::{HeapLayout, HeapStrategy};
+
+// A size or offset, in decimal or in hex with a `0x` prefix.
+fn parse_size(s: &str) -> u64 {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse::<u64>(),
+    };
+    parsed.unwrap_or_else(|_| panic!("Invalid size: {}", s))
+}
+
+fn exit_with_error(err: &str) -> ! {
+    eprintln!("{}", err);
+    std::process::exit(2)
+}
 
 fn main() {
     let _ = env_logger::try_init();
     let matches = App::new("VeriWasm")
         .version("0.1.0")
         .about("Validates safety of native Wasm code")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("module path")
                 .short("i")
@@ -30,6 +47,12 @@ fn main() {
                 .takes_value(true)
                 .help("Path to output stats file"),
         )
+        .arg(
+            Arg::with_name("sarif output path")
+                .long("sarif")
+                .takes_value(true)
+                .help("Path to output a SARIF log of verification failures"),
+        )
         .arg(
             Arg::with_name("one function")
                 .short("f")
@@ -55,44 +78,181 @@ fn main() {
         .arg(Arg::with_name("disable_call_checks").long("disable_call_checks"))
         .arg(Arg::with_name("enable_zero_cost_checks").long("enable_zero_cost_checks"))
         .arg(Arg::with_name("strict").long("strict"))
+        .arg(
+            Arg::with_name("interprocedural")
+                .long("interprocedural")
+                .help("Summarize functions and apply the summaries at direct calls"),
+        )
+        .arg(
+            Arg::with_name("vmctx heap base offset")
+                .long("vmctx_heap_base_offset")
+                .takes_value(true)
+                .help("Offset of the heap base pointer in the vmctx passed as first argument, as in Wasmtime (default: the heap base is the first argument)"),
+        )
+        .arg(
+            Arg::with_name("vmctx size")
+                .long("vmctx_size")
+                .takes_value(true)
+                .help("Size of the vmctx in bytes (default 4096)"),
+        )
+        .subcommand(
+            SubCommand::with_name("audit-opcodes")
+                .about("Reports how every instruction in the module is lifted")
+                .arg(
+                    Arg::with_name("module path")
+                        .short("i")
+                        .takes_value(true)
+                        .help("path to native Wasm module to audit")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("one function")
+                        .short("f")
+                        .long("func")
+                        .takes_value(true)
+                        .help("Single function to process (rather than whole module)"),
+                )
+                .arg(
+                    Arg::with_name("executable type")
+                        .short("c")
+                        .long("format")
+                        .takes_value(true)
+                        .help("Format of the executable (lucet | wasmtime)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("explain")
+                .about("Shows the abstract state of a pass before each instruction of a function")
+                .arg(
+                    Arg::with_name("module path")
+                        .short("i")
+                        .takes_value(true)
+                        .help("path to native Wasm module")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("one function")
+                        .short("f")
+                        .long("func")
+                        .takes_value(true)
+                        .help("Function to explain")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("pass")
+                        .long("pass")
+                        .takes_value(true)
+                        .help("Pass to explain (heap | stack | call | locals, default heap)"),
+                )
+                .arg(
+                    Arg::with_name("provenance")
+                        .long("provenance")
+                        .help("Explain why the values a failing instruction uses are unknown"),
+                )
+                .arg(
+                    Arg::with_name("executable type")
+                        .short("c")
+                        .long("format")
+                        .takes_value(true)
+                        .help("Format of the executable (lucet | wasmtime)"),
+                ),
+        )
         .get_matches();
 
+    if let Some(audit_matches) = matches.subcommand_matches("audit-opcodes") {
+        let module_path = audit_matches.value_of("module path").unwrap();
+        let mut verifier = Verifier::from_path(module_path).format(
+            ExecutableType::from_str(audit_matches.value_of("executable type").unwrap_or("lucet"))
+                .unwrap(),
+        );
+        if let Some(func_name) = audit_matches.value_of("one function") {
+            verifier = verifier.only_func(func_name);
+        }
+        match audit_opcodes(&verifier.config_for(module_path)) {
+            Ok(audit) => audit.print(),
+            Err(err) => exit_with_error(&err),
+        }
+        return;
+    }
+
+    if let Some(explain_matches) = matches.subcommand_matches("explain") {
+        let module_path = explain_matches.value_of("module path").unwrap();
+        let func_name = explain_matches.value_of("one function").unwrap();
+        let pass =
+            ExplainPass::from_str(explain_matches.value_of("pass").unwrap_or("heap")).unwrap();
+        let verifier = Verifier::from_path(module_path)
+            .format(
+                ExecutableType::from_str(
+                    explain_matches
+                        .value_of("executable type")
+                        .unwrap_or("lucet"),
+                )
+                .unwrap(),
+            )
+            .only_func(func_name);
+        let violations = explain(
+            &verifier.config_for(module_path),
+            func_name,
+            pass,
+            explain_matches.is_present("provenance"),
+        )
+        .unwrap_or_else(|err| exit_with_error(&err));
+        std::process::exit(if violations.is_empty() { 0 } else { 1 });
+    }
+
     let module_path = matches.value_of("module path").unwrap();
-    let num_jobs_opt = matches.value_of("jobs");
-    let output_path = matches.value_of("stats output path").unwrap_or("");
-    let num_jobs = num_jobs_opt
+    let num_jobs = matches
+        .value_of("jobs")
         .map(|s| s.parse::<u32>().unwrap_or(1))
         .unwrap_or(1);
-    let disable_stack_checks = matches.is_present("disable_stack_checks");
-    let disable_linear_mem_checks = matches.is_present("disable_linear_mem_checks");
-    let disable_call_checks = matches.is_present("disable_call_checks");
-    let enable_zero_cost_checks = matches.is_present("enable_zero_cost_checks");
-    let strict = matches.is_present("strict");
-    let only_func = matches.value_of("one function").map(|s| s.to_owned());
     let executable_type =
         ExecutableType::from_str(matches.value_of("executable type").unwrap_or("lucet")).unwrap();
     let arch = VwArch::from_str(matches.value_of("architecture").unwrap_or("x64")).unwrap();
 
-    let has_output = output_path != "";
+    let mut verifier = Verifier::from_path(module_path)
+        .format(executable_type)
+        .arch(arch)
+        .jobs(num_jobs)
+        .stack(!matches.is_present("disable_stack_checks"))
+        .linear_mem(!matches.is_present("disable_linear_mem_checks"))
+        .call(!matches.is_present("disable_call_checks"))
+        .zero_cost(matches.is_present("enable_zero_cost_checks"))
+        .strict(matches.is_present("strict"))
+        .interprocedural(matches.is_present("interprocedural"));
+    if let Some(func_name) = matches.value_of("one function") {
+        verifier = verifier.only_func(func_name);
+    }
+    if let Some(offset) = matches.value_of("vmctx heap base offset") {
+        verifier = verifier.heap_strategy(HeapStrategy::VMCtxFirstArgWithGuards {
+            vmctx_heap_base_ptr_offset: parse_size(offset) as usize,
+        });
+    }
+    if let Some(vmctx_size) = matches.value_of("vmctx size") {
+        verifier = verifier.heap_layout(HeapLayout {
+            vmctx_size: parse_size(vmctx_size),
+            ..HeapLayout::for_format(executable_type)
+        });
+    }
 
-    let active_passes = PassConfig {
-        stack: !disable_stack_checks,
-        linear_mem: !disable_linear_mem_checks,
-        call: !disable_call_checks,
-        zero_cost: enable_zero_cost_checks,
-    };
-
-    let config = Config {
-        module_path: module_path.to_string(),
-        _num_jobs: num_jobs,
-        output_path: output_path.to_string(),
-        has_output: has_output,
-        only_func,
-        executable_type,
-        active_passes,
-        arch,
-        strict,
-    };
-
-    run(config);
+    let report = verifier
+        .verify()
+        .unwrap_or_else(|err| exit_with_error(&err.to_string()));
+    report.print();
+    if let Some(output_path) = matches.value_of("stats output path") {
+        println!("Dumping Stats to {}", output_path);
+        fs::write(
+            output_path,
+            serde_json::to_string_pretty(&report.to_json()).unwrap(),
+        )
+        .expect("Unable to write file");
+    }
+    if let Some(sarif_path) = matches.value_of("sarif output path") {
+        println!("Dumping SARIF log to {}", sarif_path);
+        fs::write(
+            sarif_path,
+            serde_json::to_string_pretty(&report.to_sarif()).unwrap(),
+        )
+        .expect("Unable to write file");
+    }
+    std::process::exit(report.exit_code());
 }
@@ -1,6 +1,6 @@
 use crate::{analyses, checkers, ir, lattices};
 use analyses::{AbstractAnalyzer, AnalysisResult, CallAnalyzer};
-use checkers::Checker;
+use checkers::{CheckKind, Checker, TraceStep, Violation};
 use ir::types::*;
 use lattices::calllattice::{CallCheckLattice, CallCheckValue};
 use lattices::davlattice::DAV;
@@ -22,7 +22,7 @@ pub fn check_calls(
     analyzer: &CallAnalyzer,
     funcs: &Vec<u64>,
     plt: &(u64, u64),
-) -> bool {
+) -> Vec<Violation> {
     CallChecker {
         irmap,
         analyzer,
@@ -32,8 +32,24 @@ pub fn check_calls(
     .check(result)
 }
 
+pub fn trace_calls(
+    result: AnalysisResult<CallCheckLattice>,
+    irmap: &IRMap,
+    analyzer: &CallAnalyzer,
+    funcs: &Vec<u64>,
+    plt: &(u64, u64),
+) -> Vec<TraceStep> {
+    CallChecker {
+        irmap,
+        analyzer,
+        funcs,
+        plt,
+    }
+    .trace(result)
+}
+
 impl Checker<CallCheckLattice> for CallChecker<'_> {
-    fn check(&self, result: AnalysisResult<CallCheckLattice>) -> bool {
+    fn check(&self, result: AnalysisResult<CallCheckLattice>) -> Vec<Violation> {
         self.check_state_at_statements(result)
     }
 
@@ -41,30 +57,38 @@ impl Checker<CallCheckLattice> for CallChecker<'_> {
         self.irmap
     }
     fn aexec(&self, state: &mut CallCheckLattice, ir_stmt: &Stmt, loc: &LocIdx) {
-        self.analyzer.aexec(state, ir_stmt, loc)
+        self.analyzer.replay(state, ir_stmt, loc)
+    }
+    fn kind(&self) -> CheckKind {
+        CheckKind::Call
     }
 
-    fn check_statement(&self, state: &CallCheckLattice, ir_stmt: &Stmt, loc_idx: &LocIdx) -> bool {
+    fn check_statement(
+        &self,
+        state: &CallCheckLattice,
+        ir_stmt: &Stmt,
+        loc_idx: &LocIdx,
+    ) -> Result<(), Violation> {
         //1. Check that all indirect calls use resolved function pointer
         if let Stmt::Call(v) = ir_stmt {
             if !self.check_indirect_call(state, v, loc_idx) {
                 println!("0x{:x} Failure Case: Indirect Call {:?}", loc_idx.addr, v);
-                return false;
+                return Err(self.violation(state, loc_idx, Some(v)));
             }
         }
 
         // 2. Check that lookup is using resolved DAV
-        if let Stmt::Unop(_, _, Value::Mem(_, memargs)) = ir_stmt {
+        if let Stmt::Unop(_, _, src @ Value::Mem(_, memargs)) = ir_stmt {
             if !self.check_calltable_lookup(state, memargs) {
                 println!(
                     "0x{:x} Failure Case: Lookup Call: {:?}",
                     loc_idx.addr, memargs
                 );
                 print_mem_access(state, memargs);
-                return false;
+                return Err(self.violation(state, loc_idx, Some(src)));
             }
         }
-        true
+        Ok(())
     }
 }
 
@@ -149,5 +173,12 @@ pub fn print_mem_access(state: &CallCheckLattice, memargs: &MemArgs) {
             memarg_repr(state, y),
             memarg_repr(state, z)
         ),
+        MemArgs::MemScaleDisp(x, y, z, w) => log::debug!(
+            "mem[{:?} + {:?} * {:?} + {:?}]",
+            memarg_repr(state, x),
+            memarg_repr(state, y),
+            memarg_repr(state, z),
+            memarg_repr(state, w)
+        ),
     }
 }
@@ -1,28 +1,32 @@
-use crate::analyses::{AbstractAnalyzer, AnalysisResult, HeapAnalyzer};
-use crate::checkers::Checker;
+use crate::analyses::{
+    counterexample_path, state_before, AbstractAnalyzer, AnalysisResult, CounterexamplePath,
+    HeapAnalyzer,
+};
+use crate::checkers::{CheckKind, Checker, TraceStep, Violation};
 use crate::ir::types::*;
 // use crate::ir::utils::{is_mem_access, is_stack_access};
 use crate::lattices::heaplattice::{HeapLattice, HeapValue};
 use crate::lattices::reachingdefslattice::LocIdx;
 use crate::loaders::utils::is_libcall;
+use crate::{HeapLayout, IndexWidth};
 use std::collections::HashMap;
+use yaxpeax_core::analyses::control_flow::VW_CFG;
 
 use HeapValue::*;
 use ValSize::*;
-use X86Regs::*;
 
-pub struct HeapChecker<'a> {
-    irmap: &'a IRMap,
+pub struct HeapChecker<'a, Ar: RegT> {
+    irmap: &'a IRMap<Ar>,
     analyzer: &'a HeapAnalyzer,
     name_addr_map: &'a HashMap<u64, String>,
 }
 
-pub fn check_heap(
-    result: AnalysisResult<HeapLattice>,
-    irmap: &IRMap,
+pub fn check_heap<Ar: RegT>(
+    result: AnalysisResult<HeapLattice<Ar>>,
+    irmap: &IRMap<Ar>,
     analyzer: &HeapAnalyzer,
     name_addr_map: &HashMap<u64, String>,
-) -> bool {
+) -> Vec<Violation> {
     HeapChecker {
         irmap: irmap,
         analyzer: analyzer,
@@ -31,16 +35,111 @@ pub fn check_heap(
     .check(result)
 }
 
-fn memarg_is_frame(memarg: &MemArg) -> bool {
-    if let MemArg::Reg(Rbp, size) = memarg {
-        assert_eq!(*size, Size64);
-        true
-    } else {
-        false
+pub fn trace_heap<Ar: RegT>(
+    result: AnalysisResult<HeapLattice<Ar>>,
+    irmap: &IRMap<Ar>,
+    analyzer: &HeapAnalyzer,
+    name_addr_map: &HashMap<u64, String>,
+) -> Vec<TraceStep> {
+    HeapChecker {
+        irmap: irmap,
+        analyzer: analyzer,
+        name_addr_map: name_addr_map,
+    }
+    .trace(result)
+}
+
+// The register a failed access depends on and whether a value is the one it needed there:
+// the base must be the base of a memory, and any register index must be bounded.
+fn blamed_reg<Ar: RegT>(
+    state: &HeapLattice<Ar>,
+    access: &Value<Ar>,
+) -> Option<(Ar, fn(Option<HeapValue>) -> bool)> {
+    let memargs = match access {
+        Value::Mem(_, memargs) => memargs,
+        _ => return None,
+    };
+    let mut regs: Vec<(Ar, ValSize)> = match memargs {
+        MemArgs::Mem1Arg(x) => vec![x],
+        MemArgs::Mem2Args(x, y) | MemArgs::MemScale(x, y, _) => vec![x, y],
+        MemArgs::Mem3Args(x, y, z) | MemArgs::MemScaleDisp(x, y, _, z) => vec![x, y, z],
+    }
+    .into_iter()
+    .filter_map(|memarg| match memarg {
+        MemArg::Reg(regnum, size) => Some((*regnum, *size)),
+        MemArg::Imm(..) => None,
+    })
+    .collect();
+    // `[index + base + imm]` has its base second
+    if let Some(base_pos) = regs
+        .iter()
+        .position(|(regnum, _)| is_heap_base(state.regs.get_reg(*regnum, Size64).v))
+    {
+        let base = regs.remove(base_pos);
+        regs.insert(0, base);
+    }
+    let (base, _) = *regs.first()?;
+    if !is_heap_base(state.regs.get_reg(base, Size64).v) {
+        return Some((base, is_heap_base));
+    }
+    regs[1..]
+        .iter()
+        .find(|(regnum, size)| !is_checked_index(state, *regnum, *size))
+        .map(|(regnum, _)| (*regnum, is_bounded as fn(Option<HeapValue>) -> bool))
+}
+
+fn is_heap_base(value: Option<HeapValue>) -> bool {
+    matches!(value, Some(HeapBase(_)))
+}
+
+fn is_bounded(value: Option<HeapValue>) -> bool {
+    value == Some(Bounded4GB)
+}
+
+/// For a heap violation, a path along which the register the access depends on lost the
+/// value it needed: `HeapBase` for the base register, `Bounded4GB` for an index register.
+/// Returns `None` if no register is to blame (e.g., a constant offset is too large).
+pub fn heap_counterexample<Ar: RegT>(
+    cfg: &VW_CFG,
+    irmap: &IRMap<Ar>,
+    analyzer: &HeapAnalyzer,
+    result: &AnalysisResult<HeapLattice<Ar>>,
+    name_addr_map: &HashMap<u64, String>,
+    violation: &Violation,
+) -> Option<CounterexamplePath> {
+    let loc_idx = &violation.loc_idx;
+    let (block, state) = state_before(irmap, analyzer, result, loc_idx)?;
+    let (_, stmts) = irmap[&block]
+        .iter()
+        .find(|(addr, _)| *addr == loc_idx.addr)?;
+    let operands: Vec<&Value<Ar>> = match &stmts[loc_idx.idx as usize] {
+        Stmt::Unop(_, dst, src) => vec![dst, src],
+        Stmt::Binop(_, dst, src1, src2) => vec![dst, src1, src2],
+        Stmt::Clear(dst, srcs) => std::iter::once(dst).chain(srcs.iter()).collect(),
+        _ => vec![],
+    };
+    let checker = HeapChecker {
+        irmap,
+        analyzer,
+        name_addr_map,
+    };
+    let access = operands
+        .into_iter()
+        .find(|v| v.is_mem() && !checker.check_mem_access(&state, v, loc_idx))?;
+    let (reg, is_expected) = blamed_reg(&state, access)?;
+    counterexample_path(cfg, irmap, analyzer, result, loc_idx, |state| {
+        is_expected(state.regs.get_reg(reg, Size64).v)
+    })
+}
+
+fn memarg_is_frame<Ar: RegT>(memarg: &MemArg<Ar>) -> bool {
+    match memarg {
+        MemArg::Reg(regnum, Size64) => regnum.is_rbp(),
+        _ => false,
     }
 }
 
-fn is_frame_access(v: &Value) -> bool {
+fn is_frame_access<Ar: RegT>(v: &Value<Ar>) -> bool {
     if let Value::Mem(_, memargs) = v {
         // Accept only operands of the form `[rbp + OFFSET]` where `OFFSET` is an integer. In
         // Cranelift-generated code from Wasm, there are never arrays or variable-length data in
@@ -58,81 +157,115 @@ fn is_frame_access(v: &Value) -> bool {
     }
 }
 
-impl Checker<HeapLattice> for HeapChecker<'_> {
-    fn check(&self, result: AnalysisResult<HeapLattice>) -> bool {
+impl<Ar: RegT> Checker<HeapLattice<Ar>, Ar> for HeapChecker<'_, Ar> {
+    fn check(&self, result: AnalysisResult<HeapLattice<Ar>>) -> Vec<Violation> {
         self.check_state_at_statements(result)
     }
 
-    fn irmap(&self) -> &IRMap {
+    fn irmap(&self) -> &IRMap<Ar> {
         self.irmap
     }
-    fn aexec(&self, state: &mut HeapLattice, ir_stmt: &Stmt, loc: &LocIdx) {
-        self.analyzer.aexec(state, ir_stmt, loc)
+    fn aexec(&self, state: &mut HeapLattice<Ar>, ir_stmt: &Stmt<Ar>, loc: &LocIdx) {
+        self.analyzer.replay(state, ir_stmt, loc)
+    }
+    fn kind(&self) -> CheckKind {
+        CheckKind::Heap
     }
 
-    fn check_statement(&self, state: &HeapLattice, ir_stmt: &Stmt, loc_idx: &LocIdx) -> bool {
+    fn check_statement(
+        &self,
+        state: &HeapLattice<Ar>,
+        ir_stmt: &Stmt<Ar>,
+        loc_idx: &LocIdx,
+    ) -> Result<(), Violation> {
         match ir_stmt {
-            //1. Check that at each call rdi = HeapBase
-            Stmt::Call(v) => match state.regs.get_reg(Rdi, Size64).v {
-                Some(HeapBase) => (),
-                _ => {
-                    ()
-                    // removed for lucet integration
-
-                    //if let Value::Imm(_, _, dst) = v {
-                    //    let target = (*dst + (loc_idx.addr as i64) + 5) as u64;
-                    //    let name = self.name_addr_map.get(&target).unwrap();
-                    //    if !is_libcall(name) {
-                    //        log::debug!("0x{:x}: Call failure", loc_idx.addr);
-                    //       return false;
-                    //    }
-                    //} else {
-                    //    log::debug!("0x{:x}: Call failure", loc_idx.addr);
-                    //    return false;
-                    // }
+            //1. Check that at each call the pinned register, if any, holds the heap base, and
+            //   that the heap base register holds HeapBase
+            Stmt::Call(v) => {
+                if let Some(pinned) = self.clobbered_pinned_reg(state) {
+                    return Err(self.violation(state, loc_idx, Some(&Value::Reg(pinned, Size64))));
+                }
+                match state
+                    .regs
+                    .get_reg(self.analyzer.metadata.heap_regs.heap_base_reg(), Size64)
+                    .v
+                {
+                    Some(HeapBase(_)) => (),
+                    _ => {
+                        ()
+                        // removed for lucet integration
+
+                        //if let Value::Imm(_, _, dst) = v {
+                        //    let target = (*dst + (loc_idx.addr as i64) + 5) as u64;
+                        //    let name = self.name_addr_map.get(&target).unwrap();
+                        //    if !is_libcall(name) {
+                        //        log::debug!("0x{:x}: Call failure", loc_idx.addr);
+                        //       return false;
+                        //    }
+                        //} else {
+                        //    log::debug!("0x{:x}: Call failure", loc_idx.addr);
+                        //    return false;
+                        // }
+                    }
                 }
-            },
+            }
+            // Callers assume that calls preserve the pinned register, so it must hold the heap
+            // base again on return
+            Stmt::Ret => {
+                if let Some(pinned) = self.clobbered_pinned_reg(state) {
+                    return Err(self.violation(state, loc_idx, Some(&Value::Reg(pinned, Size64))));
+                }
+            }
             //2. Check that all load and store are safe
             Stmt::Unop(_, dst, src) => {
-                if dst.is_mem() && !self.check_mem_access(state, dst, loc_idx) {
-                    return false;
+                if dst.is_mem() && !self.check_store(state, dst, loc_idx) {
+                    return Err(self.violation(state, loc_idx, Some(dst)));
                 }
                 //stack read: probestack <= stackgrowth + c < 8K
                 if src.is_mem() && !self.check_mem_access(state, src, loc_idx) {
-                    return false;
+                    return Err(self.violation(state, loc_idx, Some(src)));
                 }
             }
 
             Stmt::Binop(_, dst, src1, src2) => {
-                if dst.is_mem() && !self.check_mem_access(state, dst, loc_idx) {
-                    return false;
+                if dst.is_mem() && !self.check_store(state, dst, loc_idx) {
+                    return Err(self.violation(state, loc_idx, Some(dst)));
                 }
                 if src1.is_mem() && !self.check_mem_access(state, src1, loc_idx) {
-                    return false;
+                    return Err(self.violation(state, loc_idx, Some(src1)));
                 }
                 if src2.is_mem() && !self.check_mem_access(state, src2, loc_idx) {
-                    return false;
+                    return Err(self.violation(state, loc_idx, Some(src2)));
                 }
             }
             Stmt::Clear(dst, srcs) => {
-                if dst.is_mem() && !self.check_mem_access(state, dst, loc_idx) {
-                    return false;
+                if dst.is_mem() && !self.check_store(state, dst, loc_idx) {
+                    return Err(self.violation(state, loc_idx, Some(dst)));
                 }
                 for src in srcs {
                     if src.is_mem() && !self.check_mem_access(state, src, loc_idx) {
-                        return false;
+                        return Err(self.violation(state, loc_idx, Some(src)));
                     }
                 }
             }
             _ => (),
         }
-        true
+        Ok(())
     }
 }
 
-impl HeapChecker<'_> {
-    fn check_global_access(&self, state: &HeapLattice, access: &Value) -> bool {
-        if let Value::Mem(_, memargs) = access {
+impl<Ar: RegT> HeapChecker<'_, Ar> {
+    // The pinned register, if it no longer holds the base of memory 0.
+    fn clobbered_pinned_reg(&self, state: &HeapLattice<Ar>) -> Option<Ar> {
+        self.analyzer
+            .metadata
+            .heap_regs
+            .pinned_heap_base_reg()
+            .filter(|pinned| state.regs.get_reg(*pinned, Size64).v != Some(HeapBase(0)))
+    }
+
+    fn check_global_access(&self, state: &HeapLattice<Ar>, access: &Value<Ar>) -> bool {
+        if let Value::Mem(size, memargs) = access {
             match memargs {
                 MemArgs::Mem1Arg(MemArg::Reg(regnum, Size64)) => {
                     if let Some(GlobalsBase) = state.regs.get_reg(*regnum, Size64).v {
@@ -144,7 +277,10 @@ impl HeapChecker<'_> {
                     MemArg::Imm(_, _, globals_offset),
                 ) => {
                     if let Some(GlobalsBase) = state.regs.get_reg(*regnum, Size64).v {
-                        return *globals_offset <= 4096;
+                        let globals_size = self.analyzer.metadata.heap_layout.globals_size;
+                        return *globals_offset >= 0
+                            && (*globals_offset as i128) + access_bytes(*size)
+                                <= globals_size as i128;
                     }
                 }
                 _ => return false,
@@ -153,7 +289,7 @@ impl HeapChecker<'_> {
         false
     }
 
-    fn check_ripconst_access(&self, state: &HeapLattice, access: &Value) -> bool {
+    fn check_ripconst_access(&self, state: &HeapLattice<Ar>, access: &Value<Ar>) -> bool {
         if let Value::Mem(_, memargs) = access {
             match memargs {
                 // `RIPConst` represents a trusted value laoded from .rodata or .data; any access involving
@@ -171,10 +307,16 @@ impl HeapChecker<'_> {
                 //   relocations, we accept this approximation to the trusted
                 //   base: we trust any memory access based at such a
                 //   constant/global-variable-produced address.
+                //
+                // Jump table reads `[table + index*4]` are left to
+                // `check_jump_table_access`, which also requires the index to
+                // be below the table's entry count.
+                MemArgs::MemScale(_, _, MemArg::Imm(_, _, 4)) => {}
                 MemArgs::Mem1Arg(MemArg::Reg(regnum, Size64))
                 | MemArgs::Mem2Args(MemArg::Reg(regnum, Size64), _)
                 | MemArgs::Mem3Args(MemArg::Reg(regnum, Size64), _, _)
-                | MemArgs::MemScale(MemArg::Reg(regnum, Size64), _, _) => {
+                | MemArgs::MemScale(MemArg::Reg(regnum, Size64), _, _)
+                | MemArgs::MemScaleDisp(MemArg::Reg(regnum, Size64), _, _, _) => {
                     if let Some(RIPConst) = state.regs.get_reg(*regnum, Size64).v {
                         return true;
                     }
@@ -185,34 +327,61 @@ impl HeapChecker<'_> {
         false
     }
 
-    fn check_heap_access(&self, state: &HeapLattice, access: &Value) -> bool {
-        if let Value::Mem(_, memargs) = access {
+    fn check_heap_access(&self, state: &HeapLattice<Ar>, access: &Value<Ar>) -> bool {
+        let layout = |memory: u32| self.analyzer.heap_layout(memory);
+        // Bounded4GB indices are only valid indices of 32-bit memories
+        let wasm32 = |memory: u32| self.analyzer.strategy.index_width(memory) == IndexWidth::I32;
+        if let Value::Mem(size, memargs) = access {
+            let in_guard = |memory: u32, scale: i64, disp: i64| {
+                offset_in_guard(&layout(memory), scale, disp, *size)
+            };
+            // Is `heapbase + index * scale + disp` safe for the base of `memory` and the index
+            // in `regnum`? An index checked against the length of another memory is not.
+            let index_ok =
+                |memory: u32, regnum: &Ar, size2: &ValSize, scale: i64, disp: i64| match state
+                    .regs
+                    .get_reg(*regnum, *size2)
+                    .v
+                {
+                    Some(CheckedIndex(checked_memory, n)) if *size2 == Size64 && scale == 1 => {
+                        checked_memory == memory
+                            && checked_offset_in_bounds(&layout(memory), n, disp, *size)
+                    }
+                    Some(MaskedIndex(mask)) if *size2 == Size64 => {
+                        index_in_guard(&layout(memory), mask as i128, scale, disp, *size)
+                    }
+                    _ => {
+                        wasm32(memory)
+                            && is_bounded_index(state, *regnum, *size2)
+                            && in_guard(memory, scale, disp)
+                    }
+                };
             match memargs {
                 // if only arg is heapbase or heapaddr
                 MemArgs::Mem1Arg(MemArg::Reg(regnum, Size64)) => {
-                    if let Some(HeapBase) = state.regs.get_reg(*regnum, Size64).v {
-                        return true;
+                    if let Some(HeapBase(memory)) = state.regs.get_reg(*regnum, Size64).v {
+                        return in_guard(memory, 0, 0);
                     }
-                    if let Some(HeapAddr) = state.regs.get_reg(*regnum, Size64).v {
-                        return true;
+                    if let Some(HeapAddr(memory)) = state.regs.get_reg(*regnum, Size64).v {
+                        return wasm32(memory) && in_guard(memory, 1, 0);
                     }
                 }
                 // if arg1 is heapbase and arg2 is bounded ||
                 // if arg1 is heapaddr and arg2 is constant offset
                 MemArgs::Mem2Args(MemArg::Reg(regnum, Size64), memarg2) => {
-                    if let Some(HeapBase) = state.regs.get_reg(*regnum, Size64).v {
+                    if let Some(HeapBase(memory)) = state.regs.get_reg(*regnum, Size64).v {
                         match memarg2 {
                             MemArg::Reg(regnum2, size2) => {
-                                if let Some(Bounded4GB) = state.regs.get_reg(*regnum2, *size2).v {
-                                    return true;
-                                }
+                                return index_ok(memory, regnum2, size2, 1, 0)
                             }
-                            MemArg::Imm(_, _, v) => return *v >= -0x1000 && *v <= 0xffffffff,
+                            MemArg::Imm(_, _, v) => return in_guard(memory, 0, *v),
                         }
                     }
-                    if let Some(HeapAddr) = state.regs.get_reg(*regnum, Size64).v {
+                    if let Some(HeapAddr(memory)) = state.regs.get_reg(*regnum, Size64).v {
                         match memarg2 {
-                            MemArg::Imm(_, _, v) => return *v >= -0x1000 && *v <= 0xffffffff,
+                            MemArg::Imm(_, _, v) => {
+                                return wasm32(memory) && in_guard(memory, 1, *v)
+                            }
                             _ => {}
                         }
                     }
@@ -221,33 +390,51 @@ impl HeapChecker<'_> {
                 // if arg1 is bounded and arg1 and arg3 are bounded
                 MemArgs::Mem3Args(MemArg::Reg(regnum, Size64), memarg2, memarg3)
                 | MemArgs::Mem3Args(memarg2, MemArg::Reg(regnum, Size64), memarg3) => {
-                    if let Some(HeapBase) = state.regs.get_reg(*regnum, Size64).v {
+                    if let Some(HeapBase(memory)) = state.regs.get_reg(*regnum, Size64).v {
                         match (memarg2, memarg3) {
                             (MemArg::Reg(regnum2, size2), MemArg::Imm(_, _, v))
                             | (MemArg::Imm(_, _, v), MemArg::Reg(regnum2, size2)) => {
-                                if let Some(Bounded4GB) = state.regs.get_reg(*regnum2, *size2).v {
-                                    return *v <= 0xffffffff;
-                                }
+                                return index_ok(memory, regnum2, size2, 1, *v);
                             }
                             (MemArg::Reg(regnum2, size2), MemArg::Reg(regnum3, size3)) => {
                                 if let (Some(Bounded4GB), Some(Bounded4GB)) = (
                                     state.regs.get_reg(*regnum2, *size2).v,
                                     state.regs.get_reg(*regnum3, *size3).v,
                                 ) {
-                                    return true;
+                                    return wasm32(memory) && in_guard(memory, 2, 0);
                                 }
                             }
                             _ => (),
                         }
                     }
                 }
+                // if arg1 is heapbase and arg2 * arg3 + disp stays within the guard region
+                MemArgs::MemScale(
+                    MemArg::Reg(regnum, Size64),
+                    MemArg::Reg(regnum2, size2),
+                    MemArg::Imm(_, _, scale),
+                ) => {
+                    if let Some(HeapBase(memory)) = state.regs.get_reg(*regnum, Size64).v {
+                        return *scale > 0 && index_ok(memory, regnum2, size2, *scale, 0);
+                    }
+                }
+                MemArgs::MemScaleDisp(
+                    MemArg::Reg(regnum, Size64),
+                    MemArg::Reg(regnum2, size2),
+                    MemArg::Imm(_, _, scale),
+                    MemArg::Imm(_, _, disp),
+                ) => {
+                    if let Some(HeapBase(memory)) = state.regs.get_reg(*regnum, Size64).v {
+                        return *scale > 0 && index_ok(memory, regnum2, size2, *scale, *disp);
+                    }
+                }
                 _ => return false,
             }
         }
         false
     }
 
-    fn check_metadata_access(&self, state: &HeapLattice, access: &Value) -> bool {
+    fn check_metadata_access(&self, state: &HeapLattice<Ar>, access: &Value<Ar>) -> bool {
         if let Value::Mem(_size, memargs) = access {
             match memargs {
                 //Case 1: mem[globals_base]
@@ -290,17 +477,90 @@ impl HeapChecker<'_> {
         false
     }
 
-    fn check_jump_table_access(&self, _state: &HeapLattice, access: &Value) -> bool {
-        if let Value::Mem(_size, memargs) = access {
-            match memargs {
-                MemArgs::MemScale(_, _, MemArg::Imm(_, _, 4)) => return true,
-                _ => return false,
+    // The offset of `access` within vmctx, if it is based at the vmctx pointer.
+    fn vmctx_offset(&self, state: &HeapLattice<Ar>, access: &Value<Ar>) -> Option<i64> {
+        let (regnum, offset) = match access {
+            Value::Mem(_, MemArgs::Mem1Arg(MemArg::Reg(regnum, Size64))) => (regnum, 0),
+            Value::Mem(
+                _,
+                MemArgs::Mem2Args(MemArg::Reg(regnum, Size64), MemArg::Imm(_, _, offset)),
+            ) => (regnum, *offset),
+            _ => return None,
+        };
+        match state.regs.get_reg(*regnum, Size64).v {
+            Some(VMCtxPtr) => Some(offset),
+            _ => None,
+        }
+    }
+
+    // The vmctx is laid out by the runtime and the compiler only reads and
+    // writes its fields at constant offsets within it.
+    fn check_vmctx_access(&self, state: &HeapLattice<Ar>, access: &Value<Ar>) -> bool {
+        match (access, self.vmctx_offset(state, access)) {
+            (Value::Mem(size, _), Some(offset)) => {
+                let vmctx_size = self.analyzer.metadata.heap_layout.vmctx_size;
+                offset >= 0 && offset as i128 + access_bytes(*size) <= vmctx_size as i128
             }
+            _ => false,
         }
-        false
     }
 
-    fn check_mem_access(&self, state: &HeapLattice, access: &Value, loc_idx: &LocIdx) -> bool {
+    // Does `access` write to the heap base pointer or length of a memory in vmctx? Loads of
+    // these fields are trusted, so the code must never store to them.
+    fn writes_heap_field(&self, state: &HeapLattice<Ar>, access: &Value<Ar>) -> bool {
+        let (size, offset) = match (access, self.vmctx_offset(state, access)) {
+            (Value::Mem(size, _), Some(offset)) => (*size, offset as i128),
+            _ => return false,
+        };
+        let overlaps = |field: usize| {
+            let field = field as i128;
+            offset < field + 8 && field < offset + access_bytes(size)
+        };
+        self.analyzer
+            .strategy
+            .vmctx_offsets()
+            .iter()
+            .any(|(base_offset, bound_offset)| {
+                overlaps(*base_offset) || bound_offset.map_or(false, overlaps)
+            })
+    }
+
+    // Memory written by a statement must be safe to access, and must not be a vmctx field
+    // the heap analysis trusts.
+    fn check_store(&self, state: &HeapLattice<Ar>, dst: &Value<Ar>, loc_idx: &LocIdx) -> bool {
+        self.check_mem_access(state, dst, loc_idx) && !self.writes_heap_field(state, dst)
+    }
+
+    // A jump table read `[table + index*4]`, where the table is a constant in the code
+    // section and the index has been compared below the table's entry count by the
+    // `cmp idx, n; jae` that guards the `br_table`. A heap-checked or merely 32-bit index
+    // does not bound the read to the table.
+    fn check_jump_table_access(&self, state: &HeapLattice<Ar>, access: &Value<Ar>) -> bool {
+        match access {
+            Value::Mem(
+                _,
+                MemArgs::MemScale(
+                    MemArg::Reg(base, Size64),
+                    MemArg::Reg(index, index_size),
+                    MemArg::Imm(_, _, 4),
+                ),
+            ) => {
+                state.regs.get_reg(*base, Size64).v == Some(RIPConst)
+                    && matches!(
+                        state.regs.get_reg(*index, *index_size).v,
+                        Some(TableIndex(_))
+                    )
+            }
+            _ => false,
+        }
+    }
+
+    fn check_mem_access(
+        &self,
+        state: &HeapLattice<Ar>,
+        access: &Value<Ar>,
+        loc_idx: &LocIdx,
+    ) -> bool {
         // Case 1: its a stack access
         if access.is_stack_access() {
             return true;
@@ -329,18 +589,72 @@ impl HeapChecker<'_> {
         if self.check_global_access(state, access) {
             return true;
         };
-        // Case 7: Jump table access
+        // Case 7: its a vmctx access
+        if self.check_vmctx_access(state, access) {
+            return true;
+        };
+        // Case 8: Jump table access
         if self.check_jump_table_access(state, access) {
             return true;
         };
-        // Case 8: its unknown
+        // Case 9: its unknown
         log::debug!("None of the memory accesses at 0x{:x}", loc_idx.addr);
         print_mem_access(state, access);
         return false;
     }
 }
 
-pub fn memarg_repr(state: &HeapLattice, memarg: &MemArg) -> String {
+// The largest value of a Bounded4GB index.
+const MAX_BOUNDED_INDEX: i128 = 0xffffffff;
+
+// A 32-bit index is zero-extended into the address (AArch64's `[base, wN, uxtw]`), so it is
+// bounded as well.
+fn is_bounded_index<Ar: RegT>(state: &HeapLattice<Ar>, regnum: Ar, size: ValSize) -> bool {
+    size == Size32 || state.regs.get_reg(regnum, size).v == Some(Bounded4GB)
+}
+
+// An index that is bounded, or that has been compared against or masked to a memory's size.
+fn is_checked_index<Ar: RegT>(state: &HeapLattice<Ar>, regnum: Ar, size: ValSize) -> bool {
+    is_bounded_index(state, regnum, size)
+        || matches!(
+            state.regs.get_reg(regnum, size).v,
+            Some(CheckedIndex(..)) | Some(MaskedIndex(_))
+        )
+}
+
+// The number of bytes an access of `size` reads or writes.
+fn access_bytes(size: ValSize) -> i128 {
+    ((size.into_bits() + 7) / 8) as i128
+}
+
+// Does an access of `size` at `index * scale + disp` past the heap base stay within the
+// reservation and guard region of `layout` for every Bounded4GB index? A `scale` of 0 means
+// there is no index.
+fn offset_in_guard(layout: &HeapLayout, scale: i64, disp: i64, size: ValSize) -> bool {
+    index_in_guard(layout, MAX_BOUNDED_INDEX, scale, disp, size)
+}
+
+// Like `offset_in_guard`, for every index up to `max_index`.
+fn index_in_guard(
+    layout: &HeapLayout,
+    max_index: i128,
+    scale: i64,
+    disp: i64,
+    size: ValSize,
+) -> bool {
+    scale >= 0
+        && disp >= -0x1000
+        && max_index * scale as i128 + disp as i128 + access_bytes(size)
+            <= layout.guarded_size() as i128
+}
+
+// Does an access of `size` at `index + disp` past the heap base stay within the heap and the
+// guard region past its length, for an index with `checked` bytes past it within the heap?
+fn checked_offset_in_bounds(layout: &HeapLayout, checked: u32, disp: i64, size: ValSize) -> bool {
+    disp >= 0 && disp as i128 + access_bytes(size) <= checked as i128 + layout.guard as i128
+}
+
+pub fn memarg_repr<Ar: RegT>(state: &HeapLattice<Ar>, memarg: &MemArg<Ar>) -> String {
     match memarg {
         MemArg::Reg(regnum, size) => {
             format!("{:?}: {:?}", regnum, state.regs.get_reg(*regnum, *size).v)
@@ -349,7 +663,7 @@ pub fn memarg_repr(state: &HeapLattice, memarg: &MemArg) -> String {
     }
 }
 
-pub fn print_mem_access(state: &HeapLattice, access: &Value) {
+pub fn print_mem_access<Ar: RegT>(state: &HeapLattice<Ar>, access: &Value<Ar>) {
     if let Value::Mem(_, memargs) = access {
         match memargs {
             MemArgs::Mem1Arg(x) => log::debug!("mem[{:?}]", memarg_repr(state, x)),
@@ -370,6 +684,71 @@ pub fn print_mem_access(state: &HeapLattice, access: &Value) {
                 memarg_repr(state, y),
                 memarg_repr(state, z)
             ),
+            MemArgs::MemScaleDisp(x, y, z, w) => log::debug!(
+                "mem[{:?} + {:?} * {:?} + {:?}]",
+                memarg_repr(state, x),
+                memarg_repr(state, y),
+                memarg_repr(state, z),
+                memarg_repr(state, w)
+            ),
         }
     }
 }
+
+#[test]
+fn offset_in_guard_test() {
+    let lucet = HeapLayout::default();
+    assert_eq!(offset_in_guard(&lucet, 1, 0xffffffff, Size16), true);
+    assert_eq!(offset_in_guard(&lucet, 1, 0xffffffff, Size32), false);
+    assert_eq!(offset_in_guard(&lucet, 0, 0x1_0000_0000, Size64), true);
+    assert_eq!(offset_in_guard(&lucet, 2, 0, Size16), true);
+    assert_eq!(offset_in_guard(&lucet, 2, 0, Size32), false);
+    assert_eq!(offset_in_guard(&lucet, 2, -0x1000, Size64), true);
+    assert_eq!(offset_in_guard(&lucet, 2, -0x1001, Size8), false);
+    assert_eq!(offset_in_guard(&lucet, 4, -0x1000, Size8), false);
+    assert_eq!(offset_in_guard(&lucet, 8, 0, Size8), false);
+
+    // A 2GB guard region
+    let small_guard = HeapLayout {
+        guard: 0x8000_0000,
+        ..HeapLayout::default()
+    };
+    assert_eq!(offset_in_guard(&small_guard, 1, 0, Size64), true);
+    assert_eq!(offset_in_guard(&small_guard, 1, 0x7ffffff9, Size64), true);
+    assert_eq!(offset_in_guard(&small_guard, 1, 0x7ffffffa, Size64), false);
+    assert_eq!(offset_in_guard(&small_guard, 2, 0, Size8), false);
+
+    // An index masked with 0xffff
+    assert_eq!(index_in_guard(&small_guard, 0xffff, 1, 0, Size64), true);
+    assert_eq!(
+        index_in_guard(&small_guard, 0xffff, 1, -0x1001, Size64),
+        false
+    );
+}
+
+#[test]
+fn checked_offset_in_bounds_test() {
+    let no_guard = HeapLayout {
+        reservation: 0,
+        guard: 0,
+        globals_size: 0x1000,
+        vmctx_size: 0x1000,
+    };
+    assert_eq!(checked_offset_in_bounds(&no_guard, 1, 0, Size8), true);
+    assert_eq!(checked_offset_in_bounds(&no_guard, 1, 0, Size32), false);
+    assert_eq!(checked_offset_in_bounds(&no_guard, 0, 0, Size8), false);
+    assert_eq!(checked_offset_in_bounds(&no_guard, 1, -1, Size8), false);
+
+    let small_guard = HeapLayout {
+        guard: 0x1_0000,
+        ..no_guard
+    };
+    assert_eq!(
+        checked_offset_in_bounds(&small_guard, 0, 0xfff8, Size64),
+        true
+    );
+    assert_eq!(
+        checked_offset_in_bounds(&small_guard, 0, 0xfff9, Size64),
+        false
+    );
+}
@@ -1,36 +1,52 @@
 use crate::{analyses, ir, lattices};
 use analyses::{AbstractAnalyzer, AnalysisResult, SwitchAnalyzer};
 use ir::types::*;
+use ir::LiftError;
 use lattices::reachingdefslattice::LocIdx;
 use lattices::switchlattice::{SwitchLattice, SwitchValue, SwitchValueLattice};
 use std::collections::HashMap;
 use yaxpeax_core::memory::repr::process::ModuleData;
 use yaxpeax_core::memory::MemoryRepr;
 
-fn load_target<A: yaxpeax_arch::Arch, M: MemoryRepr<A>>(program: &M, addr: A::Address) -> i64 {
+// None if the entry is not in the module.
+fn load_target<A: yaxpeax_arch::Arch, M: MemoryRepr<A>>(
+    program: &M,
+    addr: A::Address,
+) -> Option<i64> {
     let mut data = [0u8; 4];
     let mut addr = addr;
     for i in 0..4 {
-        data[i] = program.read(addr).unwrap();
+        data[i] = program.read(addr)?;
         addr = addr + yaxpeax_arch::AddressDiff::one();
     }
-    u32::from_le_bytes(data) as i32 as i64
+    Some(u32::from_le_bytes(data) as i32 as i64)
 }
 
-fn extract_jmp_targets(program: &ModuleData, aval: &SwitchValueLattice) -> Vec<i64> {
+fn extract_jmp_targets(
+    program: &ModuleData,
+    aval: &SwitchValueLattice,
+) -> Result<Vec<i64>, String> {
     let mut targets: Vec<i64> = Vec::new();
     match aval.v {
         Some(SwitchValue::JmpTarget(base, upper_bound)) => {
             for idx in 0..upper_bound {
                 let addr = base + idx * 4;
-                let target = load_target::<yaxpeax_x86::x86_64, _>(program, addr.into());
+                let target = load_target::<yaxpeax_x86::x86_64, _>(program, addr.into())
+                    .ok_or_else(|| {
+                        format!("jump table entry at 0x{:x} is outside the module", addr)
+                    })?;
                 let resolved_target = ((base as i32) + (target as i32)) as i64;
                 targets.push(resolved_target);
             }
         }
-        _ => panic!("Jump Targets Broken, target = {:?}", aval.v),
+        _ => {
+            return Err(format!(
+                "the indirect jump does not go through a bounded jump table (target = {:?})",
+                aval.v
+            ))
+        }
     }
-    targets
+    Ok(targets)
 }
 
 // addr -> vec of targets
@@ -39,13 +55,13 @@ pub fn resolve_jumps(
     result: AnalysisResult<SwitchLattice>,
     irmap: &IRMap,
     analyzer: &SwitchAnalyzer,
-) -> HashMap<u64, Vec<i64>> {
+) -> Result<HashMap<u64, Vec<i64>>, LiftError> {
     let mut switch_targets: HashMap<u64, Vec<i64>> = HashMap::new();
 
     for (block_addr, mut state) in result.clone() {
         for (addr, ir_stmts) in irmap.get(&block_addr).unwrap() {
             for (idx, ir_stmt) in ir_stmts.iter().enumerate() {
-                analyzer.aexec(
+                analyzer.replay(
                     &mut state,
                     ir_stmt,
                     &LocIdx {
@@ -63,16 +79,23 @@ pub fn resolve_jumps(
                 match ir_stmt {
                     Stmt::Branch(_, Value::Reg(regnum, regsize)) => {
                         let aval = state.regs.get_reg(*regnum, *regsize);
-                        let targets = extract_jmp_targets(program, &aval);
+                        let targets =
+                            extract_jmp_targets(program, &aval).map_err(|reason| LiftError {
+                                addr: *addr,
+                                reason,
+                            })?;
                         switch_targets.insert(*addr, targets);
                     }
                     Stmt::Branch(_, Value::Mem(_, _)) => {
-                        panic!("Illegal Jump!");
+                        return Err(LiftError {
+                            addr: *addr,
+                            reason: "indirect jump through memory".to_string(),
+                        });
                     }
                     _ => (),
                 }
 
-                analyzer.aexec(
+                analyzer.replay(
                     &mut state,
                     ir_stmt,
                     &LocIdx {
@@ -83,5 +106,5 @@ pub fn resolve_jumps(
             }
         }
     }
-    switch_targets
+    Ok(switch_targets)
 }
@@ -4,7 +4,7 @@ use std::convert::TryFrom;
 
 use analyses::locals_analyzer::LocalsAnalyzer;
 use analyses::{AbstractAnalyzer, AnalysisResult};
-use checkers::Checker;
+use checkers::{CheckKind, Checker, TraceStep, Violation};
 use ir::types::*;
 use lattices::localslattice::{LocalsLattice, SlotVal};
 use lattices::reachingdefslattice::LocIdx;
@@ -25,10 +25,18 @@ pub fn check_locals(
     result: AnalysisResult<LocalsLattice>,
     irmap: &IRMap,
     analyzer: &LocalsAnalyzer,
-) -> bool {
+) -> Vec<Violation> {
     LocalsChecker { irmap, analyzer }.check(result)
 }
 
+pub fn trace_locals(
+    result: AnalysisResult<LocalsLattice>,
+    irmap: &IRMap,
+    analyzer: &LocalsAnalyzer,
+) -> Vec<TraceStep> {
+    LocalsChecker { irmap, analyzer }.trace(result)
+}
+
 fn is_noninit_illegal(v: &Value) -> bool {
     v.is_mem() && !v.is_stack_access()
 }
@@ -76,9 +84,18 @@ impl LocalsChecker<'_> {
     }
 
     // Check if callee-saved registers have been restored properly
-    // RSP and RBP are handled by stack analysis
+    // RSP and RBP are handled by stack analysis, and the pinned register is never saved
     fn regs_not_restored(&self, state: &LocalsLattice) -> bool {
-        for reg in vec![Rbx, R12, R13, R14, R15].iter() {
+        let pinned = self
+            .analyzer
+            .call_analyzer
+            .metadata
+            .heap_regs
+            .pinned_heap_base_reg();
+        for reg in vec![Rbx, R12, R13, R14, R15]
+            .iter()
+            .filter(|reg| Some(**reg) != pinned)
+        {
             let v = state.regs.get_reg(*reg, Size64);
             if v != UninitCalleeReg(*reg) {
                 return true;
@@ -89,7 +106,7 @@ impl LocalsChecker<'_> {
 }
 
 impl Checker<LocalsLattice> for LocalsChecker<'_> {
-    fn check(&self, result: AnalysisResult<LocalsLattice>) -> bool {
+    fn check(&self, result: AnalysisResult<LocalsLattice>) -> Vec<Violation> {
         self.check_state_at_statements(result)
     }
 
@@ -98,10 +115,19 @@ impl Checker<LocalsLattice> for LocalsChecker<'_> {
     }
 
     fn aexec(&self, state: &mut LocalsLattice, ir_stmt: &Stmt, loc: &LocIdx) {
-        self.analyzer.aexec(state, ir_stmt, loc)
+        self.analyzer.replay(state, ir_stmt, loc)
     }
 
-    fn check_statement(&self, state: &LocalsLattice, stmt: &Stmt, loc_idx: &LocIdx) -> bool {
+    fn kind(&self) -> CheckKind {
+        CheckKind::Locals
+    }
+
+    fn check_statement(
+        &self,
+        state: &LocalsLattice,
+        stmt: &Stmt,
+        loc_idx: &LocIdx,
+    ) -> Result<(), Violation> {
         let debug_addrs: HashSet<u64> = vec![].into_iter().collect();
         if debug_addrs.contains(&loc_idx.addr) {
             println!("~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~");
@@ -169,14 +195,17 @@ impl Checker<LocalsLattice> for LocalsChecker<'_> {
                             });
                         if let Some(n) = name {
                             if is_libcall(n) {
-                                return true;
+                                return Ok(());
                             }
                         }
                         v
                     }
                     // 4.2 Check indirect calls
                     Value::Reg(reg, sz) => {
-                        return state.regs.get_reg(*reg, *sz) == Init;
+                        if state.regs.get_reg(*reg, *sz) == Init {
+                            return Ok(());
+                        }
+                        return Err(self.violation(state, loc_idx, Some(val)));
                         // println!("Indirect call: {:?} {:?}", reg, state.regs.get_reg(*reg, *sz));
                         // self
                         // .analyzer
@@ -208,8 +237,16 @@ impl Checker<LocalsLattice> for LocalsChecker<'_> {
             println!("Darn: 0x{:x?}: {:?}", loc_idx.addr, stmt);
             // println!("", self.irmap.get(loc_idx));
             // println!("{:?}", self.analyzer.fun_type);
-            println!("----------------------------------------")
+            println!("----------------------------------------");
+            let value = match stmt {
+                Stmt::Clear(dst, _) | Stmt::Unop(_, dst, _) | Stmt::Binop(_, dst, _, _) => {
+                    Some(dst)
+                }
+                Stmt::Call(val) => Some(val),
+                _ => None,
+            };
+            return Err(self.violation(state, loc_idx, value));
         }
-        !error
+        Ok(())
     }
 }
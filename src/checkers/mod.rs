@@ -1,9 +1,10 @@
 use crate::{analyses, ir, lattices};
-use analyses::AnalysisResult;
+use analyses::{AnalysisError, AnalysisResult};
 use ir::types::*;
 use itertools::Itertools;
 use lattices::reachingdefslattice::LocIdx;
-use lattices::Lattice;
+use lattices::{Explain, Lattice};
+use std::fmt;
 
 mod call_checker;
 mod heap_checker;
@@ -12,17 +13,230 @@ pub mod locals_checker;
 mod stack_checker;
 
 /*      Public API for checker submodule      */
-pub use self::call_checker::check_calls;
-pub use self::heap_checker::check_heap;
+pub use self::call_checker::{check_calls, trace_calls};
+pub use self::heap_checker::{check_heap, heap_counterexample, trace_heap};
 pub use self::jump_resolver::resolve_jumps;
-pub use self::stack_checker::check_stack;
+pub use self::stack_checker::{check_stack, trace_stack};
 
-pub trait Checker<State: Lattice + Clone> {
-    fn check(&self, result: AnalysisResult<State>) -> bool;
-    fn irmap(&self) -> &IRMap;
-    fn aexec(&self, state: &mut State, ir_stmt: &Stmt, loc: &LocIdx);
+/// Which safety property a violation breaks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckKind {
+    Heap,
+    Stack,
+    Call,
+    Locals,
+}
+
+impl fmt::Display for CheckKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckKind::Heap => write!(f, "heap"),
+            CheckKind::Stack => write!(f, "stack"),
+            CheckKind::Call => write!(f, "call"),
+            CheckKind::Locals => write!(f, "locals"),
+        }
+    }
+}
+
+/// A register operand, named the way the disassembler prints it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegOperand {
+    pub name: String,
+    pub bits: u32,
+}
+
+impl RegOperand {
+    fn new<Ar: RegT>(reg: &Ar, size: &ValSize) -> Self {
+        RegOperand {
+            name: format!("{:?}", reg).to_lowercase(),
+            bits: size.into_bits(),
+        }
+    }
+}
+
+impl fmt::Display for RegOperand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.name, self.bits)
+    }
+}
+
+/// The operand of a violation. This is a `Value` with its registers named,
+/// so that violations of every architecture have the same type; it is only
+/// turned into text by the reports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Reg(RegOperand),
+    Imm {
+        value: i64,
+        bits: u32,
+    },
+    /// `[base + index * scale + disp]`, accessing `bits` bits.
+    Mem {
+        bits: u32,
+        base: Option<RegOperand>,
+        index: Option<RegOperand>,
+        scale: i64,
+        disp: i64,
+    },
+    RIPConst,
+}
+
+impl<Ar: RegT> From<&Value<Ar>> for Operand {
+    fn from(value: &Value<Ar>) -> Self {
+        match value {
+            Value::Reg(reg, size) => Operand::Reg(RegOperand::new(reg, size)),
+            Value::Imm(_, size, imm) => Operand::Imm {
+                value: *imm,
+                bits: size.into_bits(),
+            },
+            Value::RIPConst => Operand::RIPConst,
+            Value::Mem(size, memargs) => {
+                let (terms, scaled) = match memargs {
+                    MemArgs::Mem1Arg(arg) => (vec![arg], None),
+                    MemArgs::Mem2Args(arg1, arg2) => (vec![arg1, arg2], None),
+                    MemArgs::Mem3Args(arg1, arg2, arg3) => (vec![arg1, arg2, arg3], None),
+                    MemArgs::MemScale(arg1, arg2, arg3) => (vec![arg1], Some((arg2, arg3))),
+                    MemArgs::MemScaleDisp(arg1, arg2, arg3, arg4) => {
+                        (vec![arg1, arg4], Some((arg2, arg3)))
+                    }
+                };
+                let mut base = None;
+                let mut index = None;
+                let mut scale = 1;
+                let mut disp = 0;
+                for term in terms {
+                    match term {
+                        MemArg::Reg(reg, size) if base.is_none() => {
+                            base = Some(RegOperand::new(reg, size))
+                        }
+                        MemArg::Reg(reg, size) => index = Some(RegOperand::new(reg, size)),
+                        MemArg::Imm(_, _, imm) => disp += imm,
+                    }
+                }
+                if let Some((MemArg::Reg(reg, size), factor)) = scaled {
+                    index = Some(RegOperand::new(reg, size));
+                    if let MemArg::Imm(_, _, imm) = factor {
+                        scale = *imm;
+                    }
+                }
+                Operand::Mem {
+                    bits: size.into_bits(),
+                    base,
+                    index,
+                    scale,
+                    disp,
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Reg(reg) => write!(f, "{}", reg),
+            Operand::Imm { value, bits } => write!(f, "{:#x}:{}", value, bits),
+            Operand::Mem {
+                bits,
+                base,
+                index,
+                scale,
+                disp,
+            } => {
+                let mut terms = Vec::new();
+                if let Some(base) = base {
+                    terms.push(base.to_string());
+                }
+                if let Some(index) = index {
+                    terms.push(format!("{}*{}", index, scale));
+                }
+                if *disp != 0 || terms.is_empty() {
+                    terms.push(format!("{:#x}", disp));
+                }
+                write!(f, "[{}]:{}", terms.join(" + "), bits)
+            }
+            Operand::RIPConst => write!(f, "rip-relative constant"),
+        }
+    }
+}
 
-    fn check_state_at_statements(&self, result: AnalysisResult<State>) -> bool {
+/// A statement that failed a check, along with the abstract state the
+/// check saw when it failed.
+#[derive(Clone, Debug)]
+pub struct Violation {
+    /// Filled in by the caller; checkers only see a single function.
+    pub func_name: String,
+    pub addr: u64,
+    pub loc_idx: LocIdx,
+    pub kind: CheckKind,
+    /// The operand that failed the check, if the failure is tied to one.
+    pub value: Option<Operand>,
+    pub state: String,
+}
+
+impl Violation {
+    /// The stack violation for a stack pointer the stack analysis could not track.
+    pub fn stack_update(err: AnalysisError) -> Self {
+        match err {
+            AnalysisError::StackUpdate {
+                loc_idx,
+                value,
+                reason,
+            } => Violation {
+                func_name: String::new(),
+                addr: loc_idx.addr,
+                loc_idx,
+                kind: CheckKind::Stack,
+                value,
+                state: reason,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "0x{:x} ({}): {} violation",
+            self.addr, self.func_name, self.kind
+        )?;
+        if let Some(value) = &self.value {
+            write!(f, " at {}", value)?;
+        }
+        Ok(())
+    }
+}
+
+/// A statement of a checked function, with the abstract state before it and
+/// the violation it causes, if any.
+#[derive(Clone, Debug)]
+pub struct TraceStep {
+    pub loc_idx: LocIdx,
+    /// The state before the statement, rendered with `Explain`.
+    pub state: String,
+    pub violation: Option<Violation>,
+}
+
+pub trait Checker<State: Lattice + Clone, Ar: RegT = X86Regs> {
+    fn check(&self, result: AnalysisResult<State>) -> Vec<Violation>;
+    fn irmap(&self) -> &IRMap<Ar>;
+    fn aexec(&self, state: &mut State, ir_stmt: &Stmt<Ar>, loc: &LocIdx);
+    fn kind(&self) -> CheckKind;
+
+    fn violation(&self, state: &State, loc_idx: &LocIdx, value: Option<&Value<Ar>>) -> Violation {
+        Violation {
+            func_name: String::new(),
+            addr: loc_idx.addr,
+            loc_idx: *loc_idx,
+            kind: self.kind(),
+            value: value.map(Operand::from),
+            state: format!("{:?}", state),
+        }
+    }
+
+    fn check_state_at_statements(&self, result: AnalysisResult<State>) -> Vec<Violation> {
+        let mut violations = Vec::new();
         // for (block_addr, mut state) in result {
         //     log::debug!(
         //         "Checking block 0x{:x} with start state {:?}",
@@ -48,14 +262,77 @@ pub trait Checker<State: Lattice + Clone> {
                         addr: *addr,
                         idx: idx as u32,
                     };
-                    if !self.check_statement(&state, ir_stmt, &loc_idx) {
-                        return false;
+                    if let Err(violation) = self.check_statement(&state, ir_stmt, &loc_idx) {
+                        violations.push(violation);
                     }
                     self.aexec(&mut state, ir_stmt, &loc_idx);
                 }
             }
         }
-        true
+        violations
+    }
+
+    // Like `check_state_at_statements`, but keeps the state before every statement.
+    fn trace(&self, result: AnalysisResult<State>) -> Vec<TraceStep>
+    where
+        State: Explain,
+    {
+        let mut steps = Vec::new();
+        for block_addr in result.keys().sorted() {
+            let mut state = result[block_addr].clone();
+            for (addr, ir_stmts) in self.irmap().get(&block_addr).unwrap() {
+                for (idx, ir_stmt) in ir_stmts.iter().enumerate() {
+                    let loc_idx = LocIdx {
+                        addr: *addr,
+                        idx: idx as u32,
+                    };
+                    steps.push(TraceStep {
+                        loc_idx,
+                        state: state.explain(),
+                        violation: self.check_statement(&state, ir_stmt, &loc_idx).err(),
+                    });
+                    self.aexec(&mut state, ir_stmt, &loc_idx);
+                }
+            }
+        }
+        steps
     }
-    fn check_statement(&self, state: &State, ir_stmt: &Stmt, loc_idx: &LocIdx) -> bool;
+
+    fn check_statement(
+        &self,
+        state: &State,
+        ir_stmt: &Stmt<Ar>,
+        loc_idx: &LocIdx,
+    ) -> Result<(), Violation>;
+}
+
+#[test]
+fn operand_from_value_test() {
+    let value: Value = Value::Mem(
+        ValSize::Size32,
+        MemArgs::MemScaleDisp(
+            MemArg::Reg(X86Regs::Rdi, ValSize::Size64),
+            MemArg::Reg(X86Regs::Rsi, ValSize::Size64),
+            MemArg::Imm(ImmType::Signed, ValSize::Size32, 4),
+            MemArg::Imm(ImmType::Signed, ValSize::Size32, 0x10),
+        ),
+    );
+    let operand = Operand::from(&value);
+    assert_eq!(
+        operand,
+        Operand::Mem {
+            bits: 32,
+            base: Some(RegOperand {
+                name: "rdi".to_string(),
+                bits: 64
+            }),
+            index: Some(RegOperand {
+                name: "rsi".to_string(),
+                bits: 64
+            }),
+            scale: 4,
+            disp: 0x10,
+        }
+    );
+    assert_eq!(operand.to_string(), "[rdi:64 + rsi:64*4 + 0x10]:32");
 }
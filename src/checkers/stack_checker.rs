@@ -1,21 +1,21 @@
 use crate::{analyses, checkers, ir, lattices};
 use analyses::StackAnalyzer;
 use analyses::{AbstractAnalyzer, AnalysisResult};
-use checkers::Checker;
+use checkers::{CheckKind, Checker, TraceStep, Violation};
 use ir::types::*;
 use lattices::reachingdefslattice::LocIdx;
 use lattices::stackgrowthlattice::StackGrowthLattice;
 
-pub struct StackChecker<'a> {
-    irmap: &'a IRMap,
+pub struct StackChecker<'a, Ar: RegT> {
+    irmap: &'a IRMap<Ar>,
     analyzer: &'a StackAnalyzer,
 }
 
-pub fn check_stack(
+pub fn check_stack<Ar: RegT>(
     result: AnalysisResult<StackGrowthLattice>,
-    irmap: &IRMap,
+    irmap: &IRMap<Ar>,
     analyzer: &StackAnalyzer,
-) -> bool {
+) -> Vec<Violation> {
     StackChecker {
         irmap: irmap,
         analyzer: analyzer,
@@ -23,33 +23,48 @@ pub fn check_stack(
     .check(result)
 }
 
-impl Checker<StackGrowthLattice> for StackChecker<'_> {
-    fn check(&self, result: AnalysisResult<StackGrowthLattice>) -> bool {
+pub fn trace_stack<Ar: RegT>(
+    result: AnalysisResult<StackGrowthLattice>,
+    irmap: &IRMap<Ar>,
+    analyzer: &StackAnalyzer,
+) -> Vec<TraceStep> {
+    StackChecker {
+        irmap: irmap,
+        analyzer: analyzer,
+    }
+    .trace(result)
+}
+
+impl<Ar: RegT> Checker<StackGrowthLattice, Ar> for StackChecker<'_, Ar> {
+    fn check(&self, result: AnalysisResult<StackGrowthLattice>) -> Vec<Violation> {
         self.check_state_at_statements(result)
     }
 
-    fn irmap(&self) -> &IRMap {
+    fn irmap(&self) -> &IRMap<Ar> {
         self.irmap
     }
-    fn aexec(&self, state: &mut StackGrowthLattice, ir_stmt: &Stmt, loc: &LocIdx) {
-        self.analyzer.aexec(state, ir_stmt, loc)
+    fn aexec(&self, state: &mut StackGrowthLattice, ir_stmt: &Stmt<Ar>, loc: &LocIdx) {
+        self.analyzer.replay(state, ir_stmt, loc)
+    }
+    fn kind(&self) -> CheckKind {
+        CheckKind::Stack
     }
 
     fn check_statement(
         &self,
         state: &StackGrowthLattice,
-        ir_stmt: &Stmt,
-        _loc_idx: &LocIdx,
-    ) -> bool {
+        ir_stmt: &Stmt<Ar>,
+        loc_idx: &LocIdx,
+    ) -> Result<(), Violation> {
         //1, stackgrowth is never Bottom or >= 0
         match state.v {
             None => {
                 println!("Failure Case at {:?}: Stackgrowth = None", ir_stmt);
-                return false;
+                return Err(self.violation(state, loc_idx, None));
             }
             Some((stackgrowth, _, _)) => {
                 if stackgrowth > 0 {
-                    return false;
+                    return Err(self.violation(state, loc_idx, None));
                 }
             }
         }
@@ -67,7 +82,7 @@ impl Checker<StackGrowthLattice> for StackChecker<'_> {
                             dst,
                             state
                         );
-                        return false;
+                        return Err(self.violation(state, loc_idx, Some(dst)));
                     }
                 }
                 if dst.is_frame_access() {
@@ -77,7 +92,7 @@ impl Checker<StackGrowthLattice> for StackChecker<'_> {
                             dst,
                             state
                         );
-                        return false;
+                        return Err(self.violation(state, loc_idx, Some(dst)));
                     }
                 }
                 //stack read: probestack <= stackgrowth + c < 8K
@@ -88,7 +103,7 @@ impl Checker<StackGrowthLattice> for StackChecker<'_> {
                             src,
                             state
                         );
-                        return false;
+                        return Err(self.violation(state, loc_idx, Some(src)));
                     }
                 } else if src.is_frame_access() {
                     if !self.check_bp_read(state, src) {
@@ -97,7 +112,7 @@ impl Checker<StackGrowthLattice> for StackChecker<'_> {
                             src,
                             state
                         );
-                        return false;
+                        return Err(self.violation(state, loc_idx, Some(src)));
                     }
                 }
             }
@@ -109,17 +124,17 @@ impl Checker<StackGrowthLattice> for StackChecker<'_> {
             if let Some((stackgrowth, _, _)) = state.v {
                 if stackgrowth != 0 {
                     log::debug!("stackgrowth != 0 at ret: stackgrowth = {:?}", stackgrowth);
-                    return false;
+                    return Err(self.violation(state, loc_idx, None));
                 }
             }
         }
 
-        true
+        Ok(())
     }
 }
 
-impl StackChecker<'_> {
-    fn check_stack_read(&self, state: &StackGrowthLattice, src: &Value) -> bool {
+impl<Ar: RegT> StackChecker<'_, Ar> {
+    fn check_stack_read(&self, state: &StackGrowthLattice, src: &Value<Ar>) -> bool {
         if let Value::Mem(_, memargs) = src {
             match memargs {
                 MemArgs::Mem1Arg(_memarg) => {
@@ -138,7 +153,7 @@ impl StackChecker<'_> {
         panic!("Unreachable")
     }
 
-    fn check_bp_read(&self, state: &StackGrowthLattice, src: &Value) -> bool {
+    fn check_bp_read(&self, state: &StackGrowthLattice, src: &Value<Ar>) -> bool {
         if let Value::Mem(_, memargs) = src {
             match memargs {
                 MemArgs::Mem1Arg(_memarg) => {
@@ -156,7 +171,7 @@ impl StackChecker<'_> {
         panic!("Unreachable")
     }
 
-    fn check_stack_write(&self, state: &StackGrowthLattice, dst: &Value) -> bool {
+    fn check_stack_write(&self, state: &StackGrowthLattice, dst: &Value<Ar>) -> bool {
         if let Value::Mem(_, memargs) = dst {
             match memargs {
                 MemArgs::Mem1Arg(_memarg) => {
@@ -175,7 +190,7 @@ impl StackChecker<'_> {
         panic!("Unreachable")
     }
 
-    fn check_bp_write(&self, state: &StackGrowthLattice, dst: &Value) -> bool {
+    fn check_bp_write(&self, state: &StackGrowthLattice, dst: &Value<Ar>) -> bool {
         if let Value::Mem(_, memargs) = dst {
             match memargs {
                 MemArgs::Mem1Arg(_memarg) => {
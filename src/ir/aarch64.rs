@@ -1,11 +1,971 @@
-use crate::ir::types::Stmt;
-use crate::VwMetadata;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+
+use crate::ir::types::*;
+use crate::ir::{LiftError, Liftable};
+use crate::loaders::types::{VwMetadata, VwModule};
+use petgraph::graphmap::GraphMap;
+use yaxpeax_arch::{Decoder, U8Reader};
+use yaxpeax_arm::armv8::a64::{
+    InstDecoder, Instruction as Aarch64Instruction, Opcode, Operand, SIMDSizeCode, ShiftStyle,
+    SizeCode,
+};
+use yaxpeax_core::analyses::control_flow::{VW_Block, VW_CFG};
+use yaxpeax_x86::long_mode::Opcode as JumpOpcode;
+use Aarch64Regs::*;
+use ValSize::{Size128, Size16, Size32, Size64, Size8};
+
+const INSTR_LEN: u64 = 4;
+
+pub struct Aarch64;
+
+impl Liftable for Aarch64 {
+    type Reg = Aarch64Regs;
+    type Instruction = Aarch64Instruction;
+
+    fn lift(
+        instr: &Aarch64Instruction,
+        addr: &u64,
+        metadata: &VwMetadata,
+        strict: bool,
+    ) -> Result<Vec<Stmt<Aarch64Regs>>, LiftError> {
+        lift(instr, addr, metadata, strict)
+    }
+
+    fn lift_cfg(
+        module: &VwModule,
+        cfg: &VW_CFG,
+        strict: bool,
+    ) -> Result<IRMap<Aarch64Regs>, LiftError> {
+        let mut irmap = IRMap::new();
+        for block_addr in cfg.graph.nodes() {
+            let block = cfg.get_block(block_addr);
+            let mut block_ir: IRBlock<Aarch64Regs> = Vec::new();
+            let mut addr = block.start;
+            while addr <= block.end {
+                let stmts = match decode(module, addr)? {
+                    Some(instr) => lift(&instr, &addr, &module.metadata, strict)?,
+                    None => vec![Stmt::Undefined],
+                };
+                log::info!("Lifted block: 0x{:x} {:?}", addr, stmts);
+                block_ir.push((addr, stmts));
+                addr += INSTR_LEN;
+            }
+            irmap.insert(block_addr, block_ir);
+        }
+        Ok(irmap)
+    }
+}
+
+fn fetch(module: &VwModule, addr: u64) -> Option<[u8; 4]> {
+    for segment in module.program.segments.iter() {
+        let start = segment.start as u64;
+        let end = start + segment.data.len() as u64;
+        if addr >= start && addr + INSTR_LEN <= end {
+            let offset = (addr - start) as usize;
+            let mut word = [0; 4];
+            word.copy_from_slice(&segment.data[offset..offset + INSTR_LEN as usize]);
+            return Some(word);
+        }
+    }
+    None
+}
+
+// Returns None for `udf`, which Cranelift emits for traps. Its encoding has
+// all upper 16 bits clear.
+fn decode(module: &VwModule, addr: u64) -> Result<Option<Aarch64Instruction>, LiftError> {
+    let lift_error = |reason: String| LiftError { addr, reason };
+    let word = fetch(module, addr).ok_or_else(|| lift_error("no code".to_string()))?;
+    if word[2] == 0 && word[3] == 0 {
+        return Ok(None);
+    }
+    match InstDecoder::default().decode(&mut U8Reader::new(&word)) {
+        Ok(instr) => Ok(Some(instr)),
+        Err(err) => Err(lift_error(format!(
+            "could not decode instruction: {:?}",
+            err
+        ))),
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Flow {
+    Next,
+    Jump(u64),
+    CondJump(u64),
+    Stop,
+}
+
+fn pc_relative(op: &Operand, addr: u64) -> Result<u64, String> {
+    match op {
+        Operand::PCOffset(offset) => Ok((addr as i64 + offset) as u64),
+        _ => Err(format!("not a branch target: {:?}", op)),
+    }
+}
+
+fn control_flow(instr: Option<&Aarch64Instruction>, addr: u64) -> Result<Flow, String> {
+    let instr = match instr {
+        Some(instr) => instr,
+        None => return Ok(Flow::Stop),
+    };
+    let flow = match instr.opcode {
+        Opcode::B => Flow::Jump(pc_relative(&instr.operands[0], addr)?),
+        Opcode::Bcc(_) => Flow::CondJump(pc_relative(&instr.operands[0], addr)?),
+        Opcode::CBZ | Opcode::CBNZ => Flow::CondJump(pc_relative(&instr.operands[1], addr)?),
+        Opcode::TBZ | Opcode::TBNZ => Flow::CondJump(pc_relative(&instr.operands[2], addr)?),
+        Opcode::RET | Opcode::BRK => Flow::Stop,
+        Opcode::BR => return Err("indirect jumps are not supported on aarch64".to_string()),
+        _ => Flow::Next,
+    };
+    Ok(flow)
+}
+
+// yaxpeax-core only recovers x86-64 CFGs, so AArch64 functions are
+// recovered here by following direct branches from the entrypoint.
+// Instructions are a fixed 4 bytes, so no other disassembly is needed.
+pub fn get_cfg(module: &VwModule, entrypoint: u64) -> Result<VW_CFG, LiftError> {
+    let mut flows: BTreeMap<u64, Flow> = BTreeMap::new();
+    let mut leaders: BTreeSet<u64> = BTreeSet::new();
+    let mut worklist: Vec<u64> = vec![entrypoint];
+    leaders.insert(entrypoint);
+    while let Some(mut addr) = worklist.pop() {
+        while !flows.contains_key(&addr) {
+            let flow = control_flow(decode(module, addr)?.as_ref(), addr)
+                .map_err(|reason| LiftError { addr, reason })?;
+            flows.insert(addr, flow);
+            match flow {
+                Flow::Next => addr += INSTR_LEN,
+                Flow::Jump(target) => {
+                    leaders.insert(target);
+                    worklist.push(target);
+                    break;
+                }
+                Flow::CondJump(target) => {
+                    leaders.insert(target);
+                    leaders.insert(addr + INSTR_LEN);
+                    worklist.push(target);
+                    worklist.push(addr + INSTR_LEN);
+                    break;
+                }
+                Flow::Stop => break,
+            }
+        }
+    }
+
+    let mut cfg = VW_CFG {
+        entrypoint,
+        blocks: BTreeMap::new(),
+        graph: GraphMap::new(),
+    };
+    let mut edges: Vec<(u64, u64)> = Vec::new();
+    for &start in leaders.iter() {
+        let mut end = start;
+        loop {
+            let next = end + INSTR_LEN;
+            match flows[&end] {
+                Flow::Next if !leaders.contains(&next) => end = next,
+                Flow::Next => {
+                    edges.push((start, next));
+                    break;
+                }
+                Flow::Jump(target) => {
+                    edges.push((start, target));
+                    break;
+                }
+                Flow::CondJump(target) => {
+                    edges.push((start, target));
+                    edges.push((start, next));
+                    break;
+                }
+                Flow::Stop => break,
+            }
+        }
+        let end = end + INSTR_LEN - 1; // `end` is inclusive!
+        cfg.blocks.insert(start, VW_Block { start, end });
+        cfg.graph.add_node(start);
+    }
+    for (from, to) in edges {
+        cfg.graph.add_edge(from, to, ());
+    }
+    Ok(cfg)
+}
+
+fn convert_reg(num: u16) -> Result<Aarch64Regs, String> {
+    Aarch64Regs::try_from(num as u8)
+}
+
+fn convert_size(size: SizeCode) -> ValSize {
+    match size {
+        SizeCode::X => Size64,
+        SizeCode::W => Size32,
+    }
+}
+
+fn simd_size(size: SIMDSizeCode) -> ValSize {
+    match size {
+        SIMDSizeCode::B => Size8,
+        SIMDSizeCode::H => Size16,
+        SIMDSizeCode::S => Size32,
+        SIMDSizeCode::D => Size64,
+        SIMDSizeCode::Q => Size128,
+    }
+}
+
+// Width of the register operand of a load or store, which is the width of
+// the memory access unless the opcode says otherwise.
+fn reg_operand_size(op: &Operand) -> Result<ValSize, String> {
+    match op {
+        Operand::Register(size, _) | Operand::RegisterOrSP(size, _) => Ok(convert_size(*size)),
+        Operand::SIMDRegister(size, _) => Ok(simd_size(*size)),
+        _ => Err(format!("not a register operand: {:?}", op)),
+    }
+}
+
+// Register 31 is the zero register, except in the operands yaxpeax-arm
+// decodes as `RegisterOrSP`.
+fn convert_operand(op: &Operand) -> Result<Value<Aarch64Regs>, String> {
+    let value = match op {
+        Operand::Register(size, 31) => Value::Imm(ImmType::Unsigned, convert_size(*size), 0),
+        Operand::Register(size, num) | Operand::RegisterOrSP(size, num) => {
+            Value::Reg(convert_reg(*num)?, convert_size(*size))
+        }
+        Operand::SIMDRegister(_, num) => Value::Reg(
+            convert_reg(*num + Aarch64Regs::simd_offset() as u16)?,
+            Size128,
+        ),
+        Operand::Immediate(imm) => Value::Imm(ImmType::Unsigned, Size64, *imm as i64),
+        Operand::Imm64(imm) => Value::Imm(ImmType::Unsigned, Size64, *imm as i64),
+        Operand::Imm16(imm) => Value::Imm(ImmType::Unsigned, Size64, *imm as i64),
+        Operand::ImmShift(imm, shift) => {
+            Value::Imm(ImmType::Unsigned, Size64, (*imm as i64) << *shift)
+        }
+        // A register operand is only passed through unchanged when it is not
+        // shifted, or when a 32-bit register is zero-extended.
+        Operand::RegShift(ShiftStyle::LSL, 0, size, num) if *num != 31 => {
+            Value::Reg(convert_reg(*num)?, convert_size(*size))
+        }
+        Operand::RegShift(ShiftStyle::UXTW, 0, SizeCode::W, num) if *num != 31 => {
+            Value::Reg(convert_reg(*num)?, Size32)
+        }
+        _ => return Err(format!("unhandled operand {:?}", op)),
+    };
+    Ok(value)
+}
+
+fn is_plain_operand(op: &Operand) -> bool {
+    match op {
+        Operand::RegShift(ShiftStyle::LSL, 0, _, num) => *num != 31,
+        Operand::RegShift(ShiftStyle::UXTW, 0, SizeCode::W, num) => *num != 31,
+        Operand::RegShift(_, _, _, _) => false,
+        _ => true,
+    }
+}
+
+// Writes to the zero register are discarded.
+fn convert_dst(op: &Operand) -> Result<Option<Value<Aarch64Regs>>, String> {
+    match op {
+        Operand::Register(_, 31) => Ok(None),
+        _ => Ok(Some(convert_operand(op)?)),
+    }
+}
+
+fn register_sources(
+    instr: &Aarch64Instruction,
+    from: usize,
+) -> Result<Vec<Value<Aarch64Regs>>, String> {
+    let mut sources = Vec::new();
+    for op in instr.operands[from..].iter() {
+        match op {
+            Operand::Register(_, 31) => (),
+            Operand::Register(_, _) | Operand::RegisterOrSP(_, _) | Operand::SIMDRegister(_, _) => {
+                sources.push(convert_operand(op)?)
+            }
+            Operand::RegShift(_, _, size, num) if *num != 31 => {
+                sources.push(Value::Reg(convert_reg(*num)?, convert_size(*size)))
+            }
+            _ => (),
+        }
+    }
+    Ok(sources)
+}
+
+fn mk_imm(imm: i64) -> Value<Aarch64Regs> {
+    Value::Imm(ImmType::Signed, Size64, imm)
+}
+
+fn mk_pc_relative(target: u64) -> Value<Aarch64Regs> {
+    Value::Imm(ImmType::RIPRelative, Size64, target as i64)
+}
+
+fn adjust_base(base: Aarch64Regs, offset: i64) -> Stmt<Aarch64Regs> {
+    let base = Value::Reg(base, Size64);
+    if offset < 0 {
+        Stmt::Binop(Binopcode::Sub, base.clone(), base, mk_imm(-offset))
+    } else {
+        Stmt::Binop(Binopcode::Add, base.clone(), base, mk_imm(offset))
+    }
+}
+
+fn mem_at(base: Aarch64Regs, offset: i64, memsize: ValSize) -> Value<Aarch64Regs> {
+    let base = MemArg::Reg(base, Size64);
+    if offset == 0 {
+        Value::Mem(memsize, MemArgs::Mem1Arg(base))
+    } else {
+        Value::Mem(
+            memsize,
+            MemArgs::Mem2Args(base, MemArg::Imm(ImmType::Signed, Size64, offset)),
+        )
+    }
+}
+
+// A memory operand, split into the base-register update that happens before
+// the access, the accesses themselves and the update that happens after.
+// `count` consecutive accesses of `memsize` are made, for load/store pair.
+struct MemAccess {
+    pre: Vec<Stmt<Aarch64Regs>>,
+    values: Vec<Value<Aarch64Regs>>,
+    post: Vec<Stmt<Aarch64Regs>>,
+}
+
+fn convert_mem(op: &Operand, memsize: ValSize, count: u32) -> Result<MemAccess, String> {
+    let stride = memsize.into_bytes() as i64;
+    let consecutive = |base: Aarch64Regs, offset: i64| -> Vec<Value<Aarch64Regs>> {
+        (0..count as i64)
+            .map(|idx| mem_at(base, offset + idx * stride, memsize))
+            .collect()
+    };
+    let access = match op {
+        // `[base, #offset]`, and `[base, #offset]!` if `wback` is set
+        Operand::RegPreIndex(base, offset, wback) => {
+            let base = convert_reg(*base)?;
+            if *wback {
+                MemAccess {
+                    pre: vec![adjust_base(base, *offset as i64)],
+                    values: consecutive(base, 0),
+                    post: vec![],
+                }
+            } else {
+                MemAccess {
+                    pre: vec![],
+                    values: consecutive(base, *offset as i64),
+                    post: vec![],
+                }
+            }
+        }
+        Operand::RegPostIndex(base, offset) => {
+            let base = convert_reg(*base)?;
+            MemAccess {
+                pre: vec![],
+                values: consecutive(base, 0),
+                post: vec![adjust_base(base, *offset as i64)],
+            }
+        }
+        // `[base, wN, uxtw #k]` and `[base, xN, lsl #k]`. A sign-extended
+        // index cannot be expressed in the IR.
+        Operand::RegRegOffset(base, index, index_size, style, shift) if *index != 31 => {
+            let index_size = match (index_size, style) {
+                (SizeCode::W, ShiftStyle::UXTW) => Size32,
+                (SizeCode::X, ShiftStyle::LSL) | (SizeCode::X, ShiftStyle::UXTX) => Size64,
+                _ => return Err(format!("unsupported index extension {:?}", op)),
+            };
+            if count != 1 {
+                return Err("register offset on a load/store pair".to_string());
+            }
+            let base = MemArg::Reg(convert_reg(*base)?, Size64);
+            let index = MemArg::Reg(convert_reg(*index)?, index_size);
+            let memargs = if *shift == 0 {
+                MemArgs::Mem2Args(base, index)
+            } else {
+                MemArgs::MemScale(
+                    base,
+                    index,
+                    MemArg::Imm(ImmType::Unsigned, Size64, 1 << *shift),
+                )
+            };
+            MemAccess {
+                pre: vec![],
+                values: vec![Value::Mem(memsize, memargs)],
+                post: vec![],
+            }
+        }
+        // Literal loads read constants embedded in the code.
+        Operand::PCOffset(_) => MemAccess {
+            pre: vec![],
+            values: (0..count).map(|_| Value::RIPConst).collect(),
+            post: vec![],
+        },
+        _ => return Err(format!("unhandled memory operand {:?}", op)),
+    };
+    Ok(access)
+}
+
+fn load(
+    instr: &Aarch64Instruction,
+    opcode: Unopcode,
+    memsize: Option<ValSize>,
+    regs: usize,
+) -> Result<Vec<Stmt<Aarch64Regs>>, String> {
+    let memsize = match memsize {
+        Some(memsize) => memsize,
+        None => reg_operand_size(&instr.operands[0])?,
+    };
+    let access = convert_mem(&instr.operands[regs], memsize, regs as u32)?;
+    let mut stmts = access.pre;
+    for (op, value) in instr.operands[..regs].iter().zip(access.values) {
+        match convert_dst(op)? {
+            Some(dst) => stmts.push(Stmt::Unop(opcode.clone(), dst, value)),
+            // the loaded value is discarded, but the access is still checked
+            None => stmts.push(Stmt::Clear(value.clone(), vec![value])),
+        }
+    }
+    stmts.extend(access.post);
+    Ok(stmts)
+}
+
+fn store(
+    instr: &Aarch64Instruction,
+    memsize: Option<ValSize>,
+    regs: usize,
+) -> Result<Vec<Stmt<Aarch64Regs>>, String> {
+    let memsize = match memsize {
+        Some(memsize) => memsize,
+        None => reg_operand_size(&instr.operands[0])?,
+    };
+    let access = convert_mem(&instr.operands[regs], memsize, regs as u32)?;
+    let mut stmts = access.pre;
+    for (op, value) in instr.operands[..regs].iter().zip(access.values) {
+        stmts.push(Stmt::Unop(Unopcode::Mov, value, convert_operand(op)?));
+    }
+    stmts.extend(access.post);
+    Ok(stmts)
+}
+
+fn set_flags(
+    opcode: Binopcode,
+    src1: Value<Aarch64Regs>,
+    src2: Value<Aarch64Regs>,
+) -> Vec<Stmt<Aarch64Regs>> {
+    [Z, C, N, V]
+        .iter()
+        .map(|flag| {
+            Stmt::Binop(
+                opcode.clone(),
+                Value::Reg(*flag, Size8),
+                src1.clone(),
+                src2.clone(),
+            )
+        })
+        .collect()
+}
+
+fn clear_flags(sources: Vec<Value<Aarch64Regs>>) -> Vec<Stmt<Aarch64Regs>> {
+    [Z, C, N, V]
+        .iter()
+        .map(|flag| Stmt::Clear(Value::Reg(*flag, Size8), sources.clone()))
+        .collect()
+}
+
+fn generic_clear(instr: &Aarch64Instruction) -> Result<Vec<Stmt<Aarch64Regs>>, String> {
+    match convert_dst(&instr.operands[0])? {
+        Some(dst @ Value::Reg(_, _)) => Ok(vec![Stmt::Clear(dst, register_sources(instr, 1)?)]),
+        _ => Ok(vec![]),
+    }
+}
+
+fn is_sp(op: &Operand) -> bool {
+    matches!(op, Operand::RegisterOrSP(SizeCode::X, 31))
+}
+
+fn add_sub(
+    instr: &Aarch64Instruction,
+    opcode: Binopcode,
+) -> Result<Vec<Stmt<Aarch64Regs>>, String> {
+    let ops = &instr.operands;
+    if !is_plain_operand(&ops[2]) {
+        return generic_clear(instr);
+    }
+    // `mov` to or from sp is encoded as `add xd, xn, #0`
+    let zero = matches!(ops[2], Operand::Immediate(0) | Operand::ImmShift(0, _));
+    if (is_sp(&ops[0]) || is_sp(&ops[1])) && zero {
+        return Ok(vec![Stmt::Unop(
+            Unopcode::Mov,
+            convert_operand(&ops[0])?,
+            convert_operand(&ops[1])?,
+        )]);
+    }
+    match convert_dst(&ops[0])? {
+        Some(dst) => Ok(vec![Stmt::Binop(
+            opcode,
+            dst,
+            convert_operand(&ops[1])?,
+            convert_operand(&ops[2])?,
+        )]),
+        None => Ok(vec![]),
+    }
+}
+
+fn logical(instr: &Aarch64Instruction) -> Result<Vec<Stmt<Aarch64Regs>>, String> {
+    let ops = &instr.operands;
+    if !is_plain_operand(&ops[2]) {
+        return generic_clear(instr);
+    }
+    let dst = match convert_dst(&ops[0])? {
+        Some(dst) => dst,
+        None => return Ok(vec![]),
+    };
+    let stmt = match (instr.opcode, &ops[1]) {
+        // `mov xd, xm` and `mov xd, #imm` are encoded as `orr xd, xzr, ...`
+        (Opcode::ORR, Operand::Register(_, 31)) => {
+            Stmt::Unop(Unopcode::Mov, dst, convert_operand(&ops[2])?)
+        }
+        (Opcode::AND, _) | (Opcode::ANDS, _) => Stmt::Binop(
+            Binopcode::And,
+            dst,
+            convert_operand(&ops[1])?,
+            convert_operand(&ops[2])?,
+        ),
+        _ => Stmt::Clear(dst, register_sources(instr, 1)?),
+    };
+    Ok(vec![stmt])
+}
+
+fn mov_wide(instr: &Aarch64Instruction, invert: bool) -> Result<Vec<Stmt<Aarch64Regs>>, String> {
+    let dst = match convert_dst(&instr.operands[0])? {
+        Some(dst) => dst,
+        None => return Ok(vec![]),
+    };
+    let mut imm = convert_operand(&instr.operands[1])?.as_imm_val();
+    if invert {
+        imm = !imm;
+    }
+    if dst.get_size() == Size32 {
+        imm &= 0xffff_ffff;
+    }
+    Ok(vec![Stmt::Unop(
+        Unopcode::Mov,
+        dst,
+        Value::Imm(ImmType::Unsigned, Size64, imm),
+    )])
+}
+
+// Condition codes are expressed as the x86 jump with the same meaning.
+fn condition_to_jump(cond: u8) -> JumpOpcode {
+    match cond {
+        0 => JumpOpcode::JZ,   // eq
+        1 => JumpOpcode::JNZ,  // ne
+        2 => JumpOpcode::JNB,  // hs
+        3 => JumpOpcode::JB,   // lo
+        4 => JumpOpcode::JS,   // mi
+        5 => JumpOpcode::JNS,  // pl
+        6 => JumpOpcode::JO,   // vs
+        7 => JumpOpcode::JNO,  // vc
+        8 => JumpOpcode::JA,   // hi
+        9 => JumpOpcode::JNA,  // ls
+        10 => JumpOpcode::JGE, // ge
+        11 => JumpOpcode::JL,  // lt
+        12 => JumpOpcode::JG,  // gt
+        13 => JumpOpcode::JLE, // le
+        _ => JumpOpcode::JMP,  // al, nv
+    }
+}
+
+fn branch_offset(op: &Operand) -> Result<Value<Aarch64Regs>, String> {
+    match op {
+        Operand::PCOffset(offset) => Ok(mk_imm(*offset)),
+        _ => Err(format!("not a branch target: {:?}", op)),
+    }
+}
 
 pub fn lift(
-    instr: &yaxpeax_x86::long_mode::Instruction,
+    instr: &Aarch64Instruction,
     addr: &u64,
     metadata: &VwMetadata,
     strict: bool,
-) -> Vec<Stmt> {
-    unimplemented!()
+) -> Result<Vec<Stmt<Aarch64Regs>>, LiftError> {
+    log::debug!("lift: addr 0x{:x} instr {:?}", addr, instr);
+    lift_stmts(instr, *addr, strict).map_err(|reason| LiftError {
+        addr: *addr,
+        reason: format!("{}: {}", instr, reason),
+    })
+}
+
+fn lift_stmts(
+    instr: &Aarch64Instruction,
+    addr: u64,
+    strict: bool,
+) -> Result<Vec<Stmt<Aarch64Regs>>, String> {
+    let ops = &instr.operands;
+    let mut instrs = Vec::new();
+    match instr.opcode {
+        Opcode::LDR | Opcode::LDUR | Opcode::LDAR | Opcode::LDXR | Opcode::LDAXR => {
+            instrs.extend(load(instr, Unopcode::Mov, None, 1)?)
+        }
+        Opcode::LDRB | Opcode::LDURB | Opcode::LDARB => {
+            instrs.extend(load(instr, Unopcode::Mov, Some(Size8), 1)?)
+        }
+        Opcode::LDRH | Opcode::LDURH | Opcode::LDARH => {
+            instrs.extend(load(instr, Unopcode::Mov, Some(Size16), 1)?)
+        }
+        Opcode::LDRSB | Opcode::LDURSB => {
+            instrs.extend(load(instr, Unopcode::Movsx, Some(Size8), 1)?)
+        }
+        Opcode::LDRSH | Opcode::LDURSH => {
+            instrs.extend(load(instr, Unopcode::Movsx, Some(Size16), 1)?)
+        }
+        Opcode::LDRSW | Opcode::LDURSW => {
+            instrs.extend(load(instr, Unopcode::Movsx, Some(Size32), 1)?)
+        }
+        Opcode::LDP => instrs.extend(load(instr, Unopcode::Mov, None, 2)?),
+        Opcode::LDPSW => instrs.extend(load(instr, Unopcode::Movsx, Some(Size32), 2)?),
+
+        Opcode::STR | Opcode::STUR | Opcode::STLR => instrs.extend(store(instr, None, 1)?),
+        Opcode::STRB | Opcode::STURB | Opcode::STLRB => {
+            instrs.extend(store(instr, Some(Size8), 1)?)
+        }
+        Opcode::STRH | Opcode::STURH | Opcode::STLRH => {
+            instrs.extend(store(instr, Some(Size16), 1)?)
+        }
+        Opcode::STP => instrs.extend(store(instr, None, 2)?),
+        // stxr ws, xt, [xn]: ws receives the status
+        Opcode::STXR | Opcode::STLXR => {
+            let memsize = reg_operand_size(&ops[1])?;
+            let access = convert_mem(&ops[2], memsize, 1)?;
+            instrs.extend(access.pre);
+            for value in access.values {
+                instrs.push(Stmt::Unop(Unopcode::Mov, value, convert_operand(&ops[1])?));
+            }
+            instrs.extend(access.post);
+            instrs.extend(generic_clear(instr)?);
+        }
+
+        Opcode::ADD => instrs.extend(add_sub(instr, Binopcode::Add)?),
+        Opcode::SUB => instrs.extend(add_sub(instr, Binopcode::Sub)?),
+        Opcode::ADDS | Opcode::SUBS | Opcode::ANDS => {
+            let src1 = convert_operand(&ops[1])?;
+            match (&instr.opcode, &ops[0], is_plain_operand(&ops[2])) {
+                // cmp and tst
+                (Opcode::SUBS, Operand::Register(_, 31), true) => {
+                    instrs.extend(set_flags(Binopcode::Cmp, src1, convert_operand(&ops[2])?))
+                }
+                (Opcode::ANDS, Operand::Register(_, 31), true) => {
+                    instrs.extend(set_flags(Binopcode::Test, src1, convert_operand(&ops[2])?))
+                }
+                (Opcode::ADDS, _, _) | (Opcode::SUBS, _, _) => {
+                    let opcode = if instr.opcode == Opcode::ADDS {
+                        Binopcode::Add
+                    } else {
+                        Binopcode::Sub
+                    };
+                    instrs.extend(add_sub(instr, opcode)?);
+                    instrs.extend(clear_flags(register_sources(instr, 1)?));
+                }
+                _ => {
+                    instrs.extend(logical(instr)?);
+                    instrs.extend(clear_flags(register_sources(instr, 1)?));
+                }
+            }
+        }
+        Opcode::AND | Opcode::ORR | Opcode::ORN | Opcode::EOR | Opcode::EON | Opcode::BIC => {
+            instrs.extend(logical(instr)?)
+        }
+        Opcode::BICS => {
+            instrs.extend(logical(instr)?);
+            instrs.extend(clear_flags(register_sources(instr, 1)?));
+        }
+
+        Opcode::MOVZ => instrs.extend(mov_wide(instr, false)?),
+        Opcode::MOVN => instrs.extend(mov_wide(instr, true)?),
+        Opcode::MOVK => {
+            if let Some(dst) = convert_dst(&ops[0])? {
+                instrs.push(Stmt::Clear(dst.clone(), vec![dst]));
+            }
+        }
+
+        // Addresses of constants and jump tables in the module
+        Opcode::ADR => instrs.push(Stmt::Unop(
+            Unopcode::Mov,
+            convert_operand(&ops[0])?,
+            mk_pc_relative(pc_relative(&ops[1], addr)?),
+        )),
+        Opcode::ADRP => instrs.push(Stmt::Unop(
+            Unopcode::Mov,
+            convert_operand(&ops[0])?,
+            mk_pc_relative(pc_relative(&ops[1], addr & !0xfff)?),
+        )),
+
+        Opcode::CCMN | Opcode::CCMP => instrs.extend(clear_flags(register_sources(instr, 0)?)),
+        Opcode::FCMP => instrs.extend(clear_flags(register_sources(instr, 0)?)),
+
+        Opcode::B => instrs.push(Stmt::Branch(JumpOpcode::JMP, branch_offset(&ops[0])?)),
+        Opcode::Bcc(cond) => instrs.push(Stmt::Branch(
+            condition_to_jump(cond),
+            branch_offset(&ops[0])?,
+        )),
+        // `cbz`/`tbz` do not set the flags; lift them as a compare on the register so the
+        // branch is refined on the register it tests.
+        Opcode::CBZ | Opcode::CBNZ => {
+            instrs.push(Stmt::Binop(
+                Binopcode::Cmp,
+                Value::Reg(Z, Size8),
+                convert_operand(&ops[0])?,
+                mk_imm(0),
+            ));
+            instrs.push(Stmt::Branch(
+                if instr.opcode == Opcode::CBZ {
+                    JumpOpcode::JZ
+                } else {
+                    JumpOpcode::JNZ
+                },
+                branch_offset(&ops[1])?,
+            ));
+        }
+        Opcode::TBZ | Opcode::TBNZ => {
+            let bit = match ops[1] {
+                Operand::Imm16(bit) => bit as i64,
+                _ => return Err(format!("unhandled bit operand {:?}", ops[1])),
+            };
+            instrs.push(Stmt::Binop(
+                Binopcode::Test,
+                Value::Reg(Z, Size8),
+                convert_operand(&ops[0])?,
+                mk_imm(1 << bit),
+            ));
+            instrs.push(Stmt::Branch(
+                if instr.opcode == Opcode::TBZ {
+                    JumpOpcode::JZ
+                } else {
+                    JumpOpcode::JNZ
+                },
+                branch_offset(&ops[2])?,
+            ));
+        }
+        Opcode::BR => instrs.push(Stmt::Branch(JumpOpcode::JMP, convert_operand(&ops[0])?)),
+        Opcode::BL => instrs.push(Stmt::Call(branch_offset(&ops[0])?)),
+        Opcode::BLR => instrs.push(Stmt::Call(convert_operand(&ops[0])?)),
+        Opcode::RET => instrs.push(Stmt::Ret),
+        Opcode::BRK => instrs.push(Stmt::Undefined),
+
+        Opcode::HINT | Opcode::DMB(_) | Opcode::DSB(_) | Opcode::ISB | Opcode::MSR => (),
+
+        Opcode::UBFM
+        | Opcode::SBFM
+        | Opcode::BFM
+        | Opcode::EXTR
+        | Opcode::CSEL
+        | Opcode::CSINC
+        | Opcode::CSINV
+        | Opcode::CSNEG
+        | Opcode::MADD
+        | Opcode::MSUB
+        | Opcode::SMADDL
+        | Opcode::UMADDL
+        | Opcode::SMULH
+        | Opcode::UMULH
+        | Opcode::UDIV
+        | Opcode::SDIV
+        | Opcode::LSLV
+        | Opcode::LSRV
+        | Opcode::ASRV
+        | Opcode::RORV
+        | Opcode::RBIT
+        | Opcode::REV16
+        | Opcode::REV
+        | Opcode::REV32
+        | Opcode::CLZ
+        | Opcode::CLS
+        | Opcode::ADC
+        | Opcode::SBC
+        | Opcode::MRS
+        | Opcode::FMOV
+        | Opcode::FADD
+        | Opcode::FSUB
+        | Opcode::FMUL
+        | Opcode::FDIV
+        | Opcode::SCVTF
+        | Opcode::UCVTF
+        | Opcode::FCVTZS
+        | Opcode::FCVTZU => instrs.extend(generic_clear(instr)?),
+
+        Opcode::ADCS | Opcode::SBCS => {
+            instrs.extend(generic_clear(instr)?);
+            instrs.extend(clear_flags(register_sources(instr, 1)?));
+        }
+
+        _ => {
+            if strict {
+                return Err("unsupported opcode".to_string());
+            } else {
+                instrs.extend(generic_clear(instr)?)
+            }
+        }
+    };
+    Ok(instrs)
+}
+
+#[cfg(test)]
+fn lift_bytes(bytes: &[u8]) -> Vec<(u64, Vec<Stmt<Aarch64Regs>>)> {
+    use crate::{HeapLayout, HeapRegs};
+
+    let metadata = VwMetadata {
+        guest_table_0: 0,
+        lucet_tables: 0,
+        lucet_probestack: 0,
+        heap_layout: HeapLayout::default(),
+        heap_regs: HeapRegs::default(),
+    };
+    bytes
+        .chunks(INSTR_LEN as usize)
+        .enumerate()
+        .map(|(idx, word)| {
+            let addr = idx as u64 * INSTR_LEN;
+            let instr = InstDecoder::default()
+                .decode(&mut U8Reader::new(word))
+                .unwrap();
+            (addr, lift(&instr, &addr, &metadata, true).unwrap())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn check_heap_bytes(bytes: &[u8]) -> usize {
+    use crate::analyses::{run_worklist, HeapAnalyzer};
+    use crate::checkers::check_heap;
+    use crate::{HeapLayout, HeapRegs, HeapStrategy};
+    use std::collections::HashMap;
+
+    let mut cfg = VW_CFG {
+        entrypoint: 0,
+        blocks: BTreeMap::new(),
+        graph: GraphMap::new(),
+    };
+    let end = bytes.len() as u64 - 1;
+    cfg.blocks.insert(0, VW_Block { start: 0, end });
+    cfg.graph.add_node(0);
+    let mut irmap: IRMap<Aarch64Regs> = IRMap::new();
+    irmap.insert(0, lift_bytes(bytes));
+    let analyzer = HeapAnalyzer {
+        metadata: VwMetadata {
+            guest_table_0: 0,
+            lucet_tables: 0,
+            lucet_probestack: 0,
+            heap_layout: HeapLayout::default(),
+            heap_regs: HeapRegs::default(),
+        },
+        strategy: HeapStrategy::HeapPtrFirstArgWithGuards,
+        summaries: Default::default(),
+    };
+    let result = run_worklist(&cfg, &irmap, &analyzer).unwrap();
+    check_heap(result, &irmap, &analyzer, &HashMap::new()).len()
+}
+
+#[test]
+fn lift_prologue_test() {
+    // stp x29, x30, [sp, #-16]!
+    let stmts = lift_bytes(&[0xfd, 0x7b, 0xbf, 0xa9]).remove(0).1;
+    match &stmts[..] {
+        [Stmt::Binop(
+            Binopcode::Sub,
+            Value::Reg(Sp, Size64),
+            Value::Reg(Sp, Size64),
+            Value::Imm(_, _, 16),
+        ), Stmt::Unop(
+            Unopcode::Mov,
+            Value::Mem(Size64, MemArgs::Mem1Arg(MemArg::Reg(Sp, Size64))),
+            Value::Reg(X29, Size64),
+        ), Stmt::Unop(
+            Unopcode::Mov,
+            Value::Mem(Size64, MemArgs::Mem2Args(MemArg::Reg(Sp, Size64), MemArg::Imm(_, _, 8))),
+            Value::Reg(X30, Size64),
+        )] => (),
+        _ => panic!("Unexpected lifting: {:?}", stmts),
+    }
+}
+
+#[test]
+fn lift_uxtw_index_test() {
+    // ldr w0, [x1, w2, uxtw]
+    let stmts = lift_bytes(&[0x20, 0x48, 0x62, 0xb8]).remove(0).1;
+    match &stmts[..] {
+        [Stmt::Unop(
+            Unopcode::Mov,
+            Value::Reg(X0, Size32),
+            Value::Mem(Size32, MemArgs::Mem2Args(MemArg::Reg(X1, Size64), MemArg::Reg(X2, Size32))),
+        )] => (),
+        _ => panic!("Unexpected lifting: {:?}", stmts),
+    }
+}
+
+#[test]
+fn heap_uxtw_index_test() {
+    // ldr w1, [x0, w2, uxtw]; ret
+    assert_eq!(
+        check_heap_bytes(&[0x01, 0x48, 0x62, 0xb8, 0xc0, 0x03, 0x5f, 0xd6]),
+        0
+    );
+    // ldr w1, [x0, x2]; ret
+    assert_eq!(
+        check_heap_bytes(&[0x01, 0x68, 0x62, 0xb8, 0xc0, 0x03, 0x5f, 0xd6]),
+        1
+    );
+}
+
+#[test]
+fn lift_offset_and_cbz_test() {
+    // ldr x0, [x1, #8]
+    let stmts = lift_bytes(&[0x20, 0x04, 0x40, 0xf9]).remove(0).1;
+    match &stmts[..] {
+        [Stmt::Unop(
+            Unopcode::Mov,
+            Value::Reg(X0, Size64),
+            Value::Mem(Size64, MemArgs::Mem2Args(MemArg::Reg(X1, Size64), MemArg::Imm(_, _, 8))),
+        )] => (),
+        _ => panic!("Unexpected lifting: {:?}", stmts),
+    }
+    // cbz x0, #8
+    let stmts = lift_bytes(&[0x40, 0x00, 0x00, 0xb4]).remove(0).1;
+    match &stmts[..] {
+        [Stmt::Binop(
+            Binopcode::Cmp,
+            Value::Reg(Z, Size8),
+            Value::Reg(X0, Size64),
+            Value::Imm(_, _, 0),
+        ), Stmt::Branch(JumpOpcode::JZ, _)] => (),
+        _ => panic!("Unexpected lifting: {:?}", stmts),
+    }
+}
+
+#[test]
+fn lift_adr_test() {
+    // adr x1, #16
+    let stmts = lift_bytes(&[0x81, 0x00, 0x00, 0x10]).remove(0).1;
+    match &stmts[..] {
+        [Stmt::Unop(
+            Unopcode::Mov,
+            Value::Reg(X1, Size64),
+            Value::Imm(ImmType::RIPRelative, Size64, 0x10),
+        )] => (),
+        _ => panic!("Unexpected lifting: {:?}", stmts),
+    }
+    // adr x1, #16; ldr w0, [x1]; ret
+    assert_eq!(
+        check_heap_bytes(&[0x81, 0x00, 0x00, 0x10, 0x20, 0x00, 0x40, 0xb9, 0xc0, 0x03, 0x5f, 0xd6]),
+        0
+    );
+    // adrp x1, #0x1000; add x1, x1, #16; ldr w0, [x1]; ret
+    assert_eq!(
+        check_heap_bytes(&[
+            0x01, 0x00, 0x00, 0xb0, 0x21, 0x40, 0x00, 0x91, 0x20, 0x00, 0x40, 0xb9, 0xc0, 0x03,
+            0x5f, 0xd6
+        ]),
+        0
+    );
+}
+
+#[test]
+fn indirect_jump_test() {
+    // br x16
+    let instr = InstDecoder::default()
+        .decode(&mut U8Reader::new(&[0x00, 0x02, 0x1f, 0xd6]))
+        .unwrap();
+    assert!(control_flow(Some(&instr), 0x40).is_err());
 }
@@ -1,10 +1,10 @@
 use crate::{analyses, checkers, ir, loaders};
 use analyses::reaching_defs::analyze_reaching_defs;
 use analyses::reaching_defs::ReachingDefnAnalyzer;
-use analyses::{run_worklist, SwitchAnalyzer};
+use analyses::{run_worklist, AnalysisError, SwitchAnalyzer};
 use checkers::resolve_jumps;
-use ir::lift_cfg;
 use ir::types::*;
+use ir::{lift_cfg, Aarch64, LiftError, X64};
 use loaders::types::VwModule;
 use yaxpeax_core::analyses::control_flow::{get_cfg, VW_CFG};
 use yaxpeax_core::arch::x86_64::MergedContextTable;
@@ -32,9 +32,10 @@ fn try_resolve_jumps(
     irmap: &IRMap,
     _addr: u64,
     strict: bool,
-) -> (VW_CFG, IRMap, i32, u32) {
+) -> Result<(VW_CFG, IRMap, i32, u32), LiftError> {
     println!("Performing a reaching defs pass");
-    let reaching_defs = analyze_reaching_defs(cfg, &irmap, module.metadata.clone());
+    let reaching_defs =
+        analyze_reaching_defs(cfg, &irmap, module.metadata.clone()).map_err(analysis_error)?;
     println!("Performing a jump resolution pass");
     let switch_analyzer = SwitchAnalyzer {
         metadata: module.metadata.clone(),
@@ -44,8 +45,8 @@ fn try_resolve_jumps(
             irmap: irmap.clone(),
         },
     };
-    let switch_results = run_worklist(cfg, irmap, &switch_analyzer);
-    let switch_targets = resolve_jumps(&module.program, switch_results, &irmap, &switch_analyzer);
+    let switch_results = run_worklist(cfg, irmap, &switch_analyzer).map_err(analysis_error)?;
+    let switch_targets = resolve_jumps(&module.program, switch_results, &irmap, &switch_analyzer)?;
 
     let (new_cfg, still_unresolved) = get_cfg(
         &module.program,
@@ -53,9 +54,18 @@ fn try_resolve_jumps(
         cfg.entrypoint,
         Some(&switch_targets),
     );
-    let irmap = lift_cfg(module, &new_cfg, strict);
+    let irmap = lift_cfg::<X64>(module, &new_cfg, strict)?;
     let num_targets = switch_targets.len();
-    return (new_cfg, irmap, num_targets as i32, still_unresolved);
+    Ok((new_cfg, irmap, num_targets as i32, still_unresolved))
+}
+
+// The analyses that resolve jumps fail where the code moves the stack pointer by an unknown
+// amount.
+fn analysis_error(err: AnalysisError) -> LiftError {
+    LiftError {
+        addr: err.loc_idx().addr,
+        reason: err.reason().to_string(),
+    }
 }
 
 fn resolve_cfg(
@@ -65,23 +75,26 @@ fn resolve_cfg(
     orig_irmap: &IRMap,
     addr: u64,
     strict: bool,
-) -> (VW_CFG, IRMap) {
+) -> Result<(VW_CFG, IRMap), LiftError> {
     let (mut cfg, mut irmap, mut resolved_switches, mut still_unresolved) =
-        try_resolve_jumps(module, contexts, cfg, orig_irmap, addr, strict);
+        try_resolve_jumps(module, contexts, cfg, orig_irmap, addr, strict)?;
     while still_unresolved != 0 {
         let (new_cfg, new_irmap, new_resolved_switches, new_still_unresolved) =
-            try_resolve_jumps(module, contexts, &cfg, &irmap, addr, strict);
+            try_resolve_jumps(module, contexts, &cfg, &irmap, addr, strict)?;
         cfg = new_cfg;
         irmap = new_irmap;
         if (new_resolved_switches == resolved_switches) && (new_still_unresolved != 0) {
-            panic!("Fixed Point Error");
+            return Err(LiftError {
+                addr,
+                reason: format!("{} indirect jumps cannot be resolved", new_still_unresolved),
+            });
         }
         resolved_switches = new_resolved_switches;
         still_unresolved = new_still_unresolved;
     }
     assert_eq!(cfg.graph.node_count(), irmap.keys().len());
     assert_eq!(still_unresolved, 0);
-    (cfg, irmap)
+    Ok((cfg, irmap))
 }
 
 pub fn fully_resolved_cfg(
@@ -89,11 +102,21 @@ pub fn fully_resolved_cfg(
     contexts: &MergedContextTable,
     addr: u64,
     strict: bool,
-) -> (VW_CFG, IRMap) {
+) -> Result<(VW_CFG, IRMap), LiftError> {
     let (cfg, _) = get_cfg(&module.program, contexts, addr, None);
-    let irmap = lift_cfg(module, &cfg, strict);
+    let irmap = lift_cfg::<X64>(module, &cfg, strict)?;
     if !has_indirect_jumps(&irmap) {
-        return (cfg, irmap);
+        return Ok((cfg, irmap));
     }
-    return resolve_cfg(module, contexts, &cfg, &irmap, addr, strict);
+    resolve_cfg(module, contexts, &cfg, &irmap, addr, strict)
+}
+
+pub fn fully_resolved_aarch64_cfg(
+    module: &VwModule,
+    addr: u64,
+    strict: bool,
+) -> Result<(VW_CFG, IRMap<Aarch64Regs>), LiftError> {
+    let cfg = ir::aarch64::get_cfg(module, addr)?;
+    let irmap = lift_cfg::<Aarch64>(module, &cfg, strict)?;
+    Ok((cfg, irmap))
 }
@@ -4,34 +4,51 @@ pub mod types;
 //pub mod utils;
 mod x64;
 
-pub use self::cfg::fully_resolved_cfg;
-pub use self::x64::lift_cfg;
-use crate::ir::types::Stmt;
-use crate::loaders::types::VwArch;
+pub use self::aarch64::Aarch64;
+pub use self::cfg::{fully_resolved_aarch64_cfg, fully_resolved_cfg};
+pub use self::x64::{audit_cfg, disassemble_cfg, LiftKind, X64};
+use crate::ir::types::{IRMap, RegT, Stmt};
+use crate::loaders::types::VwModule;
 use crate::VwMetadata;
-use core::str::FromStr;
+use std::fmt;
+use yaxpeax_core::analyses::control_flow::VW_CFG;
 
+/// Why the code at `addr` could not be lifted or its CFG recovered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LiftError {
+    pub addr: u64,
+    pub reason: String,
+}
+
+impl fmt::Display for LiftError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:x}: {}", self.addr, self.reason)
+    }
+}
+
+/// An instruction set that can be lifted into the IR.
 pub trait Liftable {
+    type Reg: RegT;
+    type Instruction;
+
     fn lift(
-        &self,
-        instr: &yaxpeax_x86::long_mode::Instruction,
+        instr: &Self::Instruction,
         addr: &u64,
         metadata: &VwMetadata,
         strict: bool,
-    ) -> Vec<Stmt>;
-}
-// TODO: make static dispatch
-impl Liftable for VwArch {
-    fn lift(
-        &self,
-        instr: &yaxpeax_x86::long_mode::Instruction,
-        addr: &u64,
-        metadata: &VwMetadata,
+    ) -> Result<Vec<Stmt<Self::Reg>>, LiftError>;
+
+    fn lift_cfg(
+        module: &VwModule,
+        cfg: &VW_CFG,
         strict: bool,
-    ) -> Vec<Stmt> {
-        match self {
-            VwArch::X64 => x64::lift(instr, addr, metadata, strict),
-            VwArch::Aarch64 => aarch64::lift(instr, addr, metadata, strict),
-        }
-    }
+    ) -> Result<IRMap<Self::Reg>, LiftError>;
+}
+
+pub fn lift_cfg<A: Liftable>(
+    module: &VwModule,
+    cfg: &VW_CFG,
+    strict: bool,
+) -> Result<IRMap<A::Reg>, LiftError> {
+    A::lift_cfg(module, cfg, strict)
 }
@@ -8,6 +8,8 @@ use std::marker::PhantomData;
 pub enum ImmType {
     Signed,
     Unsigned,
+    // An address the lifter computed from a rip-relative operand, as in `lea reg, [rip + c]`
+    RIPRelative,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
@@ -84,19 +86,20 @@ impl ValSize {
 }
 
 #[derive(Debug, Clone)]
-pub enum MemArgs {
-    Mem1Arg(MemArg),                  // [arg]
-    Mem2Args(MemArg, MemArg),         // [arg1 + arg2]
-    Mem3Args(MemArg, MemArg, MemArg), // [arg1 + arg2 + arg3]
-    MemScale(MemArg, MemArg, MemArg), // [arg1 + arg2 * arg3]
+pub enum MemArgs<Ar: RegT = X86Regs> {
+    Mem1Arg(MemArg<Ar>),                                          // [arg]
+    Mem2Args(MemArg<Ar>, MemArg<Ar>),                             // [arg1 + arg2]
+    Mem3Args(MemArg<Ar>, MemArg<Ar>, MemArg<Ar>),                 // [arg1 + arg2 + arg3]
+    MemScale(MemArg<Ar>, MemArg<Ar>, MemArg<Ar>),                 // [arg1 + arg2 * arg3]
+    MemScaleDisp(MemArg<Ar>, MemArg<Ar>, MemArg<Ar>, MemArg<Ar>), // [arg1 + arg2 * arg3 + arg4]
 }
 #[derive(Debug, Clone)]
-pub enum MemArg {
-    Reg(X86Regs, ValSize),      // register mappings captured in `crate::lattices`
+pub enum MemArg<Ar: RegT = X86Regs> {
+    Reg(Ar, ValSize),           // register mappings captured in `crate::lattices`
     Imm(ImmType, ValSize, i64), // signed, size, const
 }
 
-impl MemArgs {
+impl<Ar: RegT> MemArgs<Ar> {
     fn add_imm(&self, imm: i64) -> Self {
         match self {
             MemArgs::Mem1Arg(arg) => {
@@ -111,17 +114,18 @@ impl MemArgs {
             MemArgs::Mem1Arg(_memarg) => 0,
             MemArgs::Mem2Args(_memarg1, memarg2) => memarg2.to_imm(),
             MemArgs::Mem3Args(_memarg1, _memarg2, _memarg3)
-            | MemArgs::MemScale(_memarg1, _memarg2, _memarg3) => {
+            | MemArgs::MemScale(_memarg1, _memarg2, _memarg3)
+            | MemArgs::MemScaleDisp(_memarg1, _memarg2, _memarg3, _) => {
                 panic!("extract_stack_offset failed")
             }
         }
     }
 }
 
-impl TryFrom<Value> for MemArg {
+impl<Ar: RegT> TryFrom<Value<Ar>> for MemArg<Ar> {
     type Error = &'static str;
 
-    fn try_from(v: Value) -> Result<Self, Self::Error> {
+    fn try_from(v: Value<Ar>) -> Result<Self, Self::Error> {
         match v {
             Value::Reg(r, sz) => Ok(Self::Reg(r, sz)),
             Value::Mem(_, _) => Err("Memargs cannot be nested"),
@@ -131,7 +135,7 @@ impl TryFrom<Value> for MemArg {
     }
 }
 
-impl MemArg {
+impl<Ar: RegT> MemArg<Ar> {
     pub fn is_imm(&self) -> bool {
         matches!(self, Self::Imm(_, _, _))
     }
@@ -163,7 +167,7 @@ impl MemArg {
         }
     }
 
-    pub fn to_reg(&self) -> X86Regs {
+    pub fn to_reg(&self) -> Ar {
         match self {
             Self::Reg(r, _) => *r,
             _ => panic!("That's not a reg!"),
@@ -172,14 +176,14 @@ impl MemArg {
 }
 
 #[derive(Debug, Clone)]
-pub enum Value {
-    Mem(ValSize, MemArgs), // mem[memargs]
-    Reg(X86Regs, ValSize),
+pub enum Value<Ar: RegT = X86Regs> {
+    Mem(ValSize, MemArgs<Ar>), // mem[memargs]
+    Reg(Ar, ValSize),
     Imm(ImmType, ValSize, i64), // signed, size, const
     RIPConst,
 }
 
-impl Value {
+impl<Ar: RegT> Value<Ar> {
     pub fn is_mem(&self) -> bool {
         matches!(self, Self::Mem(_, _))
     }
@@ -199,14 +203,14 @@ impl Value {
         }
     }
 
-    pub fn to_reg(&self) -> X86Regs {
+    pub fn to_reg(&self) -> Ar {
         match self {
             Self::Reg(r, _) => *r,
             _ => panic!("That's not a reg!"),
         }
     }
 
-    pub fn to_mem(&self) -> MemArgs {
+    pub fn to_mem(&self) -> MemArgs<Ar> {
         match self {
             Self::Mem(_, memargs) => memargs.clone(),
             _ => panic!("That's not a reg!"),
@@ -257,7 +261,9 @@ impl Value {
             match memargs {
                 Mem1Arg(memarg) => return memarg.is_rsp(),
                 Mem2Args(memarg1, memarg2) => return memarg1.is_rsp() || memarg2.is_rsp(),
-                Mem3Args(memarg1, memarg2, memarg3) | MemScale(memarg1, memarg2, memarg3) => {
+                Mem3Args(memarg1, memarg2, memarg3)
+                | MemScale(memarg1, memarg2, memarg3)
+                | MemScaleDisp(memarg1, memarg2, memarg3, _) => {
                     return memarg1.is_rsp() || memarg2.is_rsp() || memarg3.is_rsp()
                 } // MemArgs::MemScale(memarg1, memarg2, memarg3) => {
                   //     return memarg1.is_rsp()
@@ -275,7 +281,9 @@ impl Value {
             match memargs {
                 Mem1Arg(memarg) => return memarg.is_rbp(),
                 Mem2Args(memarg1, memarg2) => return memarg1.is_rbp() || memarg2.is_rbp(),
-                Mem3Args(memarg1, memarg2, memarg3) | MemScale(memarg1, memarg2, memarg3) => {
+                Mem3Args(memarg1, memarg2, memarg3)
+                | MemScale(memarg1, memarg2, memarg3)
+                | MemScaleDisp(memarg1, memarg2, memarg3, _) => {
                     return memarg1.is_rbp() || memarg2.is_rbp() || memarg3.is_rbp()
                 } // MemArgs::MemScale(memarg1, memarg2, memarg3) => {
                   //     return memarg1.is_rbp()
@@ -288,14 +296,14 @@ impl Value {
     }
 }
 
-impl From<i64> for Value {
+impl<Ar: RegT> From<i64> for Value<Ar> {
     fn from(num: i64) -> Self {
         Self::Imm(ImmType::Signed, Size64, num)
     }
 }
 
-impl From<MemArg> for Value {
-    fn from(arg: MemArg) -> Self {
+impl<Ar: RegT> From<MemArg<Ar>> for Value<Ar> {
+    fn from(arg: MemArg<Ar>) -> Self {
         match arg {
             MemArg::Reg(r, sz) => Self::Reg(r, sz),
             MemArg::Imm(ty, imm, sz) => Self::Imm(ty, imm, sz),
@@ -303,17 +311,19 @@ impl From<MemArg> for Value {
     }
 }
 
-// Parameterized by architecture register set
+// Parameterized by architecture register set.
+// Branch conditions are expressed with the x86 jump opcodes; other
+// architectures map their condition codes onto the equivalent jump.
 #[derive(Debug, Clone)]
-pub enum Stmt {
-    Clear(Value, Vec<Value>),                      // clear v <- vs
-    Unop(Unopcode, Value, Value),                  // v1 <- uop v2
-    Binop(Binopcode, Value, Value, Value),         // v1 <- bop v2 v3
-    Undefined,                                     // undefined
-    Ret,                                           // return
-    Branch(yaxpeax_x86::long_mode::Opcode, Value), // br branch-type v
-    Call(Value),                                   // call v
-    ProbeStack(u64),                               // probestack
+pub enum Stmt<Ar: RegT = X86Regs> {
+    Clear(Value<Ar>, Vec<Value<Ar>>),                  // clear v <- vs
+    Unop(Unopcode, Value<Ar>, Value<Ar>),              // v1 <- uop v2
+    Binop(Binopcode, Value<Ar>, Value<Ar>, Value<Ar>), // v1 <- bop v2 v3
+    Undefined,                                         // undefined
+    Ret,                                               // return
+    Branch(yaxpeax_x86::long_mode::Opcode, Value<Ar>), // br branch-type v
+    Call(Value<Ar>),                                   // call v
+    ProbeStack(u64),                                   // probestack
 }
 
 #[derive(Debug, Clone)]
@@ -332,8 +342,8 @@ pub enum Binopcode {
     Sub,
 }
 
-pub type IRBlock = Vec<(u64, Vec<Stmt>)>;
-pub type IRMap = HashMap<u64, IRBlock>;
+pub type IRBlock<Ar = X86Regs> = Vec<(u64, Vec<Stmt<Ar>>)>;
+pub type IRMap<Ar = X86Regs> = HashMap<u64, IRBlock<Ar>>;
 
 #[derive(Clone, Debug)]
 pub enum VarIndex {
@@ -404,13 +414,13 @@ impl X86Regs {
 //Phantom data denotes what type of register we are iterating over,
 // This allows us to use same Iterator and struct impl for all types of registers
 // without having an unused type parameter
-pub struct RegsIterator {
+pub struct RegsIterator<Ar: RegT> {
     current_reg: u8,
-    //reg_type: PhantomData,
+    reg_type: PhantomData<Ar>,
 }
 
-impl Iterator for RegsIterator {
-    type Item = X86Regs;
+impl<Ar: RegT> Iterator for RegsIterator<Ar> {
+    type Item = Ar;
 
     fn next(&mut self) -> Option<Self::Item> {
         let next_reg = self.current_reg + 1;
@@ -446,6 +456,7 @@ impl RegT for X86Regs {
         Rdi
     }
 
+    // System V: rax, rcx, rdx, rsi, rdi, r8-r11 and the flags are clobbered by calls.
     fn is_caller_saved(&self) -> bool {
         match self {
             Rax | Rcx | Rdx | Rsi | Rdi | R8 | R9 | R10 | R11 | Zf | Cf | Pf | Sf | Of => true,
@@ -507,6 +518,149 @@ impl From<X86Regs> for u8 {
     }
 }
 
+// General purpose registers are numbered as in the instruction encoding;
+// register 31 is the stack pointer (the zero register is lifted to an
+// immediate instead).
+#[derive(PartialEq, PartialOrd, Clone, Eq, Debug, Copy, Hash)]
+pub enum Aarch64Regs {
+    X0,
+    X1,
+    X2,
+    X3,
+    X4,
+    X5,
+    X6,
+    X7,
+    X8,
+    X9,
+    X10,
+    X11,
+    X12,
+    X13,
+    X14,
+    X15,
+    X16,
+    X17,
+    X18,
+    X19,
+    X20,
+    X21,
+    X22,
+    X23,
+    X24,
+    X25,
+    X26,
+    X27,
+    X28,
+    X29,
+    X30,
+    Sp,
+    N,
+    Z,
+    C,
+    V,
+    V0,
+    V1,
+    V2,
+    V3,
+    V4,
+    V5,
+    V6,
+    V7,
+    V8,
+    V9,
+    V10,
+    V11,
+    V12,
+    V13,
+    V14,
+    V15,
+    V16,
+    V17,
+    V18,
+    V19,
+    V20,
+    V21,
+    V22,
+    V23,
+    V24,
+    V25,
+    V26,
+    V27,
+    V28,
+    V29,
+    V30,
+    V31,
+}
+
+impl Aarch64Regs {
+    pub fn is_flag(self) -> bool {
+        match self {
+            Aarch64Regs::N | Aarch64Regs::Z | Aarch64Regs::C | Aarch64Regs::V => true,
+            _ => false,
+        }
+    }
+
+    pub fn simd_offset() -> u8 {
+        u8::from(Aarch64Regs::V0)
+    }
+}
+
+impl RegT for Aarch64Regs {
+    fn is_rsp(&self) -> bool {
+        self == &Aarch64Regs::Sp
+    }
+
+    // x29 is the frame pointer
+    fn is_rbp(&self) -> bool {
+        self == &Aarch64Regs::X29
+    }
+
+    fn is_zf(&self) -> bool {
+        self == &Aarch64Regs::Z
+    }
+
+    fn pinned_heap_reg() -> Self {
+        Aarch64Regs::X0
+    }
+
+    fn pinned_vmctx_reg() -> Self {
+        Aarch64Regs::X0
+    }
+
+    // AAPCS64: x0-x18 and the link register are clobbered by calls. Only the
+    // low halves of v8-v15 are preserved, so all vector registers are treated
+    // as clobbered.
+    fn is_caller_saved(&self) -> bool {
+        let idx = u8::from(*self);
+        idx <= 18 || *self == Aarch64Regs::X30 || self.is_flag() || idx >= Self::simd_offset()
+    }
+}
+
+impl TryFrom<u8> for Aarch64Regs {
+    type Error = std::string::String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        use self::Aarch64Regs::*;
+        const REGS: [Aarch64Regs; 68] = [
+            X0, X1, X2, X3, X4, X5, X6, X7, X8, X9, X10, X11, X12, X13, X14, X15, X16, X17, X18,
+            X19, X20, X21, X22, X23, X24, X25, X26, X27, X28, X29, X30, Sp, N, Z, C, V, V0, V1, V2,
+            V3, V4, V5, V6, V7, V8, V9, V10, V11, V12, V13, V14, V15, V16, V17, V18, V19, V20, V21,
+            V22, V23, V24, V25, V26, V27, V28, V29, V30, V31,
+        ];
+        match REGS.get(value as usize) {
+            Some(reg) => Ok(*reg),
+            None => Err(format!("Unknown register: index = {:?}", value)),
+        }
+    }
+}
+
+impl From<Aarch64Regs> for u8 {
+    fn from(value: Aarch64Regs) -> Self {
+        value as u8
+    }
+}
+
 pub enum ParseErr<E> {
     Incomplete, // input too short
     Error(E),   // recoverable
@@ -523,10 +677,10 @@ pub trait RegT:
     fn pinned_heap_reg() -> Self;
     fn pinned_vmctx_reg() -> Self;
     fn is_caller_saved(&self) -> bool;
-    fn iter() -> RegsIterator {
+    fn iter() -> RegsIterator<Self> {
         RegsIterator {
             current_reg: 0,
-            //reg_type: PhantomData,
+            reg_type: PhantomData,
         }
     }
 }
@@ -539,7 +693,7 @@ pub fn mk_value_i64(num: i64) -> Value {
     Value::Imm(ImmType::Signed, Size64, num)
 }
 
-pub fn get_rsp_offset(memargs: &MemArgs) -> Option<i64> {
+pub fn get_rsp_offset<Ar: RegT>(memargs: &MemArgs<Ar>) -> Option<i64> {
     match memargs {
         MemArgs::Mem1Arg(arg) if arg.is_rsp() => {
             return Some(0);
@@ -1,7 +1,9 @@
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::mem::discriminant;
 
 use crate::ir::types::*;
+use crate::ir::{LiftError, Liftable};
 use crate::loaders::types::{VwMetadata, VwModule};
 use yaxpeax_arch::{AddressBase, Arch, LengthedInstruction};
 use yaxpeax_core::analyses::control_flow::VW_CFG;
@@ -15,53 +17,53 @@ use yaxpeax_x86::long_mode::{
 use ValSize::{Size128, Size16, Size256, Size32, Size512, Size64, Size8};
 use X86Regs::*;
 
-fn get_reg_size(reg: yaxpeax_x86::long_mode::RegSpec) -> ValSize {
+fn get_reg_size(reg: yaxpeax_x86::long_mode::RegSpec) -> Result<ValSize, String> {
     let size = match reg.class() {
         register_class::Q => Size64,
         register_class::D => Size32,
         register_class::W => Size16,
         register_class::B => Size8,
         register_class::RB => Size8,
-        register_class::RIP => panic!("Write to RIP: {:?}", reg.class()),
-        register_class::EIP => panic!("Write to EIP: {:?}", reg.class()),
+        register_class::RIP => return Err(format!("write to RIP: {:?}", reg.class())),
+        register_class::EIP => return Err(format!("write to EIP: {:?}", reg.class())),
         register_class::X => Size128,
         register_class::Y => Size256,
         register_class::Z => Size512,
-        _ => panic!("Unknown register bank: {:?}", reg.class()),
+        _ => return Err(format!("unknown register bank: {:?}", reg.class())),
     };
-    return size;
+    Ok(size)
 }
 
-fn convert_reg(reg: yaxpeax_x86::long_mode::RegSpec) -> Value {
+fn convert_reg(reg: yaxpeax_x86::long_mode::RegSpec) -> Result<Value, String> {
     let (num, size) = match (reg.num(), reg.class()) {
         (n, register_class::Q) => (n, Size64),
         (n, register_class::D) => (n, Size32),
         (n, register_class::W) => (n, Size16),
         (n, register_class::B) => (n, Size8),
         (n, register_class::RB) => (n, Size8),
-        (_, register_class::RIP) => panic!("Write to RIP: {:?}", reg.class()),
-        (_, register_class::EIP) => panic!("Write to EIP: {:?}", reg.class()),
+        (_, register_class::RIP) => return Err(format!("write to RIP: {:?}", reg.class())),
+        (_, register_class::EIP) => return Err(format!("write to EIP: {:?}", reg.class())),
         (n, register_class::X) => (n + ValSize::fp_offset(), Size128),
         (n, register_class::Y) => (n + ValSize::fp_offset(), Size256),
         (n, register_class::Z) => (n + ValSize::fp_offset(), Size512),
-        _ => panic!("Unknown register bank: {:?}", reg.class()),
+        _ => return Err(format!("unknown register bank: {:?}", reg.class())),
     };
-    Value::Reg(X86Regs::try_from(num).unwrap(), size)
+    Ok(Value::Reg(X86Regs::try_from(num)?, size))
 }
 
-fn convert_memarg_reg(reg: yaxpeax_x86::long_mode::RegSpec) -> MemArg {
+fn convert_memarg_reg(reg: yaxpeax_x86::long_mode::RegSpec) -> Result<MemArg, String> {
     let size = match reg.class() {
         register_class::Q => Size64,
         register_class::D => Size32,
         register_class::W => Size16,
         register_class::B => Size8,
-        _ => panic!("Unknown register bank: {:?}", reg.class()),
+        _ => return Err(format!("unknown register bank: {:?}", reg.class())),
     };
-    MemArg::Reg(X86Regs::try_from(reg.num()).unwrap(), size)
+    Ok(MemArg::Reg(X86Regs::try_from(reg.num())?, size))
 }
 
-fn convert_operand(op: yaxpeax_x86::long_mode::Operand, memsize: ValSize) -> Value {
-    match op {
+fn convert_operand(op: yaxpeax_x86::long_mode::Operand, memsize: ValSize) -> Result<Value, String> {
+    let value = match op {
         Operand::ImmediateI8(imm) => Value::Imm(ImmType::Signed, Size8, imm as i64),
         Operand::ImmediateU8(imm) => Value::Imm(ImmType::Unsigned, Size8, imm as i64),
         Operand::ImmediateI16(imm) => Value::Imm(ImmType::Signed, Size16, imm as i64),
@@ -72,7 +74,7 @@ fn convert_operand(op: yaxpeax_x86::long_mode::Operand, memsize: ValSize) -> Val
         Operand::ImmediateI64(imm) => Value::Imm(ImmType::Signed, Size64, imm as i64),
         Operand::Register(reg) => {
             log::debug!("convert_operand widths {:?} {:?}", op, op.width());
-            convert_reg(reg)
+            convert_reg(reg)?
         }
         //u32 and u64 are address sizes
         Operand::DisplacementU32(imm) => Value::Mem(
@@ -84,75 +86,99 @@ fn convert_operand(op: yaxpeax_x86::long_mode::Operand, memsize: ValSize) -> Val
             MemArgs::Mem1Arg(MemArg::Imm(ImmType::Unsigned, Size64, imm as i64)),
         ), //mem[c]
         Operand::RegDeref(reg) if reg == RegSpec::rip() => Value::RIPConst,
-        Operand::RegDeref(reg) => Value::Mem(memsize, MemArgs::Mem1Arg(convert_memarg_reg(reg))), // mem[reg]
+        Operand::RegDeref(reg) => Value::Mem(memsize, MemArgs::Mem1Arg(convert_memarg_reg(reg)?)), // mem[reg]
         Operand::RegDisp(reg, _) if reg == RegSpec::rip() => Value::RIPConst,
         Operand::RegDisp(reg, imm) => Value::Mem(
             memsize,
             MemArgs::Mem2Args(
-                convert_memarg_reg(reg),
+                convert_memarg_reg(reg)?,
                 MemArg::Imm(ImmType::Signed, Size32, imm as i64),
             ),
         ), //mem[reg + c]
         Operand::RegIndexBase(reg1, reg2) => Value::Mem(
             memsize,
-            MemArgs::Mem2Args(convert_memarg_reg(reg1), convert_memarg_reg(reg2)),
+            MemArgs::Mem2Args(convert_memarg_reg(reg1)?, convert_memarg_reg(reg2)?),
         ), // mem[reg1 + reg2]
         Operand::RegIndexBaseDisp(reg1, reg2, imm) => Value::Mem(
             memsize,
             MemArgs::Mem3Args(
-                convert_memarg_reg(reg1),
-                convert_memarg_reg(reg2),
+                convert_memarg_reg(reg1)?,
+                convert_memarg_reg(reg2)?,
                 MemArg::Imm(ImmType::Signed, Size32, imm as i64),
             ),
         ), //mem[reg1 + reg2 + c]
-        Operand::RegScale(_, _) => panic!("Memory operations with scaling prohibited"), // mem[reg * c]
-        Operand::RegScaleDisp(_, _, _) => panic!("Memory operations with scaling prohibited"), //mem[reg*c1 + c2]
+        Operand::RegScale(reg, scale) => Value::Mem(
+            memsize,
+            MemArgs::MemScale(
+                MemArg::Imm(ImmType::Signed, Size32, 0),
+                convert_memarg_reg(reg)?,
+                MemArg::Imm(ImmType::Signed, Size32, scale as i64),
+            ),
+        ), // mem[reg * c]
+        Operand::RegScaleDisp(reg, scale, imm) => Value::Mem(
+            memsize,
+            MemArgs::MemScale(
+                MemArg::Imm(ImmType::Signed, Size32, imm as i64),
+                convert_memarg_reg(reg)?,
+                MemArg::Imm(ImmType::Signed, Size32, scale as i64),
+            ),
+        ), //mem[c2 + reg*c1]
         Operand::RegIndexBaseScale(reg1, reg2, scale) =>
         //mem[reg1 + reg2*c]
         {
             if scale == 1 {
                 Value::Mem(
                     memsize,
-                    MemArgs::Mem2Args(convert_memarg_reg(reg1), convert_memarg_reg(reg2)),
+                    MemArgs::Mem2Args(convert_memarg_reg(reg1)?, convert_memarg_reg(reg2)?),
                 )
             } else {
                 Value::Mem(
                     memsize,
                     MemArgs::MemScale(
-                        convert_memarg_reg(reg1),
-                        convert_memarg_reg(reg2),
+                        convert_memarg_reg(reg1)?,
+                        convert_memarg_reg(reg2)?,
                         MemArg::Imm(ImmType::Signed, Size32, scale as i64),
                     ),
                 )
             }
         }
         Operand::RegIndexBaseScaleDisp(reg1, reg2, scale, imm) => {
-            assert_eq!(scale, 1);
-            Value::Mem(
-                memsize,
-                MemArgs::Mem3Args(
-                    convert_memarg_reg(reg1),
-                    convert_memarg_reg(reg2),
-                    MemArg::Imm(ImmType::Signed, Size32, imm as i64),
-                ),
-            )
+            if scale == 1 {
+                Value::Mem(
+                    memsize,
+                    MemArgs::Mem3Args(
+                        convert_memarg_reg(reg1)?,
+                        convert_memarg_reg(reg2)?,
+                        MemArg::Imm(ImmType::Signed, Size32, imm as i64),
+                    ),
+                )
+            } else {
+                Value::Mem(
+                    memsize,
+                    MemArgs::MemScaleDisp(
+                        convert_memarg_reg(reg1)?,
+                        convert_memarg_reg(reg2)?,
+                        MemArg::Imm(ImmType::Signed, Size32, scale as i64),
+                        MemArg::Imm(ImmType::Signed, Size32, imm as i64),
+                    ),
+                )
+            }
         } //mem[reg1 + reg2*c1 + c2]
-        Operand::Nothing => panic!("Nothing Operand?"),
-        op => {
-            panic!("Unhandled operand {}", op);
-        }
-    }
+        Operand::Nothing => return Err("missing operand".to_string()),
+        op => return Err(format!("unhandled operand {}", op)),
+    };
+    Ok(value)
 }
 
 // Captures all register and flag sources
 // TODO: Memory?
-fn get_sources(instr: &X64Instruction) -> Vec<Value> {
+fn get_sources(instr: &X64Instruction) -> Result<Vec<Value>, String> {
     let uses_vec = <AMD64 as ValueLocations>::decompose(instr);
     let mut sources = Vec::new();
     for (loc, dir) in uses_vec {
         match (loc, dir) {
             (Some(Location::Register(reg)), Direction::Read) => {
-                sources.push(convert_reg(reg));
+                sources.push(convert_reg(reg)?);
             }
             (Some(Location::ZF), Direction::Read) => {
                 sources.push(Value::Reg(Zf, Size8));
@@ -161,24 +187,24 @@ fn get_sources(instr: &X64Instruction) -> Vec<Value> {
                 sources.push(Value::Reg(Cf, Size8));
             }
             (Some(Location::UnevalMem(op)), Direction::Read) => {
-                sources.push(convert_operand(instr.operand(op), Size32)); // is Size32 right?
+                sources.push(convert_operand(instr.operand(op), Size32)?); // is Size32 right?
             }
             _ => {}
         }
     }
-    return sources;
+    Ok(sources)
 }
 
 // Captures all register and flag destinations
 // TODO: Memory?
-fn get_destinations(instr: &X64Instruction) -> Vec<Value> {
+fn get_destinations(instr: &X64Instruction) -> Result<Vec<Value>, String> {
     let uses_vec = <AMD64 as ValueLocations>::decompose(instr);
     let mut destinations = Vec::new();
     for (loc, dir) in uses_vec {
         match (loc, dir) {
             (Some(Location::Register(reg)), Direction::Write) => {
                 // println!("destination: {:?} width = {:?}", reg, reg.width());
-                destinations.push(convert_reg(reg));
+                destinations.push(convert_reg(reg)?);
             }
             (Some(Location::ZF), Direction::Write) => {
                 destinations.push(Value::Reg(Zf, Size8));
@@ -187,31 +213,31 @@ fn get_destinations(instr: &X64Instruction) -> Vec<Value> {
                 destinations.push(Value::Reg(Cf, Size8));
             }
             (Some(Location::UnevalMem(op)), Direction::Read) => {
-                destinations.push(convert_operand(instr.operand(op), Size32)); // is Size32 right?
+                destinations.push(convert_operand(instr.operand(op), Size32)?); // is Size32 right?
             }
             _ => {}
         }
     }
-    return destinations;
+    Ok(destinations)
 }
 
-fn generic_clear(instr: &X64Instruction) -> Vec<Stmt> {
+fn generic_clear(instr: &X64Instruction) -> Result<Vec<Stmt>, String> {
     let mut stmts = vec![];
-    let sources = get_sources(&instr);
-    let dsts = get_destinations(&instr);
+    let sources = get_sources(&instr)?;
+    let dsts = get_destinations(&instr)?;
     for dst in dsts {
         stmts.push(Stmt::Clear(dst, sources.clone()));
     }
-    stmts
+    Ok(stmts)
 }
 
-fn get_operand_size(op: &yaxpeax_x86::long_mode::Operand) -> Option<ValSize> {
-    match op {
+fn get_operand_size(op: &yaxpeax_x86::long_mode::Operand) -> Result<Option<ValSize>, String> {
+    let size = match op {
         Operand::ImmediateI8(_) | Operand::ImmediateU8(_) => Some(Size8),
         Operand::ImmediateI16(_) | Operand::ImmediateU16(_) => Some(Size16),
         Operand::ImmediateU32(_) | Operand::ImmediateI32(_) => Some(Size32),
         Operand::ImmediateU64(_) | Operand::ImmediateI64(_) => Some(Size64),
-        Operand::Register(reg) => Some(get_reg_size(*reg)),
+        Operand::Register(reg) => Some(get_reg_size(*reg)?),
         //u32 and u64 are address sizes
         Operand::DisplacementU32(_)
         | Operand::DisplacementU64(_)
@@ -224,218 +250,254 @@ fn get_operand_size(op: &yaxpeax_x86::long_mode::Operand) -> Option<ValSize> {
         | Operand::RegIndexBaseScale(_, _, _)
         | Operand::RegIndexBaseScaleDisp(_, _, _, _)
         | Operand::Nothing => None,
-        op => {
-            panic!("unsupported operand size: {}", op);
-        }
+        op => return Err(format!("unsupported operand size: {}", op)),
+    };
+    Ok(size)
+}
+
+// The size of a register or immediate operand.
+fn operand_size(op: &yaxpeax_x86::long_mode::Operand) -> Result<ValSize, String> {
+    get_operand_size(op)?.ok_or_else(|| format!("no size for operand {}", op))
+}
+
+// The size of the memory access of a two-operand instruction, taken from its register or
+// immediate operand.
+fn memsize(instr: &X64Instruction) -> Result<ValSize, String> {
+    match (
+        get_operand_size(&instr.operand(0))?,
+        get_operand_size(&instr.operand(1))?,
+    ) {
+        (None, None) => Err("two memory operands".to_string()),
+        (Some(x), None) => Ok(x),
+        (None, Some(x)) => Ok(x),
+        (Some(x), Some(_y)) => Ok(x),
     }
 }
 
-fn set_from_flags(operand: Operand, flags: Vec<X86Regs>) -> Stmt {
-    Stmt::Clear(
-        convert_operand(operand, Size8),
+fn set_from_flags(operand: Operand, flags: Vec<X86Regs>) -> Result<Stmt, String> {
+    Ok(Stmt::Clear(
+        convert_operand(operand, Size8)?,
         flags.iter().map(|flag| Value::Reg(*flag, Size8)).collect(),
-    )
+    ))
 }
 
-fn unop(opcode: Unopcode, instr: &X64Instruction) -> Stmt {
-    let memsize = match (
-        get_operand_size(&instr.operand(0)),
-        get_operand_size(&instr.operand(1)),
-    ) {
-        (None, None) => panic!("Two Memory Args?"),
-        (Some(x), None) => x,
-        (None, Some(x)) => x,
-        (Some(x), Some(_y)) => x,
-    };
-    Stmt::Unop(
-        opcode,
-        convert_operand(instr.operand(0), memsize),
-        convert_operand(instr.operand(1), memsize),
-    )
+fn unop(opcode: Unopcode, instr: &X64Instruction) -> Result<Stmt, String> {
+    let memsize = memsize(instr)?;
+    unop_w_memsize(opcode, instr, memsize)
 }
 
-fn unop_w_memsize(opcode: Unopcode, instr: &X64Instruction, memsize: ValSize) -> Stmt {
-    Stmt::Unop(
+fn unop_w_memsize(
+    opcode: Unopcode,
+    instr: &X64Instruction,
+    memsize: ValSize,
+) -> Result<Stmt, String> {
+    Ok(Stmt::Unop(
         opcode,
-        convert_operand(instr.operand(0), memsize),
-        convert_operand(instr.operand(1), memsize),
-    )
+        convert_operand(instr.operand(0), memsize)?,
+        convert_operand(instr.operand(1), memsize)?,
+    ))
 }
 
-fn binop(opcode: Binopcode, instr: &X64Instruction) -> Stmt {
-    let memsize = match (
-        get_operand_size(&instr.operand(0)),
-        get_operand_size(&instr.operand(1)),
-    ) {
-        (None, None) => panic!("Two Memory Args?"),
-        (Some(x), None) => x,
-        (None, Some(x)) => x,
-        (Some(x), Some(_y)) => x,
-    };
+fn binop(opcode: Binopcode, instr: &X64Instruction) -> Result<Stmt, String> {
+    let memsize = memsize(instr)?;
     // if two operands than dst is src1
     if instr.operand_count() == 2 {
-        Stmt::Binop(
+        Ok(Stmt::Binop(
             opcode,
-            convert_operand(instr.operand(0), memsize),
-            convert_operand(instr.operand(0), memsize),
-            convert_operand(instr.operand(1), memsize),
-        )
+            convert_operand(instr.operand(0), memsize)?,
+            convert_operand(instr.operand(0), memsize)?,
+            convert_operand(instr.operand(1), memsize)?,
+        ))
     } else {
-        Stmt::Binop(
+        Ok(Stmt::Binop(
             opcode,
-            convert_operand(instr.operand(0), memsize),
-            convert_operand(instr.operand(1), memsize),
-            convert_operand(instr.operand(2), memsize),
-        )
+            convert_operand(instr.operand(0), memsize)?,
+            convert_operand(instr.operand(1), memsize)?,
+            convert_operand(instr.operand(2), memsize)?,
+        ))
     }
 }
 
-fn branch(instr: &X64Instruction) -> Stmt {
-    Stmt::Branch(instr.opcode(), convert_operand(instr.operand(0), Size64))
+fn branch(instr: &X64Instruction) -> Result<Stmt, String> {
+    Ok(Stmt::Branch(
+        instr.opcode(),
+        convert_operand(instr.operand(0), Size64)?,
+    ))
 }
 
-fn call(instr: &X64Instruction, _metadata: &VwMetadata) -> Stmt {
-    let dst = convert_operand(instr.operand(0), Size64);
-    Stmt::Call(dst)
+fn call(instr: &X64Instruction, _metadata: &VwMetadata) -> Result<Stmt, String> {
+    let dst = convert_operand(instr.operand(0), Size64)?;
+    Ok(Stmt::Call(dst))
 }
 
-fn lea(instr: &X64Instruction, addr: &Addr) -> Vec<Stmt> {
+// Returns None if the address computation is not tracked and the destination should be
+// cleared instead.
+fn lea(instr: &X64Instruction, addr: &Addr) -> Result<Option<Vec<Stmt>>, String> {
     let dst = instr.operand(0);
     let src1 = instr.operand(1);
-    if let Operand::RegDisp(reg, disp) = src1 {
-        if reg == RegSpec::rip() {
-            //addr + instruction length + displacement
-            let length = 0u64.wrapping_offset(instr.len()).to_linear();
-            let target = (*addr as i64) + (length as i64) + (disp as i64);
-            return vec![Stmt::Unop(
-                Unopcode::Mov,
-                convert_operand(dst.clone(), get_operand_size(&dst).unwrap()),
-                Value::Imm(ImmType::Signed, Size64, target),
-            )];
-        }
+    let rip_disp = match src1 {
+        Operand::RegDisp(reg, disp) if reg == RegSpec::rip() => Some(disp),
+        Operand::RegDeref(reg) if reg == RegSpec::rip() => Some(0),
+        _ => None,
+    };
+    if let Some(disp) = rip_disp {
+        //addr + instruction length + displacement
+        let length = 0u64.wrapping_offset(instr.len()).to_linear();
+        let target = (*addr as i64) + (length as i64) + (disp as i64);
+        return Ok(Some(vec![Stmt::Unop(
+            Unopcode::Mov,
+            convert_operand(dst.clone(), operand_size(&dst)?)?,
+            Value::Imm(ImmType::RIPRelative, Size64, target),
+        )]));
     }
-    match convert_operand(src1, get_operand_size(&dst).unwrap()) {
+    match convert_operand(src1.clone(), operand_size(&dst)?)? {
         Value::Mem(_, memargs) => match memargs {
             MemArgs::Mem1Arg(arg) => match arg {
-                MemArg::Imm(_, _, _val) => vec![unop(Unopcode::Mov, instr)],
-                _ => generic_clear(instr), //clear_dst(instr),
+                MemArg::Imm(_, _, _val) => Ok(Some(vec![unop(Unopcode::Mov, instr)?])),
+                _ => Ok(None), //clear_dst(instr),
             },
-            _ => generic_clear(instr), //clear_dst(instr),
+            _ => Ok(None), //clear_dst(instr),
         },
-        _ => panic!("Illegal lea"),
+        _ => Err(format!("lea of a non-memory operand {}", src1)),
     }
 }
 
-pub fn lift(instr: &X64Instruction, addr: &Addr, metadata: &VwMetadata, strict: bool) -> Vec<Stmt> {
+/// How precisely an instruction is modeled by the lifter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LiftKind {
+    /// The instruction has a dedicated lifting.
+    Precise,
+    /// The instruction is known, but its destinations are conservatively cleared.
+    GenericClear,
+    /// The instruction is not handled by the lifter. It is cleared like `GenericClear`
+    /// in non-strict mode and rejected in strict mode.
+    Unsupported,
+}
+
+pub fn lift(
+    instr: &X64Instruction,
+    addr: &Addr,
+    metadata: &VwMetadata,
+    strict: bool,
+) -> Result<Vec<Stmt>, LiftError> {
+    let lift_error = |reason: String| LiftError {
+        addr: *addr,
+        reason: format!("{}: {}", instr, reason),
+    };
+    let (kind, instrs) = lift_with_kind(instr, addr, metadata).map_err(lift_error)?;
+    if strict && kind == LiftKind::Unsupported {
+        return Err(lift_error("unsupported opcode".to_string()));
+    }
+    Ok(instrs)
+}
+
+/// Lifts `instr` and classifies how precisely it is modeled. Fails on operands the IR cannot
+/// represent.
+pub fn lift_with_kind(
+    instr: &X64Instruction,
+    addr: &Addr,
+    metadata: &VwMetadata,
+) -> Result<(LiftKind, Vec<Stmt>), String> {
     log::debug!("lift: addr 0x{:x} instr {:?}", addr, instr);
+    let mut kind = LiftKind::Precise;
     let mut instrs = Vec::new();
     match instr.opcode() {
-        Opcode::MOV => instrs.push(unop(Unopcode::Mov, instr)),
-        Opcode::MOVQ => instrs.push(unop_w_memsize(Unopcode::Mov, instr, Size64)),
-        Opcode::MOVZX => instrs.push(unop(Unopcode::Mov, instr)),
+        Opcode::MOV => instrs.push(unop(Unopcode::Mov, instr)?),
+        Opcode::MOVQ => instrs.push(unop_w_memsize(Unopcode::Mov, instr, Size64)?),
+        Opcode::MOVZX => instrs.push(unop(Unopcode::Mov, instr)?),
 
-        Opcode::MOVD  => instrs.push(unop_w_memsize(Unopcode::Mov, instr, Size32)),
-        Opcode::MOVSD => instrs.push(unop_w_memsize(Unopcode::Mov, instr, Size64)),
+        Opcode::MOVD  => instrs.push(unop_w_memsize(Unopcode::Mov, instr, Size32)?),
+        Opcode::MOVSD => instrs.push(unop_w_memsize(Unopcode::Mov, instr, Size64)?),
 
         Opcode::MOVSX |
-        Opcode::MOVSXD => instrs.push(unop(Unopcode::Movsx, instr)),
+        Opcode::MOVSXD => instrs.push(unop(Unopcode::Movsx, instr)?),
 
-        Opcode::LEA => instrs.extend(lea(instr, addr)),
+        Opcode::LEA => match lea(instr, addr)? {
+            Some(stmts) => instrs.extend(stmts),
+            None => {
+                kind = LiftKind::GenericClear;
+                instrs.extend(generic_clear(instr)?)
+            }
+        },
 
         Opcode::TEST => {
-            let memsize = match (
-                get_operand_size(&instr.operand(0)),
-                get_operand_size(&instr.operand(1)),
-            ) {
-                (None, None) => panic!("Two Memory Args?"),
-                (Some(x), None) => x,
-                (None, Some(x)) => x,
-                (Some(x), Some(_y)) => x,
-            };
+            let memsize = memsize(instr)?;
             instrs.push(Stmt::Binop(
                 Binopcode::Test,
                 Value::Reg(Zf, Size8),
-                convert_operand(instr.operand(0), memsize),
-                convert_operand(instr.operand(1), memsize),
+                convert_operand(instr.operand(0), memsize)?,
+                convert_operand(instr.operand(1), memsize)?,
             ));
             instrs.push(Stmt::Binop(
                 Binopcode::Test,
                 Value::Reg(Cf, Size8),
-                convert_operand(instr.operand(0), memsize),
-                convert_operand(instr.operand(1), memsize),
+                convert_operand(instr.operand(0), memsize)?,
+                convert_operand(instr.operand(1), memsize)?,
             ));
         }
 
         Opcode::UCOMISS
         | Opcode::UCOMISD
         | Opcode::CMP => {
-            let memsize = match (
-                get_operand_size(&instr.operand(0)),
-                get_operand_size(&instr.operand(1)),
-            ) {
-                (None, None) => panic!("Two Memory Args?"),
-                (Some(x), None) => x,
-                (None, Some(x)) => x,
-                (Some(x), Some(_y)) => x,
-            };
+            let memsize = memsize(instr)?;
             instrs.push(Stmt::Binop(
                 Binopcode::Cmp,
                 Value::Reg(Zf, Size8),
-                convert_operand(instr.operand(0), memsize),
-                convert_operand(instr.operand(1), memsize),
+                convert_operand(instr.operand(0), memsize)?,
+                convert_operand(instr.operand(1), memsize)?,
             ));
             instrs.push(Stmt::Binop(
                 Binopcode::Cmp,
                 Value::Reg(Cf, Size8),
-                convert_operand(instr.operand(0), memsize),
-                convert_operand(instr.operand(1), memsize),
+                convert_operand(instr.operand(0), memsize)?,
+                convert_operand(instr.operand(1), memsize)?,
             ));
             instrs.push(Stmt::Binop(
                 Binopcode::Cmp,
                 Value::Reg(Pf, Size8),
-                convert_operand(instr.operand(0), memsize),
-                convert_operand(instr.operand(1), memsize),
+                convert_operand(instr.operand(0), memsize)?,
+                convert_operand(instr.operand(1), memsize)?,
             ));
             instrs.push(Stmt::Binop(
                 Binopcode::Cmp,
                 Value::Reg(Sf, Size8),
-                convert_operand(instr.operand(0), memsize),
-                convert_operand(instr.operand(1), memsize),
+                convert_operand(instr.operand(0), memsize)?,
+                convert_operand(instr.operand(1), memsize)?,
             ));
             instrs.push(Stmt::Binop(
                 Binopcode::Cmp,
                 Value::Reg(Of, Size8),
-                convert_operand(instr.operand(0), memsize),
-                convert_operand(instr.operand(1), memsize),
+                convert_operand(instr.operand(0), memsize)?,
+                convert_operand(instr.operand(1), memsize)?,
             ));
         },
 
         Opcode::AND => {
-            instrs.push(binop(Binopcode::And, instr));
+            instrs.push(binop(Binopcode::And, instr)?);
             instrs.push(Stmt::Clear(
                 Value::Reg(Zf, Size8),
-                get_sources(instr),
+                get_sources(instr)?,
             ))
         }
         Opcode::ADD => {
-            instrs.push(binop(Binopcode::Add, instr));
+            instrs.push(binop(Binopcode::Add, instr)?);
             instrs.push(Stmt::Clear(
                 Value::Reg(Zf, Size8),
-                get_sources(instr),
+                get_sources(instr)?,
             ))
         }
         Opcode::SUB => {
-            instrs.push(binop(Binopcode::Sub, instr));
+            instrs.push(binop(Binopcode::Sub, instr)?);
             instrs.push(Stmt::Clear(
                 Value::Reg(Zf, Size8),
-                get_sources(instr),
+                get_sources(instr)?,
             ))
         }
         Opcode::SHL => {
-            instrs.push(binop(Binopcode::Shl, instr));
+            instrs.push(binop(Binopcode::Shl, instr)?);
             instrs.push(Stmt::Clear(
                 Value::Reg(Zf, Size8),
-                get_sources(instr),
+                get_sources(instr)?,
             ))
         }
 
@@ -449,7 +511,7 @@ pub fn lift(instr: &X64Instruction, addr: &Addr, metadata: &VwMetadata, strict:
 
         Opcode::RETURN => instrs.push(Stmt::Ret),
 
-        Opcode::JMP => instrs.push(branch(instr)),
+        Opcode::JMP => instrs.push(branch(instr)?),
         Opcode::JO
         | Opcode::JNO
         | Opcode::JB
@@ -465,13 +527,12 @@ pub fn lift(instr: &X64Instruction, addr: &Addr, metadata: &VwMetadata, strict:
         | Opcode::JL
         | Opcode::JGE
         | Opcode::JLE
-        | Opcode::JG => instrs.push(branch(instr)),
+        | Opcode::JG => instrs.push(branch(instr)?),
 
-        Opcode::CALL => instrs.push(call(instr, metadata)),
+        Opcode::CALL => instrs.push(call(instr, metadata)?),
 
         Opcode::PUSH => {
-            let width = instr.operand(0).width().expect("push operand has a width");
-            assert_eq!(width, 8); //8 bytes
+            let width = push_pop_width(instr)?;
             instrs.push(Stmt::Binop(
                 Binopcode::Sub,
                 Value::Reg(Rsp, Size64),
@@ -484,15 +545,14 @@ pub fn lift(instr: &X64Instruction, addr: &Addr, metadata: &VwMetadata, strict:
                     valsize((width * 8) as u32),
                     MemArgs::Mem1Arg(MemArg::Reg(Rsp, Size64)),
                 ),
-                convert_operand(instr.operand(0), Size64),
+                convert_operand(instr.operand(0), Size64)?,
             ))
         }
         Opcode::POP => {
-            let width = instr.operand(0).width().expect("pop operand has a width");
-            assert_eq!(width, 8); //8 bytes
+            let width = push_pop_width(instr)?;
             instrs.push(Stmt::Unop(
                 Unopcode::Mov,
-                convert_operand(instr.operand(0), Size64),
+                convert_operand(instr.operand(0), Size64)?,
                 Value::Mem(
                     valsize((width * 8) as u32),
                     MemArgs::Mem1Arg(MemArg::Reg(Rsp, Size64)),
@@ -513,7 +573,7 @@ pub fn lift(instr: &X64Instruction, addr: &Addr, metadata: &VwMetadata, strict:
         //     instrs.push(Stmt::Clear(Value::Reg(Rdx, Size64), vec![])); // clear RDX
         //     instrs.push(Stmt::Clear(
         //         Value::Reg(Zf, Size8),
-        //         get_sources(instr),
+        //         get_sources(instr)?,
         //     ));
         // }
 
@@ -524,15 +584,16 @@ pub fn lift(instr: &X64Instruction, addr: &Addr, metadata: &VwMetadata, strict:
             if instr.operand_count() == 2 && instr.operand(0) == instr.operand(1) {
                 instrs.push(Stmt::Unop(
                     Unopcode::Mov,
-                    convert_operand(instr.operand(0), Size64),
+                    convert_operand(instr.operand(0), Size64)?,
                     Value::Imm(ImmType::Signed, Size64, 0),
                 ));
                 instrs.push(Stmt::Clear(
                     Value::Reg(Zf, Size8),
-                    get_sources(instr),
+                    get_sources(instr)?,
                 ));
             } else {
-                instrs.extend(generic_clear(instr));
+                kind = LiftKind::GenericClear;
+                instrs.extend(generic_clear(instr)?);
                 // instrs.extend(clear_dst(instr))
             }
         }
@@ -545,80 +606,80 @@ pub fn lift(instr: &X64Instruction, addr: &Addr, metadata: &VwMetadata, strict:
         }
 
         SETG
-        | SETLE => instrs.push(set_from_flags(instr.operand(0), vec![Zf, Sf, Of])),
+        | SETLE => instrs.push(set_from_flags(instr.operand(0), vec![Zf, Sf, Of])?),
 
         SETO
-        | SETNO => instrs.push(set_from_flags(instr.operand(0), vec![Of])),
+        | SETNO => instrs.push(set_from_flags(instr.operand(0), vec![Of])?),
 
         SETS
-        | SETNS => instrs.push(set_from_flags(instr.operand(0), vec![Sf])),
+        | SETNS => instrs.push(set_from_flags(instr.operand(0), vec![Sf])?),
 
         SETGE
-        | SETL => instrs.push(set_from_flags(instr.operand(0), vec![Sf, Of])),
+        | SETL => instrs.push(set_from_flags(instr.operand(0), vec![Sf, Of])?),
 
         SETNZ
-        | SETZ => instrs.push(set_from_flags(instr.operand(0), vec![Zf])),
+        | SETZ => instrs.push(set_from_flags(instr.operand(0), vec![Zf])?),
 
         SETAE
-        | SETB => instrs.push(set_from_flags(instr.operand(0), vec![Cf])),
+        | SETB => instrs.push(set_from_flags(instr.operand(0), vec![Cf])?),
 
 
         SETA
-        | SETBE => instrs.push(set_from_flags(instr.operand(0), vec![Cf, Zf])),
+        | SETBE => instrs.push(set_from_flags(instr.operand(0), vec![Cf, Zf])?),
 
         SETP
-        | SETNP => instrs.push(set_from_flags(instr.operand(0), vec![Pf])),
+        | SETNP => instrs.push(set_from_flags(instr.operand(0), vec![Pf])?),
 
         Opcode::BSF => {
             instrs.push(Stmt::Clear(
                 Value::Reg(Zf, Size8),
-                vec![convert_operand(instr.operand(1), get_operand_size(&instr.operand(1)).unwrap())],
+                vec![convert_operand(instr.operand(1), operand_size(&instr.operand(1))?)?],
             ));
             instrs.push(Stmt::Clear(
-                convert_operand(instr.operand(0), get_operand_size(&instr.operand(0)).unwrap()),
+                convert_operand(instr.operand(0), operand_size(&instr.operand(0))?)?,
                 vec![
-                    // convert_operand(instr.operand(0), get_operand_size(&instr.operand(0)).unwrap()),
-                    convert_operand(instr.operand(1), get_operand_size(&instr.operand(1)).unwrap()),
+                    // convert_operand(instr.operand(0), operand_size(&instr.operand(0))?)?,
+                    convert_operand(instr.operand(1), operand_size(&instr.operand(1))?)?,
                 ],
             ));
         }
         Opcode::BSR => {
             instrs.push(Stmt::Clear(
                 Value::Reg(Zf, Size8),
-                vec![convert_operand(instr.operand(1), get_operand_size(&instr.operand(1)).unwrap())],
+                vec![convert_operand(instr.operand(1), operand_size(&instr.operand(1))?)?],
             ));
             instrs.push(Stmt::Clear(
-                convert_operand(instr.operand(0), get_operand_size(&instr.operand(0)).unwrap()),
+                convert_operand(instr.operand(0), operand_size(&instr.operand(0))?)?,
                 vec![
-                    convert_operand(instr.operand(1), get_operand_size(&instr.operand(1)).unwrap()),
+                    convert_operand(instr.operand(1), operand_size(&instr.operand(1))?)?,
                 ],
             ));
         }
         Opcode::LZCNT | Opcode::TZCNT => {
             instrs.push(Stmt::Clear(
                 Value::Reg(Zf, Size8),
-                vec![convert_operand(instr.operand(1), get_operand_size(&instr.operand(1)).unwrap())],
+                vec![convert_operand(instr.operand(1), operand_size(&instr.operand(1))?)?],
             ));
             instrs.push(Stmt::Clear(
-                convert_operand(instr.operand(0), get_operand_size(&instr.operand(0)).unwrap()),
+                convert_operand(instr.operand(0), operand_size(&instr.operand(0))?)?,
                 vec![
-                    convert_operand(instr.operand(1), get_operand_size(&instr.operand(1)).unwrap()),
+                    convert_operand(instr.operand(1), operand_size(&instr.operand(1))?)?,
                 ],
             ));
         }
 
         // TODO: is this right?
         Opcode::MOVSS => {
-            instrs.push(unop_w_memsize(Unopcode::Mov, instr, Size32));
+            instrs.push(unop_w_memsize(Unopcode::Mov, instr, Size32)?);
         }
         Opcode::MOVAPS => {
-            instrs.push(unop(Unopcode::Mov, instr));
+            instrs.push(unop(Unopcode::Mov, instr)?);
         }
         Opcode::CVTSI2SS => {
             instrs.push(Stmt::Clear(
-                convert_operand(instr.operand(0), get_operand_size(&instr.operand(0)).unwrap()),
+                convert_operand(instr.operand(0), operand_size(&instr.operand(0))?)?,
                 vec![
-                    convert_operand(instr.operand(1), get_operand_size(&instr.operand(1)).unwrap()),
+                    convert_operand(instr.operand(1), operand_size(&instr.operand(1))?)?,
                 ],
             ));
         }
@@ -778,15 +839,25 @@ pub fn lift(instr: &X64Instruction, addr: &Addr, metadata: &VwMetadata, strict:
         | Opcode::SBB
         | Opcode::ANDPD
         | Opcode::CVTTSD2SI
-        | Opcode::ORPD => instrs.extend(generic_clear(instr)),/*instrs.extend(clear_dst(instr)),*/
+        | Opcode::ORPD => {
+            kind = LiftKind::GenericClear;
+            instrs.extend(generic_clear(instr)?)/*instrs.extend(clear_dst(instr)),*/
+        }
 
-        _ => if strict{
-                unimplemented!()
-            } else {
-                instrs.extend(generic_clear(instr))
-            },
+        _ => {
+            kind = LiftKind::Unsupported;
+            instrs.extend(generic_clear(instr)?)
+        }
     };
-    instrs
+    Ok((kind, instrs))
+}
+
+// The width of the operand of a push or pop, which must be 8 bytes.
+fn push_pop_width(instr: &X64Instruction) -> Result<u8, String> {
+    match instr.operand(0).width() {
+        Some(8) => Ok(8),
+        width => Err(format!("{:?}-byte push or pop", width)),
+    }
 }
 
 fn parse_probestack_arg<'a>(
@@ -881,6 +952,18 @@ fn parse_probestack<'a>(
     Ok((rest, (addr, stmts)))
 }
 
+// Operand `idx` of `instr`, the first of `instrs`. An operand the IR cannot represent fails
+// the match, so that the instruction is lifted on its own.
+fn convert_bsf_operand<'a>(
+    instrs: BlockInstrs<'a>,
+    instr: &X64Instruction,
+    idx: u8,
+) -> Result<Value, ParseErr<BlockInstrs<'a>>> {
+    operand_size(&instr.operand(idx))
+        .and_then(|size| convert_operand(instr.operand(idx), size))
+        .map_err(|_| ParseErr::Error(instrs))
+}
+
 // returns (addr, operand(0), operand(1))
 fn parse_bsf<'a>(instrs: BlockInstrs<'a>) -> IResult<'a, (Addr, Value, Value)> {
     if let Some(((addr, instr), rest)) = instrs.split_first() {
@@ -889,14 +972,8 @@ fn parse_bsf<'a>(instrs: BlockInstrs<'a>) -> IResult<'a, (Addr, Value, Value)> {
                 rest,
                 (
                     *addr,
-                    convert_operand(
-                        instr.operand(0),
-                        get_operand_size(&instr.operand(0)).unwrap(),
-                    ),
-                    convert_operand(
-                        instr.operand(1),
-                        get_operand_size(&instr.operand(1)).unwrap(),
-                    ),
+                    convert_bsf_operand(instrs, instr, 0)?,
+                    convert_bsf_operand(instrs, instr, 1)?,
                 ),
             ));
         }
@@ -912,14 +989,8 @@ fn parse_bsr<'a>(instrs: BlockInstrs<'a>) -> IResult<'a, (Addr, Value, Value)> {
                 rest,
                 (
                     *addr,
-                    convert_operand(
-                        instr.operand(0),
-                        get_operand_size(&instr.operand(0)).unwrap(),
-                    ),
-                    convert_operand(
-                        instr.operand(1),
-                        get_operand_size(&instr.operand(1)).unwrap(),
-                    ),
+                    convert_bsf_operand(instrs, instr, 0)?,
+                    convert_bsf_operand(instrs, instr, 1)?,
                 ),
             ));
         }
@@ -932,22 +1003,10 @@ fn parse_bsr<'a>(instrs: BlockInstrs<'a>) -> IResult<'a, (Addr, Value, Value)> {
 fn parse_cmovez<'a>(instrs: BlockInstrs<'a>, bsf_dst: &Value) -> IResult<'a, (Addr, Value)> {
     if let Some(((addr, instr), rest)) = instrs.split_first() {
         if let Opcode::CMOVZ = instr.opcode() {
-            let mov_dst = convert_operand(
-                instr.operand(0),
-                get_operand_size(&instr.operand(0)).unwrap(),
-            );
+            let mov_dst = convert_bsf_operand(instrs, instr, 0)?;
             if let (Value::Reg(bsf_dst_reg, _), Value::Reg(mov_dst_reg, _)) = (bsf_dst, mov_dst) {
                 if *bsf_dst_reg == mov_dst_reg {
-                    return Ok((
-                        rest,
-                        (
-                            *addr,
-                            convert_operand(
-                                instr.operand(1),
-                                get_operand_size(&instr.operand(1)).unwrap(),
-                            ),
-                        ),
-                    ));
+                    return Ok((rest, (*addr, convert_bsf_operand(instrs, instr, 1)?)));
                 }
             }
         }
@@ -979,7 +1038,8 @@ fn parse_single_instr<'a>(
     strict: bool,
 ) -> IResult<'a, StmtResult> {
     if let Some(((addr, instr), rest)) = instrs.split_first() {
-        Ok((rest, (*addr, lift(instr, addr, metadata, strict))))
+        let stmts = lift(instr, addr, metadata, strict).map_err(ParseErr::Failure)?;
+        Ok((rest, (*addr, stmts)))
     } else {
         Err(ParseErr::Incomplete)
     }
@@ -1005,10 +1065,15 @@ fn parse_instrs<'a>(
     instrs: BlockInstrs,
     metadata: &VwMetadata,
     strict: bool,
-) -> Vec<(Addr, Vec<Stmt>)> {
+) -> Result<Vec<(Addr, Vec<Stmt>)>, LiftError> {
     let mut block_ir: Vec<(Addr, Vec<Stmt>)> = Vec::new();
     let mut rest = instrs;
-    while let Ok((more, (addr, stmts))) = parse_instr(rest, metadata, strict) {
+    loop {
+        let (more, (addr, stmts)) = match parse_instr(rest, metadata, strict) {
+            Ok(parsed) => parsed,
+            Err(ParseErr::Failure(err)) => return Err(err),
+            Err(_) => break,
+        };
         rest = more;
         if stmts.len() == 1 {
             if let Stmt::Branch(Opcode::JMP, _) = stmts[0] {
@@ -1023,10 +1088,30 @@ fn parse_instrs<'a>(
         log::info!("Lifted block: 0x{:x} {:?}", addr, stmts);
         block_ir.push((addr, stmts));
     }
-    block_ir
+    Ok(block_ir)
+}
+
+pub struct X64;
+
+impl Liftable for X64 {
+    type Reg = X86Regs;
+    type Instruction = X64Instruction;
+
+    fn lift(
+        instr: &X64Instruction,
+        addr: &u64,
+        metadata: &VwMetadata,
+        strict: bool,
+    ) -> Result<Vec<Stmt>, LiftError> {
+        lift(instr, addr, metadata, strict)
+    }
+
+    fn lift_cfg(module: &VwModule, cfg: &VW_CFG, strict: bool) -> Result<IRMap, LiftError> {
+        lift_cfg(module, cfg, strict)
+    }
 }
 
-pub fn lift_cfg(module: &VwModule, cfg: &VW_CFG, strict: bool) -> IRMap {
+fn lift_cfg(module: &VwModule, cfg: &VW_CFG, strict: bool) -> Result<IRMap, LiftError> {
     let mut irmap = IRMap::new();
     let g = &cfg.graph;
     for block_addr in g.nodes() {
@@ -1036,11 +1121,52 @@ pub fn lift_cfg(module: &VwModule, cfg: &VW_CFG, strict: bool) -> IRMap {
             yaxpeax_x86::x86_64::instructions_spanning(&module.program, block.start, block.end)
                 .collect();
         let instrs = instrs_vec.as_slice();
-        let block_ir = parse_instrs(instrs, &module.metadata, strict);
+        let block_ir = parse_instrs(instrs, &module.metadata, strict)?;
 
         irmap.insert(block_addr, block_ir);
     }
-    irmap
+    Ok(irmap)
+}
+
+/// The disassembly of every block in `cfg`, keyed by block address. Like `lift_cfg`, this
+/// stops at unconditional jumps.
+pub fn disassemble_cfg(module: &VwModule, cfg: &VW_CFG) -> BTreeMap<Addr, Vec<(Addr, String)>> {
+    let mut blocks = BTreeMap::new();
+    for block_addr in cfg.graph.nodes() {
+        let block = cfg.get_block(block_addr);
+        let mut block_instrs = Vec::new();
+        let instrs =
+            yaxpeax_x86::x86_64::instructions_spanning(&module.program, block.start, block.end);
+        for (addr, instr) in instrs {
+            block_instrs.push((addr, instr.to_string()));
+            if instr.opcode() == Opcode::JMP {
+                break;
+            }
+        }
+        blocks.insert(block_addr, block_instrs);
+    }
+    blocks
+}
+
+/// Classifies every instruction in `cfg` by how it is lifted. Like `lift_cfg`, this stops
+/// at unconditional jumps so that constants embedded in the code are not counted. An
+/// instruction with an operand the IR cannot represent is unsupported.
+pub fn audit_cfg(module: &VwModule, cfg: &VW_CFG) -> Vec<(Addr, Opcode, LiftKind)> {
+    let mut audit = Vec::new();
+    for block_addr in cfg.graph.nodes() {
+        let block = cfg.get_block(block_addr);
+        let instrs =
+            yaxpeax_x86::x86_64::instructions_spanning(&module.program, block.start, block.end);
+        for (addr, instr) in instrs {
+            let kind = lift_with_kind(&instr, &addr, &module.metadata)
+                .map_or(LiftKind::Unsupported, |(kind, _)| kind);
+            audit.push((addr, instr.opcode(), kind));
+            if instr.opcode() == Opcode::JMP {
+                break;
+            }
+        }
+    }
+    audit
 }
 
 // TODO: baby version of nom, resolve crate incompatibilities later
@@ -1050,9 +1176,109 @@ type StmtResult = (Addr, Vec<Stmt>);
 type Addr = u64;
 
 enum ParseErr<E> {
-    Incomplete, // input too short
-    Error(E),   // recoverable
-    Failure(E), // unrecoverable
+    Incomplete,         // input too short
+    Error(E),           // recoverable
+    Failure(LiftError), // unrecoverable
 }
 
 type BlockInstrs<'a> = &'a [(Addr, X64Instruction)];
+
+#[cfg(test)]
+fn lift_bytes(bytes: &[u8]) -> Vec<Stmt> {
+    use crate::{HeapLayout, HeapRegs};
+    use yaxpeax_arch::{Decoder, U8Reader};
+    use yaxpeax_x86::long_mode::InstDecoder;
+
+    let metadata = VwMetadata {
+        guest_table_0: 0,
+        lucet_tables: 0,
+        lucet_probestack: 0,
+        heap_layout: HeapLayout::default(),
+        heap_regs: HeapRegs::default(),
+    };
+    let instr = InstDecoder::default()
+        .decode(&mut U8Reader::new(bytes))
+        .unwrap();
+    lift(&instr, &0, &metadata, true).unwrap()
+}
+
+#[test]
+fn lift_reg_scale_test() {
+    // mov eax, [rcx*4]
+    let stmts = lift_bytes(&[0x8b, 0x04, 0x8d, 0x00, 0x00, 0x00, 0x00]);
+    match &stmts[..] {
+        [Stmt::Unop(
+            Unopcode::Mov,
+            Value::Reg(Rax, Size32),
+            Value::Mem(
+                Size32,
+                MemArgs::MemScale(
+                    MemArg::Imm(_, _, 0),
+                    MemArg::Reg(Rcx, Size64),
+                    MemArg::Imm(_, _, 4),
+                ),
+            ),
+        )] => (),
+        _ => panic!("Unexpected lifting: {:?}", stmts),
+    }
+}
+
+#[test]
+fn lift_reg_scale_disp_test() {
+    // mov eax, [rcx*4 + 0x10]
+    let stmts = lift_bytes(&[0x8b, 0x04, 0x8d, 0x10, 0x00, 0x00, 0x00]);
+    match &stmts[..] {
+        [Stmt::Unop(
+            Unopcode::Mov,
+            Value::Reg(Rax, Size32),
+            Value::Mem(
+                Size32,
+                MemArgs::MemScale(
+                    MemArg::Imm(_, _, 0x10),
+                    MemArg::Reg(Rcx, Size64),
+                    MemArg::Imm(_, _, 4),
+                ),
+            ),
+        )] => (),
+        _ => panic!("Unexpected lifting: {:?}", stmts),
+    }
+}
+
+#[test]
+fn lift_reg_index_base_scale_disp_test() {
+    // mov eax, [rsi + rcx*4 + 0x10]
+    let stmts = lift_bytes(&[0x8b, 0x44, 0x8e, 0x10]);
+    match &stmts[..] {
+        [Stmt::Unop(
+            Unopcode::Mov,
+            Value::Reg(Rax, Size32),
+            Value::Mem(
+                Size32,
+                MemArgs::MemScaleDisp(
+                    MemArg::Reg(Rsi, Size64),
+                    MemArg::Reg(Rcx, Size64),
+                    MemArg::Imm(_, _, 4),
+                    MemArg::Imm(_, _, 0x10),
+                ),
+            ),
+        )] => (),
+        _ => panic!("Unexpected lifting: {:?}", stmts),
+    }
+    // mov eax, [rsi + rcx + 0x10] keeps the three-argument form
+    let stmts = lift_bytes(&[0x8b, 0x44, 0x0e, 0x10]);
+    match &stmts[..] {
+        [Stmt::Unop(
+            Unopcode::Mov,
+            Value::Reg(Rax, Size32),
+            Value::Mem(
+                Size32,
+                MemArgs::Mem3Args(
+                    MemArg::Reg(Rsi, Size64),
+                    MemArg::Reg(Rcx, Size64),
+                    MemArg::Imm(_, _, 0x10),
+                ),
+            ),
+        )] => (),
+        _ => panic!("Unexpected lifting: {:?}", stmts),
+    }
+}
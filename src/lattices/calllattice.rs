@@ -2,7 +2,7 @@ use crate::ir::types::X86Regs;
 use crate::lattices;
 use lattices::davlattice::DAV;
 use lattices::reachingdefslattice::LocIdx;
-use lattices::{Lattice, VariableState};
+use lattices::{Explain, Lattice, VariableState};
 use std::cmp::Ordering;
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Debug)]
@@ -35,6 +35,15 @@ impl Default for CallCheckValueLattice {
     }
 }
 
+impl Explain for CallCheckValueLattice {
+    fn explain(&self) -> String {
+        match &self.v {
+            Some(v) => format!("{:?}", v),
+            None => "⊥".to_string(),
+        }
+    }
+}
+
 impl CallCheckValueLattice {
     pub fn new(v: CallCheckValue) -> Self {
         CallCheckValueLattice { v: Some(v) }
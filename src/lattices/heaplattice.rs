@@ -1,19 +1,33 @@
+use crate::ir::types::X86Regs;
 use crate::lattices::{ConstLattice, VariableState};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum HeapValue {
-    HeapBase,
+    /// The base of the memory with this index.
+    HeapBase(u32),
     Bounded4GB,
-    HeapAddr,
+    /// The base of the memory with this index plus a Bounded4GB offset.
+    HeapAddr(u32),
     LucetTables,
     GuestTable0,
     GlobalsBase,
     RIPConst,
+    VMCtxPtr,
+    /// The current length of the memory with this index.
+    HeapBound(u32),
+    /// An index such that the index plus the given number of bytes is at most the length of
+    /// the memory with the given index: `CheckedIndex(memory, bytes)`.
+    CheckedIndex(u32, u32),
+    /// An index masked with this non-negative constant, so at most the constant.
+    MaskedIndex(u64),
+    /// An index compared below this entry count, as a `br_table` index is before it is used
+    /// to read the jump table.
+    TableIndex(u64),
 }
 
 pub type HeapValueLattice = ConstLattice<HeapValue>;
 
-pub type HeapLattice = VariableState<HeapValueLattice>;
+pub type HeapLattice<Ar = X86Regs> = VariableState<HeapValueLattice, Ar>;
 
 #[test]
 fn heap_lattice_test() {
@@ -22,10 +36,10 @@ fn heap_lattice_test() {
 
     let x1 = HeapValueLattice { v: None };
     let x2 = HeapValueLattice {
-        v: Some(HeapValue::HeapBase),
+        v: Some(HeapValue::HeapBase(0)),
     };
     let x3 = HeapValueLattice {
-        v: Some(HeapValue::HeapBase),
+        v: Some(HeapValue::HeapBase(0)),
     };
     let x4 = HeapValueLattice {
         v: Some(HeapValue::Bounded4GB),
@@ -54,7 +68,7 @@ fn heap_lattice_test() {
     assert_eq!(
         x2.meet(&x3, &LocIdx { addr: 0, idx: 0 })
             == HeapValueLattice {
-                v: Some(HeapValue::HeapBase)
+                v: Some(HeapValue::HeapBase(0))
             },
         true
     );
@@ -1,6 +1,6 @@
 use crate::ir::types::X86Regs;
 use crate::lattices::reachingdefslattice::LocIdx;
-use crate::lattices::Lattice;
+use crate::lattices::{Explain, Lattice};
 pub use crate::lattices::{VarState, VariableState};
 use std::cmp::Ordering;
 use std::convert::TryFrom;
@@ -62,4 +62,10 @@ impl Lattice for SlotVal {
     }
 }
 
+impl Explain for SlotVal {
+    fn explain(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
 pub type LocalsLattice = VariableState<SlotVal>;
@@ -8,15 +8,13 @@ pub mod stackgrowthlattice;
 pub mod stacklattice;
 pub mod switchlattice;
 use crate::{ir, lattices};
-use ir::types::{Binopcode, MemArg, MemArgs, ValSize, Value, X86Regs};
+use ir::types::{Binopcode, MemArg, MemArgs, RegT, ValSize, Value, X86Regs};
 use lattices::reachingdefslattice::LocIdx;
-use lattices::regslattice::X86RegsLattice;
+use lattices::regslattice::ArchRegsLattice;
 use lattices::stacklattice::StackLattice;
 use std::cmp::Ordering;
 use std::fmt::Debug;
 
-use X86Regs::*;
-
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct VarSlot<T> {
     pub size: u32,
@@ -37,13 +35,31 @@ pub trait Lattice: PartialOrd + Eq + Default + Debug {
     fn meet(&self, other: &Self, loc: &LocIdx) -> Self;
 }
 
-pub trait VarState {
+/// A compact, human-readable rendering of an abstract value or state, used by
+/// `veriwasm explain`. States leave out everything that is at bottom.
+pub trait Explain {
+    fn explain(&self) -> String;
+}
+
+pub trait VarState<Ar: RegT = X86Regs> {
     type Var;
-    fn get(&self, index: &Value) -> Option<Self::Var>;
-    fn set(&mut self, index: &Value, v: Self::Var) -> ();
-    fn set_to_bot(&mut self, index: &Value) -> ();
+    fn get(&self, index: &Value<Ar>) -> Option<Self::Var>;
+    fn set(&mut self, index: &Value<Ar>, v: Self::Var) -> ();
+    fn set_to_bot(&mut self, index: &Value<Ar>) -> ();
     fn on_call(&mut self) -> ();
-    fn adjust_stack_offset(&mut self, opcode: &Binopcode, dst: &Value, src1: &Value, src2: &Value);
+    /// Moves the stack along with an update of the stack pointer, if `dst` is the stack
+    /// pointer. Fails if the update is not by a known multiple of 4 bytes.
+    fn adjust_stack_offset(
+        &mut self,
+        opcode: &Binopcode,
+        dst: &Value<Ar>,
+        src1: &Value<Ar>,
+        src2: &Value<Ar>,
+    ) -> Result<(), String>;
+    /// Where the stack pointer is, relative to where it was on entry, if the state tracks it.
+    fn stack_offset(&self) -> Option<i64> {
+        None
+    }
 }
 
 #[derive(Eq, Clone, Copy, Debug)]
@@ -123,38 +139,87 @@ impl<T: Eq + Clone + Debug> Default for ConstLattice<T> {
     }
 }
 
+impl<T: Eq + Clone + Debug> Explain for ConstLattice<T> {
+    fn explain(&self) -> String {
+        match &self.v {
+            Some(v) => format!("{:?}", v),
+            None => "⊥".to_string(),
+        }
+    }
+}
+
 impl<T: Eq + Clone + Debug> ConstLattice<T> {
     pub fn new(v: T) -> Self {
         ConstLattice { v: Some(v) }
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Default, Clone, Debug)]
-pub struct VariableState<T> {
-    pub regs: X86RegsLattice<T>,
+#[derive(PartialEq, Eq, PartialOrd, Clone, Debug)]
+pub struct VariableState<T, Ar: RegT = X86Regs> {
+    pub regs: ArchRegsLattice<T, Ar>,
     pub stack: StackLattice<T>,
 }
 
-impl<T: std::fmt::Debug + Clone> std::fmt::Display for VariableState<T> {
+impl<T: Default, Ar: RegT> Default for VariableState<T, Ar> {
+    fn default() -> Self {
+        VariableState {
+            regs: Default::default(),
+            stack: Default::default(),
+        }
+    }
+}
+
+impl<T: std::fmt::Debug + Clone, Ar: RegT> std::fmt::Display for VariableState<T, Ar> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{{\n\t{:?}\n\n\t{}\n}}", self.regs, self.stack)
     }
 }
 
+impl<T: Lattice + Clone + Explain, Ar: RegT> Explain for VariableState<T, Ar> {
+    fn explain(&self) -> String {
+        let mut regs: Vec<(&Ar, &VarSlot<T>)> = self
+            .regs
+            .map
+            .iter()
+            .filter(|(_, slot)| slot.value != Default::default())
+            .collect();
+        regs.sort_by_key(|(reg, _)| (**reg).into());
+        let mut slots: Vec<(&i64, &VarSlot<T>)> = self
+            .stack
+            .map
+            .iter()
+            .filter(|(_, slot)| slot.value != Default::default())
+            .collect();
+        slots.sort_by_key(|(offset, _)| **offset);
+        let regs = regs
+            .into_iter()
+            .map(|(reg, slot)| format!("{:?}={}", reg, slot.value.explain()));
+        // stack slots are shown relative to the current stack pointer
+        let slots = slots.into_iter().map(|(offset, slot)| {
+            format!(
+                "[sp{:+}]={}",
+                offset - self.stack.offset,
+                slot.value.explain()
+            )
+        });
+        regs.chain(slots).collect::<Vec<String>>().join(" ")
+    }
+}
+
 // offset from current stack pointer
 // returns None if points to heap
-pub fn mem_to_stack_offset(memargs: &MemArgs) -> Option<i64> {
+pub fn mem_to_stack_offset<Ar: RegT>(memargs: &MemArgs<Ar>) -> Option<i64> {
     match memargs {
         MemArgs::Mem1Arg(arg) => {
             if let MemArg::Reg(regnum, _) = arg {
-                if *regnum == Rsp {
+                if regnum.is_rsp() {
                     return Some(0);
                 }
             }
         }
         MemArgs::Mem2Args(arg1, arg2) => {
             if let MemArg::Reg(regnum, _) = arg1 {
-                if *regnum == Rsp {
+                if regnum.is_rsp() {
                     if let MemArg::Imm(_, _, offset) = arg2 {
                         return Some(*offset);
                     }
@@ -166,7 +231,7 @@ pub fn mem_to_stack_offset(memargs: &MemArgs) -> Option<i64> {
     return None;
 }
 
-impl<T: Lattice + Clone> Lattice for VariableState<T> {
+impl<T: Lattice + Clone, Ar: RegT> Lattice for VariableState<T, Ar> {
     fn meet(&self, other: &Self, loc_idx: &LocIdx) -> Self {
         VariableState {
             regs: self.regs.meet(&other.regs, loc_idx),
@@ -175,9 +240,24 @@ impl<T: Lattice + Clone> Lattice for VariableState<T> {
     }
 }
 
-impl<T: Lattice + Clone> VarState for VariableState<T> {
+impl<T: Lattice + Clone, Ar: RegT> VariableState<T, Ar> {
+    /// Like `on_call`, for a callee known to return with the registers `preserved` unchanged.
+    pub fn on_call_preserving(&mut self, preserved: &[Ar]) {
+        let values: Vec<(Ar, T)> = preserved
+            .iter()
+            .map(|reg| (*reg, self.regs.get_reg(*reg, ValSize::Size64)))
+            .filter(|(_, value)| *value != Default::default())
+            .collect();
+        self.on_call();
+        for (reg, value) in values {
+            self.regs.set_reg(reg, ValSize::Size64, value);
+        }
+    }
+}
+
+impl<T: Lattice + Clone, Ar: RegT> VarState<Ar> for VariableState<T, Ar> {
     type Var = T;
-    fn set(&mut self, index: &Value, value: T) -> () {
+    fn set(&mut self, index: &Value<Ar>, value: T) -> () {
         match index {
             Value::Mem(memsize, memargs) => {
                 if let Some(offset) = mem_to_stack_offset(memargs) {
@@ -190,7 +270,7 @@ impl<T: Lattice + Clone> VarState for VariableState<T> {
         }
     }
 
-    fn get(&self, index: &Value) -> Option<T> {
+    fn get(&self, index: &Value<Ar>) -> Option<T> {
         match index {
             Value::Mem(memsize, memargs) => mem_to_stack_offset(memargs)
                 .map(|offset| self.stack.get(offset, memsize.into_bytes())),
@@ -200,27 +280,35 @@ impl<T: Lattice + Clone> VarState for VariableState<T> {
         }
     }
 
-    fn set_to_bot(&mut self, index: &Value) {
+    fn set_to_bot(&mut self, index: &Value<Ar>) {
         self.set(index, Default::default())
     }
 
     fn on_call(&mut self) {
         self.regs.clear_caller_save_regs();
+        self.stack.clear_callee_frame();
     }
 
-    fn adjust_stack_offset(&mut self, opcode: &Binopcode, dst: &Value, src1: &Value, src2: &Value) {
-        if dst.is_rsp() {
-            if src1.is_rsp() {
-                let adjustment = src2.as_imm_val();
-                match opcode {
-                    Binopcode::Add => self.stack.update_stack_offset(adjustment),
-                    Binopcode::Sub => self.stack.update_stack_offset(-adjustment),
-                    _ => panic!("Illegal RSP write"),
-                }
-            } else {
-                panic!("Illegal RSP write")
-            }
+    fn adjust_stack_offset(
+        &mut self,
+        opcode: &Binopcode,
+        dst: &Value<Ar>,
+        src1: &Value<Ar>,
+        src2: &Value<Ar>,
+    ) -> Result<(), String> {
+        if !dst.is_rsp() {
+            return Ok(());
         }
+        let adjustment = match (opcode, src2) {
+            (Binopcode::Add, Value::Imm(_, _, imm)) if src1.is_rsp() => *imm,
+            (Binopcode::Sub, Value::Imm(_, _, imm)) if src1.is_rsp() => -imm,
+            _ => return Err("the stack pointer is updated by an unknown amount".to_string()),
+        };
+        self.stack.update_stack_offset(adjustment)
+    }
+
+    fn stack_offset(&self) -> Option<i64> {
+        Some(self.stack.offset)
     }
 }
 
@@ -1,19 +1,29 @@
 use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::convert::TryFrom;
 
-use crate::ir::types::{ValSize, X86Regs};
+use crate::ir::types::{RegT, ValSize, X86Regs};
 use crate::lattices::reachingdefslattice::LocIdx;
 use crate::lattices::{Lattice, VarSlot};
 
-use X86Regs::*;
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ArchRegsLattice<T, Ar: RegT = X86Regs> {
+    pub map: HashMap<Ar, VarSlot<T>>,
+}
+
+pub type X86RegsLattice<T> = ArchRegsLattice<T, X86Regs>;
 
-#[derive(Default, PartialEq, Eq, Clone, Debug)]
-pub struct X86RegsLattice<T> {
-    pub map: HashMap<X86Regs, VarSlot<T>>,
+impl<T, Ar: RegT> Default for ArchRegsLattice<T, Ar> {
+    fn default() -> Self {
+        ArchRegsLattice {
+            map: HashMap::new(),
+        }
+    }
 }
 
-fn hashmap_le<T: PartialOrd>(s1: &X86RegsLattice<T>, s2: &X86RegsLattice<T>) -> bool {
+fn hashmap_le<T: PartialOrd, Ar: RegT>(
+    s1: &ArchRegsLattice<T, Ar>,
+    s2: &ArchRegsLattice<T, Ar>,
+) -> bool {
     for (k1, v1) in s1.map.iter() {
         if !s2.map.contains_key(k1) {
             return false;
@@ -27,7 +37,7 @@ fn hashmap_le<T: PartialOrd>(s1: &X86RegsLattice<T>, s2: &X86RegsLattice<T>) ->
     true
 }
 
-impl<T: PartialOrd> PartialOrd for X86RegsLattice<T> {
+impl<T: PartialOrd, Ar: RegT> PartialOrd for ArchRegsLattice<T, Ar> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         if hashmap_le(self, other) {
             Some(Ordering::Less)
@@ -41,8 +51,8 @@ impl<T: PartialOrd> PartialOrd for X86RegsLattice<T> {
     }
 }
 
-impl<T: Lattice + Clone> X86RegsLattice<T> {
-    pub fn get_reg(&self, index: X86Regs, size: ValSize) -> T {
+impl<T: Lattice + Clone, Ar: RegT> ArchRegsLattice<T, Ar> {
+    pub fn get_reg(&self, index: Ar, size: ValSize) -> T {
         if let Some(slot) = self.map.get(&index) {
             slot.value.clone()
         } else {
@@ -51,14 +61,14 @@ impl<T: Lattice + Clone> X86RegsLattice<T> {
     }
 
     pub fn get_reg_index(&self, index: u8, size: ValSize) -> T {
-        let reg_index = match X86Regs::try_from(index) {
-            Err(err) => panic!("{}", err),
+        let reg_index = match Ar::try_from(index) {
+            Err(_) => panic!("Unknown register: index = {:?}", index),
             Ok(reg) => reg,
         };
         self.get_reg(reg_index, size)
     }
 
-    pub fn set_reg(&mut self, index: X86Regs, size: ValSize, value: T) {
+    pub fn set_reg(&mut self, index: Ar, size: ValSize, value: T) {
         self.map.insert(
             index,
             VarSlot {
@@ -69,8 +79,8 @@ impl<T: Lattice + Clone> X86RegsLattice<T> {
     }
 
     pub fn set_reg_index(&mut self, index: u8, size: ValSize, value: T) -> () {
-        let reg_index = match X86Regs::try_from(index) {
-            Err(err) => panic!("{}", err),
+        let reg_index = match Ar::try_from(index) {
+            Err(_) => panic!("Unknown register: index = {:?}", index),
             Ok(reg) => reg,
         };
         self.set_reg(reg_index, size, value)
@@ -82,27 +92,13 @@ impl<T: Lattice + Clone> X86RegsLattice<T> {
 
     // TODO: should this do the inverse?
     pub fn clear_caller_save_regs(&mut self) {
-        // x86-64 calling convention: rax, rcx, rdx, rsi, rdi, r8, r9, r10, r11 must be saved by
-        // the caller (are clobbered by the callee), so their states become unknown after calls.
+        // Registers that must be saved by the caller (are clobbered by the callee) become
+        // unknown after calls. See `RegT::is_caller_saved` for each architecture's convention.
         //
         // TODO: get calling convention from program's target ABI; on Windows, rsi and rdi are
         // callee-save. The below is thus sound but conservative (and possibly
         // false-positive-producing) on Windows.
-        self.map.remove(&Rax);
-        self.map.remove(&Rcx);
-        self.map.remove(&Rdx);
-        self.map.remove(&Rsi);
-        self.map.remove(&Rdi);
-
-        self.map.remove(&R8);
-        self.map.remove(&R9);
-        self.map.remove(&R10);
-        self.map.remove(&R11);
-        self.map.remove(&Zf);
-        self.map.remove(&Cf);
-        self.map.remove(&Pf);
-        self.map.remove(&Sf);
-        self.map.remove(&Of);
+        self.map.retain(|reg, _| !reg.is_caller_saved());
     }
 
     pub fn show(&self) -> () {
@@ -111,9 +107,9 @@ impl<T: Lattice + Clone> X86RegsLattice<T> {
     }
 }
 
-impl<T: Lattice + Clone> Lattice for X86RegsLattice<T> {
+impl<T: Lattice + Clone, Ar: RegT> Lattice for ArchRegsLattice<T, Ar> {
     fn meet(&self, other: &Self, loc_idx: &LocIdx) -> Self {
-        let mut newmap: HashMap<X86Regs, VarSlot<T>> = HashMap::new();
+        let mut newmap: HashMap<Ar, VarSlot<T>> = HashMap::new();
         for (var_index, v1) in self.map.iter() {
             match other.map.get(var_index) {
                 Some(v2) => {
@@ -127,7 +123,7 @@ impl<T: Lattice + Clone> Lattice for X86RegsLattice<T> {
                 None => (), // this means v2 = ⊥ so v1 ∧ v2 = ⊥
             }
         }
-        X86RegsLattice { map: newmap }
+        ArchRegsLattice { map: newmap }
     }
 }
 
@@ -1,4 +1,4 @@
-use crate::ir::types::{Binopcode, Value};
+use crate::ir::types::{Binopcode, RegT, Value};
 use crate::lattices::{ConstLattice, VarState};
 
 /// Fields are: (stackgrowth, probestack, rbp_stackgrowth)
@@ -10,15 +10,15 @@ use crate::lattices::{ConstLattice, VarState};
 /// from `rbp` in the epilogue, it is copied back.
 pub type StackGrowthLattice = ConstLattice<(i64, i64, i64)>;
 
-impl VarState for StackGrowthLattice {
+impl<Ar: RegT> VarState<Ar> for StackGrowthLattice {
     type Var = i64;
-    fn get(&self, _index: &Value) -> Option<Self::Var> {
+    fn get(&self, _index: &Value<Ar>) -> Option<Self::Var> {
         unimplemented!()
     }
-    fn set(&mut self, _index: &Value, _v: Self::Var) -> () {
+    fn set(&mut self, _index: &Value<Ar>, _v: Self::Var) -> () {
         unimplemented!()
     }
-    fn set_to_bot(&mut self, _index: &Value) -> () {
+    fn set_to_bot(&mut self, _index: &Value<Ar>) -> () {
         unimplemented!()
     }
     fn on_call(&mut self) -> () {
@@ -27,10 +27,10 @@ impl VarState for StackGrowthLattice {
     fn adjust_stack_offset(
         &mut self,
         _opcode: &Binopcode,
-        _dst: &Value,
-        _src1: &Value,
-        _src2: &Value,
-    ) {
+        _dst: &Value<Ar>,
+        _src1: &Value<Ar>,
+        _src2: &Value<Ar>,
+    ) -> Result<(), String> {
         unimplemented!()
     }
 }
@@ -23,12 +23,15 @@ impl<T: std::fmt::Debug + Clone> std::fmt::Display for StackLattice<T> {
 impl<T: Lattice + Clone> StackLattice<T> {
     pub fn update(&mut self, offset: i64, value: T, size: u32) -> () {
         // println!("stack update: {:?} + {:?} = {:?} <- {:?} {:?}", self.offset, offset, self.offset + offset, value, size);
-        //Check if 4 aligned
-        if (offset & 3) != 0 {
-            panic!("Unsafe: Attempt to store value on the stack on not 4-byte aligned address.");
-        }
-        if size > 8 {
-            panic!("Store too large!");
+        // Slots are 4-byte aligned and at most 8 bytes. Other stores are not tracked, and
+        // only clobber the slots they overlap.
+        if (offset & 3) != 0 || size > 8 {
+            let start = self.offset + offset;
+            let end = start + size as i64;
+            self.map.retain(|slot_offset, slot| {
+                *slot_offset + slot.size as i64 <= start || *slot_offset >= end
+            });
+            return;
         }
         //remove overlapping entries
         //if write is size 8: remove next slot (offset + 4) if one exists
@@ -53,8 +56,9 @@ impl<T: Lattice + Clone> StackLattice<T> {
     }
 
     pub fn get(&self, offset: i64, size: u32) -> T {
+        // Only 4- and 8-byte slots are tracked
         if !(size == 4 || size == 8) {
-            panic!("Load wrong size! size = {:?}", size);
+            return Default::default();
         }
 
         // TODO: is this correct?
@@ -70,11 +74,22 @@ impl<T: Lattice + Clone> StackLattice<T> {
         }
     }
 
-    pub fn update_stack_offset(&mut self, adjustment: i64) -> () {
+    pub fn update_stack_offset(&mut self, adjustment: i64) -> Result<(), String> {
         if (adjustment & 3) != 0 {
-            panic!("Unsafe: Attempt to make stack not 4-byte aligned.");
+            return Err(format!(
+                "the stack pointer is moved by {}, which is not a multiple of 4",
+                adjustment
+            ));
         }
         self.offset += adjustment;
+        Ok(())
+    }
+
+    // A callee's frame, and the return address the call pushes, are below the stack pointer at
+    // the call, so only the slots at or above it survive the call.
+    pub fn clear_callee_frame(&mut self) {
+        let offset = self.offset;
+        self.map.retain(|slot_offset, _| *slot_offset >= offset);
     }
 }
 
@@ -170,15 +185,15 @@ fn stack_lattice_test_eq() {
     assert_eq!(x1 == x2, true);
 
     //check equality with adjusted stack
-    x1.update_stack_offset(4);
-    x2.update_stack_offset(4);
+    x1.update_stack_offset(4).unwrap();
+    x2.update_stack_offset(4).unwrap();
     assert_eq!(x1 == x2, true);
 
     //check inequality of different stack adjustments
-    x1.update_stack_offset(4);
-    x2.update_stack_offset(8);
+    x1.update_stack_offset(4).unwrap();
+    x2.update_stack_offset(8).unwrap();
     assert_eq!(x1 == x2, false);
-    x1.update_stack_offset(4);
+    x1.update_stack_offset(4).unwrap();
     assert_eq!(x1 == x2, true);
 
     let y1 = BooleanLattice { v: false };
@@ -242,11 +257,28 @@ fn stack_lattice_test_overlapping_entries() {
     let y3 = BooleanLattice { v: true };
 
     //overlapping entries
-    x1.update_stack_offset(16);
-    x2.update_stack_offset(16);
+    x1.update_stack_offset(16).unwrap();
+    x2.update_stack_offset(16).unwrap();
     x1.update(0, y2, 8);
     x1.update(4, y1, 4);
     x2.update(4, y3, 4);
     print!("{:?} {:?}", x1, x2);
     assert_eq!(x1 == x2, true);
 }
+
+#[test]
+fn stack_lattice_test_callee_frame() {
+    use crate::lattices::BooleanLattice;
+    let mut x: StackLattice<BooleanLattice> = Default::default();
+    let y = BooleanLattice { v: true };
+
+    x.update(0, y, 8);
+    x.update_stack_offset(-16).unwrap();
+    x.update(8, y, 4);
+    x.update(-8, y, 8);
+    x.clear_callee_frame();
+    //slots at or above the stack pointer survive, slots below it do not
+    assert_eq!(x.get(16, 8), y);
+    assert_eq!(x.get(8, 4), y);
+    assert_eq!(x.get(-8, 8), Default::default());
+}
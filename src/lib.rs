@@ -1,5 +1,5 @@
-//! Library entry point for stack and heap validation, given a single
-//! function's machine code and basic-block offsets.
+//! Library entry point for stack, heap and call validation, given a
+//! single function's machine code, basic-block offsets and relocations.
 
 #![allow(dead_code, unused_imports, unused_variables)]
 pub mod analyses;
@@ -9,24 +9,98 @@ pub mod lattices;
 pub mod loaders;
 pub mod runner;
 
-use analyses::run_worklist;
-use analyses::HeapAnalyzer;
-use checkers::check_heap;
-use ir::lift_cfg;
-use ir::types::IRMap;
+use analyses::reaching_defs::ReachingDefnAnalyzer;
+use analyses::{run_worklist, AbstractAnalyzer, AnalysisResult};
+use analyses::{CallAnalyzer, HeapAnalyzer, StackAnalyzer};
+use checkers::{check_calls, check_heap, check_stack, CheckKind, Violation};
+use ir::types::{IRMap, RegT};
+use ir::{lift_cfg, X64};
+use lattices::{Lattice, VarState};
 use loaders::types::{ExecutableType, VwArch, VwMetadata, VwModule};
 use petgraph::graphmap::GraphMap;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
 use yaxpeax_core::analyses::control_flow::{VW_Block, VW_CFG};
 use yaxpeax_core::memory::repr::process::{ModuleData, ModuleInfo, Segment};
 
-#[derive(Clone, Copy, Debug)]
+/// Why a function body failed validation.
+#[derive(Clone, Debug)]
 pub enum ValidationError {
-    HeapUnsafe,
+    /// A heap access may fall outside the linear memory and its guard region. `offset` is
+    /// the code offset of the first such access.
+    HeapUnsafe {
+        offset: u64,
+        violations: Vec<Violation>,
+    },
+    /// The instruction at `offset` is one the lifter or the analyses do not model.
+    UnsupportedInstruction { offset: u64, reason: String },
+    /// The basic blocks or edges do not describe a CFG of the code.
+    CfgInvalid(String),
+    /// The relocation at `offset` cannot be applied to the code.
+    InvalidRelocation { offset: u64, reason: String },
+    /// A stack access or stack-pointer update may be unsafe. `offset` is the code offset of
+    /// the first such instruction.
+    StackUnsafe {
+        offset: u64,
+        violations: Vec<Violation>,
+    },
+    /// An indirect call may not go through a checked table entry. `offset` is the code
+    /// offset of the first such call.
+    CallUnsafe {
+        offset: u64,
+        violations: Vec<Violation>,
+    },
 }
+
+impl ValidationError {
+    // The error for the non-empty violations a checker found, which are all of the same kind.
+    fn from_violations(violations: Vec<Violation>) -> Self {
+        let kind = violations
+            .first()
+            .expect("a validation error needs a violation")
+            .kind;
+        let offset = violations.iter().map(|v| v.addr).min().unwrap_or(0);
+        match kind {
+            CheckKind::Heap => ValidationError::HeapUnsafe { offset, violations },
+            CheckKind::Stack => ValidationError::StackUnsafe { offset, violations },
+            CheckKind::Call | CheckKind::Locals => {
+                ValidationError::CallUnsafe { offset, violations }
+            }
+        }
+    }
+}
+
 impl std::fmt::Display for ValidationError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        std::fmt::Debug::fmt(self, f)
+        match self {
+            ValidationError::HeapUnsafe { offset, violations } => write!(
+                f,
+                "unsafe heap access at offset 0x{:x} ({} violations)",
+                offset,
+                violations.len()
+            ),
+            ValidationError::UnsupportedInstruction { offset, reason } => write!(
+                f,
+                "unsupported instruction at offset 0x{:x}: {}",
+                offset, reason
+            ),
+            ValidationError::CfgInvalid(reason) => write!(f, "invalid CFG: {}", reason),
+            ValidationError::InvalidRelocation { offset, reason } => {
+                write!(f, "invalid relocation at offset 0x{:x}: {}", offset, reason)
+            }
+            ValidationError::StackUnsafe { offset, violations } => write!(
+                f,
+                "unsafe stack access at offset 0x{:x} ({} violations)",
+                offset,
+                violations.len()
+            ),
+            ValidationError::CallUnsafe { offset, violations } => write!(
+                f,
+                "unsafe indirect call at offset 0x{:x} ({} violations)",
+                offset,
+                violations.len()
+            ),
+        }
     }
 }
 impl std::error::Error for ValidationError {}
@@ -35,13 +109,13 @@ impl std::error::Error for ValidationError {}
 /// check to be parameterized to work with different VMs -- first
 /// Lucet, eventually Wasmtime, perhaps others -- that have slightly
 /// different VM-context data structure layouts.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum HeapStrategy {
     /// The first argument to functions is a hidden argument that is
     /// the heap base. Accesses to the heap are computed relative to
-    /// this base. The virtual-memory layout has sufficient guard
-    /// regions that no bounds-checks are necessary as long as only an
-    /// unsigned 32-bit offset is added to the base.
+    /// this base. The virtual-memory layout (see `HeapLayout`) has
+    /// sufficient guard regions that no bounds-checks are necessary as
+    /// long as only an unsigned 32-bit offset is added to the base.
     ///
     /// This corresponds to Lucet's design.
     HeapPtrFirstArgWithGuards,
@@ -54,13 +128,381 @@ pub enum HeapStrategy {
     ///
     /// This corresponds to Wasmtime's design.
     VMCtxFirstArgWithGuards { vmctx_heap_base_ptr_offset: usize },
+
+    /// As above, but the heap may be smaller than its guard regions
+    /// assume, so an index must be compared against the current heap
+    /// length before it is used (`cmp idx, [vmctx + bound]; jae trap`).
+    /// The heap length is at `vmctx_heap_bound_offset` within vmctx.
+    /// `HeapLayout` then describes the part of the heap that is mapped
+    /// whatever its length (`reservation`) and the guard region past
+    /// the heap length (`guard`).
+    ///
+    /// Indices of a 64-bit memory must be bounds-checked or masked;
+    /// a zero-extended 32-bit register is not a valid index.
+    ///
+    /// This corresponds to Wasmtime's dynamic memories.
+    VMCtxFirstArgWithBoundsChecks {
+        vmctx_heap_base_ptr_offset: usize,
+        vmctx_heap_bound_offset: usize,
+        index_width: IndexWidth,
+    },
+
+    /// The first argument to functions is a hidden VM-context struct
+    /// pointer, through which functions reach several linear memories
+    /// (the multi-memory proposal). Memory `i` is `memories[i]`. An
+    /// access based at one memory must not use an index that was
+    /// bounds-checked against another.
+    VMCtxFirstArgMultiMemory { memories: Vec<VMCtxMemory> },
+}
+
+impl HeapStrategy {
+    /// The width of the indices of memory `memory`. Heaps relying on
+    /// guard regions only have 32-bit indices.
+    pub fn index_width(&self, memory: u32) -> IndexWidth {
+        match self {
+            HeapStrategy::VMCtxFirstArgWithBoundsChecks { index_width, .. } => *index_width,
+            HeapStrategy::VMCtxFirstArgMultiMemory { memories } => memories
+                .get(memory as usize)
+                .map_or(IndexWidth::I32, |m| m.index_width),
+            _ => IndexWidth::I32,
+        }
+    }
+
+    /// The offsets within vmctx of the base pointer and, if its
+    /// indices are bounds-checked, of the length of each memory.
+    pub fn vmctx_offsets(&self) -> Vec<(usize, Option<usize>)> {
+        match self {
+            HeapStrategy::HeapPtrFirstArgWithGuards => vec![],
+            HeapStrategy::VMCtxFirstArgWithGuards {
+                vmctx_heap_base_ptr_offset,
+            } => vec![(*vmctx_heap_base_ptr_offset, None)],
+            HeapStrategy::VMCtxFirstArgWithBoundsChecks {
+                vmctx_heap_base_ptr_offset,
+                vmctx_heap_bound_offset,
+                ..
+            } => vec![(*vmctx_heap_base_ptr_offset, Some(*vmctx_heap_bound_offset))],
+            HeapStrategy::VMCtxFirstArgMultiMemory { memories } => memories
+                .iter()
+                .map(|m| (m.base_ptr_offset, m.bound_offset))
+                .collect(),
+        }
+    }
+
+    /// Whether memory `memory` is bounds-checked against its current
+    /// length. Such a memory may move when it grows, so its base does
+    /// not survive a call.
+    pub fn is_bounds_checked(&self, memory: u32) -> bool {
+        self.vmctx_offsets()
+            .get(memory as usize)
+            .map_or(false, |(_, bound_offset)| bound_offset.is_some())
+    }
+}
+
+/// One of the linear memories of `HeapStrategy::VMCtxFirstArgMultiMemory`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VMCtxMemory {
+    /// The offset within vmctx of the pointer to the memory's base.
+    pub base_ptr_offset: usize,
+    /// The offset within vmctx of the memory's current length, if
+    /// indices are bounds-checked against it; `None` if the memory
+    /// relies on guard regions only.
+    pub bound_offset: Option<usize>,
+    pub index_width: IndexWidth,
+    /// The layout of this memory, in place of the module's.
+    pub layout: HeapLayout,
+}
+
+/// The width of the indices of a Wasm memory: 32-bit, or 64-bit for
+/// memory64.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexWidth {
+    I32,
+    I64,
+}
+
+/// The virtual-memory layout around the Wasm heap, in bytes. The
+/// heap base is followed by `reservation` bytes of address space for
+/// the heap, then by `guard` bytes of unmapped guard region; an access
+/// that may reach past both must be bounds-checked. The globals base
+/// points to a `globals_size`-byte area, and the vmctx pointer to a
+/// `vmctx_size`-byte struct.
+///
+/// The default is Lucet's layout: a 4GB reservation, a 4GB guard
+/// region and a 4KB globals area, with a 4KB vmctx. Wasmtime's static
+/// memories on x86-64 only have a 2GB guard region (`HeapLayout::wasmtime`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeapLayout {
+    pub reservation: u64,
+    pub guard: u64,
+    pub globals_size: u64,
+    pub vmctx_size: u64,
+}
+
+impl HeapLayout {
+    /// The size of the reservation and the guard region together: no
+    /// access at a smaller offset from the heap base can escape.
+    pub fn guarded_size(&self) -> u64 {
+        self.reservation.saturating_add(self.guard)
+    }
+
+    /// The layout of Wasmtime's default static memories on x86-64.
+    pub fn wasmtime() -> Self {
+        HeapLayout {
+            guard: 0x8000_0000,
+            ..HeapLayout::default()
+        }
+    }
+
+    /// The layout the runtime of modules of `format` sets up by default.
+    pub fn for_format(format: ExecutableType) -> Self {
+        match format {
+            ExecutableType::Lucet => HeapLayout::default(),
+            ExecutableType::Wasmtime => HeapLayout::wasmtime(),
+        }
+    }
+}
+
+impl Default for HeapLayout {
+    fn default() -> Self {
+        HeapLayout {
+            reservation: 0x1_0000_0000,
+            guard: 0x1_0000_0000,
+            globals_size: 0x1000,
+            vmctx_size: 0x1000,
+        }
+    }
+}
+
+/// The registers the heap base and vmctx are in, numbered as in the
+/// architecture's register enum (`X86Regs` or `Aarch64Regs`). On
+/// entry, `heap_base` holds the heap base or `vmctx` the vmctx
+/// pointer, whichever the `HeapStrategy` passes; unset, they are the
+/// first argument register. A `pinned_heap_base` holds the base of
+/// memory 0 throughout every function, as with Cranelift's
+/// `enable_pinned_reg`: it must still hold the heap base at each call,
+/// and since callers assume that calls preserve it, at each return.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapRegs {
+    pub heap_base: Option<u8>,
+    pub vmctx: Option<u8>,
+    pub pinned_heap_base: Option<u8>,
+}
+
+impl HeapRegs {
+    pub fn heap_base_reg<Ar: RegT>(&self) -> Ar {
+        self.heap_base
+            .map_or_else(Ar::pinned_heap_reg, reg_numbered)
+    }
+
+    pub fn vmctx_reg<Ar: RegT>(&self) -> Ar {
+        self.vmctx.map_or_else(Ar::pinned_vmctx_reg, reg_numbered)
+    }
+
+    pub fn pinned_heap_base_reg<Ar: RegT>(&self) -> Option<Ar> {
+        self.pinned_heap_base.map(reg_numbered)
+    }
+}
+
+fn reg_numbered<Ar: RegT>(regnum: u8) -> Ar {
+    Ar::try_from(regnum).unwrap_or_else(|_| panic!("No register numbered {}", regnum))
+}
+
+/// A relocation of a function body, as known to the compiler that
+/// emitted it. Relocations stand in for the symbols of an ELF binary:
+/// they tell `validate_stack` and `validate_calls` which calls are
+/// stack probes or direct calls and which constants are the tables
+/// that indirect calls go through. Offsets are from the start of the
+/// body.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Relocation {
+    /// The `call rel32` at `offset` calls the stack probe
+    /// (`lucet_probestack`).
+    ProbestackCall { offset: usize },
+    /// The `call rel32` at `offset` calls the function numbered
+    /// `func_index` in the module.
+    DirectCall { offset: usize, func_index: u32 },
+    /// The 64-bit immediate at `offset` is the address of the
+    /// function table (`guest_table_0`).
+    FunctionTable { offset: usize },
+    /// The 64-bit immediate at `offset` is the address of the
+    /// table-size global (`lucet_tables`).
+    TableSize { offset: usize },
+}
+
+/// The options of `validate_function` and of the single-check entry
+/// points. Start from `ValidateOptions::default()` and set the options
+/// that differ.
+///
+/// By default the heap base is the first argument, the heap is laid
+/// out as Lucet lays it out, no register is pinned, the body has no
+/// relocations and calls no other function, and no call target has a
+/// symbol name.
+#[derive(Clone, Debug)]
+pub struct ValidateOptions {
+    heap_strategy: HeapStrategy,
+    heap_layout: HeapLayout,
+    heap_regs: HeapRegs,
+    relocations: Vec<Relocation>,
+    callees: Vec<u32>,
+    symbols: HashMap<u64, String>,
+}
+
+impl Default for ValidateOptions {
+    fn default() -> Self {
+        ValidateOptions {
+            heap_strategy: HeapStrategy::HeapPtrFirstArgWithGuards,
+            heap_layout: HeapLayout::default(),
+            heap_regs: HeapRegs::default(),
+            relocations: vec![],
+            callees: vec![],
+            symbols: HashMap::new(),
+        }
+    }
+}
+
+impl ValidateOptions {
+    pub fn heap_strategy(mut self, heap_strategy: HeapStrategy) -> Self {
+        self.heap_strategy = heap_strategy;
+        self
+    }
+
+    pub fn heap_layout(mut self, heap_layout: HeapLayout) -> Self {
+        self.heap_layout = heap_layout;
+        self
+    }
+
+    pub fn heap_regs(mut self, heap_regs: HeapRegs) -> Self {
+        self.heap_regs = heap_regs;
+        self
+    }
+
+    pub fn relocations(mut self, relocations: &[Relocation]) -> Self {
+        self.relocations = relocations.to_vec();
+        self
+    }
+
+    /// The functions, by index, that the body may call directly.
+    pub fn callees(mut self, callees: &[u32]) -> Self {
+        self.callees = callees.to_vec();
+        self
+    }
+
+    /// A map from call-target addresses to symbol names (e.g. recovered
+    /// from relocations by the caller), used to name the calls of the
+    /// heap check's reports.
+    pub fn symbols(mut self, symbols: HashMap<u64, String>) -> Self {
+        self.symbols = symbols;
+        self
+    }
+}
+
+// Where relocated symbols are placed. The tables are far from any code address; the stack
+// probe and the functions of the module are just past the code, so that a rel32 reaches them.
+const GUEST_TABLE_0: u64 = 0x123456789abcdef0;
+const LUCET_TABLES: u64 = 0x123456789abcdef8;
+
+// The address of the stack probe.
+fn probestack_addr(code: &[u8]) -> u64 {
+    (code.len() as u64 + 0xf) & !0xf
+}
+
+// The address of the function numbered `func_index` in the module.
+fn func_addr(code: &[u8], func_index: u32) -> u64 {
+    probestack_addr(code) + 0x10 * (func_index as u64 + 1)
+}
+
+// `code` with `relocations` applied.
+fn apply_relocations(code: &[u8], relocations: &[Relocation]) -> Result<Vec<u8>, ValidationError> {
+    let invalid = |offset: usize, reason: &str| {
+        Err(ValidationError::InvalidRelocation {
+            offset: offset as u64,
+            reason: reason.to_string(),
+        })
+    };
+    let probestack = probestack_addr(code);
+    let mut patched = code.to_vec();
+    for relocation in relocations {
+        let (offset, len, bytes) = match *relocation {
+            Relocation::ProbestackCall { offset } | Relocation::DirectCall { offset, .. } => {
+                let target = match *relocation {
+                    Relocation::DirectCall { func_index, .. } => func_addr(code, func_index),
+                    _ => probestack,
+                };
+                if code.get(offset) != Some(&0xe8) {
+                    return invalid(offset, "not a call rel32");
+                }
+                let rel = match i32::try_from(target as i128 - (offset as i128 + 5)) {
+                    Ok(rel) => rel,
+                    Err(_) => return invalid(offset, "call target out of rel32 range"),
+                };
+                (offset + 1, 4, rel.to_le_bytes().to_vec())
+            }
+            Relocation::FunctionTable { offset } => {
+                (offset, 8, GUEST_TABLE_0.to_le_bytes().to_vec())
+            }
+            Relocation::TableSize { offset } => (offset, 8, LUCET_TABLES.to_le_bytes().to_vec()),
+        };
+        match offset.checked_add(len) {
+            Some(end) if end <= patched.len() => patched[offset..end].copy_from_slice(&bytes),
+            _ => return invalid(offset, "past the end of the code"),
+        }
+    }
+    Ok(patched)
+}
+
+// Checks that `basic_blocks` and `cfg_edges` describe a CFG of `code` whose entry is at 0.
+fn check_cfg_input(
+    code: &[u8],
+    basic_blocks: &[usize],
+    cfg_edges: &[(usize, usize)],
+) -> Result<(), ValidationError> {
+    let invalid = |reason: String| Err(ValidationError::CfgInvalid(reason));
+    match basic_blocks.first() {
+        None => return invalid("no basic blocks".to_string()),
+        Some(&first) if first != 0 => {
+            return invalid(format!("first block starts at 0x{:x}, not at 0", first))
+        }
+        _ => (),
+    }
+    for pair in basic_blocks.windows(2) {
+        if pair[1] == pair[0] {
+            return invalid(format!("block at 0x{:x} has zero length", pair[0]));
+        }
+        if pair[1] < pair[0] {
+            return invalid(format!(
+                "block at 0x{:x} follows block at 0x{:x}",
+                pair[1], pair[0]
+            ));
+        }
+    }
+    let last = basic_blocks[basic_blocks.len() - 1];
+    if last >= code.len() {
+        return invalid(format!(
+            "block at 0x{:x} starts past the end of the code (0x{:x} bytes)",
+            last,
+            code.len()
+        ));
+    }
+    for &(from, to) in cfg_edges {
+        if basic_blocks.binary_search(&from).is_err() || basic_blocks.binary_search(&to).is_err() {
+            return invalid(format!(
+                "edge 0x{:x} -> 0x{:x} does not connect two blocks",
+                from, to
+            ));
+        }
+    }
+    Ok(())
 }
 
 fn func_body_and_bbs_to_cfg(
     code: &[u8],
     basic_blocks: &[usize],
     cfg_edges: &[(usize, usize)],
-) -> (VW_CFG, IRMap, VwModule) {
+    relocations: &[Relocation],
+) -> Result<(VW_CFG, IRMap, VwModule), ValidationError> {
+    check_cfg_input(code, basic_blocks, cfg_edges)?;
+    let code = &apply_relocations(code, relocations)?;
+
     // We build the VW_CFG manually; we skip the CFG-recovery
     // algorithm that has to analyze the machine code and compute
     // reaching-defs in a fixpoint loop.
@@ -77,7 +519,6 @@ fn func_body_and_bbs_to_cfg(
         } else {
             basic_blocks[i + 1] as u64
         };
-        assert!(end > start, "block has zero length: {} -> {}", start, end);
         let end = end - 1; // `end` is inclusive!
         let bb = VW_Block { start, end };
         cfg.blocks.insert(start, bb);
@@ -128,9 +569,11 @@ fn func_body_and_bbs_to_cfg(
         module_info,
     };
     let lucet = VwMetadata {
-        guest_table_0: 0x123456789abcdef0,
-        lucet_tables: 0x123456789abcdef0,
-        lucet_probestack: 0x123456789abcdef0,
+        guest_table_0: GUEST_TABLE_0,
+        lucet_tables: LUCET_TABLES,
+        lucet_probestack: probestack_addr(code),
+        heap_layout: HeapLayout::default(),
+        heap_regs: HeapRegs::default(),
     };
 
     let module = VwModule {
@@ -138,17 +581,116 @@ fn func_body_and_bbs_to_cfg(
         metadata: lucet,
         format: ExecutableType::Lucet,
         arch: VwArch::X64,
+        plt_funcs: vec![],
+        debug_sections: Default::default(),
     };
 
-    let irmap = lift_cfg(&module, &cfg, false);
+    let irmap = lift_cfg::<X64>(&module, &cfg, false).map_err(|err| {
+        ValidationError::UnsupportedInstruction {
+            offset: err.addr,
+            reason: err.reason,
+        }
+    })?;
 
-    (cfg, irmap, module)
+    Ok((cfg, irmap, module))
 
     // TODO: regalloc checker from Lucet too.
     // TODO: audit opcodes. Fallback to just clear dest(s) on unknown?
-    // TODO: how hard would this be to adapt to Wasmtime? Extra level of indirection:
-    //       heap-base loaded from vmctx (rdi) instead. Take a mode argument?
-    //       "Heap-access style"
+}
+
+// Runs `analyzer`, which serves the `kind` check, to a fixpoint and checks the result with
+// `check`. A stack pointer the analysis cannot track fails the stack check; the other checks
+// do not model such code.
+fn run_pass<State, Ar, A>(
+    cfg: &VW_CFG,
+    irmap: &IRMap<Ar>,
+    analyzer: &A,
+    kind: CheckKind,
+    check: impl FnOnce(&AnalysisResult<State>) -> Vec<Violation>,
+) -> Result<AnalysisResult<State>, ValidationError>
+where
+    State: Lattice + VarState<Ar> + Clone,
+    Ar: RegT,
+    A: AbstractAnalyzer<State, Ar>,
+{
+    let result = run_worklist(cfg, irmap, analyzer).map_err(|err| match kind {
+        CheckKind::Stack => ValidationError::from_violations(vec![Violation::stack_update(err)]),
+        _ => ValidationError::UnsupportedInstruction {
+            offset: err.loc_idx().addr,
+            reason: err.reason().to_string(),
+        },
+    })?;
+    let violations = check(&result);
+    if violations.is_empty() {
+        Ok(result)
+    } else {
+        Err(ValidationError::from_violations(violations))
+    }
+}
+
+fn check_heap_pass(
+    cfg: &VW_CFG,
+    irmap: &IRMap,
+    metadata: &VwMetadata,
+    options: &ValidateOptions,
+) -> Result<(), ValidationError> {
+    let heap_analyzer = HeapAnalyzer {
+        metadata: VwMetadata {
+            heap_layout: options.heap_layout,
+            heap_regs: options.heap_regs,
+            ..metadata.clone()
+        },
+        strategy: options.heap_strategy.clone(),
+        summaries: Default::default(),
+    };
+    run_pass(cfg, irmap, &heap_analyzer, CheckKind::Heap, |result| {
+        check_heap(result.clone(), irmap, &heap_analyzer, &options.symbols)
+    })?;
+    Ok(())
+}
+
+fn check_stack_pass(cfg: &VW_CFG, irmap: &IRMap) -> Result<(), ValidationError> {
+    let stack_analyzer = StackAnalyzer {};
+    run_pass(cfg, irmap, &stack_analyzer, CheckKind::Stack, |result| {
+        check_stack(result.clone(), irmap, &stack_analyzer)
+    })?;
+    Ok(())
+}
+
+fn check_call_pass(
+    cfg: &VW_CFG,
+    irmap: &IRMap,
+    metadata: &VwMetadata,
+    code: &[u8],
+    callees: &[u32],
+) -> Result<(), ValidationError> {
+    let reaching_analyzer = ReachingDefnAnalyzer {
+        cfg: cfg.clone(),
+        irmap: irmap.clone(),
+    };
+    let reaching_defs = run_pass(cfg, irmap, &reaching_analyzer, CheckKind::Call, |_| vec![])?;
+    // The function may call itself and, through a `DirectCall` relocation, the functions
+    // numbered in `callees`. There is no PLT.
+    let funcs: Vec<u64> = std::iter::once(cfg.entrypoint)
+        .chain(
+            callees
+                .iter()
+                .map(|func_index| func_addr(code, *func_index)),
+        )
+        .collect();
+    let call_analyzer = CallAnalyzer {
+        metadata: metadata.clone(),
+        reaching_defs,
+        reaching_analyzer,
+        funcs: funcs.clone(),
+        irmap: irmap.clone(),
+        cfg: cfg.clone(),
+        summaries: Default::default(),
+    };
+    run_pass(cfg, irmap, &call_analyzer, CheckKind::Call, |result| {
+        check_calls(result.clone(), irmap, &call_analyzer, &funcs, &(0, 0))
+    })?;
+    Ok(())
 }
 
 pub fn validate_heap(
@@ -156,25 +698,30 @@ pub fn validate_heap(
     basic_blocks: &[usize],
     cfg_edges: &[(usize, usize)],
     heap_strategy: HeapStrategy,
+) -> Result<(), ValidationError> {
+    validate_heap_with_options(
+        code,
+        basic_blocks,
+        cfg_edges,
+        &ValidateOptions::default().heap_strategy(heap_strategy),
+    )
+}
+
+/// Like `validate_heap`, with the heap strategy, layout and registers,
+/// the relocations and the symbol names of call targets in `options`.
+pub fn validate_heap_with_options(
+    code: &[u8],
+    basic_blocks: &[usize],
+    cfg_edges: &[(usize, usize)],
+    options: &ValidateOptions,
 ) -> Result<(), ValidationError> {
     log::debug!(
         "validate_heap: basic_blocks = {:?}, edges = {:?}",
         basic_blocks,
         cfg_edges
     );
-    // For now, we don't support Wasmtime-style heap accesses.
-    // TODO: implement these:
-    // - Add a lattice value: VMCtxPtr
-    // - Add a rule: load from [VMCtxPtr + heap_base_ptr_offset] -> HeapBase
-    match heap_strategy {
-        HeapStrategy::HeapPtrFirstArgWithGuards => {}
-        _ => {
-            log::debug!("Unknown heap strategy: {:?}", heap_strategy);
-            return Err(ValidationError::HeapUnsafe);
-        }
-    }
-
-    let (cfg, irmap, module) = func_body_and_bbs_to_cfg(code, basic_blocks, cfg_edges);
+    let (cfg, irmap, module) =
+        func_body_and_bbs_to_cfg(code, basic_blocks, cfg_edges, &options.relocations)?;
 
     // This entry point is designed to allow checking of a single
     // function body, just after it has been generated in memory,
@@ -184,7 +731,9 @@ pub fn validate_heap(
     // Without symbols/relocs, we don't know which calls are to
     // `lucet_probestack()`, so we can't do a stack-use soundness
     // check, and we don't know which globals are `lucet_tables` and
-    // `guest_table_0`, so we can't check instance function calls. We
+    // `guest_table_0`, so we can't check instance function calls
+    // (`validate_stack` and `validate_calls` take these from the
+    // caller's relocations instead). We
     // also can't really do the full CFG recovery analysis and CFI
     // checks because it's very expensive (the reaching-defs analysis
     // has not been optimized) and requires knowing other function
@@ -203,14 +752,51 @@ pub fn validate_heap(
     // heap access is the most exposed -- it's just ordinary CLIF IR
     // that goes through the compilation pipeline with opt passes like
     // all other code. It's also the fastest and simplest to check.
-    let heap_analyzer = HeapAnalyzer {
-        metadata: module.metadata.clone(),
-    };
-    let heap_result = run_worklist(&cfg, &irmap, &heap_analyzer);
-    // let heap_safe = check_heap(heap_result, &irmap, &heap_analyzer);
-    // if !heap_safe {
-    //     return Err(ValidationError::HeapUnsafe);
-    // }
+    check_heap_pass(&cfg, &irmap, &module.metadata, options)
+}
 
-    Ok(())
+/// Checks the stack safety of a single function body: the stack
+/// pointer is known everywhere, and every stack access stays within
+/// the frame and the region the stack probe has touched. Calls to the
+/// stack probe are identified by the relocations of `options`.
+pub fn validate_stack(
+    code: &[u8],
+    basic_blocks: &[usize],
+    cfg_edges: &[(usize, usize)],
+    options: &ValidateOptions,
+) -> Result<(), ValidationError> {
+    let (cfg, irmap, _) =
+        func_body_and_bbs_to_cfg(code, basic_blocks, cfg_edges, &options.relocations)?;
+    check_stack_pass(&cfg, &irmap)
+}
+
+/// Checks that every indirect call of a single function body goes
+/// through a bounds-checked entry of the function table, and that
+/// direct calls target the body itself or one of the functions
+/// numbered in the callees of `options`. The tables and direct calls
+/// are identified by the relocations of `options`.
+pub fn validate_calls(
+    code: &[u8],
+    basic_blocks: &[usize],
+    cfg_edges: &[(usize, usize)],
+    options: &ValidateOptions,
+) -> Result<(), ValidationError> {
+    let (cfg, irmap, module) =
+        func_body_and_bbs_to_cfg(code, basic_blocks, cfg_edges, &options.relocations)?;
+    check_call_pass(&cfg, &irmap, &module.metadata, code, &options.callees)
+}
+
+/// Runs the stack, heap and call checks on a single function body,
+/// in that order, and returns the first failure.
+pub fn validate_function(
+    code: &[u8],
+    basic_blocks: &[usize],
+    cfg_edges: &[(usize, usize)],
+    options: &ValidateOptions,
+) -> Result<(), ValidationError> {
+    let (cfg, irmap, module) =
+        func_body_and_bbs_to_cfg(code, basic_blocks, cfg_edges, &options.relocations)?;
+    check_stack_pass(&cfg, &irmap)?;
+    check_heap_pass(&cfg, &irmap, &module.metadata, options)?;
+    check_call_pass(&cfg, &irmap, &module.metadata, code, &options.callees)
 }
@@ -1,99 +1,72 @@
-use crate::{loaders, runner};
-use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
-use elfkit::relocation::RelocationType;
-use elfkit::{symbol, types, DynamicContent, Elf, SectionContent};
-use goblin::Object;
+use crate::{loaders, runner, HeapLayout, HeapRegs};
+use byteorder::{LittleEndian, ReadBytesExt};
 use loaders::types::{VwFuncInfo, VwMetadata, VwModule};
 use loaders::utils::*;
 use loaders::utils::{deconstruct_elf, get_symbol_addr};
 use lucet_module;
 use std::collections::HashMap;
-use std::fs::File;
-use std::fs::OpenOptions;
-use std::io::Cursor;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Cursor, Seek, SeekFrom};
 use std::mem;
-use std::path::Path;
 use std::string::String;
+use yaxpeax_core::goblin::elf::section_header::{SHT_DYNSYM, SHT_SYMTAB};
+use yaxpeax_core::goblin::elf::Elf;
 use yaxpeax_core::memory::repr::process::{ModuleData, Segment};
-use yaxpeax_core::memory::repr::FileRepr;
 
-pub fn lucet_get_plt_funcs(binpath: &str) -> Option<Vec<(u64, String)>> {
-    //Extract relocation symbols
-    let mut in_file = OpenOptions::new().read(true).open(binpath).unwrap();
-    let mut elf = Elf::from_reader(&mut in_file).unwrap();
-    elf.load_all().unwrap();
+// The imported functions of the ELF image `buffer`, parsed as `elf`, by the address of their
+// PLT entry.
+fn lucet_plt_funcs(elf: &Elf, buffer: &[u8]) -> Result<Vec<(u64, String)>, String> {
     // Parse relocs to get mapping from target to name
     let mut target_to_name = HashMap::new();
-    for section in &elf.sections {
-        match section.content {
-            SectionContent::Relocations(ref relocs) => {
-                for reloc in relocs {
-                    elf.sections
-                        .get(section.header.link as usize)
-                        .and_then(|sec| sec.content.as_symbols())
-                        .and_then(|symbols| symbols.get(reloc.sym as usize))
-                        .and_then(|symbol| {
-                            if symbol.name.len() > 0 {
-                                target_to_name.insert(reloc.addr, symbol.name.clone());
-                                // println!("{:x} {:} ", reloc.addr, symbol.name);
-                                Some(())
-                            } else {
-                                None
-                            }
-                        })
-                        .unwrap_or_else(|| {
-                            // print!("{: <20.20} ", reloc.sym);
-                        });
+    for (section_idx, relocs) in elf.shdr_relocs.iter() {
+        let symtab = elf
+            .section_headers
+            .get(*section_idx)
+            .and_then(|section| elf.section_headers.get(section.sh_link as usize))
+            .ok_or_else(|| format!("relocations for missing section {}", section_idx))?;
+        let (syms, strtab) = match symtab.sh_type {
+            SHT_DYNSYM => (&elf.dynsyms, &elf.dynstrtab),
+            SHT_SYMTAB => (&elf.syms, &elf.strtab),
+            _ => continue,
+        };
+        for reloc in relocs.iter() {
+            let name = syms
+                .get(reloc.r_sym)
+                .and_then(|sym| strtab.get(sym.st_name))
+                .and_then(Result::ok);
+            match name {
+                Some(name) if !name.is_empty() => {
+                    target_to_name.insert(reloc.r_offset, name.to_string());
                 }
+                _ => (),
             }
-            _ => (),
         }
     }
     // Parse PLT to get mapping from address to target
-    let mut addr_to_target = HashMap::new();
-    let plt_section = elf.sections.iter().find(|sec| sec.name == ".plt");
-    // if plt_section.is_none(){
-    //     return Vec::new();
-    // };
-    let plt_section = plt_section?;
-    let plt_start = plt_section.header.addr;
-    if let SectionContent::Raw(buf) = &plt_section.content {
-        let mut rdr = Cursor::new(buf);
-        let mut idx: usize = 0;
-        while idx < buf.len() {
-            rdr.seek(SeekFrom::Current(2)).unwrap();
-            let offset = rdr.read_i32::<LittleEndian>().unwrap();
-            rdr.seek(SeekFrom::Current(10)).unwrap();
-            let addr = plt_start + (idx as u64);
-            println!("{:x} {:x} {:x}", plt_start, idx, offset);
-            let target = plt_start + (idx as u64) + (offset as u64) + 6;
-            addr_to_target.insert(addr, target);
-            idx += 16;
-        }
-    } else {
-        panic!("No plt section?");
-    }
-    // println!("===== Addr to Target =======");
-    // for (addr,target) in &addr_to_target{
-    //     println!("{:x} {:x}", addr, target);
-    // }
-    // println!("===== Target to Name =======");
-    // for (target, name) in &target_to_name{
-    //     println!("{:x} {:}", target, name);
-    // }
-
+    let plt_section = elf
+        .section_headers
+        .iter()
+        .find(|section| section_name(elf, section).ok() == Some(".plt"));
+    let plt_section = match plt_section {
+        Some(section) => section,
+        None => return Ok(vec![]),
+    };
+    let plt_start = plt_section.sh_addr;
+    let plt = (plt_section.sh_offset as usize)
+        .checked_add(plt_section.sh_size as usize)
+        .and_then(|end| buffer.get(plt_section.sh_offset as usize..end))
+        .ok_or_else(|| "section .plt is out of bounds".to_string())?;
+    // Each 16-byte entry starts with a `jmp [rip + offset]` through the GOT slot that the
+    // relocation of the imported function fills in.
     let mut plt_funcs: Vec<(u64, String)> = Vec::new();
-    for (addr, target) in &addr_to_target {
-        if target_to_name.contains_key(target) {
-            let name = target_to_name[target].clone();
-            plt_funcs.push((*addr, name));
+    for (idx, entry) in plt.chunks_exact(16).enumerate() {
+        let offset = i32::from_le_bytes([entry[2], entry[3], entry[4], entry[5]]);
+        let addr = plt_start + (idx as u64) * 16;
+        let target = addr.wrapping_add(offset as u64).wrapping_add(6);
+        if let Some(name) = target_to_name.get(&target) {
+            plt_funcs.push((addr, name.clone()));
         }
     }
-    // println!("plt_funcs: {:?}", plt_funcs);
-
-    // unimplemented!();
-    Some(plt_funcs)
+    Ok(plt_funcs)
 }
 
 // pub fn load_lucet_program(binpath: &str) -> ModuleData {
@@ -121,33 +94,37 @@ pub fn lucet_get_plt_funcs(binpath: &str) -> Option<Vec<(u64, String)>> {
 //     }
 // }
 
-pub fn load_lucet_metadata(program: &ModuleData) -> VwMetadata {
+pub fn load_lucet_metadata(
+    program: &ModuleData,
+    heap_layout: HeapLayout,
+    heap_regs: HeapRegs,
+) -> Result<VwMetadata, String> {
     let (_, sections, entrypoint, imports, exports, symbols) = deconstruct_elf(program);
 
-    let guest_table_0 = get_symbol_addr(symbols, "guest_table_0").unwrap();
-    let lucet_tables = get_symbol_addr(symbols, "lucet_tables").unwrap();
-    let lucet_probestack = get_symbol_addr(symbols, "lucet_probestack").unwrap();
-    VwMetadata {
-        guest_table_0: guest_table_0,
-        lucet_tables: lucet_tables,
-        lucet_probestack: lucet_probestack,
-    }
+    let symbol_addr =
+        |name: &str| get_symbol_addr(symbols, name).ok_or_else(|| format!("no {} symbol", name));
+    Ok(VwMetadata {
+        guest_table_0: symbol_addr("guest_table_0")?,
+        lucet_tables: symbol_addr("lucet_tables")?,
+        lucet_probestack: symbol_addr("lucet_probestack")?,
+        heap_layout: heap_layout,
+        heap_regs: heap_regs,
+    })
 }
 
-pub fn load_lucet_program(config: &runner::Config) -> VwModule {
-    let program =
-        yaxpeax_core::memory::reader::load_from_path(Path::new(&config.module_path)).unwrap();
-    if let FileRepr::Executable(program) = program {
-        let metadata = load_lucet_metadata(&program);
-        VwModule {
-            program,
-            metadata,
-            format: config.executable_type,
-            arch: config.arch,
-        }
-    } else {
-        panic!("function:{} is not a valid path", config.module_path)
-    }
+pub fn load_lucet_program(config: &runner::Config, buffer: Vec<u8>) -> Result<VwModule, String> {
+    let program = ModuleData::load_from(&buffer, config.module_path.clone())
+        .ok_or_else(|| "not a valid ELF file".to_string())?;
+    let metadata = load_lucet_metadata(&program, config.heap_layout, config.heap_regs)?;
+    let elf = Elf::parse(&buffer).map_err(|err| format!("not a valid ELF file: {}", err))?;
+    Ok(VwModule {
+        program,
+        metadata,
+        format: config.executable_type,
+        arch: config.arch,
+        plt_funcs: lucet_plt_funcs(&elf, &buffer)?,
+        debug_sections: debug_sections(&elf, &buffer)?,
+    })
 }
 
 // func name is valid if:
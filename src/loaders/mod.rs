@@ -7,22 +7,33 @@ use crate::runner::Config;
 use loaders::lucet::*;
 use loaders::types::*;
 use loaders::wasmtime::*;
+use std::fs;
 use yaxpeax_core::memory::repr::process::ModuleData;
 
 // TODO: this should be static dispatch, not dynamic dispatch
 // not performance critical, but static dispatch is more rusty
 
-pub fn load_program(config: &Config) -> VwModule {
+/// Loads the module at `config.module_path`, or describes why it is not a valid module of
+/// its format.
+pub fn load_program(config: &Config) -> Result<VwModule, String> {
+    let buffer = fs::read(&config.module_path)
+        .map_err(|err| format!("Unable to read {}: {}", config.module_path, err))?;
+    load_program_from_bytes(config, buffer)
+}
+
+/// Like `load_program`, for a module that is already in memory. `config.module_path` only
+/// names the module.
+pub fn load_program_from_bytes(config: &Config, buffer: Vec<u8>) -> Result<VwModule, String> {
     match config.executable_type {
-        ExecutableType::Lucet => load_lucet_program(config),
-        ExecutableType::Wasmtime => load_wasmtime_program(config),
+        ExecutableType::Lucet => load_lucet_program(config, buffer),
+        ExecutableType::Wasmtime => load_wasmtime_program(config, buffer),
     }
+    .map_err(|err| format!("Unable to load {}: {}", config.module_path, err))
 }
 
 pub trait Loadable {
     fn is_valid_func_name(&self, name: &String) -> bool;
     fn get_func_signatures(&self, program: &ModuleData) -> VwFuncInfo;
-    fn get_plt_funcs(&self, binpath: &str) -> Option<Vec<(u64, String)>>;
 }
 
 impl Loadable for ExecutableType {
@@ -39,11 +50,4 @@ impl Loadable for ExecutableType {
             ExecutableType::Wasmtime => get_wasmtime_func_signatures(program),
         }
     }
-
-    fn get_plt_funcs(&self, binpath: &str) -> Option<Vec<(u64, String)>> {
-        match self {
-            ExecutableType::Lucet => lucet_get_plt_funcs(binpath),
-            ExecutableType::Wasmtime => wasmtime_get_plt_funcs(binpath),
-        }
-    }
 }
@@ -1,3 +1,4 @@
+use crate::{HeapLayout, HeapRegs};
 use core::str::FromStr;
 use lucet_module::Signature;
 use std::collections::HashMap;
@@ -46,6 +47,17 @@ pub struct VwModule {
     pub metadata: VwMetadata,
     pub format: ExecutableType,
     pub arch: VwArch,
+    /// The imported functions called through the PLT, by the address of their PLT entry.
+    pub plt_funcs: Vec<(u64, String)>,
+    /// The DWARF sections of the ELF image `program` was loaded from.
+    pub debug_sections: DebugSections,
+}
+
+/// The `.debug_*` sections of an ELF image, by name, which are not loaded into memory.
+#[derive(Clone, Debug, Default)]
+pub struct DebugSections {
+    pub little_endian: bool,
+    pub sections: HashMap<String, Vec<u8>>,
 }
 
 #[derive(Clone, Debug)]
@@ -53,6 +65,8 @@ pub struct VwMetadata {
     pub guest_table_0: u64,
     pub lucet_tables: u64,
     pub lucet_probestack: u64,
+    pub heap_layout: HeapLayout,
+    pub heap_regs: HeapRegs,
 }
 
 #[derive(Clone, Debug)]
@@ -4,13 +4,14 @@ use lucet_module::{Signature, ValueType};
 use std::collections::HashMap;
 
 use crate::ir::types::{FunType, ValSize, VarIndex, X86Regs};
-use crate::loaders::types::ExecutableType;
+use crate::loaders::types::{DebugSections, ExecutableType};
 use crate::loaders::Loadable;
 
 use yaxpeax_arch::Arch;
 use yaxpeax_core::arch::x86_64::x86_64Data;
 use yaxpeax_core::arch::{BaseUpdate, Library, Symbol, SymbolQuery};
 use yaxpeax_core::goblin::elf::program_header::ProgramHeader;
+use yaxpeax_core::goblin::elf::{Elf, SectionHeader};
 use yaxpeax_core::memory::repr::process::{
     ELFExport, ELFImport, ELFSection, ELFSymbol, ModuleData, ModuleInfo,
 };
@@ -32,6 +33,38 @@ pub fn get_symbol_addr(symbols: &Vec<ELFSymbol>, name: &str) -> Option<u64> {
         .map(|sym| sym.addr)
 }
 
+/// The name of `section`, or an error if the section name table does not hold one.
+pub fn section_name<'a>(elf: &Elf<'a>, section: &SectionHeader) -> Result<&'a str, String> {
+    match elf.shdr_strtab.get(section.sh_name) {
+        Some(Ok(name)) => Ok(name),
+        Some(Err(err)) => Err(format!("invalid section name: {}", err)),
+        None => Err(format!(
+            "section name {:#x} is out of bounds",
+            section.sh_name
+        )),
+    }
+}
+
+/// The `.debug_*` sections of `elf`, which was parsed from `buffer`.
+pub fn debug_sections(elf: &Elf, buffer: &[u8]) -> Result<DebugSections, String> {
+    let mut sections = HashMap::new();
+    for section in elf.section_headers.iter() {
+        let name = section_name(elf, section)?;
+        if !name.starts_with(".debug_") {
+            continue;
+        }
+        let data = (section.sh_offset as usize)
+            .checked_add(section.sh_size as usize)
+            .and_then(|end| buffer.get(section.sh_offset as usize..end))
+            .ok_or_else(|| format!("section {} is out of bounds", name))?;
+        sections.insert(name.to_string(), data.to_vec());
+    }
+    Ok(DebugSections {
+        little_endian: elf.little_endian,
+        sections,
+    })
+}
+
 pub fn deconstruct_elf(
     program: &ModuleData,
 ) -> (
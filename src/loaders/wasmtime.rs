@@ -1,108 +1,190 @@
-use crate::runner::Config;
-use crate::{loaders, runner};
+use crate::{loaders, runner, HeapLayout, HeapRegs};
 use loaders::types::{VwFuncInfo, VwMetadata, VwModule};
 use loaders::utils::deconstruct_elf;
 use loaders::utils::*;
-use std::fs;
-#[cfg(feature = "wasmtime")]
-use wasmtime::*;
-use yaxpeax_core::goblin::Object;
+use yaxpeax_core::goblin::elf::header::ET_REL;
+use yaxpeax_core::goblin::elf::reloc::{R_X86_64_64, R_X86_64_PC32, R_X86_64_PLT32};
+use yaxpeax_core::goblin::elf::section_header::{SHF_ALLOC, SHT_NOBITS};
+use yaxpeax_core::goblin::elf::Elf;
 use yaxpeax_core::memory::repr::process::ModuleData;
 use yaxpeax_core::memory::repr::process::Segment;
 
-//yaxpeax doesnt load .o files correctly, so this code
-// manually adds memory regions corresponding to ELF sections
-// (yaxpeax does this by segments, but .o files may not have segments)
-#[cfg(feature = "wasmtime")]
-fn fixup_object_file(program: &mut ModuleData, obj: &[u8]) {
-    // let elf = program.module_info().unwrap();
-    let elf = match Object::parse(obj) {
-        Ok(obj @ Object::Elf(_)) => match obj {
-            Object::Elf(elf) => elf,
-            _ => panic!(),
-        },
-        _ => panic!(),
-    };
+const ELF_MAGIC: &[u8] = b"\x7fELF";
 
+// Wasmtime serializes compiled modules as a small header followed by the
+// compilation artifacts, one of which is the ELF object containing the code.
+// Newer versions of Wasmtime emit the ELF object directly.
+// Either way, find the object and return exactly the bytes that belong to it.
+fn extract_wasmtime_object(buffer: &[u8]) -> Option<Vec<u8>> {
+    let start = buffer
+        .windows(ELF_MAGIC.len())
+        .position(|window| window == ELF_MAGIC)?;
+    let elf = Elf::parse(&buffer[start..]).ok()?;
+    let shdrs_end = (elf.header.e_shoff as usize)
+        .checked_add((elf.header.e_shnum as usize).checked_mul(elf.header.e_shentsize as usize)?)?;
+    let mut sections_end = 0;
     for section in elf.section_headers.iter() {
-        if section.sh_name == 0 {
+        sections_end = std::cmp::max(
+            sections_end,
+            section.sh_offset.checked_add(section.sh_size)? as usize,
+        );
+    }
+    let end = start.checked_add(std::cmp::max(shdrs_end, sections_end))?;
+    if end > buffer.len() {
+        return None;
+    }
+    Some(buffer[start..end].to_vec())
+}
+
+// Wasmtime code is emitted as a relocatable object, so direct calls between
+// functions have not been resolved yet. Resolve the relocations that target
+// symbols defined in .text so that call targets are real function addresses.
+// Relocations against undefined symbols (libcalls) are left untouched.
+fn apply_text_relocations(obj: &mut Vec<u8>) -> Result<(), String> {
+    let elf = Elf::parse(&obj).map_err(|err| format!("not a valid ELF file: {}", err))?;
+    if elf.header.e_type != ET_REL {
+        return Ok(());
+    }
+    let mut text_idx = None;
+    for (idx, section) in elf.section_headers.iter().enumerate() {
+        if section_name(&elf, section)? == ".text" {
+            text_idx = Some(idx);
+            break;
+        }
+    }
+    let text_idx = match text_idx {
+        Some(idx) => idx,
+        None => return Ok(()),
+    };
+    let text = &elf.section_headers[text_idx];
+    let text_addr = text.sh_addr;
+    let text_offset = text.sh_offset as usize;
+
+    let mut patches: Vec<(usize, Vec<u8>)> = Vec::new();
+    for (section_idx, relocs) in elf.shdr_relocs.iter() {
+        let section = elf
+            .section_headers
+            .get(*section_idx)
+            .ok_or_else(|| format!("relocations for missing section {}", section_idx))?;
+        // only relocations that apply to .text
+        if section.sh_info as usize != text_idx {
             continue;
         }
-        //Load data for section
-        let mut section_data = vec![0; section.sh_size as usize];
-        for idx in 0..section.sh_size {
-            section_data[idx as usize] = obj[(section.sh_offset + idx) as usize];
+        for reloc in relocs.iter() {
+            let sym = match elf.syms.get(reloc.r_sym) {
+                Some(sym) => sym,
+                None => continue,
+            };
+            if sym.st_shndx != text_idx {
+                continue;
+            }
+            let s = text_addr.wrapping_add(sym.st_value) as i64;
+            let a = reloc.r_addend.unwrap_or(0);
+            let p = text_addr.wrapping_add(reloc.r_offset) as i64;
+            let offset = (reloc.r_offset as usize)
+                .checked_add(text_offset)
+                .ok_or_else(|| format!("relocation offset {:#x} overflows", reloc.r_offset))?;
+            match reloc.r_type {
+                R_X86_64_PC32 | R_X86_64_PLT32 => patches.push((
+                    offset,
+                    (s.wrapping_add(a).wrapping_sub(p) as i32)
+                        .to_le_bytes()
+                        .to_vec(),
+                )),
+                R_X86_64_64 => patches.push((offset, s.wrapping_add(a).to_le_bytes().to_vec())),
+                _ => (),
+            }
+        }
+    }
+
+    for (offset, bytes) in patches {
+        match offset
+            .checked_add(bytes.len())
+            .and_then(|end| obj.get_mut(offset..end))
+        {
+            Some(dst) => dst.copy_from_slice(&bytes),
+            None => return Err(format!("relocation at {:#x} is out of bounds", offset)),
         }
+    }
+    Ok(())
+}
+
+//yaxpeax doesnt load .o files correctly, so this code
+// manually adds memory regions corresponding to ELF sections
+// (yaxpeax does this by segments, but .o files may not have segments)
+fn fixup_object_file(program: &mut ModuleData, elf: &Elf, obj: &[u8]) -> Result<(), String> {
+    // Sections in a .o file all start at address 0, so make sure .text is found
+    // first when looking up an address.
+    let mut sections: Vec<_> = elf
+        .section_headers
+        .iter()
+        .filter(|section| section.sh_name != 0 && section.sh_flags & (SHF_ALLOC as u64) != 0)
+        .collect();
+    sections.sort_by_key(|section| section_name(elf, section).ok() != Some(".text"));
+
+    for section in sections {
+        let name = section_name(elf, section)?;
+        //Load data for section
+        let section_data = if section.sh_type == SHT_NOBITS {
+            vec![0; section.sh_size as usize]
+        } else {
+            (section.sh_offset as usize)
+                .checked_add(section.sh_size as usize)
+                .and_then(|end| obj.get(section.sh_offset as usize..end))
+                .ok_or_else(|| format!("section {} is out of bounds", name))?
+                .to_vec()
+        };
         //add as segment
         let new_section = Segment {
             start: section.sh_addr as usize, // virtual addr
             data: section_data,
-            name: elf
-                .shdr_strtab
-                .get(section.sh_name)
-                .unwrap()
-                .unwrap()
-                .to_string(),
+            name: name.to_string(),
         };
         program.segments.push(new_section);
     }
+    Ok(())
 }
 
-#[cfg(not(feature = "wasmtime"))]
-fn load_wasmtime_metadata(program: &ModuleData) -> VwMetadata {
-    unimplemented!("Enable the veriwasm feature to load wasmtime modules")
-}
-
-#[cfg(feature = "wasmtime")]
-fn load_wasmtime_metadata(program: &ModuleData) -> VwMetadata {
+fn load_wasmtime_metadata(
+    program: &ModuleData,
+    heap_layout: HeapLayout,
+    heap_regs: HeapRegs,
+) -> VwMetadata {
     let (_, sections, entrypoint, imports, exports, symbols) = deconstruct_elf(program);
 
-    // unimplemented!();
-
-    // let guest_table_0 = get_symbol_addr(symbols, "guest_table_0").unwrap();
-    // let lucet_tables = get_symbol_addr(symbols, "lucet_tables").unwrap();
-    // let lucet_probestack = get_symbol_addr(symbols, "lucet_probestack").unwrap();
-    // println!(
-    //     "guest_table_0 = {:x} lucet_tables = {:x} probestack = {:x}",
-    //     guest_table_0, lucet_tables, lucet_probestack
-    // );
+    // Wasmtime reaches tables through the vmctx rather than through symbols,
+    // so there are no table addresses; `Verifier` refuses to run the call pass
+    // on Wasmtime modules. Stack limits are checked inline instead of by
+    // calling a probestack function. If the object does contain a probestack
+    // function, use it.
+    let probestack = get_symbol_addr(symbols, "__cranelift_probestack").unwrap_or(0);
     VwMetadata {
         guest_table_0: 0,
         lucet_tables: 0,
-        lucet_probestack: 0,
+        lucet_probestack: probestack,
+        heap_layout: heap_layout,
+        heap_regs: heap_regs,
     }
 }
 
-#[cfg(not(feature = "wasmtime"))]
-pub fn load_wasmtime_program(config: &runner::Config) -> VwModule {
-    unimplemented!("Enable wasmtime feature to use this function");
-}
-
-#[cfg(feature = "wasmtime")]
-pub fn load_wasmtime_program(config: &runner::Config) -> VwModule {
-    let path = &config.module_path;
-    let buffer = fs::read(path).expect("Something went wrong reading the file");
-    let store: Store<()> = Store::default();
-    // Deserialize wasmtime module
-    let module = unsafe { Module::deserialize(store.engine(), buffer).unwrap() };
-    unimplemented!();
-    // let obj = module.obj();
-
-    // match ModuleData::load_from(&obj, path.to_string()) {
-    //     Some(mut program) => {
-    //         fixup_object_file(&mut program, &obj);
-    //         let metadata = load_wasmtime_metadata(&program);
-    //         VwModule {
-    //             program,
-    //             metadata,
-    //             format: config.executable_type,
-    //             arch: config.arch,
-    //         }
-    //     } //{ FileRepr::Executable(data) }
-    //     None => {
-    //         panic!("function:{} is not a valid path", path)
-    //     }
-    // }
+pub fn load_wasmtime_program(config: &runner::Config, buffer: Vec<u8>) -> Result<VwModule, String> {
+    let mut obj = extract_wasmtime_object(&buffer)
+        .ok_or_else(|| "does not contain a compiled Wasmtime module".to_string())?;
+    apply_text_relocations(&mut obj)?;
+    let mut program = ModuleData::load_from(&obj, config.module_path.clone())
+        .ok_or_else(|| "not a valid ELF object".to_string())?;
+    let elf = Elf::parse(&obj).map_err(|err| format!("not a valid ELF file: {}", err))?;
+    fixup_object_file(&mut program, &elf, &obj)?;
+    let metadata = load_wasmtime_metadata(&program, config.heap_layout, config.heap_regs);
+    Ok(VwModule {
+        program,
+        metadata,
+        format: config.executable_type,
+        arch: config.arch,
+        // Wasmtime objects have no PLT: libcalls are resolved by the runtime at load time.
+        plt_funcs: vec![],
+        debug_sections: debug_sections(&elf, &obj)?,
+    })
 }
 
 // We do not need to check handwritten trampoline functions
@@ -111,10 +193,8 @@ pub fn is_valid_wasmtime_func_name(name: &String) -> bool {
     !name.starts_with("_trampoline")
 }
 
+// Compiled Wasmtime modules do not carry Wasm signatures in the object file,
+// so there are no signatures to report.
 pub fn get_wasmtime_func_signatures(program: &ModuleData) -> VwFuncInfo {
-    unimplemented!();
-}
-
-pub fn wasmtime_get_plt_funcs(binpath: &str) -> Option<Vec<(u64, String)>> {
-    unimplemented!();
+    VwFuncInfo::new()
 }
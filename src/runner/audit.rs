@@ -0,0 +1,146 @@
+use crate::{ir, loaders};
+
+use super::{panic_message, Config};
+use ir::{audit_cfg, fully_resolved_cfg, LiftKind};
+use loaders::load_program;
+use loaders::types::{VwArch, VwModule};
+use loaders::utils::get_data;
+use std::collections::BTreeMap;
+use std::panic;
+
+// Number of example addresses kept for each opcode.
+const MAX_EXAMPLES: usize = 3;
+
+#[derive(Clone, Debug, Default)]
+pub struct OpcodeStats {
+    pub count: usize,
+    pub examples: Vec<u64>,
+}
+
+/// How every instruction in a module is lifted, grouped by opcode.
+#[derive(Clone, Debug, Default)]
+pub struct OpcodeAudit {
+    pub opcodes: BTreeMap<(LiftKind, String), OpcodeStats>,
+    /// Functions whose CFG could not be recovered, with the reason.
+    pub failed_funcs: Vec<(String, String)>,
+}
+
+impl OpcodeAudit {
+    fn record(&mut self, addr: u64, opcode: String, kind: LiftKind) {
+        let stats = self.opcodes.entry((kind, opcode)).or_default();
+        stats.count += 1;
+        if stats.examples.len() < MAX_EXAMPLES {
+            stats.examples.push(addr);
+        }
+    }
+
+    /// Number of instructions lifted as `kind`.
+    pub fn count(&self, kind: LiftKind) -> usize {
+        self.opcodes
+            .iter()
+            .filter(|((k, _), _)| *k == kind)
+            .map(|(_, stats)| stats.count)
+            .sum()
+    }
+
+    pub fn print(&self) {
+        let precise = self.count(LiftKind::Precise);
+        let cleared = self.count(LiftKind::GenericClear);
+        let unsupported = self.count(LiftKind::Unsupported);
+        let total = precise + cleared + unsupported;
+        let mut opcodes: Vec<_> = self.opcodes.iter().collect();
+        // Most imprecise first, then most frequent
+        opcodes.sort_by_key(|((kind, opcode), stats)| {
+            (
+                std::cmp::Reverse(*kind),
+                std::cmp::Reverse(stats.count),
+                opcode.clone(),
+            )
+        });
+        for ((kind, opcode), stats) in opcodes {
+            let examples: Vec<String> = stats
+                .examples
+                .iter()
+                .map(|addr| format!("0x{:x}", addr))
+                .collect();
+            println!(
+                "{:<14} {:<16} {:>8}  {}",
+                format!("{:?}", kind),
+                opcode,
+                stats.count,
+                examples.join(", ")
+            );
+        }
+        for (func_name, msg) in &self.failed_funcs {
+            println!("{}: could not recover CFG: {}", func_name, msg);
+        }
+        println!(
+            "Instructions: {:?} Precise: {:?} Generic clear: {:?} Unsupported: {:?}",
+            total, precise, cleared, unsupported
+        );
+        if total != 0 {
+            println!(
+                "{:.2}% of instructions are conservatively cleared",
+                100.0 * (cleared + unsupported) as f64 / total as f64
+            );
+        }
+    }
+}
+
+/// Disassembles every function in the module and records how each instruction is lifted.
+/// Lifting is never strict here, so unsupported opcodes are counted rather than rejected.
+pub fn audit_opcodes(config: &Config) -> Result<OpcodeAudit, String> {
+    if config.arch != VwArch::X64 {
+        panic!("Opcode audits are only implemented for x64");
+    }
+    Ok(audit_module(config, &load_program(config)?))
+}
+
+fn audit_module(config: &Config, module: &VwModule) -> OpcodeAudit {
+    let (x86_64_data, func_addrs, _, _) = get_data(&module.program, &config.executable_type);
+
+    let mut audit = OpcodeAudit::default();
+    for (addr, func_name) in func_addrs {
+        if let Some(only_func) = &config.only_func {
+            if &func_name != only_func {
+                continue;
+            }
+        }
+        let lifted = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            fully_resolved_cfg(module, &x86_64_data.contexts, addr, false)
+        }));
+        match lifted.map_err(panic_message) {
+            Ok(Ok((cfg, _))) => {
+                for (addr, opcode, kind) in audit_cfg(module, &cfg) {
+                    audit.record(addr, opcode.to_string(), kind);
+                }
+            }
+            Ok(Err(err)) => audit.failed_funcs.push((func_name, err.to_string())),
+            Err(msg) => audit.failed_funcs.push((func_name, msg)),
+        }
+    }
+    audit
+}
+
+#[test]
+fn audit_module_test() {
+    use super::{test_module, Verifier};
+
+    // mov eax, esi; mov eax, esi; sbb eax, ecx; cpuid; ret
+    let code = [0x89, 0xf0, 0x89, 0xf0, 0x19, 0xc8, 0x0f, 0xa2, 0xc3];
+    let config = Verifier::from_path("module.so").config_for("module.so");
+    let audit = audit_module(&config, &test_module(&code, &[("f", 0)]));
+    assert!(audit.failed_funcs.is_empty());
+    assert_eq!(audit.count(LiftKind::Precise), 3);
+    assert_eq!(audit.count(LiftKind::GenericClear), 1);
+    assert_eq!(audit.count(LiftKind::Unsupported), 1);
+    let mov = &audit.opcodes[&(LiftKind::Precise, "mov".to_string())];
+    assert_eq!(mov.count, 2);
+    assert_eq!(mov.examples, vec![0, 2]);
+    assert!(audit
+        .opcodes
+        .contains_key(&(LiftKind::GenericClear, "sbb".to_string())));
+    assert!(audit
+        .opcodes
+        .contains_key(&(LiftKind::Unsupported, "cpuid".to_string())));
+}
@@ -0,0 +1,284 @@
+use crate::{analyses, checkers, ir, lattices, loaders};
+
+use super::{call_analyzer, lift_module, locals_analyzer, module_summaries, Config};
+use analyses::reaching_defs::analyze_reaching_defs;
+use analyses::{
+    provenance, run_worklist, CounterexamplePath, HeapAnalyzer, Provenance, StackAnalyzer,
+};
+use checkers::locals_checker::trace_locals;
+use checkers::{heap_counterexample, trace_calls, trace_heap, trace_stack, TraceStep, Violation};
+use colored::Colorize;
+use ir::types::{IRMap, Stmt};
+use ir::{audit_cfg, disassemble_cfg, fully_resolved_cfg, LiftKind};
+use lattices::reachingdefslattice::LocIdx;
+use loaders::load_program;
+use loaders::types::{ExecutableType, VwArch};
+use loaders::utils::get_data;
+use loaders::Loadable;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::iter::FromIterator;
+use std::str::FromStr;
+
+/// The pass whose abstract state `explain` shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExplainPass {
+    Heap,
+    Stack,
+    Call,
+    Locals,
+}
+
+impl FromStr for ExplainPass {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_string().to_lowercase()[..] {
+            "heap" => Ok(ExplainPass::Heap),
+            "stack" => Ok(ExplainPass::Stack),
+            "call" => Ok(ExplainPass::Call),
+            "locals" => Ok(ExplainPass::Locals),
+            _ => Err("Unknown pass"),
+        }
+    }
+}
+
+/// Prints the disassembly of `func_name` interleaved with its lifted statements and the state
+/// of `pass` before each instruction, and highlights the instructions that fail the pass.
+/// With `with_provenance`, each violation is followed by why the locations its statement uses
+/// are bottom. Returns the violations that were found.
+pub fn explain(
+    config: &Config,
+    func_name: &str,
+    pass: ExplainPass,
+    with_provenance: bool,
+) -> Result<Vec<Violation>, String> {
+    if config.arch != VwArch::X64 {
+        return Err("explain is only implemented for x64".to_string());
+    }
+    if config.executable_type == ExecutableType::Wasmtime
+        && (pass == ExplainPass::Call || pass == ExplainPass::Locals)
+    {
+        return Err("the call pass does not model Wasmtime's vmctx tables".to_string());
+    }
+    let module = load_program(config)?;
+    let (x86_64_data, func_addrs, plt, mut all_addrs) =
+        get_data(&module.program, &config.executable_type);
+    let addr = func_addrs
+        .iter()
+        .find(|(_, name)| name == func_name)
+        .map(|(addr, _)| *addr)
+        .unwrap_or_else(|| panic!("No function named {:?}", func_name));
+    let valid_funcs: Vec<u64> = func_addrs.iter().map(|x| x.0).collect();
+    let mut lifted = lift_module(config, &module, &x86_64_data, &func_addrs);
+    let (summaries, _) = module_summaries(config, &module, &lifted);
+    let (cfg, irmap) = match lifted.remove(&addr) {
+        Some(Ok((cfg, irmap, _))) => (cfg, irmap),
+        _ => fully_resolved_cfg(&module, &x86_64_data.contexts, addr, config.strict)
+            .map_err(|err| err.to_string())?,
+    };
+    if pass == ExplainPass::Locals {
+        all_addrs.extend(module.plt_funcs.clone());
+    }
+    let all_addrs_map = HashMap::from_iter(all_addrs);
+    let generic_cleared: HashSet<u64> = audit_cfg(&module, &cfg)
+        .into_iter()
+        .filter(|(_, _, kind)| *kind != LiftKind::Precise)
+        .map(|(addr, _, _)| addr)
+        .collect();
+    let why_bottom = |provenance: Provenance, steps: &Vec<TraceStep>| {
+        let mut why: BTreeMap<LocIdx, Vec<String>> = BTreeMap::new();
+        for step in steps.iter().filter(|step| step.violation.is_some()) {
+            let stmt = &stmt_at(&irmap, &step.loc_idx);
+            why.insert(
+                step.loc_idx,
+                provenance
+                    .of_stmt(&step.loc_idx, stmt)
+                    .into_iter()
+                    .map(|(location, record)| record.describe(&location, &all_addrs_map))
+                    .collect(),
+            );
+        }
+        why
+    };
+
+    // Counterexample paths and bottom provenance of the failing statements, for the passes
+    // that have them
+    let mut paths: BTreeMap<LocIdx, CounterexamplePath> = BTreeMap::new();
+    let mut why: BTreeMap<LocIdx, Vec<String>> = BTreeMap::new();
+    let steps = match pass {
+        ExplainPass::Heap => {
+            let heap_analyzer = HeapAnalyzer {
+                metadata: module.metadata.clone(),
+                strategy: config.heap_strategy.clone(),
+                summaries: summaries.clone(),
+            };
+            let heap_result =
+                run_worklist(&cfg, &irmap, &heap_analyzer).map_err(|err| err.to_string())?;
+            let steps = trace_heap(heap_result.clone(), &irmap, &heap_analyzer, &all_addrs_map);
+            for violation in steps.iter().filter_map(|step| step.violation.as_ref()) {
+                if let Some(path) = heap_counterexample(
+                    &cfg,
+                    &irmap,
+                    &heap_analyzer,
+                    &heap_result,
+                    &all_addrs_map,
+                    violation,
+                ) {
+                    paths.insert(violation.loc_idx, path);
+                }
+            }
+            if with_provenance {
+                let provenance =
+                    provenance(&cfg, &irmap, &heap_analyzer, &heap_result, &generic_cleared);
+                why = why_bottom(provenance, &steps);
+            }
+            steps
+        }
+        ExplainPass::Stack => {
+            let stack_analyzer = StackAnalyzer {};
+            let stack_result =
+                run_worklist(&cfg, &irmap, &stack_analyzer).map_err(|err| err.to_string())?;
+            if with_provenance {
+                println!("The stack pass does not track provenance");
+            }
+            trace_stack(stack_result, &irmap, &stack_analyzer)
+        }
+        ExplainPass::Call => {
+            let reaching_defs = analyze_reaching_defs(&cfg, &irmap, module.metadata.clone())
+                .map_err(|err| err.to_string())?;
+            let call_analyzer = call_analyzer(
+                &cfg,
+                &irmap,
+                &module.metadata,
+                &valid_funcs,
+                reaching_defs,
+                &summaries,
+            );
+            let call_result =
+                run_worklist(&cfg, &irmap, &call_analyzer).map_err(|err| err.to_string())?;
+            let steps = trace_calls(
+                call_result.clone(),
+                &irmap,
+                &call_analyzer,
+                &valid_funcs,
+                &plt,
+            );
+            if with_provenance {
+                let provenance =
+                    provenance(&cfg, &irmap, &call_analyzer, &call_result, &generic_cleared);
+                why = why_bottom(provenance, &steps);
+            }
+            steps
+        }
+        ExplainPass::Locals => {
+            let func_signatures = config.executable_type.get_func_signatures(&module.program);
+            let reaching_defs = analyze_reaching_defs(&cfg, &irmap, module.metadata.clone())
+                .map_err(|err| err.to_string())?;
+            let call_analyzer = call_analyzer(
+                &cfg,
+                &irmap,
+                &module.metadata,
+                &valid_funcs,
+                reaching_defs,
+                &summaries,
+            );
+            let call_result =
+                run_worklist(&cfg, &irmap, &call_analyzer).map_err(|err| err.to_string())?;
+            let locals_analyzer = locals_analyzer(
+                call_analyzer,
+                call_result,
+                plt,
+                &all_addrs_map,
+                &func_signatures,
+                &func_name.to_string(),
+            );
+            let locals_result =
+                run_worklist(&cfg, &irmap, &locals_analyzer).map_err(|err| err.to_string())?;
+            let steps = trace_locals(locals_result.clone(), &irmap, &locals_analyzer);
+            if with_provenance {
+                let provenance = provenance(
+                    &cfg,
+                    &irmap,
+                    &locals_analyzer,
+                    &locals_result,
+                    &generic_cleared,
+                );
+                why = why_bottom(provenance, &steps);
+            }
+            steps
+        }
+    };
+
+    let stmts: HashMap<u64, &Vec<Stmt>> = irmap
+        .values()
+        .flat_map(|block| block.iter().map(|(addr, stmts)| (*addr, stmts)))
+        .collect();
+    let mut steps_at: HashMap<u64, Vec<&TraceStep>> = HashMap::new();
+    for step in &steps {
+        steps_at.entry(step.loc_idx.addr).or_default().push(step);
+    }
+
+    println!("{} ({:?} pass)", func_name, pass);
+    for (block_addr, instrs) in disassemble_cfg(&module, &cfg) {
+        println!("block 0x{:x}:", block_addr);
+        for (addr, text) in instrs {
+            // Blocks the analysis never reached, and instructions fused into the statements of
+            // the previous instruction, have no statements or state of their own.
+            let instr_steps = match (steps_at.get(&addr), stmts.get(&addr)) {
+                (Some(instr_steps), Some(_)) => instr_steps,
+                _ => {
+                    println!("     0x{:x}: {}", addr, text);
+                    continue;
+                }
+            };
+            println!("        ; {}", instr_steps[0].state);
+            let violations: Vec<&Violation> = instr_steps
+                .iter()
+                .filter_map(|step| step.violation.as_ref())
+                .collect();
+            let line = format!("0x{:x}: {}", addr, text);
+            if violations.is_empty() {
+                println!("     {}", line);
+            } else {
+                println!("  >> {}", line.red().bold());
+            }
+            for stmt in stmts[&addr] {
+                println!("            {:?}", stmt);
+            }
+            for violation in violations {
+                println!("        {}", violation.to_string().red());
+                for line in why.get(&violation.loc_idx).into_iter().flatten() {
+                    println!("          {}", line);
+                }
+                if let Some(path) = paths.get(&violation.loc_idx) {
+                    for line in path.to_string().lines() {
+                        println!("          {}", line);
+                    }
+                }
+            }
+        }
+    }
+
+    let violations: Vec<Violation> = steps
+        .into_iter()
+        .filter_map(|step| step.violation)
+        .collect();
+    println!("{} violations", violations.len());
+    Ok(violations)
+}
+
+// The statement at `loc_idx`.
+fn stmt_at(irmap: &IRMap, loc_idx: &LocIdx) -> Stmt {
+    irmap
+        .values()
+        .flat_map(|block| block.iter())
+        .find(|(addr, _)| *addr == loc_idx.addr)
+        .map(|(_, stmts)| stmts[loc_idx.idx as usize].clone())
+        .unwrap()
+}
+
+#[test]
+fn explain_pass_test() {
+    assert_eq!(ExplainPass::from_str("heap"), Ok(ExplainPass::Heap));
+    assert_eq!(ExplainPass::from_str("Locals"), Ok(ExplainPass::Locals));
+    assert!(ExplainPass::from_str("linear_mem").is_err());
+}
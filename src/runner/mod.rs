@@ -1,37 +1,55 @@
+mod audit;
+mod explain;
+mod report;
+mod sarif;
+mod verifier;
+
 use crate::{analyses, checkers, ir, lattices, loaders};
 
 use crate::lattices::calllattice::CallCheckLattice;
 use crate::lattices::reachingdefslattice::ReachingDefnLattice;
 use crate::lattices::VariableState;
 use crate::VwModule;
+use crate::{HeapLayout, HeapRegs, HeapStrategy};
 use crate::{IRMap, VwMetadata, VW_CFG};
 use analyses::locals_analyzer::LocalsAnalyzer;
 use analyses::reaching_defs::{analyze_reaching_defs, ReachingDefnAnalyzer};
-use analyses::{run_worklist, AnalysisResult};
+use analyses::{
+    call_graph, compute_summaries, run_worklist, summary_dependents, AnalysisError, AnalysisResult,
+    CallGraph, Summaries,
+};
 
 use analyses::{CallAnalyzer, HeapAnalyzer, StackAnalyzer};
 use checkers::locals_checker::check_locals;
-use checkers::{check_calls, check_heap, check_stack};
-use ir::fully_resolved_cfg;
-use ir::types::FunType;
+use checkers::{check_calls, check_heap, check_stack, Violation};
+use ir::types::{FunType, RegT, X86Regs};
+use ir::{fully_resolved_aarch64_cfg, fully_resolved_cfg, LiftError};
 // use ir::utils::has_indirect_calls;
 use loaders::load_program;
 use loaders::types::{ExecutableType, VwArch, VwFuncInfo};
 use loaders::utils::get_data;
 use loaders::utils::to_system_v;
-use std::collections::HashMap;
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::iter::FromIterator;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use yaxpeax_core::arch::x86_64::x86_64Data;
 
 // use loaders::get_plt_funcs;
 use loaders::Loadable;
-use serde_json;
-use std::fs;
 use std::panic;
 use std::time::Instant;
 use yaxpeax_core::analyses::control_flow::check_cfg_integrity;
 
-#[derive(Debug)]
+pub use self::audit::{audit_opcodes, OpcodeAudit, OpcodeStats};
+pub use self::explain::{explain, ExplainPass};
+pub use self::report::{json_report, REPORT_VERSION};
+pub use self::sarif::{sarif_report, SARIF_VERSION};
+pub use self::verifier::{ModuleSource, VerificationReport, Verifier, VerifyError};
+
+#[derive(Clone, Debug)]
 pub struct PassConfig {
     pub stack: bool,
     pub linear_mem: bool,
@@ -39,30 +57,220 @@ pub struct PassConfig {
     pub zero_cost: bool,
 }
 
+/// The options of a verification run. Build one with `Verifier` rather than by hand.
+#[derive(Clone, Debug)]
 pub struct Config {
     pub module_path: String,
-    pub _num_jobs: u32,
-    pub output_path: String,
-    pub has_output: bool,
+    pub num_jobs: u32,
     pub only_func: Option<String>,
     pub executable_type: ExecutableType,
     pub active_passes: PassConfig,
     pub arch: VwArch,
     pub strict: bool,
+    pub heap_strategy: HeapStrategy,
+    pub heap_layout: HeapLayout,
+    pub heap_regs: HeapRegs,
+    /// Summarize the functions of the module and apply the summaries at direct calls. A
+    /// function whose checks rely on the summary of a function that fails verification is
+    /// reported as an analysis error. With `only_func`, the callees are summarized but not
+    /// verified.
+    pub interprocedural: bool,
 }
 
-pub fn run_locals(
-    reaching_defs: AnalysisResult<VariableState<ReachingDefnLattice>>,
-    call_analysis: AnalysisResult<CallCheckLattice>,
-    plt_bounds: (u64, u64),
-    all_addrs_map: &HashMap<u64, String>, // index into func_signatures.signatures
-    func_signatures: &VwFuncInfo,
-    func_name: &String,
+impl Config {
+    /// Rejects passes that cannot run on the module's format or architecture.
+    pub fn check_supported(&self) -> Result<(), String> {
+        if !self.active_passes.call {
+            return Ok(());
+        }
+        if self.executable_type == ExecutableType::Wasmtime {
+            return Err(
+                "the call pass does not model Wasmtime's vmctx tables; disable it for Wasmtime modules"
+                    .to_string(),
+            );
+        }
+        if self.arch == VwArch::Aarch64 {
+            return Err(
+                "the call and locals passes are not implemented for aarch64; disable the call pass"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of verifying a single function.
+#[derive(Clone, Debug)]
+pub enum Verdict {
+    Safe,
+    Unsafe(Vec<Violation>),
+    /// One of the analyses could not reach a fixpoint, an analysis or checker panicked, or the
+    /// checks relied on the summary of a function that failed verification.
+    AnalysisError(String),
+    /// The function could not be lifted or its CFG could not be recovered.
+    LiftError(String),
+}
+
+impl Verdict {
+    /// Exit code for this verdict. Higher is worse: a function we could not
+    /// analyze is worse than one with known violations.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Verdict::Safe => 0,
+            Verdict::Unsafe(_) => 1,
+            Verdict::AnalysisError(_) => 2,
+            Verdict::LiftError(_) => 3,
+        }
+    }
+}
+
+/// Size of a verified function and the time spent on each pass, in seconds.
+/// A pass that did not run took no time.
+#[derive(Clone, Debug, Default)]
+pub struct FuncStats {
+    pub blocks: usize,
+    pub instructions: usize,
+    pub cfg_time: f64,
+    pub stack_time: f64,
+    pub heap_time: f64,
+    pub call_time: f64,
+    pub locals_time: f64,
+}
+
+impl FuncStats {
+    pub fn total_time(&self) -> f64 {
+        self.cfg_time + self.stack_time + self.heap_time + self.call_time + self.locals_time
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FuncSummary {
+    pub name: String,
+    pub addr: u64,
+    pub verdict: Verdict,
+    /// Missing if the function could not be lifted or analyzed.
+    pub stats: Option<FuncStats>,
+}
+
+/// Per-function verdicts for every function checked in a module.
+#[derive(Clone, Debug, Default)]
+pub struct ModuleSummary {
+    pub funcs: Vec<FuncSummary>,
+}
+
+impl ModuleSummary {
+    fn push(&mut self, name: String, addr: u64, verdict: Verdict, stats: Option<FuncStats>) {
+        self.funcs.push(FuncSummary {
+            name,
+            addr,
+            verdict,
+            stats,
+        });
+    }
+
+    /// The sum of the stats of every function that was verified.
+    pub fn total_stats(&self) -> FuncStats {
+        let mut total = FuncStats::default();
+        for stats in self.funcs.iter().filter_map(|func| func.stats.as_ref()) {
+            total.blocks += stats.blocks;
+            total.instructions += stats.instructions;
+            total.cfg_time += stats.cfg_time;
+            total.stack_time += stats.stack_time;
+            total.heap_time += stats.heap_time;
+            total.call_time += stats.call_time;
+            total.locals_time += stats.locals_time;
+        }
+        total
+    }
+
+    pub fn is_safe(&self) -> bool {
+        self.exit_code() == 0
+    }
+
+    /// The exit code of the worst verdict in the module.
+    pub fn exit_code(&self) -> i32 {
+        self.funcs
+            .iter()
+            .map(|func| func.verdict.exit_code())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn violations(&self) -> Vec<&Violation> {
+        self.funcs
+            .iter()
+            .flat_map(|func| match &func.verdict {
+                Verdict::Unsafe(violations) => violations.iter().collect(),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        let count = |code| {
+            self.funcs
+                .iter()
+                .filter(|func| func.verdict.exit_code() == code)
+                .count()
+        };
+        println!(
+            "Safe: {:?} Unsafe: {:?} Analysis errors: {:?} Lift errors: {:?}",
+            count(0),
+            count(1),
+            count(2),
+            count(3),
+        );
+        for func in &self.funcs {
+            match &func.verdict {
+                Verdict::Safe => (),
+                Verdict::Unsafe(violations) => {
+                    for violation in violations {
+                        println!("{}", violation);
+                    }
+                }
+                Verdict::AnalysisError(msg) => {
+                    println!("0x{:x} ({}): analysis error: {}", func.addr, func.name, msg)
+                }
+                Verdict::LiftError(msg) => {
+                    println!("0x{:x} ({}): lift error: {}", func.addr, func.name, msg)
+                }
+            }
+        }
+    }
+}
+
+fn call_analyzer(
     cfg: &VW_CFG,
     irmap: &IRMap,
     metadata: &VwMetadata,
     valid_funcs: &Vec<u64>,
-) -> bool {
+    reaching_defs: AnalysisResult<VariableState<ReachingDefnLattice>>,
+    summaries: &Arc<Summaries>,
+) -> CallAnalyzer {
+    CallAnalyzer {
+        metadata: metadata.clone(),
+        reaching_defs,
+        reaching_analyzer: ReachingDefnAnalyzer {
+            cfg: cfg.clone(),
+            irmap: irmap.clone(),
+        },
+        funcs: valid_funcs.clone(),
+        irmap: irmap.clone(),
+        cfg: cfg.clone(),
+        summaries: summaries.clone(),
+    }
+}
+
+// The locals analyzer for `func_name`. Functions without a signature are treated as taking no
+// arguments and returning nothing.
+fn locals_analyzer<'a>(
+    call_analyzer: CallAnalyzer,
+    call_analysis: AnalysisResult<CallCheckLattice>,
+    plt_bounds: (u64, u64),
+    all_addrs_map: &'a HashMap<u64, String>,
+    func_signatures: &'a VwFuncInfo,
+    func_name: &String,
+) -> LocalsAnalyzer<'a> {
     let fun_type = func_signatures
         .indexes
         .get(func_name)
@@ -76,49 +284,70 @@ pub fn run_locals(
             args: Vec::new(),
             ret: None,
         });
-    let call_analyzer = CallAnalyzer {
-        metadata: metadata.clone(),
-        reaching_defs: reaching_defs.clone(),
-        reaching_analyzer: ReachingDefnAnalyzer {
-            cfg: cfg.clone(),
-            irmap: irmap.clone(),
-        },
-        funcs: valid_funcs.clone(),
-        irmap: irmap.clone(),
-        cfg: cfg.clone(),
-    };
-    let locals_analyzer = LocalsAnalyzer {
+    LocalsAnalyzer {
         fun_type,
         plt_bounds,
         symbol_table: func_signatures,
         name_addr_map: all_addrs_map,
         call_analysis,
         call_analyzer,
-    };
-    let locals_result = run_worklist(&cfg, &irmap, &locals_analyzer);
-    let locals_safe = check_locals(locals_result, &irmap, &locals_analyzer);
-    locals_safe
+    }
 }
 
-fn run_stack(cfg: &VW_CFG, irmap: &IRMap) -> bool {
+pub fn run_locals(
+    reaching_defs: AnalysisResult<VariableState<ReachingDefnLattice>>,
+    call_analysis: AnalysisResult<CallCheckLattice>,
+    plt_bounds: (u64, u64),
+    all_addrs_map: &HashMap<u64, String>, // index into func_signatures.signatures
+    func_signatures: &VwFuncInfo,
+    func_name: &String,
+    cfg: &VW_CFG,
+    irmap: &IRMap,
+    metadata: &VwMetadata,
+    valid_funcs: &Vec<u64>,
+    summaries: &Arc<Summaries>,
+) -> Result<Vec<Violation>, AnalysisError> {
+    let call_analyzer = call_analyzer(cfg, irmap, metadata, valid_funcs, reaching_defs, summaries);
+    let locals_analyzer = locals_analyzer(
+        call_analyzer,
+        call_analysis,
+        plt_bounds,
+        all_addrs_map,
+        func_signatures,
+        func_name,
+    );
+    let locals_result = run_worklist(&cfg, &irmap, &locals_analyzer)?;
+    Ok(check_locals(locals_result, &irmap, &locals_analyzer))
+}
+
+fn run_stack<Ar: RegT>(cfg: &VW_CFG, irmap: &IRMap<Ar>) -> Vec<Violation> {
     let stack_analyzer = StackAnalyzer {};
-    let stack_result = run_worklist(&cfg, &irmap, &stack_analyzer);
-    let stack_safe = check_stack(stack_result, &irmap, &stack_analyzer);
-    stack_safe
+    match run_worklist(&cfg, &irmap, &stack_analyzer) {
+        Ok(stack_result) => check_stack(stack_result, &irmap, &stack_analyzer),
+        Err(err) => vec![Violation::stack_update(err)],
+    }
 }
 
-fn run_heap(
+fn run_heap<Ar: RegT>(
     cfg: &VW_CFG,
-    irmap: &IRMap,
+    irmap: &IRMap<Ar>,
     metadata: &VwMetadata,
+    strategy: HeapStrategy,
     all_addrs_map: &HashMap<u64, String>,
-) -> bool {
+    summaries: &Arc<Summaries>,
+) -> Result<Vec<Violation>, AnalysisError> {
     let heap_analyzer = HeapAnalyzer {
         metadata: metadata.clone(),
+        strategy,
+        summaries: summaries.clone(),
     };
-    let heap_result = run_worklist(&cfg, &irmap, &heap_analyzer);
-    let heap_safe = check_heap(heap_result, &irmap, &heap_analyzer, &all_addrs_map);
-    heap_safe
+    let heap_result = run_worklist(&cfg, &irmap, &heap_analyzer)?;
+    Ok(check_heap(
+        heap_result,
+        &irmap,
+        &heap_analyzer,
+        &all_addrs_map,
+    ))
 }
 
 fn run_calls(
@@ -127,51 +356,421 @@ fn run_calls(
     metadata: &VwMetadata,
     valid_funcs: &Vec<u64>,
     plt: (u64, u64),
-) -> (
-    bool,
-    AnalysisResult<CallCheckLattice>,
-    AnalysisResult<VariableState<ReachingDefnLattice>>,
-) {
-    let reaching_defs = analyze_reaching_defs(&cfg, &irmap, metadata.clone());
-    let call_analyzer = CallAnalyzer {
-        metadata: metadata.clone(),
-        reaching_defs: reaching_defs.clone(),
-        reaching_analyzer: ReachingDefnAnalyzer {
-            cfg: cfg.clone(),
-            irmap: irmap.clone(),
-        },
-        funcs: valid_funcs.clone(),
-        irmap: irmap.clone(),
-        cfg: cfg.clone(),
-    };
-    let call_result = run_worklist(&cfg, &irmap, &call_analyzer);
-    let call_safe = check_calls(
+    summaries: &Arc<Summaries>,
+) -> Result<
+    (
+        Vec<Violation>,
+        AnalysisResult<CallCheckLattice>,
+        AnalysisResult<VariableState<ReachingDefnLattice>>,
+    ),
+    AnalysisError,
+> {
+    let reaching_defs = analyze_reaching_defs(&cfg, &irmap, metadata.clone())?;
+    let call_analyzer = call_analyzer(
+        cfg,
+        irmap,
+        metadata,
+        valid_funcs,
+        reaching_defs.clone(),
+        summaries,
+    );
+    let call_result = run_worklist(&cfg, &irmap, &call_analyzer)?;
+    let call_violations = check_calls(
         call_result.clone(),
         &irmap,
         &call_analyzer,
         &valid_funcs,
         &plt,
     );
-    (call_safe, call_result, reaching_defs)
+    Ok((call_violations, call_result, reaching_defs))
+}
+
+pub fn run(config: Config) -> Result<ModuleSummary, String> {
+    config.check_supported()?;
+    let module = load_program(&config)?;
+    run_module(config, module)
 }
 
-pub fn run(config: Config) {
-    let module = load_program(&config);
+/// Like `run`, for a module that has already been loaded.
+pub fn run_module(config: Config, module: VwModule) -> Result<ModuleSummary, String> {
+    config.check_supported()?;
     // We only need to load the data if we are doing zero cost checks
     if config.active_passes.zero_cost {
-        let plt_funcs = config
-            .executable_type
-            .get_plt_funcs(&config.module_path)
-            .unwrap_or(Vec::new());
+        let plt_funcs = module.plt_funcs.clone();
         let func_signatures = config.executable_type.get_func_signatures(&module.program);
-        return run_helper(config, module, plt_funcs, func_signatures);
+        return Ok(run_helper(config, module, plt_funcs, func_signatures));
     }
     //let plt_funcs = config.executable_type.get_plt_funcs(&config.module_path);
     let plt_funcs = Vec::new();
     // all_addrs.extend(plt_funcs);
     //let func_signatures = config.executable_type.get_func_signatures(&module.program);
     let func_signatures = VwFuncInfo::new();
-    run_helper(config, module, plt_funcs, func_signatures);
+    Ok(run_helper(config, module, plt_funcs, func_signatures))
+}
+
+/// The message a panic was raised with, for reporting a caught panic.
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+// Lifts the x64 function at `addr` and checks its CFG.
+fn lift_x64(
+    config: &Config,
+    module: &VwModule,
+    x86_64_data: &x86_64Data,
+    addr: u64,
+) -> Result<(VW_CFG, IRMap), LiftError> {
+    let (cfg, irmap) = fully_resolved_cfg(module, &x86_64_data.contexts, addr, config.strict)?;
+    check_cfg_integrity(&cfg.blocks, &cfg.graph);
+    Ok((cfg, irmap))
+}
+
+// The functions at `funcs`, lifted up front if the run is interprocedural so that the summaries
+// and the checks share one lift of each function. Summaries are only computed for x64 modules.
+fn lift_module(
+    config: &Config,
+    module: &VwModule,
+    x86_64_data: &x86_64Data,
+    funcs: &Vec<(u64, String)>,
+) -> HashMap<u64, Lifted<X86Regs>> {
+    if !config.interprocedural || module.arch != VwArch::X64 {
+        return HashMap::new();
+    }
+    funcs
+        .iter()
+        .map(|(addr, func_name)| {
+            let lifted = lift_func(func_name, || lift_x64(config, module, x86_64_data, *addr));
+            (*addr, lifted)
+        })
+        .collect()
+}
+
+// The summaries of the lifted functions and the call graph they were computed over, if the run
+// is interprocedural.
+fn module_summaries(
+    config: &Config,
+    module: &VwModule,
+    lifted: &HashMap<u64, Lifted<X86Regs>>,
+) -> (Arc<Summaries>, CallGraph) {
+    if !config.interprocedural || module.arch != VwArch::X64 {
+        return (Arc::new(Summaries::new()), CallGraph::new());
+    }
+    let funcs: HashMap<u64, (&VW_CFG, &IRMap)> = lifted
+        .iter()
+        .filter_map(|(addr, lifted)| match lifted {
+            Ok((cfg, irmap, _)) => Some((*addr, (cfg, irmap))),
+            Err(_) => None,
+        })
+        .collect();
+    println!("Summarizing {:?} functions", funcs.len());
+    let start = Instant::now();
+    let call_graph = call_graph(&funcs);
+    let summaries = compute_summaries(
+        &module.metadata,
+        &funcs,
+        &call_graph,
+        &config.heap_strategy,
+        config.num_jobs as usize,
+    );
+    println!(
+        "Summarized {:?} functions in {:?}s",
+        summaries.len(),
+        start.elapsed().as_secs_f64()
+    );
+    (Arc::new(summaries), call_graph)
+}
+
+// Reports the functions whose checks relied on the summary of a function that failed
+// verification as analysis errors.
+fn flag_summary_dependents(
+    funcs: &Vec<(u64, String)>,
+    results: &mut Vec<(Verdict, Option<FuncStats>)>,
+    call_graph: &CallGraph,
+    summaries: &Summaries,
+    all_addrs_map: &HashMap<u64, String>,
+) {
+    let failed: HashSet<u64> = funcs
+        .iter()
+        .zip(results.iter())
+        .filter(|(_, (verdict, _))| !matches!(verdict, Verdict::Safe))
+        .map(|((addr, _), _)| *addr)
+        .collect();
+    let dependents = summary_dependents(call_graph, summaries, &failed);
+    for ((addr, func_name), (verdict, _)) in funcs.iter().zip(results.iter_mut()) {
+        if let Some(callee) = dependents.get(addr) {
+            let callee_name = all_addrs_map
+                .get(callee)
+                .cloned()
+                .unwrap_or_else(|| format!("0x{:x}", callee));
+            println!(
+                "{:?} relies on the summary of {:?}, which failed verification",
+                func_name, callee_name
+            );
+            *verdict = Verdict::AnalysisError(format!(
+                "relies on the summary of {}, which failed verification",
+                callee_name
+            ));
+        }
+    }
+}
+
+// Everything needed to verify a function that is shared by every function in the module.
+struct ModuleContext<'a> {
+    config: &'a Config,
+    module: &'a VwModule,
+    plt: (u64, u64),
+    valid_funcs: &'a Vec<u64>,
+    all_addrs_map: &'a HashMap<u64, String>,
+    func_signatures: &'a VwFuncInfo,
+    summaries: Arc<Summaries>,
+}
+
+// Call safety, followed by locals safety if it is enabled.
+fn run_call_passes(
+    ctx: &ModuleContext,
+    func_name: &String,
+    cfg: &VW_CFG,
+    irmap: &IRMap,
+    stats: &mut FuncStats,
+) -> Result<Vec<Violation>, AnalysisError> {
+    let config = ctx.config;
+    let module = ctx.module;
+    let plt = ctx.plt;
+    // if config.active_passes.linear_mem {
+    println!("Checking Call Safety");
+    let call_start = Instant::now();
+    let (call_violations, indirect_calls_result, reaching_defs) = run_calls(
+        cfg,
+        irmap,
+        &module.metadata,
+        ctx.valid_funcs,
+        plt,
+        &ctx.summaries,
+    )?;
+    stats.call_time = call_start.elapsed().as_secs_f64();
+    if !call_violations.is_empty() {
+        println!("Not Call Safe");
+        return Ok(call_violations);
+    }
+
+    if config.active_passes.zero_cost {
+        println!("Checking Locals Safety");
+        let locals_start = Instant::now();
+        let locals_violations = run_locals(
+            reaching_defs,
+            indirect_calls_result,
+            plt,
+            ctx.all_addrs_map,
+            ctx.func_signatures,
+            func_name,
+            cfg,
+            irmap,
+            &module.metadata,
+            ctx.valid_funcs,
+            &ctx.summaries,
+        )?;
+        stats.locals_time = locals_start.elapsed().as_secs_f64();
+        if !locals_violations.is_empty() {
+            println!("Not Locals Safe");
+        }
+        return Ok(locals_violations);
+    }
+    Ok(Vec::new())
+}
+
+// A lifted function and its stats so far, or the verdict for a function that could not be
+// lifted.
+type Lifted<Ar> = Result<(VW_CFG, IRMap<Ar>, FuncStats), Verdict>;
+
+// Lifts a function with `lift`.
+fn lift_func<Ar: RegT>(
+    func_name: &String,
+    lift: impl FnOnce() -> Result<(VW_CFG, IRMap<Ar>), LiftError>,
+) -> Lifted<Ar> {
+    println!("Generating CFG for {:?}", func_name);
+    let start = Instant::now();
+    // A panic while lifting or recovering the CFG of one function should not stop the others
+    // from being checked.
+    let lifted = panic::catch_unwind(panic::AssertUnwindSafe(lift))
+        .map_err(panic_message)
+        .and_then(|lifted| lifted.map_err(|err| err.to_string()));
+    let (cfg, irmap) = match lifted {
+        Ok(lifted) => lifted,
+        Err(msg) => {
+            println!("Failed to lift {:?}: {}", func_name, msg);
+            return Err(Verdict::LiftError(msg));
+        }
+    };
+    let stats = FuncStats {
+        blocks: cfg.blocks.len(),
+        instructions: irmap.values().map(|block| block.len()).sum(),
+        cfg_time: start.elapsed().as_secs_f64(),
+        ..Default::default()
+    };
+    Ok((cfg, irmap, stats))
+}
+
+// Runs the enabled passes over a lifted function. The stack and heap passes work on any
+// architecture; `call_passes` runs the rest and records its own timings.
+fn check_func<Ar: RegT>(
+    ctx: &ModuleContext,
+    func_name: &String,
+    cfg: &VW_CFG,
+    irmap: &IRMap<Ar>,
+    mut stats: FuncStats,
+    call_passes: impl FnOnce(
+        &VW_CFG,
+        &IRMap<Ar>,
+        &mut FuncStats,
+    ) -> Result<Vec<Violation>, AnalysisError>,
+) -> (Verdict, Option<FuncStats>) {
+    let config = ctx.config;
+    let module = ctx.module;
+    println!("Analyzing 0x{:x?}: {:?}", cfg.entrypoint, func_name);
+
+    let checked = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        // Once a pass finds violations, the later passes are skipped for this function.
+        let mut func_violations: Vec<Violation> = Vec::new();
+
+        if config.active_passes.stack {
+            println!("Checking Stack Safety");
+            let stack_start = Instant::now();
+            func_violations = run_stack(cfg, irmap);
+            stats.stack_time = stack_start.elapsed().as_secs_f64();
+            if !func_violations.is_empty() {
+                println!("Not Stack Safe");
+            }
+        }
+
+        if config.active_passes.linear_mem && func_violations.is_empty() {
+            println!("Checking Heap Safety");
+            let heap_start = Instant::now();
+            func_violations = run_heap(
+                cfg,
+                irmap,
+                &module.metadata,
+                config.heap_strategy.clone(),
+                ctx.all_addrs_map,
+                &ctx.summaries,
+            )?;
+            stats.heap_time = heap_start.elapsed().as_secs_f64();
+            if !func_violations.is_empty() {
+                println!("Not Heap Safe");
+            }
+        }
+
+        if config.active_passes.call && func_violations.is_empty() {
+            func_violations = call_passes(cfg, irmap, &mut stats)?;
+        }
+        Ok(func_violations)
+    }));
+    let func_violations = match checked
+        .map_err(panic_message)
+        .and_then(|checked| checked.map_err(|err: AnalysisError| err.to_string()))
+    {
+        Ok(func_violations) => func_violations,
+        Err(msg) => {
+            println!("Failed to analyze {:?}: {}", func_name, msg);
+            return (Verdict::AnalysisError(msg), None);
+        }
+    };
+
+    println!(
+        "Verified {:?} at {:?} blocks. CFG: {:?}s Stack: {:?}s Heap: {:?}s Calls: {:?}s locals {:?}s",
+        func_name,
+        stats.blocks,
+        stats.cfg_time,
+        stats.stack_time,
+        stats.heap_time,
+        stats.call_time,
+        stats.locals_time,
+    );
+    let verdict = if func_violations.is_empty() {
+        Verdict::Safe
+    } else {
+        Verdict::Unsafe(
+            func_violations
+                .into_iter()
+                .map(|mut violation| {
+                    violation.func_name = func_name.clone();
+                    violation
+                })
+                .collect(),
+        )
+    };
+    (verdict, Some(stats))
+}
+
+// Verifies `funcs`, lifting each with `lift` and running `call_passes` after the stack and heap
+// passes. `lift` may hand back a function lifted earlier. yaxpeax's context table, which lifting reads, is not thread safe, so with more than
+// one job the functions are lifted on this thread and checked on `num_jobs` worker threads.
+// Verdicts are returned in the order of `funcs` either way.
+fn verify_funcs<Ar, L, C>(
+    ctx: &ModuleContext,
+    funcs: &Vec<(u64, String)>,
+    num_jobs: usize,
+    mut lift: L,
+    call_passes: C,
+) -> Vec<(Verdict, Option<FuncStats>)>
+where
+    Ar: RegT + Send,
+    L: FnMut(u64, &String) -> Lifted<Ar>,
+    C: Fn(&String, &VW_CFG, &IRMap<Ar>, &mut FuncStats) -> Result<Vec<Violation>, AnalysisError>
+        + Sync,
+{
+    let check = |func_name: &String, lifted: Lifted<Ar>| match lifted {
+        Ok((cfg, irmap, stats)) => {
+            check_func(ctx, func_name, &cfg, &irmap, stats, |cfg, irmap, stats| {
+                call_passes(func_name, cfg, irmap, stats)
+            })
+        }
+        Err(verdict) => (verdict, None),
+    };
+    if num_jobs <= 1 {
+        return funcs
+            .iter()
+            .map(|(addr, func_name)| check(func_name, lift(*addr, func_name)))
+            .collect();
+    }
+
+    let (sender, receiver) = mpsc::sync_channel::<(usize, Lifted<Ar>)>(num_jobs);
+    let receiver = Mutex::new(receiver);
+    let mut results: Vec<(usize, (Verdict, Option<FuncStats>))> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..num_jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut worker_results = Vec::new();
+                    loop {
+                        // The lock is only held until the next function is received. The
+                        // channel closes once every function has been lifted.
+                        let next = receiver.lock().unwrap().recv();
+                        let (idx, lifted) = match next {
+                            Ok(next) => next,
+                            Err(_) => break,
+                        };
+                        worker_results.push((idx, check(&funcs[idx].1, lifted)));
+                    }
+                    worker_results
+                })
+            })
+            .collect();
+        for (idx, (addr, func_name)) in funcs.iter().enumerate() {
+            sender.send((idx, lift(*addr, func_name))).unwrap();
+        }
+        drop(sender);
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|(idx, _)| *idx);
+    results.into_iter().map(|(_, result)| result).collect()
 }
 
 pub fn run_helper(
@@ -179,7 +778,7 @@ pub fn run_helper(
     module: VwModule,
     plt_funcs: Vec<(u64, String)>,
     func_signatures: VwFuncInfo,
-) {
+) -> ModuleSummary {
     // let module = load_program(&config);
 
     // let plt_funcs = config.executable_type.get_plt_funcs(&config.module_path);
@@ -190,120 +789,235 @@ pub fn run_helper(
         get_data(&module.program, &config.executable_type);
     all_addrs.extend(plt_funcs);
 
-    let strict = config.strict;
-
-    let mut func_counter = 0;
-    let mut info: Vec<(std::string::String, usize, f64, f64, f64, f64, f64)> = vec![];
     let valid_funcs: Vec<u64> = func_addrs.clone().iter().map(|x| x.0).collect();
     let all_addrs_map = HashMap::from_iter(all_addrs.clone());
-    for (addr, func_name) in func_addrs {
-        if config.only_func.is_some() && func_name != config.only_func.as_ref().unwrap().as_str() {
-            continue;
-        }
-        println!("Generating CFG for {:?}", func_name);
-        let start = Instant::now();
-        let (cfg, irmap) = fully_resolved_cfg(&module, &x86_64_data.contexts, addr, strict);
-        func_counter += 1;
-        println!("Analyzing 0x{:x?}: {:?}", addr, func_name);
-        check_cfg_integrity(&cfg.blocks, &cfg.graph);
-
-        let stack_start = Instant::now();
-        if config.active_passes.stack {
-            println!("Checking Stack Safety");
-            let stack_safe = run_stack(&cfg, &irmap);
-            if !stack_safe {
-                panic!("Not Stack Safe");
-            }
-        }
+    let funcs: Vec<(u64, String)> = func_addrs
+        .iter()
+        .filter(|(_, func_name)| match &config.only_func {
+            Some(only_func) => func_name == only_func,
+            None => true,
+        })
+        .cloned()
+        .collect();
 
-        let heap_start = Instant::now();
-        if config.active_passes.linear_mem {
-            println!("Checking Heap Safety");
-            let heap_safe = run_heap(&cfg, &irmap, &module.metadata, &all_addrs_map);
-            if !heap_safe {
-                panic!("Not Heap Safe");
-            }
-        }
+    let mut lifted = lift_module(&config, &module, &x86_64_data, &func_addrs);
+    let (summaries, call_graph) = module_summaries(&config, &module, &lifted);
+    let ctx = ModuleContext {
+        config: &config,
+        module: &module,
+        plt,
+        valid_funcs: &valid_funcs,
+        all_addrs_map: &all_addrs_map,
+        func_signatures: &func_signatures,
+        summaries: summaries.clone(),
+    };
+    let num_jobs = std::cmp::min(config.num_jobs as usize, funcs.len());
+    let mut results = match module.arch {
+        VwArch::X64 => verify_funcs(
+            &ctx,
+            &funcs,
+            num_jobs,
+            |addr, func_name| {
+                lifted.remove(&addr).unwrap_or_else(|| {
+                    lift_func(func_name, || lift_x64(&config, &module, &x86_64_data, addr))
+                })
+            },
+            |func_name, cfg, irmap, stats| run_call_passes(&ctx, func_name, cfg, irmap, stats),
+        ),
+        VwArch::Aarch64 => verify_funcs(
+            &ctx,
+            &funcs,
+            num_jobs,
+            |addr, func_name| {
+                lift_func(func_name, || {
+                    let (cfg, irmap) = fully_resolved_aarch64_cfg(&module, addr, config.strict)?;
+                    check_cfg_integrity(&cfg.blocks, &cfg.graph);
+                    Ok((cfg, irmap))
+                })
+            },
+            // `check_supported` rejects the call pass for aarch64
+            |_, _, _, _| unreachable!("the call pass does not run on aarch64"),
+        ),
+    };
 
-        let call_start = Instant::now();
-        if config.active_passes.call {
-            // if config.active_passes.linear_mem {
-            println!("Checking Call Safety");
-            let (call_safe, indirect_calls_result, reaching_defs) =
-                run_calls(&cfg, &irmap, &module.metadata, &valid_funcs, plt);
-            if !call_safe {
-                panic!("Not Call Safe");
-            }
+    flag_summary_dependents(
+        &funcs,
+        &mut results,
+        &call_graph,
+        &summaries,
+        &all_addrs_map,
+    );
 
-            println!("Checking Locals Safety");
-            if config.active_passes.zero_cost {
-                let locals_safe = run_locals(
-                    reaching_defs,
-                    indirect_calls_result,
-                    plt,
-                    &all_addrs_map,
-                    &func_signatures,
-                    &func_name,
-                    &cfg,
-                    &irmap,
-                    &module.metadata,
-                    &valid_funcs,
-                );
-                if !locals_safe {
-                    panic!("Not Locals Safe");
-                }
-            }
-        }
-        let locals_start = Instant::now(); //alwyas 0 right now, locals time grouped with calls
-
-        let end = Instant::now();
-        info.push((
-            func_name.to_string(),
-            cfg.blocks.len(),
-            (stack_start - start).as_secs_f64(),
-            (heap_start - stack_start).as_secs_f64(),
-            (call_start - heap_start).as_secs_f64(),
-            (locals_start - call_start).as_secs_f64(), // TODO: proper timing
-            (end - locals_start).as_secs_f64(),
-        ));
-        println!(
-            "Verified {:?} at {:?} blocks. CFG: {:?}s Stack: {:?}s Heap: {:?}s Calls: {:?}s locals {:?}s",
-            func_name,
-            cfg.blocks.len(),
-            (stack_start - start).as_secs_f64(),
-            (heap_start - stack_start).as_secs_f64(),
-            (call_start - heap_start).as_secs_f64(),
-            (locals_start - call_start).as_secs_f64(),
-            (end - locals_start).as_secs_f64(), // TODO: proper timing
-        );
+    let mut summary = ModuleSummary::default();
+    for ((addr, func_name), (verdict, stats)) in funcs.into_iter().zip(results) {
+        summary.push(func_name, addr, verdict, stats);
+    }
+    summary
+}
+
+// A module of x64 `code` loaded at address 0, with a function at each of `funcs`.
+#[cfg(test)]
+fn test_module(code: &[u8], funcs: &[(&str, u64)]) -> VwModule {
+    use crate::loaders::types::ExecutableType;
+    use yaxpeax_core::goblin::elf::header::Header;
+    use yaxpeax_core::memory::repr::process::{
+        ELFSection, ELFSymbol, ISAHint, ModuleData, ModuleInfo, Segment,
+    };
+
+    let symbols = funcs
+        .iter()
+        .map(|(name, addr)| ELFSymbol {
+            name: name.to_string(),
+            section_index: 1,
+            addr: *addr,
+        })
+        .collect();
+    let text = ELFSection {
+        name: ".text".to_string(),
+        start: 0,
+        size: code.len() as u64,
+    };
+    let program = ModuleData {
+        segments: vec![Segment {
+            start: 0,
+            data: code.to_vec(),
+            name: ".text".to_string(),
+        }],
+        name: "module.so".to_string(),
+        module_info: ModuleInfo::ELF(
+            ISAHint::Hint(yaxpeax_core::arch::ISA::x86_64),
+            Header {
+                e_ident: [
+                    0x7f, 0x45, 0x4c, 0x46, 0x02, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                ],
+                e_type: 0x0003,
+                e_machine: 0x003e,
+                e_version: 0x00000001,
+                e_entry: 0,
+                e_phoff: 0,
+                e_shoff: 0,
+                e_flags: 0,
+                e_ehsize: 0,
+                e_phentsize: 0,
+                e_phnum: 0,
+                e_shentsize: 0,
+                e_shnum: 0,
+                e_shstrndx: 0,
+            },
+            vec![],
+            vec![text],
+            0,
+            vec![],
+            vec![],
+            vec![],
+            symbols,
+        ),
+    };
+    VwModule {
+        program,
+        metadata: VwMetadata {
+            guest_table_0: 0,
+            lucet_tables: 0,
+            lucet_probestack: 0,
+            heap_layout: HeapLayout::default(),
+            heap_regs: HeapRegs::default(),
+        },
+        format: ExecutableType::Lucet,
+        arch: VwArch::X64,
+        plt_funcs: vec![],
+        debug_sections: Default::default(),
     }
-    if config.has_output {
-        let data = serde_json::to_string(&info).unwrap();
-        println!("Dumping Stats to {}", config.output_path);
-        fs::write(config.output_path, data).expect("Unable to write file");
+}
+
+#[test]
+fn verify_funcs_parallel_test() {
+    // f0, f2, f4: mov eax, esi; mov eax, [rdi + rax]; ret
+    // f1, f3, f5: mov eax, [rdi + rsi]; ret
+    let safe = [0x89, 0xf0, 0x8b, 0x04, 0x07, 0xc3];
+    let unsafe_ = [0x8b, 0x04, 0x37, 0xc3];
+    let mut code = Vec::new();
+    let mut funcs = Vec::new();
+    let names = ["f0", "f1", "f2", "f3", "f4", "f5"];
+    for (idx, name) in names.iter().enumerate() {
+        funcs.push((*name, code.len() as u64));
+        code.extend_from_slice(if idx % 2 == 0 {
+            &safe[..]
+        } else {
+            &unsafe_[..]
+        });
     }
+    let verify = |num_jobs| {
+        let config = Verifier::from_path("module.so")
+            .jobs(num_jobs)
+            .config_for("module.so");
+        run_helper(
+            config,
+            test_module(&code, &funcs),
+            Vec::new(),
+            VwFuncInfo::new(),
+        )
+    };
+    let serial = verify(1);
+    let parallel = verify(4);
+    assert_eq!(serial.funcs.len(), names.len());
+    assert_eq!(parallel.funcs.len(), names.len());
+    for (serial, parallel) in serial.funcs.iter().zip(parallel.funcs.iter()) {
+        assert_eq!(serial.name, parallel.name);
+        assert_eq!(serial.addr, parallel.addr);
+        assert_eq!(
+            format!("{:?}", serial.verdict),
+            format!("{:?}", parallel.verdict)
+        );
+        let unsafe_func = ["f1", "f3", "f5"].contains(&&serial.name[..]);
+        assert_eq!(matches!(serial.verdict, Verdict::Unsafe(_)), unsafe_func);
+    }
+}
 
-    let mut total_cfg_time = 0.0;
-    let mut total_stack_time = 0.0;
-    let mut total_heap_time = 0.0;
-    let mut total_call_time = 0.0;
-    let mut total_locals_time = 0.0;
-    for (_, _, cfg_time, stack_time, heap_time, call_time, locals_time) in &info {
-        total_cfg_time += cfg_time;
-        total_stack_time += stack_time;
-        total_heap_time += heap_time;
-        total_call_time += call_time;
-        total_locals_time += locals_time;
+#[test]
+fn summary_dependents_test() {
+    // g: mov eax, [rdi + rsi]; mov rax, rdi; ret
+    // f: call g; mov eax, [rax]; ret
+    // h: mov rax, rdi; ret
+    // f2: call h; mov eax, [rax]; ret
+    let code = [
+        0x8b, 0x04, 0x37, 0x48, 0x89, 0xf8, 0xc3, // g
+        0xe8, 0xf4, 0xff, 0xff, 0xff, 0x8b, 0x00, 0xc3, // f
+        0x48, 0x89, 0xf8, 0xc3, // h
+        0xe8, 0xf7, 0xff, 0xff, 0xff, 0x8b, 0x00, 0xc3, // f2
+    ];
+    let funcs = [("g", 0), ("f", 7), ("h", 15), ("f2", 19)];
+    let verify = |interprocedural, num_jobs| {
+        let config = Verifier::from_path("module.so")
+            .interprocedural(interprocedural)
+            .jobs(num_jobs)
+            .config_for("module.so");
+        let summary = run_helper(
+            config,
+            test_module(&code, &funcs),
+            Vec::new(),
+            VwFuncInfo::new(),
+        );
+        summary
+            .funcs
+            .into_iter()
+            .map(|func| (func.name, func.verdict))
+            .collect::<HashMap<_, _>>()
+    };
+
+    // Without summaries, the callers do not know that rax holds the heap base
+    let verdicts = verify(false, 1);
+    assert!(matches!(verdicts["f"], Verdict::Unsafe(_)));
+    assert!(matches!(verdicts["f2"], Verdict::Unsafe(_)));
+
+    // g fails verification, so f cannot rely on its summary; h's summary holds
+    for num_jobs in [1, 4] {
+        let verdicts = verify(true, num_jobs);
+        assert!(matches!(verdicts["g"], Verdict::Unsafe(_)));
+        assert!(matches!(verdicts["h"], Verdict::Safe));
+        assert!(matches!(verdicts["f2"], Verdict::Safe));
+        match &verdicts["f"] {
+            Verdict::AnalysisError(msg) => assert!(msg.contains("summary of g")),
+            verdict => panic!("unexpected verdict for f: {:?}", verdict),
+        }
     }
-    println!("Verified {:?} functions", func_counter);
-    println!(
-        "Total time = {:?}s CFG: {:?} Stack: {:?}s Heap: {:?}s Call: {:?}s Locals {:?}s",
-        total_cfg_time + total_stack_time + total_heap_time + total_call_time + total_locals_time,
-        total_cfg_time,
-        total_stack_time,
-        total_heap_time,
-        total_call_time,
-        total_locals_time,
-    );
-    println!("Done!");
 }
@@ -0,0 +1,293 @@
+use super::{Config, FuncStats, FuncSummary, ModuleSummary, Verdict};
+use crate::checkers::{Operand, RegOperand, Violation};
+use crate::{HeapLayout, HeapStrategy, IndexWidth};
+use serde_json::{json, Value};
+
+/// Bumped whenever a field of the JSON report is renamed, removed, or changes meaning.
+/// Adding fields does not change the version.
+pub const REPORT_VERSION: u32 = 1;
+
+fn stats_json(stats: &FuncStats) -> Value {
+    json!({
+        "blocks": stats.blocks,
+        "instructions": stats.instructions,
+        "times": {
+            "cfg": stats.cfg_time,
+            "stack": stats.stack_time,
+            "heap": stats.heap_time,
+            "call": stats.call_time,
+            "locals": stats.locals_time,
+            "total": stats.total_time(),
+        },
+    })
+}
+
+fn reg_json(reg: &RegOperand) -> Value {
+    json!({
+        "name": reg.name,
+        "bits": reg.bits,
+    })
+}
+
+fn operand_json(operand: &Operand) -> Value {
+    match operand {
+        Operand::Reg(reg) => json!({
+            "kind": "reg",
+            "reg": reg_json(reg),
+        }),
+        Operand::Imm { value, bits } => json!({
+            "kind": "imm",
+            "value": value,
+            "bits": bits,
+        }),
+        Operand::Mem {
+            bits,
+            base,
+            index,
+            scale,
+            disp,
+        } => json!({
+            "kind": "mem",
+            "bits": bits,
+            "base": base.as_ref().map(reg_json),
+            "index": index.as_ref().map(reg_json),
+            "scale": scale,
+            "disp": disp,
+        }),
+        Operand::RIPConst => json!({ "kind": "rip_const" }),
+    }
+}
+
+fn violation_json(violation: &Violation) -> Value {
+    json!({
+        "addr": violation.addr,
+        "stmt_idx": violation.loc_idx.idx,
+        "kind": violation.kind.to_string(),
+        "value": violation.value.as_ref().map(operand_json),
+        "state": violation.state,
+    })
+}
+
+fn func_json(func: &FuncSummary) -> Value {
+    let (verdict, violations, error) = match &func.verdict {
+        Verdict::Safe => ("safe", vec![], None),
+        Verdict::Unsafe(violations) => (
+            "unsafe",
+            violations.iter().map(violation_json).collect(),
+            None,
+        ),
+        Verdict::AnalysisError(msg) => ("analysis_error", vec![], Some(msg)),
+        Verdict::LiftError(msg) => ("lift_error", vec![], Some(msg)),
+    };
+    json!({
+        "name": func.name,
+        "addr": func.addr,
+        "verdict": verdict,
+        "exit_code": func.verdict.exit_code(),
+        "violations": violations,
+        "error": error,
+        "stats": func.stats.as_ref().map(stats_json),
+    })
+}
+
+fn layout_json(layout: &HeapLayout) -> Value {
+    json!({
+        "reservation": layout.reservation,
+        "guard": layout.guard,
+        "globals_size": layout.globals_size,
+        "vmctx_size": layout.vmctx_size,
+    })
+}
+
+fn index_width_json(width: IndexWidth) -> &'static str {
+    match width {
+        IndexWidth::I32 => "i32",
+        IndexWidth::I64 => "i64",
+    }
+}
+
+fn heap_strategy_json(strategy: &HeapStrategy) -> Value {
+    match strategy {
+        HeapStrategy::HeapPtrFirstArgWithGuards => json!({
+            "kind": "heap_ptr_first_arg_with_guards",
+        }),
+        HeapStrategy::VMCtxFirstArgWithGuards {
+            vmctx_heap_base_ptr_offset,
+        } => json!({
+            "kind": "vmctx_first_arg_with_guards",
+            "vmctx_heap_base_ptr_offset": vmctx_heap_base_ptr_offset,
+        }),
+        HeapStrategy::VMCtxFirstArgWithBoundsChecks {
+            vmctx_heap_base_ptr_offset,
+            vmctx_heap_bound_offset,
+            index_width,
+        } => json!({
+            "kind": "vmctx_first_arg_with_bounds_checks",
+            "vmctx_heap_base_ptr_offset": vmctx_heap_base_ptr_offset,
+            "vmctx_heap_bound_offset": vmctx_heap_bound_offset,
+            "index_width": index_width_json(*index_width),
+        }),
+        HeapStrategy::VMCtxFirstArgMultiMemory { memories } => json!({
+            "kind": "vmctx_first_arg_multi_memory",
+            "memories": memories
+                .iter()
+                .map(|memory| json!({
+                    "base_ptr_offset": memory.base_ptr_offset,
+                    "bound_offset": memory.bound_offset,
+                    "index_width": index_width_json(memory.index_width),
+                    "layout": layout_json(&memory.layout),
+                }))
+                .collect::<Vec<_>>(),
+        }),
+    }
+}
+
+fn config_json(config: &Config) -> Value {
+    let passes = &config.active_passes;
+    json!({
+        "module_path": config.module_path,
+        "format": format!("{:?}", config.executable_type).to_lowercase(),
+        "arch": format!("{:?}", config.arch).to_lowercase(),
+        "jobs": config.num_jobs,
+        "only_func": config.only_func,
+        "strict": config.strict,
+        "interprocedural": config.interprocedural,
+        "heap_strategy": heap_strategy_json(&config.heap_strategy),
+        "heap_layout": layout_json(&config.heap_layout),
+        "heap_regs": {
+            "heap_base": config.heap_regs.heap_base,
+            "vmctx": config.heap_regs.vmctx,
+            "pinned_heap_base": config.heap_regs.pinned_heap_base,
+        },
+        "passes": {
+            "stack": passes.stack,
+            "linear_mem": passes.linear_mem,
+            "call": passes.call,
+            "zero_cost": passes.zero_cost,
+        },
+    })
+}
+
+/// A versioned report of a verification run: the configuration, module-level totals, and the
+/// verdict and stats of every function. This is what `-o` writes.
+pub fn json_report(config: &Config, summary: &ModuleSummary) -> Value {
+    let count = |code| {
+        summary
+            .funcs
+            .iter()
+            .filter(|func| func.verdict.exit_code() == code)
+            .count()
+    };
+    json!({
+        "version": REPORT_VERSION,
+        "config": config_json(config),
+        "totals": {
+            "functions": summary.funcs.len(),
+            "safe": count(0),
+            "unsafe": count(1),
+            "analysis_errors": count(2),
+            "lift_errors": count(3),
+            "violations": summary.violations().len(),
+            "exit_code": summary.exit_code(),
+            "stats": stats_json(&summary.total_stats()),
+        },
+        "functions": summary.funcs.iter().map(func_json).collect::<Vec<_>>(),
+    })
+}
+
+#[test]
+fn json_report_test() {
+    use super::PassConfig;
+    use crate::checkers::CheckKind;
+    use crate::lattices::reachingdefslattice::LocIdx;
+    use crate::loaders::types::{ExecutableType, VwArch};
+    use crate::HeapRegs;
+
+    let config = Config {
+        module_path: "module.so".to_string(),
+        num_jobs: 1,
+        only_func: None,
+        executable_type: ExecutableType::Lucet,
+        active_passes: PassConfig {
+            stack: true,
+            linear_mem: true,
+            call: true,
+            zero_cost: false,
+        },
+        arch: VwArch::X64,
+        strict: false,
+        heap_strategy: HeapStrategy::VMCtxFirstArgWithGuards {
+            vmctx_heap_base_ptr_offset: 0x50,
+        },
+        heap_layout: HeapLayout::default(),
+        heap_regs: HeapRegs::default(),
+        interprocedural: false,
+    };
+    let stats = FuncStats {
+        blocks: 3,
+        instructions: 10,
+        cfg_time: 1.0,
+        stack_time: 2.0,
+        ..Default::default()
+    };
+    let mut summary = ModuleSummary::default();
+    summary.push("f".to_string(), 0x10, Verdict::Safe, Some(stats.clone()));
+    summary.push("g".to_string(), 0x20, Verdict::Safe, Some(stats));
+    summary.push(
+        "h".to_string(),
+        0x30,
+        Verdict::LiftError("bad".to_string()),
+        None,
+    );
+    let violation = Violation {
+        func_name: "i".to_string(),
+        addr: 0x44,
+        loc_idx: LocIdx { addr: 0x44, idx: 0 },
+        kind: CheckKind::Heap,
+        value: Some(Operand::Mem {
+            bits: 32,
+            base: Some(RegOperand {
+                name: "rdi".to_string(),
+                bits: 64,
+            }),
+            index: None,
+            scale: 1,
+            disp: 8,
+        }),
+        state: String::new(),
+    };
+    summary.push(
+        "i".to_string(),
+        0x40,
+        Verdict::Unsafe(vec![violation]),
+        None,
+    );
+
+    let report = json_report(&config, &summary);
+    assert_eq!(report["version"], REPORT_VERSION);
+    assert_eq!(report["config"]["format"], "lucet");
+    assert_eq!(report["config"]["heap_layout"]["guard"], 0x1_0000_0000u64);
+    assert_eq!(
+        report["config"]["heap_strategy"]["kind"],
+        "vmctx_first_arg_with_guards"
+    );
+    assert_eq!(
+        report["config"]["heap_strategy"]["vmctx_heap_base_ptr_offset"],
+        0x50
+    );
+    assert!(report["config"]["heap_regs"]["pinned_heap_base"].is_null());
+    assert_eq!(report["totals"]["functions"], 4);
+    assert_eq!(report["totals"]["lift_errors"], 1);
+    assert_eq!(report["totals"]["exit_code"], 3);
+    assert_eq!(report["totals"]["stats"]["blocks"], 6);
+    assert_eq!(report["totals"]["stats"]["times"]["total"], 6.0);
+    assert_eq!(report["functions"][0]["verdict"], "safe");
+    assert_eq!(report["functions"][0]["stats"]["instructions"], 10);
+    assert_eq!(report["functions"][2]["error"], "bad");
+    assert!(report["functions"][2]["stats"].is_null());
+    let value = &report["functions"][3]["violations"][0]["value"];
+    assert_eq!(value["kind"], "mem");
+    assert_eq!(value["base"]["name"], "rdi");
+    assert!(value["index"].is_null());
+    assert_eq!(value["disp"], 8);
+}
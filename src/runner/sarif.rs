@@ -0,0 +1,239 @@
+use super::{FuncSummary, ModuleSummary, Verdict};
+use crate::checkers::{CheckKind, Violation};
+use crate::loaders::types::DebugSections;
+use addr2line::gimli::{self, Dwarf, EndianRcSlice, RunTimeEndian, SectionId};
+use addr2line::Context;
+use serde_json::{json, Value};
+use std::rc::Rc;
+
+pub const SARIF_VERSION: &str = "2.1.0";
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+// (rule id, short description). The index of a rule in this list is its `ruleIndex`.
+const RULES: [(&str, &str); 6] = [
+    (
+        "heap-safety",
+        "Linear memory access that may be out of bounds",
+    ),
+    (
+        "stack-safety",
+        "Stack access or stack pointer update that may be unsafe",
+    ),
+    (
+        "indirect-call-safety",
+        "Indirect call through an unchecked table entry",
+    ),
+    ("locals-safety", "Read of an uninitialized local variable"),
+    (
+        "cfg-recovery",
+        "The function could not be lifted or its CFG could not be recovered",
+    ),
+    ("analysis-error", "An analysis failed on the function"),
+];
+
+const CFG_RECOVERY_RULE: usize = 4;
+const ANALYSIS_ERROR_RULE: usize = 5;
+
+fn check_kind_rule(kind: CheckKind) -> usize {
+    match kind {
+        CheckKind::Heap => 0,
+        CheckKind::Stack => 1,
+        CheckKind::Call => 2,
+        CheckKind::Locals => 3,
+    }
+}
+
+type DebugInfo = Context<EndianRcSlice<RunTimeEndian>>;
+
+// DWARF line information for the module with `debug_sections`, if it has any.
+fn load_debug_info(debug_sections: &DebugSections) -> Option<DebugInfo> {
+    let endian = if debug_sections.little_endian {
+        RunTimeEndian::Little
+    } else {
+        RunTimeEndian::Big
+    };
+    debug_sections.sections.get(".debug_info")?;
+    let dwarf = Dwarf::load(|id: SectionId| -> Result<_, gimli::Error> {
+        let data = debug_sections
+            .sections
+            .get(id.name())
+            .map_or(&[][..], |data| &data[..]);
+        Ok(EndianRcSlice::new(Rc::from(data), endian))
+    })
+    .ok()?;
+    Context::from_dwarf(dwarf).ok()
+}
+
+fn location_json(
+    module_path: &str,
+    debug_info: Option<&DebugInfo>,
+    func_name: &str,
+    addr: u64,
+) -> Value {
+    let address = json!({ "absoluteAddress": addr, "fullyQualifiedName": func_name });
+    let source = debug_info
+        .and_then(|debug_info| debug_info.find_location(addr).ok().flatten())
+        .and_then(|location| Some((location.file?.to_string(), location.line?, location.column)));
+    let physical_location = match source {
+        Some((file, line, column)) => {
+            let mut region = json!({ "startLine": line });
+            if let Some(column) = column {
+                region["startColumn"] = json!(column);
+            }
+            json!({
+                "artifactLocation": { "uri": file },
+                "region": region,
+                "address": address,
+            })
+        }
+        None => json!({
+            "artifactLocation": { "uri": module_path },
+            "address": address,
+        }),
+    };
+    json!({
+        "physicalLocation": physical_location,
+        "logicalLocations": [{ "name": func_name, "kind": "function" }],
+    })
+}
+
+fn result_json(rule: usize, message: String, location: Value) -> Value {
+    json!({
+        "ruleId": RULES[rule].0,
+        "ruleIndex": rule,
+        "level": "error",
+        "message": { "text": message },
+        "locations": [location],
+    })
+}
+
+fn func_results(
+    module_path: &str,
+    debug_info: Option<&DebugInfo>,
+    func: &FuncSummary,
+) -> Vec<Value> {
+    let location = |addr| location_json(module_path, debug_info, &func.name, addr);
+    match &func.verdict {
+        Verdict::Safe => vec![],
+        Verdict::Unsafe(violations) => violations
+            .iter()
+            .map(|violation: &Violation| {
+                result_json(
+                    check_kind_rule(violation.kind),
+                    violation.to_string(),
+                    location(violation.addr),
+                )
+            })
+            .collect(),
+        Verdict::LiftError(msg) => vec![result_json(
+            CFG_RECOVERY_RULE,
+            format!("{}: lift error: {}", func.name, msg),
+            location(func.addr),
+        )],
+        Verdict::AnalysisError(msg) => vec![result_json(
+            ANALYSIS_ERROR_RULE,
+            format!("{}: analysis error: {}", func.name, msg),
+            location(func.addr),
+        )],
+    }
+}
+
+/// A SARIF log with one result per failed check in `summary`, for the module with
+/// `debug_sections`. Results point at the function and instruction address, and at the source
+/// line when the module has DWARF line info.
+pub fn sarif_report(
+    module_path: &str,
+    debug_sections: &DebugSections,
+    summary: &ModuleSummary,
+) -> Value {
+    let debug_info = load_debug_info(debug_sections);
+    let rules: Vec<Value> = RULES
+        .iter()
+        .map(|(id, description)| {
+            json!({
+                "id": id,
+                "shortDescription": { "text": description },
+                "defaultConfiguration": { "level": "error" },
+            })
+        })
+        .collect();
+    let results: Vec<Value> = summary
+        .funcs
+        .iter()
+        .flat_map(|func| func_results(module_path, debug_info.as_ref(), func))
+        .collect();
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "VeriWasm",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/PLSysSec/veriwasm",
+                    "rules": rules,
+                },
+            },
+            "artifacts": [{ "location": { "uri": module_path } }],
+            "results": results,
+        }],
+    })
+}
+
+#[test]
+fn sarif_report_test() {
+    use crate::lattices::reachingdefslattice::LocIdx;
+
+    let violation = Violation {
+        func_name: "f".to_string(),
+        addr: 0x14,
+        loc_idx: LocIdx { addr: 0x14, idx: 0 },
+        kind: CheckKind::Call,
+        value: None,
+        state: String::new(),
+    };
+    let mut summary = ModuleSummary::default();
+    summary.push(
+        "f".to_string(),
+        0x10,
+        Verdict::Unsafe(vec![violation]),
+        None,
+    );
+    summary.push("g".to_string(), 0x20, Verdict::Safe, None);
+    summary.push(
+        "h".to_string(),
+        0x30,
+        Verdict::LiftError("bad".to_string()),
+        None,
+    );
+
+    let sarif = sarif_report("module.so", &DebugSections::default(), &summary);
+    assert_eq!(sarif["version"], SARIF_VERSION);
+    let results = sarif["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["ruleId"], "indirect-call-safety");
+    let location = &results[0]["locations"][0];
+    assert_eq!(
+        location["physicalLocation"]["address"]["absoluteAddress"],
+        0x14
+    );
+    assert_eq!(location["logicalLocations"][0]["name"], "f");
+    assert_eq!(results[1]["ruleId"], "cfg-recovery");
+    assert_eq!(
+        results[1]["locations"][0]["physicalLocation"]["address"]["absoluteAddress"],
+        0x30
+    );
+}
+
+#[test]
+fn debug_info_test() {
+    use crate::loaders::utils::debug_sections;
+    use yaxpeax_core::goblin::elf::Elf;
+
+    // Test binaries are built with DWARF line info
+    let buffer = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+    let elf = Elf::parse(&buffer).unwrap();
+    let debug_sections = debug_sections(&elf, &buffer).unwrap();
+    assert!(debug_sections.sections.contains_key(".debug_line"));
+    assert!(load_debug_info(&debug_sections).is_some());
+}
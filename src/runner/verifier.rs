@@ -0,0 +1,336 @@
+use super::{json_report, run_module, sarif_report, Config, ModuleSummary, PassConfig};
+use crate::checkers::Violation;
+use crate::ir::types::X86Regs;
+use crate::loaders::types::{DebugSections, ExecutableType, VwArch};
+use crate::loaders::{load_program, load_program_from_bytes};
+use crate::{HeapLayout, HeapRegs, HeapStrategy};
+use serde_json::Value;
+
+// The `module_path` reported for modules verified from memory.
+const IN_MEMORY_MODULE: &str = "<memory>";
+
+/// Where the module to verify comes from.
+#[derive(Clone, Debug)]
+pub enum ModuleSource {
+    Path(String),
+    Bytes(Vec<u8>),
+}
+
+/// Verifies a native Wasm module. Start from `Verifier::from_path` or `Verifier::from_bytes`,
+/// set the options that differ from the defaults, and call `verify`.
+///
+/// By default the module is a Lucet x64 module, the stack, heap and call passes run, the heap
+/// base is the first argument, the heap is laid out as the module's runtime lays it out, no
+/// register is pinned, calls are not summarized, and every function is verified on one thread.
+#[derive(Clone, Debug)]
+pub struct Verifier {
+    source: ModuleSource,
+    format: ExecutableType,
+    arch: VwArch,
+    passes: PassConfig,
+    heap_strategy: HeapStrategy,
+    heap_layout: Option<HeapLayout>,
+    heap_regs: HeapRegs,
+    interprocedural: bool,
+    only_func: Option<String>,
+    num_jobs: u32,
+    strict: bool,
+}
+
+impl Verifier {
+    pub fn new(source: ModuleSource) -> Self {
+        Verifier {
+            source,
+            format: ExecutableType::Lucet,
+            arch: VwArch::X64,
+            passes: PassConfig {
+                stack: true,
+                linear_mem: true,
+                call: true,
+                zero_cost: false,
+            },
+            heap_strategy: HeapStrategy::HeapPtrFirstArgWithGuards,
+            heap_layout: None,
+            heap_regs: HeapRegs::default(),
+            interprocedural: false,
+            only_func: None,
+            num_jobs: 1,
+            strict: false,
+        }
+    }
+
+    pub fn from_path(path: &str) -> Self {
+        Self::new(ModuleSource::Path(path.to_string()))
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self::new(ModuleSource::Bytes(bytes))
+    }
+
+    pub fn format(mut self, format: ExecutableType) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn arch(mut self, arch: VwArch) -> Self {
+        self.arch = arch;
+        self
+    }
+
+    /// Replaces the set of passes to run.
+    pub fn passes(mut self, passes: PassConfig) -> Self {
+        self.passes = passes;
+        self
+    }
+
+    pub fn stack(mut self, enabled: bool) -> Self {
+        self.passes.stack = enabled;
+        self
+    }
+
+    pub fn linear_mem(mut self, enabled: bool) -> Self {
+        self.passes.linear_mem = enabled;
+        self
+    }
+
+    pub fn call(mut self, enabled: bool) -> Self {
+        self.passes.call = enabled;
+        self
+    }
+
+    /// The locals pass, which checks that no uninitialized local is read. It runs after the
+    /// call pass, and only if the call pass is enabled.
+    pub fn zero_cost(mut self, enabled: bool) -> Self {
+        self.passes.zero_cost = enabled;
+        self
+    }
+
+    pub fn heap_strategy(mut self, heap_strategy: HeapStrategy) -> Self {
+        self.heap_strategy = heap_strategy;
+        self
+    }
+
+    /// The sizes of the heap reservation, guard region and globals area the runtime sets up.
+    pub fn heap_layout(mut self, heap_layout: HeapLayout) -> Self {
+        self.heap_layout = Some(heap_layout);
+        self
+    }
+
+    /// The registers that hold the heap base and vmctx on entry, and the register pinned to
+    /// the heap base, if any.
+    pub fn heap_regs(mut self, heap_regs: HeapRegs) -> Self {
+        self.heap_regs = heap_regs;
+        self
+    }
+
+    /// Summarizes the functions of the module, callees first, so that the heap and call
+    /// passes know which registers a direct call preserves and what it returns. Only x64
+    /// modules are summarized.
+    pub fn interprocedural(mut self, enabled: bool) -> Self {
+        self.interprocedural = enabled;
+        self
+    }
+
+    /// Verifies only the function named `func_name`.
+    pub fn only_func(mut self, func_name: &str) -> Self {
+        self.only_func = Some(func_name.to_string());
+        self
+    }
+
+    /// Verifies functions on `num_jobs` threads.
+    pub fn jobs(mut self, num_jobs: u32) -> Self {
+        self.num_jobs = num_jobs;
+        self
+    }
+
+    /// Rejects instructions the lifter does not model, instead of clearing their destinations.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// The configuration the analyses run with, for a module at `module_path`.
+    pub fn config_for(&self, module_path: &str) -> Config {
+        Config {
+            module_path: module_path.to_string(),
+            num_jobs: self.num_jobs,
+            only_func: self.only_func.clone(),
+            executable_type: self.format,
+            active_passes: self.passes.clone(),
+            arch: self.arch,
+            strict: self.strict,
+            heap_strategy: self.heap_strategy.clone(),
+            heap_layout: self
+                .heap_layout
+                .unwrap_or_else(|| HeapLayout::for_format(self.format)),
+            heap_regs: self.heap_regs,
+            interprocedural: self.interprocedural,
+        }
+    }
+
+    pub fn verify(&self) -> Result<VerificationReport, VerifyError> {
+        self.config_for(IN_MEMORY_MODULE)
+            .check_supported()
+            .map_err(VerifyError::UnsupportedConfig)?;
+        let (config, module) = match &self.source {
+            ModuleSource::Path(path) => {
+                let config = self.config_for(path);
+                let module = load_program(&config);
+                (config, module)
+            }
+            ModuleSource::Bytes(bytes) => {
+                let config = self.config_for(IN_MEMORY_MODULE);
+                let module = load_program_from_bytes(&config, bytes.clone());
+                (config, module)
+            }
+        };
+        let module = module.map_err(VerifyError::Load)?;
+        let debug_sections = module.debug_sections.clone();
+        let summary = run_module(config.clone(), module).map_err(VerifyError::UnsupportedConfig)?;
+        Ok(VerificationReport {
+            config,
+            summary,
+            debug_sections,
+        })
+    }
+}
+
+/// Why a `Verifier` could not verify a module.
+#[derive(Clone, Debug)]
+pub enum VerifyError {
+    /// The module could not be read, or is not a valid module of its format.
+    Load(String),
+    /// A pass that was asked for cannot run on the module.
+    UnsupportedConfig(String),
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VerifyError::Load(reason) | VerifyError::UnsupportedConfig(reason) => {
+                write!(f, "{}", reason)
+            }
+        }
+    }
+}
+impl std::error::Error for VerifyError {}
+
+/// The outcome of a `Verifier` run: the configuration it ran with and the verdict and stats
+/// of every function.
+#[derive(Clone, Debug)]
+pub struct VerificationReport {
+    pub config: Config,
+    pub summary: ModuleSummary,
+    // The DWARF sections of the verified module, for source locations.
+    debug_sections: DebugSections,
+}
+
+impl VerificationReport {
+    pub fn is_safe(&self) -> bool {
+        self.summary.is_safe()
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.summary.exit_code()
+    }
+
+    pub fn violations(&self) -> Vec<&Violation> {
+        self.summary.violations()
+    }
+
+    /// The versioned JSON report that `-o` writes.
+    pub fn to_json(&self) -> Value {
+        json_report(&self.config, &self.summary)
+    }
+
+    /// A SARIF log of the failed checks.
+    pub fn to_sarif(&self) -> Value {
+        sarif_report(
+            &self.config.module_path,
+            &self.debug_sections,
+            &self.summary,
+        )
+    }
+
+    /// Prints the total time spent in each pass and the failures of every function.
+    pub fn print(&self) {
+        let total = self.summary.total_stats();
+        println!("Verified {:?} functions", self.summary.funcs.len());
+        println!(
+            "Total time = {:?}s CFG: {:?} Stack: {:?}s Heap: {:?}s Call: {:?}s Locals {:?}s",
+            total.total_time(),
+            total.cfg_time,
+            total.stack_time,
+            total.heap_time,
+            total.call_time,
+            total.locals_time,
+        );
+        self.summary.print();
+    }
+}
+
+#[test]
+fn verifier_config_test() {
+    let verifier = Verifier::from_path("module.so")
+        .format(ExecutableType::Wasmtime)
+        .linear_mem(false)
+        .zero_cost(true)
+        .heap_layout(HeapLayout {
+            guard: 0x8000_0000,
+            ..HeapLayout::default()
+        })
+        .heap_regs(HeapRegs {
+            pinned_heap_base: Some(X86Regs::R15.into()),
+            ..HeapRegs::default()
+        })
+        .interprocedural(true)
+        .only_func("f")
+        .jobs(4);
+    let config = verifier.config_for("module.so");
+    assert_eq!(config.executable_type, ExecutableType::Wasmtime);
+    assert_eq!(config.arch, VwArch::X64);
+    assert!(config.active_passes.stack);
+    assert!(!config.active_passes.linear_mem);
+    assert!(config.active_passes.zero_cost);
+    assert_eq!(config.only_func, Some("f".to_string()));
+    assert_eq!(config.num_jobs, 4);
+    assert_eq!(
+        config.heap_strategy,
+        HeapStrategy::HeapPtrFirstArgWithGuards
+    );
+    assert_eq!(config.heap_layout.guard, 0x8000_0000);
+    assert_eq!(config.heap_layout.reservation, 0x1_0000_0000);
+    assert_eq!(config.heap_regs.pinned_heap_base, Some(X86Regs::R15.into()));
+    assert_eq!(config.heap_regs.heap_base, None);
+    assert!(config.interprocedural);
+}
+
+#[test]
+fn verifier_wasmtime_defaults_test() {
+    let verifier = Verifier::from_bytes(Vec::new()).format(ExecutableType::Wasmtime);
+    assert_eq!(
+        verifier.config_for("module.cwasm").heap_layout,
+        HeapLayout::wasmtime()
+    );
+    assert!(matches!(
+        verifier.verify(),
+        Err(VerifyError::UnsupportedConfig(_))
+    ));
+    assert!(matches!(
+        verifier.call(false).verify(),
+        Err(VerifyError::Load(_))
+    ));
+}
+
+#[test]
+fn verifier_aarch64_call_pass_test() {
+    let verifier = Verifier::from_bytes(Vec::new()).arch(VwArch::Aarch64);
+    assert!(matches!(
+        verifier.verify(),
+        Err(VerifyError::UnsupportedConfig(_))
+    ));
+    assert!(matches!(
+        verifier.call(false).verify(),
+        Err(VerifyError::Load(_))
+    ));
+}
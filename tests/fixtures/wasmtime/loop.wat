@@ -0,0 +1,25 @@
+(module
+  (memory 1)
+  (func (export "sum") (param $ptr i32) (param $len i32) (result i32)
+    (local $acc i32)
+    (block $done
+      (loop $next
+        local.get $len
+        i32.eqz
+        br_if $done
+        local.get $acc
+        local.get $ptr
+        i32.load
+        i32.add
+        local.set $acc
+        local.get $ptr
+        i32.const 4
+        i32.add
+        local.set $ptr
+        local.get $len
+        i32.const 1
+        i32.sub
+        local.set $len
+        br $next))
+    local.get $acc)
+)
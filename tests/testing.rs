@@ -4,17 +4,26 @@ use veriwasm::{analyses, checkers, ir, loaders, runner};
 use analyses::reaching_defs::{analyze_reaching_defs, ReachingDefnAnalyzer};
 use analyses::run_worklist;
 use analyses::{CallAnalyzer, HeapAnalyzer, StackAnalyzer};
-use checkers::{check_calls, check_heap, check_stack};
+use checkers::{check_calls, check_heap, check_stack, CheckKind};
 use ir::fully_resolved_cfg;
+use ir::types::X86Regs;
 use loaders::types::VwFuncInfo;
 use loaders::types::{ExecutableType, VwArch};
 use loaders::utils::get_data;
 use loaders::Loadable;
 use lucet_module::{Signature, ValueType};
+use runner::{ModuleSummary, Verdict, Verifier, VerifyError};
 use std::collections::HashMap;
 use std::panic;
 use veriwasm::loaders::load_program;
 use veriwasm::runner::run_locals;
+use veriwasm::{
+    validate_calls, validate_function, validate_heap, validate_heap_with_options, validate_stack,
+};
+use veriwasm::{
+    HeapLayout, HeapRegs, HeapStrategy, IndexWidth, Relocation, VMCtxMemory, ValidateOptions,
+    ValidationError,
+};
 use yaxpeax_core::analyses::control_flow::check_cfg_integrity;
 
 fn get_proxy_func_signatures() -> VwFuncInfo {
@@ -58,192 +67,235 @@ fn get_proxy_func_signatures() -> VwFuncInfo {
 
 fn full_test_helper(path: &str, format: ExecutableType, arch: VwArch) {
     let _ = env_logger::builder().is_test(true).try_init();
-    let active_passes = runner::PassConfig {
-        stack: true,
-        linear_mem: true,
-        call: true,
-        zero_cost: false,
-    };
-    let config = runner::Config {
-        module_path: path.to_string(),
-        _num_jobs: 1,
-        output_path: "".to_string(),
-        has_output: false,
-        only_func: None,
-        executable_type: format,
-        active_passes,
-        arch,
-        strict: true,
-    };
-    runner::run(config);
+    let report = Verifier::from_path(path)
+        .format(format)
+        .arch(arch)
+        .strict(true)
+        .verify()
+        .unwrap();
+    assert!(report.is_safe());
 }
 
 fn full_test_with_locals(path: &str, format: ExecutableType, arch: VwArch) {
     let _ = env_logger::builder().is_test(true).try_init();
-    let active_passes = runner::PassConfig {
-        stack: true,
-        linear_mem: true,
-        call: true,
-        zero_cost: true,
-    };
-    let config = runner::Config {
-        module_path: path.to_string(),
-        _num_jobs: 1,
-        output_path: "".to_string(),
-        has_output: false,
-        only_func: None,
-        executable_type: format,
-        active_passes,
-        arch,
-        strict: true,
-    };
-    runner::run(config);
+    let report = Verifier::from_path(path)
+        .format(format)
+        .arch(arch)
+        .zero_cost(true)
+        .strict(true)
+        .verify()
+        .unwrap();
+    assert!(report.is_safe());
 }
 
-fn negative_test_helper(path: &str, func_name: &str, format: ExecutableType, arch: VwArch) {
+// Wasmtime loads the heap base out of the vmctx. Its offset depends on the
+// module's vmctx layout, which is not recorded in the compiled object; this
+// is where the Wasmtime the fixtures are compiled with keeps it.
+const WASMTIME_VMCTX_HEAP_BASE_PTR_OFFSET: usize = 0x50;
+
+// Wasmtime modules are compiled ahead of time with `make build_wasmtime_fixtures`,
+// so the tests using them are ignored unless run with `--ignored`.
+fn wasmtime_test_helper(path: &str) {
     let _ = env_logger::builder().is_test(true).try_init();
-    let active_passes = runner::PassConfig {
-        stack: true,
-        linear_mem: true,
-        call: true,
-        zero_cost: false,
-    };
-    let config = runner::Config {
-        module_path: path.to_string(),
-        _num_jobs: 1,
-        output_path: "".to_string(),
-        has_output: false,
-        only_func: Some(func_name.to_string()),
-        executable_type: format,
-        active_passes,
-        arch,
-        strict: true,
-    };
+    let report = Verifier::from_path(path)
+        .format(ExecutableType::Wasmtime)
+        .call(false)
+        .heap_strategy(HeapStrategy::VMCtxFirstArgWithGuards {
+            vmctx_heap_base_ptr_offset: WASMTIME_VMCTX_HEAP_BASE_PTR_OFFSET,
+        })
+        .strict(true)
+        .verify()
+        .unwrap();
+    assert!(report.is_safe());
+}
 
-    let module = load_program(&config);
-    runner::run_helper(config, module, Vec::new(), get_proxy_func_signatures());
+// Small modules compiled with Wasmtime 25.0.3 by `make build_wasmtime_test_fixtures`,
+// verified with the offset of the heap base in each module's vmctx. The objects also hold
+// the trampolines between the host and Wasm calling conventions, which move arguments
+// through host pointers that the heap pass rejects, so only the Wasm functions are
+// expected to be safe.
+fn wasmtime_fixture_helper(name: &str, vmctx_heap_base_ptr_offset: usize) -> Vec<Verdict> {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let report = Verifier::from_path(&format!("./tests/fixtures/wasmtime/{}.cwasm", name))
+        .format(ExecutableType::Wasmtime)
+        .call(false)
+        .heap_strategy(HeapStrategy::VMCtxFirstArgWithGuards {
+            vmctx_heap_base_ptr_offset,
+        })
+        .strict(true)
+        .verify()
+        .unwrap();
+    let verdicts: Vec<Verdict> = report
+        .summary
+        .funcs
+        .into_iter()
+        .filter(|func| func.name.starts_with("wasm[0]::function["))
+        .map(|func| func.verdict)
+        .collect();
+    assert!(!verdicts.is_empty());
+    verdicts
 }
 
-fn negative_test_with_locals(path: &str, func_name: &str, format: ExecutableType, arch: VwArch) {
+fn negative_test_helper(
+    path: &str,
+    func_name: &str,
+    format: ExecutableType,
+    arch: VwArch,
+) -> ModuleSummary {
     let _ = env_logger::builder().is_test(true).try_init();
-    let active_passes = runner::PassConfig {
-        stack: true,
-        linear_mem: true,
-        call: true,
-        zero_cost: true,
-    };
-    let config = runner::Config {
-        module_path: path.to_string(),
-        _num_jobs: 1,
-        output_path: "".to_string(),
-        has_output: false,
-        only_func: Some(func_name.to_string()),
-        executable_type: format,
-        active_passes,
-        arch,
-        strict: true,
-    };
-    let module = load_program(&config);
-    runner::run_helper(config, module, Vec::new(), get_proxy_func_signatures());
+    let config = Verifier::from_path(path)
+        .format(format)
+        .arch(arch)
+        .only_func(func_name)
+        .strict(true)
+        .config_for(path);
+
+    let module = load_program(&config).unwrap();
+    runner::run_helper(config, module, Vec::new(), get_proxy_func_signatures())
+}
+
+fn negative_test_with_locals(
+    path: &str,
+    func_name: &str,
+    format: ExecutableType,
+    arch: VwArch,
+) -> ModuleSummary {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let config = Verifier::from_path(path)
+        .format(format)
+        .arch(arch)
+        .zero_cost(true)
+        .only_func(func_name)
+        .strict(true)
+        .config_for(path);
+    let module = load_program(&config).unwrap();
+    runner::run_helper(config, module, Vec::new(), get_proxy_func_signatures())
 }
 
 #[test]
-#[should_panic(expected = "Not Stack Safe")]
 fn negative_test_zerocost_1() {
-    negative_test_with_locals(
+    let summary = negative_test_with_locals(
         "veriwasm_public_data/negative_tests/negative_tests_locals.so",
         "func1",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Stack)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Stack Safe")]
 fn negative_test_zerocost_2() {
-    negative_test_with_locals(
+    let summary = negative_test_with_locals(
         "veriwasm_public_data/negative_tests/negative_tests_locals.so",
         "func2",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Stack)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Stack Safe")]
 fn negative_test_zerocost_3() {
-    negative_test_with_locals(
+    let summary = negative_test_with_locals(
         "veriwasm_public_data/negative_tests/negative_tests_locals.so",
         "func3",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Stack)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Locals Safe")]
 fn negative_test_zerocost_4() {
-    negative_test_with_locals(
+    let summary = negative_test_with_locals(
         "veriwasm_public_data/negative_tests/negative_tests_locals.so",
         "func4",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Locals)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Locals Safe")]
 fn negative_test_zerocost_5() {
-    negative_test_with_locals(
+    let summary = negative_test_with_locals(
         "veriwasm_public_data/negative_tests/negative_tests_locals.so",
         "func5",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Locals)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Locals Safe")]
 fn negative_test_zerocost_6() {
-    negative_test_with_locals(
+    let summary = negative_test_with_locals(
         "veriwasm_public_data/negative_tests/negative_tests_locals.so",
         "func6",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Locals)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Locals Safe")]
 fn negative_test_zerocost_7() {
-    negative_test_with_locals(
+    let summary = negative_test_with_locals(
         "veriwasm_public_data/negative_tests/negative_tests_locals.so",
         "func7",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Locals)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Locals Safe")]
 fn negative_test_zerocost_8() {
-    negative_test_with_locals(
+    let summary = negative_test_with_locals(
         "veriwasm_public_data/negative_tests/negative_tests_locals.so",
         "func8",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Locals)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Call Safe")]
 fn negative_test_zerocost_9() {
-    negative_test_with_locals(
+    let summary = negative_test_with_locals(
         "veriwasm_public_data/negative_tests/negative_tests_locals.so",
         "func9",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Call)
+    );
 }
 
 // These tests are commented out for the branch that I am merging into Lucet
@@ -285,6 +337,66 @@ fn full_test_libexpat() {
     )
 }
 
+#[test]
+fn full_test_libogg_from_bytes() {
+    let path = "./veriwasm_public_data/firefox_libs/liboggwasm.so";
+    let bytes = std::fs::read(path).unwrap();
+    let from_bytes = Verifier::from_bytes(bytes).strict(true).verify().unwrap();
+    let from_path = Verifier::from_path(path).strict(true).verify().unwrap();
+    assert!(from_bytes.is_safe());
+    assert_eq!(
+        from_bytes.summary.funcs.len(),
+        from_path.summary.funcs.len()
+    );
+}
+
+#[test]
+fn wasmtime_fixture_memory_from_bytes() {
+    let bytes = std::fs::read("./tests/fixtures/wasmtime/memory.cwasm").unwrap();
+    let report = Verifier::from_bytes(bytes)
+        .format(ExecutableType::Wasmtime)
+        .call(false)
+        .heap_strategy(HeapStrategy::VMCtxFirstArgWithGuards {
+            vmctx_heap_base_ptr_offset: 0x60,
+        })
+        .strict(true)
+        .verify()
+        .unwrap();
+    let verdicts: Vec<&Verdict> = report
+        .summary
+        .funcs
+        .iter()
+        .filter(|func| func.name.starts_with("wasm[0]::function["))
+        .map(|func| &func.verdict)
+        .collect();
+    assert_eq!(verdicts.len(), 2);
+    assert!(verdicts
+        .iter()
+        .all(|verdict| matches!(verdict, Verdict::Safe)));
+}
+
+#[test]
+fn load_negative_malformed_modules() {
+    // Not an ELF file at all
+    let report = Verifier::from_bytes(b"not a module".to_vec())
+        .format(ExecutableType::Wasmtime)
+        .call(false)
+        .verify();
+    assert!(matches!(report, Err(VerifyError::Load(_))));
+    // An ELF header whose section headers are past the end of the file
+    let mut truncated = b"\x7fELF\x02\x01\x01".to_vec();
+    truncated.resize(64, 0);
+    truncated[40] = 0xff;
+    truncated[60] = 1;
+    for format in &[ExecutableType::Wasmtime, ExecutableType::Lucet] {
+        let report = Verifier::from_bytes(truncated.clone())
+            .format(*format)
+            .call(false)
+            .verify();
+        assert!(matches!(report, Err(VerifyError::Load(_))));
+    }
+}
+
 #[test]
 fn full_test_soundtouch() {
     full_test_helper(
@@ -340,113 +452,143 @@ fn full_test_soundtouch() {
 //}
 
 #[test]
-#[should_panic(expected = "Not Stack Safe")]
 fn negative_test_1() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_1_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Stack)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Stack Safe")]
 fn negative_test_2() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_2_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Stack)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Stack Safe")]
 fn negative_test_3() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_3_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Stack)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Jump Targets Broken, target = None")]
 fn negative_test_4() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_4_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert!(matches!(
+        &summary.funcs[0].verdict,
+        Verdict::LiftError(msg) if msg.contains("does not go through a bounded jump table")
+    ));
 }
 
 #[test]
-#[should_panic(expected = "Not Call Safe")]
 fn negative_test_5() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_5_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Call)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Jump Targets Broken, target = None")]
 fn negative_test_6() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_6_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert!(matches!(
+        &summary.funcs[0].verdict,
+        Verdict::LiftError(msg) if msg.contains("does not go through a bounded jump table")
+    ));
 }
 
 #[test]
-#[should_panic(expected = "Not Heap Safe")]
 fn negative_test_7() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_7_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Heap)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Heap Safe")]
 fn negative_test_8() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_8_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Heap)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Heap Safe")]
 fn negative_test_9() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_9_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Heap)
+    );
 }
 
 #[test]
-#[should_panic(expected = "Not Heap Safe")]
 fn negative_test_10() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_10_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Heap)
+    );
 }
 
 //#[test]
@@ -461,81 +603,102 @@ fn negative_test_10() {
 //}
 
 #[test]
-#[should_panic(expected = "not implemented")]
 fn negative_test_12() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_12_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert!(matches!(
+        &summary.funcs[0].verdict,
+        Verdict::LiftError(msg) if msg.contains("not implemented")
+    ));
 }
 
 #[test]
-#[should_panic(expected = "not implemented")]
 fn negative_test_13() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_13_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert!(matches!(
+        &summary.funcs[0].verdict,
+        Verdict::LiftError(msg) if msg.contains("not implemented")
+    ));
 }
 
 #[test]
-#[should_panic(expected = "Not Stack Safe")]
 fn negative_test_14() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_14_testfail",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Stack)
+    );
 }
 
 // # NaCl issue #23
 #[test]
-#[should_panic(expected = "Not Call Safe")]
 fn negative_test_nacl_23() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_nacl_23",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Call)
+    );
 }
 
 #[test]
-#[should_panic(expected = "not implemented")]
 fn negative_test_nacl_323_1() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_nacl_323_1",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert!(matches!(
+        &summary.funcs[0].verdict,
+        Verdict::LiftError(msg) if msg.contains("not implemented")
+    ));
 }
 
 #[test]
-#[should_panic(expected = "not implemented")]
 fn negative_test_nacl_323_2() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_nacl_323_2",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert!(matches!(
+        &summary.funcs[0].verdict,
+        Verdict::LiftError(msg) if msg.contains("not implemented")
+    ));
 }
 
 #[test]
-#[should_panic(expected = "Not Stack Safe")]
 fn negative_test_misfit_1() {
-    negative_test_helper(
+    let summary = negative_test_helper(
         "veriwasm_public_data/negative_tests/negative_tests.so",
         "guest_func_misfit_1",
         ExecutableType::Lucet,
         VwArch::X64,
     );
+    assert_eq!(
+        summary.violations().first().map(|v| v.kind),
+        Some(CheckKind::Stack)
+    );
 }
 
 // fn get_proxy_func_signatures() -> VwFuncInfo {
@@ -753,158 +916,968 @@ fn negative_test_misfit_1() {
 //     );
 // }
 
-// #[test]
-// fn wasmtime_wasm_callback() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/callback.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+fn wasmtime_fixture_memory() {
+    let verdicts = wasmtime_fixture_helper("memory", 0x60);
+    assert_eq!(verdicts.len(), 2);
+    assert!(verdicts
+        .iter()
+        .all(|verdict| matches!(verdict, Verdict::Safe)));
+}
 
-// #[test]
-// fn wasmtime_wasm_fib() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/fib-wasm.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+fn wasmtime_fixture_loop() {
+    let verdicts = wasmtime_fixture_helper("loop", 0x60);
+    assert_eq!(verdicts.len(), 1);
+    assert!(matches!(verdicts[0], Verdict::Safe));
+}
 
-// #[test]
-// fn wasmtime_wasm_fraction_norm() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/fraction-norm.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+fn wasmtime_fixture_negative_wrong_vmctx_offset() {
+    // The heap base is at 0x60 in this module's vmctx, not at 0x50
+    let verdicts = wasmtime_fixture_helper("memory", 0x50);
+    assert!(verdicts
+        .iter()
+        .all(|verdict| matches!(verdict, Verdict::Unsafe(_))));
+}
 
-// #[test]
-// fn wasmtime_wasm_hello() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/hello.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_callback() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/callback.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_memory() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/memory.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_fib() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/fib-wasm.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_reflect() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/reflect.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_fraction_norm() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/fraction-norm.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_serialize() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/serialize.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_hello() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/hello.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_table() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/table.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_memory() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/memory.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_trap() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/trap.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_reflect() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/reflect.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_fib_wasm_dwarf5() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/fib-wasm-dwarf5.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_serialize() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/serialize.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_finalize() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/finalize.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_table() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/table.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_global() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/global.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_trap() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/trap.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_issue_1306() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/issue-1306-name-section-with-u32-max-function-index.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_fib_wasm_dwarf5() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/fib-wasm-dwarf5.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_multi() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/multi.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_finalize() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/finalize.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_reverse_str() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/reverse-str.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_global() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/global.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_start() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/start.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_issue_1306() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/issue-1306-name-section-with-u32-max-function-index.cwasm")
+}
 
-// #[test]
-// fn wasmtime_wasm_threads() {
-//     full_test_helper(
-//         "./veriwasm_public_data/wasmtime/bin/wasm/threads.wasm",
-//         ExecutableType::Wasmtime,
-//         VwArch::X64,
-//     )
-// }
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_multi() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/multi.cwasm")
+}
+
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_reverse_str() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/reverse-str.cwasm")
+}
+
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_start() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/start.cwasm")
+}
+
+#[test]
+#[ignore = "needs `make build_wasmtime_fixtures`"]
+fn wasmtime_wasm_threads() {
+    wasmtime_test_helper("./veriwasm_public_data/wasmtime/bin/cwasm/threads.cwasm")
+}
+
+// mov eax, esi
+// mov eax, [rdi + rax]
+// ret
+#[test]
+fn validate_heap_bounded_access() {
+    let code = [0x89, 0xf0, 0x8b, 0x04, 0x07, 0xc3];
+    assert!(validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards).is_ok());
+}
+
+// mov eax, esi
+// mov eax, [rdi + rax]
+// ret
+#[test]
+fn validate_heap_negative_small_guard() {
+    let code = [0x89, 0xf0, 0x8b, 0x04, 0x07, 0xc3];
+    let layout = HeapLayout {
+        reservation: 0x8000_0000,
+        guard: 0x1_0000,
+        globals_size: 0x1000,
+        vmctx_size: 0x1000,
+    };
+    assert!(matches!(
+        validate_heap_with_options(
+            &code,
+            &[0],
+            &[],
+            &ValidateOptions::default().heap_layout(layout)
+        ),
+        Err(ValidationError::HeapUnsafe { offset: 2, .. })
+    ));
+}
+
+// Heap base pinned in r15
+fn pinned_r15() -> HeapRegs {
+    HeapRegs {
+        pinned_heap_base: Some(X86Regs::R15.into()),
+        ..HeapRegs::default()
+    }
+}
+
+// mov eax, esi
+// mov eax, [r15 + rax]
+// ret
+#[test]
+fn validate_heap_pinned_reg_access() {
+    let code = [0x89, 0xf0, 0x41, 0x8b, 0x04, 0x07, 0xc3];
+    let validate = |heap_regs| {
+        validate_heap_with_options(
+            &code,
+            &[0],
+            &[],
+            &ValidateOptions::default().heap_regs(heap_regs),
+        )
+    };
+    assert!(validate(pinned_r15()).is_ok());
+    assert!(matches!(
+        validate(HeapRegs::default()),
+        Err(ValidationError::HeapUnsafe { offset: 2, .. })
+    ));
+}
+
+// xor r15d, r15d
+// call 0x8
+// ret
+#[test]
+fn validate_heap_negative_pinned_reg_clobbered_at_call() {
+    let code = [0x45, 0x31, 0xff, 0xe8, 0x00, 0x00, 0x00, 0x00, 0xc3];
+    assert!(matches!(
+        validate_heap_with_options(
+            &code,
+            &[0],
+            &[],
+            &ValidateOptions::default().heap_regs(pinned_r15())
+        ),
+        Err(ValidationError::HeapUnsafe { offset: 3, .. })
+    ));
+}
+
+// xor r15d, r15d
+// ret
+#[test]
+fn validate_heap_negative_pinned_reg_clobbered_at_ret() {
+    let code = [0x45, 0x31, 0xff, 0xc3];
+    assert!(matches!(
+        validate_heap_with_options(
+            &code,
+            &[0],
+            &[],
+            &ValidateOptions::default().heap_regs(pinned_r15())
+        ),
+        Err(ValidationError::HeapUnsafe { offset: 3, .. })
+    ));
+}
+
+// sub rsp, 8
+// mov [rsp], rdi
+// call 0xd
+// mov rcx, [rsp]
+// mov eax, esi
+// mov eax, [rcx + rax]
+// add rsp, 8
+// ret
+#[test]
+fn validate_heap_base_spilled_across_call() {
+    let code = [
+        0x48, 0x83, 0xec, 0x08, 0x48, 0x89, 0x3c, 0x24, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x48, 0x8b,
+        0x0c, 0x24, 0x89, 0xf0, 0x8b, 0x04, 0x01, 0x48, 0x83, 0xc4, 0x08, 0xc3,
+    ];
+    assert!(validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards).is_ok());
+}
+
+// mov rbx, rdi
+// call 0x8
+// mov eax, edx
+// mov eax, [rbx + rax]
+// ret
+#[test]
+fn validate_heap_base_in_callee_saved_register_across_call() {
+    let code = [
+        0x48, 0x89, 0xfb, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x89, 0xd0, 0x8b, 0x04, 0x03, 0xc3,
+    ];
+    assert!(validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards).is_ok());
+}
+
+// The callee may clobber the caller-saved rsi
+// mov rsi, rdi
+// call 0x8
+// mov eax, edx
+// mov eax, [rsi + rax]
+// ret
+#[test]
+fn validate_heap_negative_base_in_caller_saved_register_across_call() {
+    let code = [
+        0x48, 0x89, 0xfe, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x89, 0xd0, 0x8b, 0x04, 0x06, 0xc3,
+    ];
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards),
+        Err(ValidationError::HeapUnsafe { offset: 10, .. })
+    ));
+}
+
+// The callee's frame overwrites the slot below the stack pointer
+// mov [rsp - 8], rdi
+// call 0xa
+// mov rcx, [rsp - 8]
+// mov eax, esi
+// mov eax, [rcx + rax]
+// ret
+#[test]
+fn validate_heap_negative_base_below_stack_pointer_across_call() {
+    let code = [
+        0x48, 0x89, 0x7c, 0x24, 0xf8, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x48, 0x8b, 0x4c, 0x24, 0xf8,
+        0x89, 0xf0, 0x8b, 0x04, 0x01, 0xc3,
+    ];
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards),
+        Err(ValidationError::HeapUnsafe { offset: 17, .. })
+    ));
+}
+
+// mov rax, rsi
+// mov eax, [rdi + rax]
+// ret
+#[test]
+fn validate_heap_negative_unbounded_index() {
+    let code = [0x48, 0x89, 0xf0, 0x8b, 0x04, 0x07, 0xc3];
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards),
+        Err(ValidationError::HeapUnsafe { .. })
+    ));
+}
+
+// mov eax, [rsi]
+// ret
+#[test]
+fn validate_heap_negative_unknown_base() {
+    let code = [0x8b, 0x06, 0xc3];
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards),
+        Err(ValidationError::HeapUnsafe { .. })
+    ));
+}
+
+// mov eax, [0x1000]
+// ret
+#[test]
+fn validate_heap_negative_absolute_address() {
+    let code = [0x8b, 0x04, 0x25, 0x00, 0x10, 0x00, 0x00, 0xc3];
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards),
+        Err(ValidationError::HeapUnsafe { .. })
+    ));
+}
+
+// mov rax, [rdi + 0x40]
+// mov ecx, esi
+// mov eax, [rax + rcx]
+// ret
+#[test]
+fn validate_heap_vmctx_access() {
+    let code = [0x48, 0x8b, 0x47, 0x40, 0x89, 0xf1, 0x8b, 0x04, 0x08, 0xc3];
+    let strategy = HeapStrategy::VMCtxFirstArgWithGuards {
+        vmctx_heap_base_ptr_offset: 0x40,
+    };
+    assert!(validate_heap(&code, &[0], &[], strategy).is_ok());
+}
+
+// mov [rsp], rdi
+// mov rax, [rsp]
+// mov rax, [rax + 0x40]
+// mov ecx, esi
+// mov eax, [rax + rcx]
+// ret
+#[test]
+fn validate_heap_vmctx_spilled() {
+    let code = [
+        0x48, 0x89, 0x3c, 0x24, 0x48, 0x8b, 0x04, 0x24, 0x48, 0x8b, 0x40, 0x40, 0x89, 0xf1, 0x8b,
+        0x04, 0x08, 0xc3,
+    ];
+    let strategy = HeapStrategy::VMCtxFirstArgWithGuards {
+        vmctx_heap_base_ptr_offset: 0x40,
+    };
+    assert!(validate_heap(&code, &[0], &[], strategy).is_ok());
+}
+
+// mov rax, [rdi + 0x48]
+// mov ecx, esi
+// mov eax, [rax + rcx]
+// ret
+#[test]
+fn validate_heap_negative_vmctx_wrong_offset() {
+    let code = [0x48, 0x8b, 0x47, 0x48, 0x89, 0xf1, 0x8b, 0x04, 0x08, 0xc3];
+    let strategy = HeapStrategy::VMCtxFirstArgWithGuards {
+        vmctx_heap_base_ptr_offset: 0x40,
+    };
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], strategy),
+        Err(ValidationError::HeapUnsafe { .. })
+    ));
+}
+
+// mov [rdi + 0x40], rsi
+// mov rax, [rdi + 0x40]
+// mov ecx, esi
+// mov eax, [rax + rcx]
+// ret
+#[test]
+fn validate_heap_negative_vmctx_heap_base_overwritten() {
+    let code = [
+        0x48, 0x89, 0x77, 0x40, 0x48, 0x8b, 0x47, 0x40, 0x89, 0xf1, 0x8b, 0x04, 0x08, 0xc3,
+    ];
+    let strategy = HeapStrategy::VMCtxFirstArgWithGuards {
+        vmctx_heap_base_ptr_offset: 0x40,
+    };
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], strategy),
+        Err(ValidationError::HeapUnsafe { offset: 0, .. })
+    ));
+}
+
+// mov rax, [rdi + 0x2000]
+// ret
+#[test]
+fn validate_heap_negative_vmctx_access_past_end() {
+    let code = [0x48, 0x8b, 0x87, 0x00, 0x20, 0x00, 0x00, 0xc3];
+    let strategy = HeapStrategy::VMCtxFirstArgWithGuards {
+        vmctx_heap_base_ptr_offset: 0x40,
+    };
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], strategy),
+        Err(ValidationError::HeapUnsafe { offset: 0, .. })
+    ));
+}
+
+// mov esi, esi
+// lea rcx, [rip + 0x10]
+// cmp esi, 4
+// jae default
+// movsxd rax, dword [rcx + rsi*4]
+// ret
+// default:
+// ud2
+#[test]
+fn validate_heap_jump_table_access() {
+    let code = [
+        0x89, 0xf6, 0x48, 0x8d, 0x0d, 0x10, 0x00, 0x00, 0x00, 0x83, 0xfe, 0x04, 0x73, 0x05, 0x48,
+        0x63, 0x04, 0xb1, 0xc3, 0x0f, 0x0b,
+    ];
+    assert!(validate_heap(
+        &code,
+        &[0, 14, 19],
+        &[(0, 14), (0, 19)],
+        HeapStrategy::HeapPtrFirstArgWithGuards
+    )
+    .is_ok());
+}
+
+// The upper half of rsi is not known to be zero
+// lea rcx, [rip + 0x10]
+// cmp esi, 4
+// jae default
+// movsxd rax, dword [rcx + rsi*4]
+// ret
+// default:
+// ud2
+#[test]
+fn validate_heap_negative_jump_table_upper_half_unknown() {
+    let code = [
+        0x48, 0x8d, 0x0d, 0x10, 0x00, 0x00, 0x00, 0x83, 0xfe, 0x04, 0x73, 0x05, 0x48, 0x63, 0x04,
+        0xb1, 0xc3, 0x0f, 0x0b,
+    ];
+    assert!(matches!(
+        validate_heap(
+            &code,
+            &[0, 12, 17],
+            &[(0, 12), (0, 17)],
+            HeapStrategy::HeapPtrFirstArgWithGuards
+        ),
+        Err(ValidationError::HeapUnsafe { offset: 12, .. })
+    ));
+}
+
+// A 32-bit index is not bounded by the table's entry count
+// lea rcx, [rip + 0x10]
+// mov eax, esi
+// movsxd rax, dword [rcx + rax*4]
+// ret
+#[test]
+fn validate_heap_negative_jump_table_unchecked_index() {
+    let code = [
+        0x48, 0x8d, 0x0d, 0x10, 0x00, 0x00, 0x00, 0x89, 0xf0, 0x48, 0x63, 0x04, 0x81, 0xc3,
+    ];
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards),
+        Err(ValidationError::HeapUnsafe { offset: 9, .. })
+    ));
+}
+
+// lea rcx, [rip + 0x10]
+// movsxd rax, dword [rcx + rsi*4]
+// ret
+#[test]
+fn validate_heap_negative_jump_table_unbounded_index() {
+    let code = [
+        0x48, 0x8d, 0x0d, 0x10, 0x00, 0x00, 0x00, 0x48, 0x63, 0x04, 0xb1, 0xc3,
+    ];
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards),
+        Err(ValidationError::HeapUnsafe { offset: 7, .. })
+    ));
+}
+
+// mov eax, esi
+// movsxd rax, dword [rdx + rax*4]
+// ret
+#[test]
+fn validate_heap_negative_jump_table_unknown_base() {
+    let code = [0x89, 0xf0, 0x48, 0x63, 0x04, 0x82, 0xc3];
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards),
+        Err(ValidationError::HeapUnsafe { offset: 2, .. })
+    ));
+}
+
+// mov rax, [rdi + 0x10]
+// mov ecx, esi
+// cmp rcx, [rdi + 0x18]
+// jae trap
+// mov eax, [rax + rcx]
+// ret
+// trap:
+// ud2
+#[test]
+fn validate_heap_bounds_checked_access() {
+    let code = [
+        0x48, 0x8b, 0x47, 0x10, 0x89, 0xf1, 0x48, 0x3b, 0x4f, 0x18, 0x73, 0x04, 0x8b, 0x04, 0x08,
+        0xc3, 0x0f, 0x0b,
+    ];
+    let strategy = HeapStrategy::VMCtxFirstArgWithBoundsChecks {
+        vmctx_heap_base_ptr_offset: 0x10,
+        vmctx_heap_bound_offset: 0x18,
+        index_width: IndexWidth::I32,
+    };
+    let layout = HeapLayout {
+        reservation: 0,
+        guard: 0x1_0000,
+        globals_size: 0x1000,
+        vmctx_size: 0x1000,
+    };
+    assert!(validate_heap_with_options(
+        &code,
+        &[0, 12, 16],
+        &[(0, 12), (0, 16)],
+        &ValidateOptions::default()
+            .heap_strategy(strategy)
+            .heap_layout(layout)
+    )
+    .is_ok());
+}
+
+// A callee may grow the memory and move it, so its base must be reloaded after a call
+// mov rbx, [rdi + 0x10]
+// mov r12d, esi
+// cmp r12, [rdi + 0x18]
+// jae trap
+// call 0x12
+// mov [rbx + r12], eax
+// ret
+// trap:
+// ud2
+#[test]
+fn validate_heap_negative_bounds_checked_base_across_call() {
+    let mut code = [
+        0x48, 0x8b, 0x5f, 0x10, 0x41, 0x89, 0xf4, 0x4c, 0x3b, 0x67, 0x18, 0x73, 0x0a, 0xe8, 0x00,
+        0x00, 0x00, 0x00, 0x42, 0x89, 0x04, 0x23, 0xc3, 0x0f, 0x0b,
+    ];
+    let strategy = HeapStrategy::VMCtxFirstArgWithBoundsChecks {
+        vmctx_heap_base_ptr_offset: 0x10,
+        vmctx_heap_bound_offset: 0x18,
+        index_width: IndexWidth::I32,
+    };
+    let layout = HeapLayout {
+        reservation: 0,
+        guard: 0x1_0000,
+        globals_size: 0x1000,
+        vmctx_size: 0x1000,
+    };
+    let validate = |code: &[u8]| {
+        validate_heap_with_options(
+            code,
+            &[0, 13, 23],
+            &[(0, 13), (0, 23)],
+            &ValidateOptions::default()
+                .heap_strategy(strategy.clone())
+                .heap_layout(layout),
+        )
+    };
+    assert!(matches!(
+        validate(&code),
+        Err(ValidationError::HeapUnsafe { offset: 18, .. })
+    ));
+    // Without the call, the base is still valid: nop dword [rax + rax]
+    code[13..18].copy_from_slice(&[0x0f, 0x1f, 0x44, 0x00, 0x00]);
+    assert!(validate(&code).is_ok());
+}
+
+// mov rax, [rdi + 0x10]
+// mov ecx, esi
+// cmp rcx, [rdi + 0x18]
+// jb trap
+// mov eax, [rax + rcx]
+// ret
+// trap:
+// ud2
+#[test]
+fn validate_heap_negative_bounds_check_wrong_edge() {
+    let code = [
+        0x48, 0x8b, 0x47, 0x10, 0x89, 0xf1, 0x48, 0x3b, 0x4f, 0x18, 0x72, 0x04, 0x8b, 0x04, 0x08,
+        0xc3, 0x0f, 0x0b,
+    ];
+    let strategy = HeapStrategy::VMCtxFirstArgWithBoundsChecks {
+        vmctx_heap_base_ptr_offset: 0x10,
+        vmctx_heap_bound_offset: 0x18,
+        index_width: IndexWidth::I32,
+    };
+    let layout = HeapLayout {
+        reservation: 0,
+        guard: 0x1_0000,
+        globals_size: 0x1000,
+        vmctx_size: 0x1000,
+    };
+    assert!(matches!(
+        validate_heap_with_options(
+            &code,
+            &[0, 12, 16],
+            &[(0, 12), (0, 16)],
+            &ValidateOptions::default()
+                .heap_strategy(strategy)
+                .heap_layout(layout)
+        ),
+        Err(ValidationError::HeapUnsafe { offset: 12, .. })
+    ));
+}
+
+// mov rax, [rdi + 0x10]
+// mov rcx, rsi
+// cmp rcx, [rdi + 0x18]
+// jae trap
+// mov eax, [rax + rcx]
+// ret
+// trap:
+// ud2
+#[test]
+fn validate_heap_memory64_bounds_checked_access() {
+    let code = [
+        0x48, 0x8b, 0x47, 0x10, 0x48, 0x89, 0xf1, 0x48, 0x3b, 0x4f, 0x18, 0x73, 0x04, 0x8b, 0x04,
+        0x08, 0xc3, 0x0f, 0x0b,
+    ];
+    let strategy = HeapStrategy::VMCtxFirstArgWithBoundsChecks {
+        vmctx_heap_base_ptr_offset: 0x10,
+        vmctx_heap_bound_offset: 0x18,
+        index_width: IndexWidth::I64,
+    };
+    assert!(validate_heap(&code, &[0, 13, 17], &[(0, 13), (0, 17)], strategy).is_ok());
+}
+
+// mov rax, [rdi + 0x10]
+// mov ecx, esi
+// mov eax, [rax + rcx]
+// ret
+#[test]
+fn validate_heap_negative_memory64_zero_extended_index() {
+    let code = [0x48, 0x8b, 0x47, 0x10, 0x89, 0xf1, 0x8b, 0x04, 0x08, 0xc3];
+    let strategy = HeapStrategy::VMCtxFirstArgWithBoundsChecks {
+        vmctx_heap_base_ptr_offset: 0x10,
+        vmctx_heap_bound_offset: 0x18,
+        index_width: IndexWidth::I64,
+    };
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], strategy),
+        Err(ValidationError::HeapUnsafe { offset: 6, .. })
+    ));
+}
+
+// mov rax, [rdi + 0x10]
+// mov rcx, rsi
+// and rcx, 0xffff
+// mov eax, [rax + rcx]
+// ret
+#[test]
+fn validate_heap_memory64_masked_index() {
+    let code = [
+        0x48, 0x8b, 0x47, 0x10, 0x48, 0x89, 0xf1, 0x48, 0x81, 0xe1, 0xff, 0xff, 0x00, 0x00, 0x8b,
+        0x04, 0x08, 0xc3,
+    ];
+    let strategy = HeapStrategy::VMCtxFirstArgWithBoundsChecks {
+        vmctx_heap_base_ptr_offset: 0x10,
+        vmctx_heap_bound_offset: 0x18,
+        index_width: IndexWidth::I64,
+    };
+    let layout = HeapLayout {
+        reservation: 0x1_0000,
+        guard: 0x1_0000,
+        globals_size: 0x1000,
+        vmctx_size: 0x1000,
+    };
+    assert!(validate_heap_with_options(
+        &code,
+        &[0],
+        &[],
+        &ValidateOptions::default()
+            .heap_strategy(strategy)
+            .heap_layout(layout)
+    )
+    .is_ok());
+}
+
+// Two bounds-checked memories, with bases at vmctx + 0x10 and vmctx + 0x20 and lengths at
+// vmctx + 0x18 and vmctx + 0x28
+fn two_memories() -> HeapStrategy {
+    let memory = |base_ptr_offset, bound_offset| VMCtxMemory {
+        base_ptr_offset,
+        bound_offset: Some(bound_offset),
+        index_width: IndexWidth::I32,
+        layout: HeapLayout {
+            reservation: 0,
+            guard: 0x1_0000,
+            globals_size: 0,
+            vmctx_size: 0,
+        },
+    };
+    HeapStrategy::VMCtxFirstArgMultiMemory {
+        memories: vec![memory(0x10, 0x18), memory(0x20, 0x28)],
+    }
+}
+
+// mov rax, [rdi + 0x20]
+// mov ecx, esi
+// cmp rcx, [rdi + 0x28]
+// jae trap
+// mov eax, [rax + rcx]
+// ret
+// trap:
+// ud2
+#[test]
+fn validate_heap_multi_memory_access() {
+    let code = [
+        0x48, 0x8b, 0x47, 0x20, 0x89, 0xf1, 0x48, 0x3b, 0x4f, 0x28, 0x73, 0x04, 0x8b, 0x04, 0x08,
+        0xc3, 0x0f, 0x0b,
+    ];
+    assert!(validate_heap(&code, &[0, 12, 16], &[(0, 12), (0, 16)], two_memories()).is_ok());
+}
+
+// mov rax, [rdi + 0x20]
+// mov ecx, esi
+// cmp rcx, [rdi + 0x18]
+// jae trap
+// mov eax, [rax + rcx]
+// ret
+// trap:
+// ud2
+#[test]
+fn validate_heap_negative_multi_memory_mixed_index() {
+    let code = [
+        0x48, 0x8b, 0x47, 0x20, 0x89, 0xf1, 0x48, 0x3b, 0x4f, 0x18, 0x73, 0x04, 0x8b, 0x04, 0x08,
+        0xc3, 0x0f, 0x0b,
+    ];
+    assert!(matches!(
+        validate_heap(&code, &[0, 12, 16], &[(0, 12), (0, 16)], two_memories()),
+        Err(ValidationError::HeapUnsafe { offset: 12, .. })
+    ));
+}
+
+// mov eax, esi
+// ret
+#[test]
+fn validate_heap_negative_zero_length_block() {
+    let code = [0x89, 0xf0, 0xc3];
+    assert!(matches!(
+        validate_heap(
+            &code,
+            &[0, 2, 2],
+            &[(0, 2)],
+            HeapStrategy::HeapPtrFirstArgWithGuards
+        ),
+        Err(ValidationError::CfgInvalid(_))
+    ));
+}
+
+// mov eax, esi
+// ret
+#[test]
+fn validate_heap_negative_dangling_edge() {
+    let code = [0x89, 0xf0, 0xc3];
+    assert!(matches!(
+        validate_heap(
+            &code,
+            &[0, 2],
+            &[(0, 5)],
+            HeapStrategy::HeapPtrFirstArgWithGuards
+        ),
+        Err(ValidationError::CfgInvalid(_))
+    ));
+}
+
+// mov rax, rsi
+// mov eax, [rdi + rax]
+// ret
+#[test]
+fn validate_heap_unsafe_offset() {
+    let code = [0x48, 0x89, 0xf0, 0x8b, 0x04, 0x07, 0xc3];
+    match validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards) {
+        Err(ValidationError::HeapUnsafe { offset, violations }) => {
+            assert_eq!(offset, 3);
+            assert_eq!(violations.len(), 1);
+        }
+        other => panic!("expected a heap violation, got {:?}", other),
+    }
+}
+
+// sub rsp, rax
+// ret
+#[test]
+fn validate_heap_negative_unknown_stack_adjustment() {
+    let code = [0x48, 0x29, 0xc4, 0xc3];
+    // The stack pass reports the adjustment; other passes only fail to model it
+    assert!(matches!(
+        validate_stack(&code, &[0], &[], &ValidateOptions::default()),
+        Err(ValidationError::StackUnsafe { offset: 0, .. })
+    ));
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards),
+        Err(ValidationError::UnsupportedInstruction { offset: 0, .. })
+    ));
+}
+
+// test rdi, rdi
+// je 9
+// sub rsp, 8
+// ret
+#[test]
+fn validate_stack_negative_stack_pointer_differs_at_join() {
+    let code = [0x48, 0x85, 0xff, 0x74, 0x04, 0x48, 0x83, 0xec, 0x08, 0xc3];
+    assert!(matches!(
+        validate_stack(&code, &[0], &[], &ValidateOptions::default()),
+        Err(ValidationError::StackUnsafe { offset: 9, .. })
+    ));
+}
+
+// push ax
+// ret
+#[test]
+fn validate_heap_negative_unsupported_operand() {
+    let code = [0x66, 0x50, 0xc3];
+    assert!(matches!(
+        validate_heap(&code, &[0], &[], HeapStrategy::HeapPtrFirstArgWithGuards),
+        Err(ValidationError::UnsupportedInstruction { offset: 0, .. })
+    ));
+}
+
+// sub rsp, 0x10
+// mov [rsp + 8], rdi
+// add rsp, 0x10
+// ret
+#[test]
+fn validate_stack_frame_access() {
+    let code = [
+        0x48, 0x83, 0xec, 0x10, 0x48, 0x89, 0x7c, 0x24, 0x08, 0x48, 0x83, 0xc4, 0x10, 0xc3,
+    ];
+    assert!(validate_stack(&code, &[0], &[], &ValidateOptions::default()).is_ok());
+}
+
+// mov eax, 0x2000
+// call lucet_probestack
+// sub rsp, rax
+// mov [rsp], rdi
+// add rsp, 0x2000
+// ret
+#[test]
+fn validate_stack_probed_frame() {
+    let code = [
+        0xb8, 0x00, 0x20, 0x00, 0x00, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x48, 0x29, 0xc4, 0x48, 0x89,
+        0x3c, 0x24, 0x48, 0x81, 0xc4, 0x00, 0x20, 0x00, 0x00, 0xc3,
+    ];
+    let relocations = [Relocation::ProbestackCall { offset: 5 }];
+    assert!(validate_stack(
+        &code,
+        &[0],
+        &[],
+        &ValidateOptions::default().relocations(&relocations)
+    )
+    .is_ok());
+    assert!(matches!(
+        validate_stack(&code, &[0], &[], &ValidateOptions::default()),
+        Err(ValidationError::StackUnsafe { .. })
+    ));
+}
+
+// mov eax, esi
+// ret
+#[test]
+fn validate_stack_negative_relocation_not_a_call() {
+    let code = [0x89, 0xf0, 0xc3];
+    let relocations = [Relocation::ProbestackCall { offset: 0 }];
+    assert!(matches!(
+        validate_stack(
+            &code,
+            &[0],
+            &[],
+            &ValidateOptions::default().relocations(&relocations)
+        ),
+        Err(ValidationError::InvalidRelocation { offset: 0, .. })
+    ));
+}
+
+// call f
+// ret
+#[test]
+fn validate_calls_direct_call() {
+    let code = [0xe8, 0x00, 0x00, 0x00, 0x00, 0xc3];
+    let relocations = [Relocation::DirectCall {
+        offset: 0,
+        func_index: 3,
+    }];
+    assert!(validate_calls(
+        &code,
+        &[0],
+        &[],
+        &ValidateOptions::default()
+            .relocations(&relocations)
+            .callees(&[1, 3])
+    )
+    .is_ok());
+    assert!(validate_function(
+        &code,
+        &[0],
+        &[],
+        &ValidateOptions::default()
+            .relocations(&relocations)
+            .callees(&[3])
+    )
+    .is_ok());
+}
+
+// call f
+// ret
+#[test]
+fn validate_calls_negative_direct_call_to_unknown_function() {
+    let code = [0xe8, 0x00, 0x00, 0x00, 0x00, 0xc3];
+    let relocations = [Relocation::DirectCall {
+        offset: 0,
+        func_index: 2,
+    }];
+    assert!(matches!(
+        validate_calls(
+            &code,
+            &[0],
+            &[],
+            &ValidateOptions::default()
+                .relocations(&relocations)
+                .callees(&[1, 3])
+        ),
+        Err(ValidationError::CallUnsafe { offset: 0, .. })
+    ));
+}
+
+// mov rax, guest_table_0
+// ret
+#[test]
+fn validate_calls_negative_relocation_past_end() {
+    let code = [0x48, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0xc3];
+    let relocations = [Relocation::FunctionTable {
+        offset: usize::max_value(),
+    }];
+    assert!(matches!(
+        validate_calls(
+            &code,
+            &[0],
+            &[],
+            &ValidateOptions::default().relocations(&relocations)
+        ),
+        Err(ValidationError::InvalidRelocation { .. })
+    ));
+}
+
+// call rax
+// ret
+#[test]
+fn validate_calls_negative_unchecked_indirect_call() {
+    let code = [0xff, 0xd0, 0xc3];
+    assert!(matches!(
+        validate_calls(&code, &[0], &[], &ValidateOptions::default()),
+        Err(ValidationError::CallUnsafe { offset: 0, .. })
+    ));
+}
 
 // #[test]
 // fn wasmtime_wat_fuel() {